@@ -0,0 +1,64 @@
+//! Throughput of `Counter::add_u64` for every counter and latency of
+//! `Counter::estimate` by HLL precision.
+//!
+//! Run with `cargo bench --features bench --bench counters`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use hll_rust::{
+    AdaptiveCounter, Counter, FMCounter, HLLCounter, HashCounter, HyperBitBit, HyperMinHashCounter,
+    KMVCounter, LinearCounter, PCSACounter, PackedHLLCounter, ThetaSketch, ULLCounter,
+};
+use std::hint::black_box;
+use xxhash_rust::xxh64::Xxh64Builder;
+
+// Number of items added per iteration
+const ITEMS: u64 = 100_000;
+
+fn bench_add<C: Counter>(c: &mut Criterion, name: &str, size: usize) {
+    let mut group = c.benchmark_group("add");
+    group.throughput(Throughput::Elements(ITEMS));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            let mut counter = C::new(size);
+            for i in 0..ITEMS {
+                counter.add_u64(black_box(i));
+            }
+            counter
+        })
+    });
+    group.finish();
+}
+
+fn add(c: &mut Criterion) {
+    bench_add::<HLLCounter<Xxh64Builder>>(c, "HLLCounter", 14);
+    bench_add::<PackedHLLCounter<Xxh64Builder>>(c, "PackedHLLCounter", 14);
+    bench_add::<ULLCounter<Xxh64Builder>>(c, "ULLCounter", 14);
+    bench_add::<HyperMinHashCounter<Xxh64Builder>>(c, "HyperMinHashCounter", 14);
+    bench_add::<AdaptiveCounter<Xxh64Builder>>(c, "AdaptiveCounter", 14);
+    bench_add::<PCSACounter<Xxh64Builder>>(c, "PCSACounter", 14);
+    bench_add::<FMCounter<Xxh64Builder>>(c, "FMCounter", 32);
+    bench_add::<HyperBitBit<Xxh64Builder>>(c, "HyperBitBit", 5);
+    bench_add::<LinearCounter<Xxh64Builder>>(c, "LinearCounter", 1 << 20);
+    bench_add::<KMVCounter<Xxh64Builder>>(c, "KMVCounter", 1024);
+    bench_add::<ThetaSketch<Xxh64Builder>>(c, "ThetaSketch", 1024);
+    bench_add::<HashCounter<Xxh64Builder>>(c, "HashCounter", 0);
+}
+
+fn estimate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("estimate");
+    for precision in [4, 8, 12, 14, 16, 18, 20] {
+        let mut counter = HLLCounter::<Xxh64Builder>::new(precision);
+        for i in 0..1_000_000 {
+            counter.add_u64(i);
+        }
+        group.bench_with_input(
+            BenchmarkId::new("HLLCounter", precision),
+            &counter,
+            |b, counter| b.iter(|| counter.estimate()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, add, estimate);
+criterion_main!(benches);
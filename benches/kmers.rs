@@ -0,0 +1,104 @@
+//! Canonical k-mer extraction from text and 2-bit encoded kmers, and scaling
+//! of the parallel pipeline with the number of threads.
+//!
+//! Run with `cargo bench --features bench --bench kmers`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use hll_rust::fasta::FastaReader;
+use hll_rust::parallel_counting::run_parallel_fasta_analysis_from_reader;
+use hll_rust::{Counter, HLLCounter};
+use std::hint::black_box;
+use xxhash_rust::xxh64::Xxh64Builder;
+
+const K: usize = 31;
+
+// FASTA data of `records` random sequences of `len` bases in lines of 80
+fn random_fasta(records: usize, len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut data = Vec::new();
+    for record in 0..records {
+        data.extend_from_slice(format!(">seq{record}\n").as_bytes());
+        for i in 0..len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            data.push(b"ACGT"[(state >> 62) as usize]);
+            if i % 80 == 79 {
+                data.push(b'\n');
+            }
+        }
+        data.push(b'\n');
+    }
+    data
+}
+
+fn canonical_kmers(c: &mut Criterion) {
+    let data = random_fasta(1, 1_000_000);
+    let mut group = c.benchmark_group("canonical_kmers");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("text", |b| {
+        b.iter(|| {
+            let mut reader = FastaReader::new(&data[..]);
+            reader.next_record().unwrap();
+            let mut kmers = reader.kmers(K);
+            let mut checksum = 0u64;
+            while let Some(kmer) = kmers.next_canonical_kmer().unwrap() {
+                checksum = checksum.wrapping_add(kmer[0] as u64);
+            }
+            checksum
+        })
+    });
+    group.bench_function("2-bit", |b| {
+        b.iter(|| {
+            let mut reader = FastaReader::new(&data[..]);
+            reader.next_record().unwrap();
+            reader
+                .encoded_kmers::<u64>(K)
+                .map(Result::unwrap)
+                .fold(0u64, u64::wrapping_add)
+        })
+    });
+    group.bench_function("ntHash", |b| {
+        b.iter(|| {
+            let mut reader = FastaReader::new(&data[..]);
+            reader.next_record().unwrap();
+            reader
+                .nthashes(K)
+                .map(Result::unwrap)
+                .fold(0u64, u64::wrapping_add)
+        })
+    });
+    group.finish();
+}
+
+fn parallel_scaling(c: &mut Criterion) {
+    let data = random_fasta(16, 500_000);
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut group = c.benchmark_group("parallel_pipeline");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+
+    let mut threads = 1;
+    while threads <= max_threads {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("threads", threads), &data, |b, data| {
+            b.iter(|| {
+                pool.install(|| {
+                    run_parallel_fasta_analysis_from_reader(black_box(&data[..]), K, || {
+                        HLLCounter::<Xxh64Builder>::new(14)
+                    })
+                    .unwrap()
+                })
+            })
+        });
+        threads *= 2;
+    }
+    group.finish();
+}
+
+criterion_group!(benches, canonical_kmers, parallel_scaling);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+//! Sequential read throughput of a large FASTA file with `BufReader` and
+//! with the io_uring reader, buffered and with `O_DIRECT`.
+//!
+//! Run with `cargo bench --features bench,uring --bench readers`. Set
+//! `HLL_BENCH_FILE` to read a file on the drive under test instead of a
+//! generated file in the temporary directory, which may be in memory.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use hll_rust::uring::{UringOptions, UringReader};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+// Size of the generated file
+const FILE_SIZE: usize = 256 << 20;
+
+// Returns the file to read, writing a file of random bases if
+// `HLL_BENCH_FILE` is not set
+fn bench_file() -> PathBuf {
+    if let Some(path) = std::env::var_os("HLL_BENCH_FILE") {
+        return path.into();
+    }
+    let path = std::env::temp_dir().join("hll_rust_bench_readers.fa");
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut data = b">seq\n".to_vec();
+    for i in 0..FILE_SIZE {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.push(if i % 81 == 80 {
+            b'\n'
+        } else {
+            b"ACGT"[(state >> 62) as usize]
+        });
+    }
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+// Reads all data, counting the `A`s so the reads are not optimized away
+fn consume<R: BufRead>(mut reader: R) -> usize {
+    let mut count = 0;
+    loop {
+        let buf = reader.fill_buf().unwrap();
+        if buf.is_empty() {
+            return count;
+        }
+        count += buf.iter().filter(|&&byte| byte == b'A').count();
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+fn sequential_reads(c: &mut Criterion) {
+    let path = bench_file();
+    let len = std::fs::metadata(&path).unwrap().len();
+    let mut group = c.benchmark_group("sequential_reads");
+    group.throughput(Throughput::Bytes(len));
+    group.sample_size(10);
+
+    group.bench_function("BufReader", |b| {
+        b.iter(|| consume(BufReader::new(File::open(&path).unwrap())))
+    });
+    group.bench_function("BufReader 1 MiB", |b| {
+        b.iter(|| {
+            consume(BufReader::with_capacity(
+                1 << 20,
+                File::open(&path).unwrap(),
+            ))
+        })
+    });
+    for direct in [false, true] {
+        let options = UringOptions {
+            direct,
+            ..UringOptions::default()
+        };
+        let name = if direct {
+            "io_uring O_DIRECT"
+        } else {
+            "io_uring"
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| consume(UringReader::with_options(&path, options).unwrap()))
+        });
+    }
+    group.finish();
+
+    if std::env::var_os("HLL_BENCH_FILE").is_none() {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+criterion_group!(benches, sequential_reads);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+#ifndef HLL_RUST_H
+#define HLL_RUST_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Opaque handle to a HyperLogLog sketch.
+typedef struct HllSketch HllSketch;
+
+// Creates an empty sketch with 2^precision registers.
+//
+// Returns NULL if the precision is not between 4 and 24. The sketch must be
+// released with `hll_free`.
+struct HllSketch *hll_new(uint32_t precision);
+
+// Releases a sketch created by `hll_new` or `hll_deserialize`. NULL is ignored.
+//
+// # Safety
+//
+// `sketch` must be NULL or a sketch that was not freed before.
+void hll_free(struct HllSketch *sketch);
+
+// Adds the item of `len` bytes at `data` to the sketch.
+//
+// # Safety
+//
+// `sketch` must be a valid sketch and `data` must point to `len` readable bytes.
+void hll_add_bytes(struct HllSketch *sketch, const uint8_t *data, size_t len);
+
+// Merges `src` into `dst`.
+//
+// Returns 0 on success and -1 if the precisions differ.
+//
+// # Safety
+//
+// `dst` and `src` must be valid sketches.
+int hll_merge(struct HllSketch *dst, const struct HllSketch *src);
+
+// Returns the cardinality estimate of the sketch.
+//
+// # Safety
+//
+// `sketch` must be a valid sketch.
+double hll_estimate(const struct HllSketch *sketch);
+
+// Serializes the sketch into `out`, which has room for `capacity` bytes.
+//
+// Returns the size of the serialized sketch. Nothing is written if it is
+// larger than `capacity`, so the size can be queried with a NULL `out` and a
+// capacity of 0.
+//
+// # Safety
+//
+// `sketch` must be a valid sketch and `out` must point to `capacity` writable bytes.
+size_t hll_serialize(const struct HllSketch *sketch, uint8_t *out, size_t capacity);
+
+// Reads a sketch written by `hll_serialize` or the `hll sketch` command.
+//
+// Returns NULL if the data is not a valid sketch. The sketch must be
+// released with `hll_free`.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes.
+struct HllSketch *hll_deserialize(const uint8_t *data, size_t len);
+
+#endif  /* HLL_RUST_H */
@@ -0,0 +1,453 @@
+//! Reading the read sequences of SAM and BAM alignment files, e.g. to
+//! sketch sequencing runs that are only archived as alignments.
+//!
+//! The format is detected from the data: BAM files are bgzip compressed and
+//! decompressed like other sequence files, and everything else is read as
+//! SAM text. CRAM files are not supported.
+
+use crate::fasta::{FastaError, PHRED_OFFSET, open_sequence_file, validate_sequence};
+use std::io::{self, BufRead};
+use std::path::Path;
+
+const BAM_MAGIC: &[u8; 4] = b"BAM\x01";
+const CRAM_MAGIC: &[u8; 4] = b"CRAM";
+
+// The bases of the 4-bit codes of BAM sequences, `=` read as `N`
+const BAM_BASES: &[u8; 16] = b"NACMGRSVTWYHKDBN";
+
+// Size of the fixed fields of a BAM record, up to the read name
+const BAM_FIXED_SIZE: usize = 32;
+
+/// The read is unmapped.
+pub const FLAG_UNMAPPED: u16 = 0x4;
+/// The alignment is secondary, one of several of a multi-mapping read.
+pub const FLAG_SECONDARY: u16 = 0x100;
+/// The read is a PCR or optical duplicate.
+pub const FLAG_DUPLICATE: u16 = 0x400;
+/// The alignment is supplementary, a part of a chimeric read.
+pub const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+/// Which alignment records are skipped by [`AlignmentReader::next_record`].
+///
+/// Secondary and supplementary alignments repeat (parts of) the sequence of
+/// their primary alignment, so the default skips them to count every read
+/// once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlignmentFilter {
+    /// Skip secondary alignments ([`FLAG_SECONDARY`]).
+    pub skip_secondary: bool,
+    /// Skip supplementary alignments ([`FLAG_SUPPLEMENTARY`]).
+    pub skip_supplementary: bool,
+    /// Skip reads marked as duplicates ([`FLAG_DUPLICATE`]).
+    pub skip_duplicates: bool,
+    /// Skip unmapped reads ([`FLAG_UNMAPPED`]).
+    pub skip_unmapped: bool,
+}
+
+impl AlignmentFilter {
+    /// Keeps every record.
+    pub const ALL: AlignmentFilter = AlignmentFilter {
+        skip_secondary: false,
+        skip_supplementary: false,
+        skip_duplicates: false,
+        skip_unmapped: false,
+    };
+
+    fn skips(&self, flags: u16) -> bool {
+        (self.skip_secondary && flags & FLAG_SECONDARY != 0)
+            || (self.skip_supplementary && flags & FLAG_SUPPLEMENTARY != 0)
+            || (self.skip_duplicates && flags & FLAG_DUPLICATE != 0)
+            || (self.skip_unmapped && flags & FLAG_UNMAPPED != 0)
+    }
+}
+
+impl Default for AlignmentFilter {
+    fn default() -> Self {
+        AlignmentFilter {
+            skip_secondary: true,
+            skip_supplementary: true,
+            ..AlignmentFilter::ALL
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Sam,
+    Bam,
+}
+
+/// A reader of the records of SAM or BAM data, one by one.
+///
+/// The sequence and quality of a record are stored as in a FASTQ file, with
+/// Phred qualities offset by [`PHRED_OFFSET`]. Records without a stored
+/// sequence, e.g. secondary alignments written with `*`, have an empty one,
+/// and records without qualities an empty quality.
+///
+/// ```
+/// use hll_rust::alignment::AlignmentReader;
+///
+/// let sam: &[u8] = b"@HD\tVN:1.6\nr1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\tIIII\n\
+///                    r1\t256\tchr2\t1\t0\t4M\t*\t0\t0\t*\t*\n";
+/// let mut reader = AlignmentReader::new(sam)?;
+/// assert!(reader.next_record()?);
+/// assert_eq!(reader.sequence, b"ACGT");
+/// // The secondary alignment is skipped
+/// assert!(!reader.next_record()?);
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub struct AlignmentReader<R: BufRead> {
+    reader: R,
+    format: Format,
+    filter: AlignmentFilter,
+    // Current SAM line or BAM record
+    line: String,
+    block: Vec<u8>,
+    // Number of records read so far, for error messages
+    record_no: usize,
+    pub name: Vec<u8>,
+    pub flags: u16,
+    pub sequence: Vec<u8>,
+    pub quality: Vec<u8>,
+}
+
+/// Opens a SAM or BAM file for reading.
+///
+/// `-` reads from standard input.
+pub fn open_alignment_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<AlignmentReader<Box<dyn BufRead + Send>>, FastaError> {
+    AlignmentReader::new(open_sequence_file(path)?)
+}
+
+impl<R: BufRead> AlignmentReader<R> {
+    /// Creates a new `AlignmentReader` from decompressed SAM or BAM data,
+    /// reading the header.
+    ///
+    /// Use [`open_sequence_reader`](crate::fasta::open_sequence_reader) to
+    /// decompress BAM data that is not read from a file.
+    pub fn new(mut reader: R) -> Result<Self, FastaError> {
+        let start = reader.fill_buf()?;
+        if start.starts_with(CRAM_MAGIC) {
+            return Err(unsupported("CRAM files are not supported.").into());
+        }
+        let format = if start.starts_with(BAM_MAGIC) {
+            Format::Bam
+        } else {
+            Format::Sam
+        };
+
+        let mut alignment_reader = AlignmentReader {
+            reader,
+            format,
+            filter: AlignmentFilter::default(),
+            line: String::new(),
+            block: Vec::new(),
+            record_no: 0,
+            name: Vec::new(),
+            flags: 0,
+            sequence: Vec::new(),
+            quality: Vec::new(),
+        };
+        if format == Format::Bam {
+            alignment_reader.skip_bam_header()?;
+        }
+        Ok(alignment_reader)
+    }
+
+    /// Skips the records matched by `filter` instead of the default
+    /// [`AlignmentFilter`].
+    pub fn with_filter(mut self, filter: AlignmentFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Advances the reader to the next record that passes the filter.
+    ///
+    /// Returns `Ok(true)` if a record was found, `Ok(false)` if EOF was reached.
+    /// The read name, flags, sequence and quality string are stored in
+    /// `self.name`, `self.flags`, `self.sequence` and `self.quality`.
+    pub fn next_record(&mut self) -> Result<bool, FastaError> {
+        loop {
+            let found = match self.format {
+                Format::Sam => self.next_sam_record()?,
+                Format::Bam => self.next_bam_record()?,
+            };
+            if !found {
+                return Ok(false);
+            }
+            if !self.filter.skips(self.flags) {
+                return Ok(true);
+            }
+        }
+    }
+
+    // Reads the next SAM alignment line, skipping header lines.
+    fn next_sam_record(&mut self) -> Result<bool, FastaError> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            if !self.line.starts_with('@') && !self.line.trim().is_empty() {
+                break;
+            }
+        }
+        self.record_no += 1;
+
+        let fields: Vec<&str> = self.line.trim_end().split('\t').collect();
+        let malformed = FastaError::MalformedAlignment {
+            record: self.record_no,
+        };
+        let [name, flags, _, _, _, _, _, _, _, sequence, quality, ..] = fields[..] else {
+            return Err(malformed);
+        };
+        self.flags = flags.parse().map_err(|_| malformed)?;
+        self.name.clear();
+        self.name.extend_from_slice(name.as_bytes());
+
+        self.sequence.clear();
+        if sequence != "*" {
+            validate_sequence(sequence.as_bytes(), 0)?;
+            self.sequence.extend_from_slice(sequence.as_bytes());
+        }
+        self.quality.clear();
+        if quality != "*" {
+            if quality.len() != self.sequence.len() {
+                return Err(FastaError::MalformedAlignment {
+                    record: self.record_no,
+                });
+            }
+            self.quality.extend_from_slice(quality.as_bytes());
+        }
+        Ok(true)
+    }
+
+    // Skips the magic, the header text and the reference sequences.
+    fn skip_bam_header(&mut self) -> Result<(), FastaError> {
+        self.read_block(BAM_MAGIC.len())?;
+        let text_len = self.read_u32()? as usize;
+        self.read_block(text_len)?;
+        let num_refs = self.read_u32()?;
+        for _ in 0..num_refs {
+            let name_len = self.read_u32()? as usize;
+            // The name and the length of the sequence
+            self.read_block(name_len + 4)?;
+        }
+        Ok(())
+    }
+
+    // Reads the next BAM record.
+    fn next_bam_record(&mut self) -> Result<bool, FastaError> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let block_size = self.read_u32()? as usize;
+        self.read_block(block_size)?;
+        self.record_no += 1;
+
+        let block = &self.block;
+        let malformed = FastaError::MalformedAlignment {
+            record: self.record_no,
+        };
+        if block.len() < BAM_FIXED_SIZE {
+            return Err(malformed);
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([block[i], block[i + 1]]);
+        let name_len = block[8] as usize;
+        let num_cigar_ops = u16_at(12) as usize;
+        self.flags = u16_at(14);
+        let seq_len = u32::from_le_bytes(block[16..20].try_into().unwrap()) as usize;
+
+        let name_end = BAM_FIXED_SIZE + name_len;
+        let seq_start = name_end + 4 * num_cigar_ops;
+        let quality_start = seq_start + seq_len.div_ceil(2);
+        if block.len() < quality_start + seq_len {
+            return Err(malformed);
+        }
+
+        // The name is NUL terminated
+        self.name.clear();
+        self.name
+            .extend_from_slice(&block[BAM_FIXED_SIZE..name_end.saturating_sub(1)]);
+        self.sequence.clear();
+        self.sequence.extend(
+            (0..seq_len).map(|i| {
+                BAM_BASES[((block[seq_start + i / 2] >> (4 - 4 * (i % 2))) & 0xF) as usize]
+            }),
+        );
+        let quality = &block[quality_start..quality_start + seq_len];
+        self.quality.clear();
+        // Missing qualities are stored as 0xFF
+        if quality.first().is_some_and(|&q| q != 0xFF) {
+            self.quality
+                .extend(quality.iter().map(|&q| q.saturating_add(PHRED_OFFSET)));
+        }
+        Ok(true)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, FastaError> {
+        self.read_block(4)?;
+        Ok(u32::from_le_bytes(self.block[..4].try_into().unwrap()))
+    }
+
+    // Reads exactly `len` bytes into `self.block`.
+    fn read_block(&mut self, len: usize) -> Result<(), FastaError> {
+        self.block.resize(len, 0);
+        self.reader.read_exact(&mut self.block).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                FastaError::TruncatedRecord
+            } else {
+                e.into()
+            }
+        })
+    }
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::open_sequence_reader;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{Read, Write};
+
+    const SAM: &[u8] = b"@HD\tVN:1.6\tSO:unsorted\n\
+        @SQ\tSN:chr1\tLN:100\n\
+        r1\t0\tchr1\t1\t60\t8M\t*\t0\t0\tACGTTGCA\tIIIIIII#\n\
+        r1\t256\tchr1\t50\t0\t8M\t*\t0\t0\t*\t*\n\
+        r2\t2064\tchr1\t9\t60\t5M\t*\t0\t0\tGGCCA\t*\n\
+        r3\t1028\t*\t0\t0\t*\t*\t0\t0\tTTAN\t!!!!\n";
+
+    // Name, flags, sequence and quality of a record
+    type Record = (Vec<u8>, u16, Vec<u8>, Vec<u8>);
+    type BamRecord<'a> = (&'a [u8], u16, &'a [u8], Option<&'a [u8]>);
+
+    // Encodes the records of `SAM` as BAM, gzip compressed
+    fn bam() -> Vec<u8> {
+        let mut data = BAM_MAGIC.to_vec();
+        let text = b"@HD\tVN:1.6\n";
+        data.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        data.extend_from_slice(text);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&5u32.to_le_bytes());
+        data.extend_from_slice(b"chr1\0");
+        data.extend_from_slice(&100u32.to_le_bytes());
+
+        let records: [BamRecord; 4] = [
+            (
+                b"r1",
+                0,
+                b"ACGTTGCA",
+                Some(&[40, 40, 40, 40, 40, 40, 40, 2]),
+            ),
+            (b"r1", 256, b"", None),
+            (b"r2", 2064, b"GGCCA", None),
+            (b"r3", 1028, b"TTAN", Some(&[0, 0, 0, 0])),
+        ];
+        for (name, flags, seq, quality) in records {
+            let mut block = Vec::new();
+            block.extend_from_slice(&0i32.to_le_bytes());
+            block.extend_from_slice(&0i32.to_le_bytes());
+            block.push(name.len() as u8 + 1);
+            block.push(60);
+            block.extend_from_slice(&0u16.to_le_bytes());
+            // One cigar operation
+            block.extend_from_slice(&1u16.to_le_bytes());
+            block.extend_from_slice(&flags.to_le_bytes());
+            block.extend_from_slice(&(seq.len() as u32).to_le_bytes());
+            block.extend_from_slice(&[0xFF; 4]);
+            block.extend_from_slice(&0i32.to_le_bytes());
+            block.extend_from_slice(&0i32.to_le_bytes());
+            block.extend_from_slice(name);
+            block.push(0);
+            block.extend_from_slice(&((seq.len() as u32) << 4).to_le_bytes());
+            for pair in seq.chunks(2) {
+                let code = |base| BAM_BASES.iter().rposition(|&b| b == base).unwrap() as u8;
+                block.push(code(pair[0]) << 4 | pair.get(1).map_or(0, |&base| code(base)));
+            }
+            match quality {
+                Some(quality) => block.extend_from_slice(quality),
+                None => block.extend(std::iter::repeat_n(0xFF, seq.len())),
+            }
+            data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            data.extend_from_slice(&block);
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn records<R: BufRead>(mut reader: AlignmentReader<R>) -> Vec<Record> {
+        let mut records = Vec::new();
+        while reader.next_record().unwrap() {
+            records.push((
+                reader.name.clone(),
+                reader.flags,
+                reader.sequence.clone(),
+                reader.quality.clone(),
+            ));
+        }
+        records
+    }
+
+    #[test]
+    fn test_sam_and_bam_match() {
+        let sam = records(AlignmentReader::new(SAM).unwrap());
+        assert_eq!(
+            sam,
+            [
+                (
+                    b"r1".to_vec(),
+                    0,
+                    b"ACGTTGCA".to_vec(),
+                    b"IIIIIII#".to_vec()
+                ),
+                (b"r3".to_vec(), 1028, b"TTAN".to_vec(), b"!!!!".to_vec()),
+            ]
+        );
+        let bam_reader =
+            AlignmentReader::new(open_sequence_reader(io::Cursor::new(bam())).unwrap());
+        assert_eq!(records(bam_reader.unwrap()), sam);
+
+        for data in [SAM.to_vec(), bam()] {
+            let reader = open_sequence_reader(io::Cursor::new(data)).unwrap();
+            let reader = AlignmentReader::new(reader)
+                .unwrap()
+                .with_filter(AlignmentFilter {
+                    skip_duplicates: true,
+                    ..AlignmentFilter::ALL
+                });
+            let names: Vec<Vec<u8>> = records(reader).into_iter().map(|r| r.0).collect();
+            assert_eq!(names, [b"r1".to_vec(), b"r1".to_vec(), b"r2".to_vec()]);
+        }
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let mut reader = AlignmentReader::new(&b"r1\t0\tchr1\n"[..]).unwrap();
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::MalformedAlignment { record: 1 })
+        ));
+
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&bam()[..])
+            .read_to_end(&mut data)
+            .unwrap();
+        data.truncate(data.len() - 3);
+        let mut reader = AlignmentReader::new(&data[..]).unwrap();
+        assert!(reader.next_record().unwrap());
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::TruncatedRecord)
+        ));
+
+        assert!(AlignmentReader::new(&b"CRAM\x03\x00"[..]).is_err());
+    }
+}
@@ -0,0 +1,326 @@
+//! Asynchronous FASTA reading with tokio, e.g. to sketch genomes uploaded to
+//! a web service without blocking its runtime.
+
+use crate::fasta::{FastaError, KmerPolicy, RecordStats, get_canonical_into, validate_sequence};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// An asynchronous FASTA reader that reads records one by one, like
+/// [`FastaReader`](crate::fasta::FastaReader).
+///
+/// Compressed data is not detected; decompress it before, e.g. with
+/// `async-compression`.
+///
+/// ```
+/// use hll_rust::async_fasta::AsyncFastaReader;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let data: &[u8] = b">a\nACGTAC\n>b\nGGG\nCC\n";
+/// let mut reader = AsyncFastaReader::new(data);
+/// let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+/// while reader.next_record().await? {
+///     let mut kmers = reader.kmers(3);
+///     while let Some(kmer) = kmers.next_canonical_kmer().await? {
+///         counter.add(kmer);
+///     }
+/// }
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// # }).unwrap();
+/// ```
+pub struct AsyncFastaReader<R: AsyncBufRead + Unpin> {
+    reader: R,
+    line: String,
+    // Number of lines read so far, for error messages
+    line_no: usize,
+    finished: bool,
+    // Whether sequence lines of the current record may be left unread
+    in_record: bool,
+    kmer_policy: KmerPolicy,
+    stats: RecordStats,
+    pub id: Option<Vec<u8>>,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncFastaReader<R> {
+    /// Creates a new `AsyncFastaReader` from a type implementing `AsyncBufRead`.
+    pub fn new(reader: R) -> Self {
+        AsyncFastaReader {
+            reader,
+            line: String::new(),
+            line_no: 0,
+            finished: false,
+            in_record: false,
+            kmer_policy: KmerPolicy::default(),
+            stats: RecordStats::default(),
+            id: None,
+        }
+    }
+
+    /// Applies the given policy to the kmers of all streams.
+    pub fn with_kmer_policy(mut self, policy: KmerPolicy) -> Self {
+        self.kmer_policy = policy;
+        self
+    }
+
+    // Reads the next line into `self.line`, returning the number of bytes read.
+    async fn read_line(&mut self) -> Result<usize, FastaError> {
+        self.line.clear();
+        let bytes_read = self.reader.read_line(&mut self.line).await?;
+        if bytes_read > 0 {
+            self.line_no += 1;
+        }
+        Ok(bytes_read)
+    }
+
+    /// Advances the reader to the next record, skipping the unread sequence
+    /// of the current one.
+    ///
+    /// Returns `Ok(true)` if a record was found, `Ok(false)` if EOF was reached.
+    /// The record ID is stored in `self.id`.
+    pub async fn next_record(&mut self) -> Result<bool, FastaError> {
+        if self.in_record {
+            self.in_record = false;
+            while !self.finished && !self.line.starts_with('>') {
+                if self.read_line().await? == 0 {
+                    self.finished = true;
+                } else if !self.line.starts_with('>') {
+                    self.stats.add_line(self.line.trim().as_bytes());
+                }
+            }
+        }
+        if self.finished {
+            return Ok(false);
+        }
+
+        if self.line.is_empty() && self.read_line().await? == 0 {
+            self.finished = true;
+            return Ok(false);
+        }
+
+        if !self.line.starts_with('>') {
+            return Err(FastaError::MalformedHeader {
+                line_no: self.line_no,
+            });
+        }
+
+        self.stats = RecordStats::default();
+        self.id = Some(
+            self.line
+                .trim_start_matches('>')
+                .trim_end()
+                .as_bytes()
+                .to_vec(),
+        );
+        self.line.clear();
+        self.in_record = true;
+
+        Ok(true)
+    }
+
+    /// Statistics of the sequence lines of the current record read so far.
+    pub fn stats(&self) -> &RecordStats {
+        &self.stats
+    }
+
+    /// Reads the full sequence of the current record.
+    ///
+    /// This consumes the rest of the current record.
+    pub async fn read_sequence(&mut self) -> Result<Vec<u8>, FastaError> {
+        let mut sequence = Vec::new();
+        while let Some(line) = self.next_sequence_line().await? {
+            validate_sequence(line, sequence.len())?;
+            sequence.extend_from_slice(line);
+        }
+        Ok(sequence)
+    }
+
+    // Reads the next sequence line of the current record, `None` at the next
+    // header or the end of the data.
+    async fn next_sequence_line(&mut self) -> Result<Option<&[u8]>, FastaError> {
+        if !self.in_record {
+            return Ok(None);
+        }
+        if self.read_line().await? == 0 {
+            self.finished = true;
+            self.in_record = false;
+            return Ok(None);
+        }
+        if self.line.starts_with('>') {
+            self.in_record = false;
+            return Ok(None);
+        }
+        let line = self.line.trim().as_bytes();
+        self.stats.add_line(line);
+        Ok(Some(line))
+    }
+
+    /// Returns a stream of the kmers of the current record.
+    pub fn kmers(&mut self, k: usize) -> AsyncKmerStream<'_, R> {
+        let policy = self.kmer_policy;
+        AsyncKmerStream {
+            reader: self,
+            k,
+            buffer: Vec::with_capacity(k * 2),
+            start: 0,
+            canonical: vec![0; k],
+            bases_read: 0,
+            policy,
+        }
+    }
+}
+
+/// A stream of the kmers of a record of an [`AsyncFastaReader`], like
+/// [`KmerStream`](crate::fasta::KmerStream).
+///
+/// The kmers are slices into an internal buffer, so this is not a
+/// `futures::Stream`. Bases left unread when the stream is dropped are
+/// skipped by the next [`AsyncFastaReader::next_record`].
+pub struct AsyncKmerStream<'a, R: AsyncBufRead + Unpin> {
+    reader: &'a mut AsyncFastaReader<R>,
+    k: usize,
+    // Contiguous buffer of the sequence; the current kmer starts at `start`
+    buffer: Vec<u8>,
+    start: usize,
+    // Scratch space for the reverse complement of the current kmer
+    canonical: Vec<u8>,
+    // Number of bases of the record read so far
+    bases_read: usize,
+    policy: KmerPolicy,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncKmerStream<'_, R> {
+    // Number of buffered bases from the start of the current kmer
+    fn available(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    async fn fill_buffer(&mut self) -> Result<(), FastaError> {
+        while self.available() < self.k {
+            // Move the (fewer than k) remaining bases to the front, so the
+            // buffer never grows beyond one line plus k bases.
+            self.buffer.drain(..self.start);
+            self.start = 0;
+
+            let Some(line) = self.reader.next_sequence_line().await? else {
+                break;
+            };
+            validate_sequence(line, self.bases_read)?;
+            self.bases_read += line.len();
+            let appended = self.buffer.len();
+            self.buffer.extend_from_slice(line);
+            if self.policy.uppercase {
+                self.buffer[appended..].make_ascii_uppercase();
+            }
+        }
+        Ok(())
+    }
+
+    // Advances to the next kmer and returns its start in the buffer.
+    async fn advance(&mut self) -> Result<Option<usize>, FastaError> {
+        loop {
+            self.fill_buffer().await?;
+            if self.available() < self.k {
+                return Ok(None);
+            }
+
+            let start = self.start;
+            let kmer = &self.buffer[start..start + self.k];
+            if let Some(i) = self.policy.first_ambiguous(kmer) {
+                let error = FastaError::AmbiguousBase {
+                    byte: kmer[i],
+                    // The buffer ends with the last base read
+                    position: self.bases_read - (self.buffer.len() - start - i),
+                };
+                // Report every ambiguous base once
+                self.start = start + i + 1;
+                return Err(error);
+            }
+            if let Some(rejected) = self.policy.last_rejected(kmer) {
+                // No kmer containing this base passes the policy
+                self.start = start + rejected + 1;
+                continue;
+            }
+            self.start += 1;
+            return Ok(Some(start));
+        }
+    }
+
+    /// Returns the next kmer as a slice into the internal buffer, without allocating.
+    pub async fn next_kmer(&mut self) -> Result<Option<&[u8]>, FastaError> {
+        Ok(self
+            .advance()
+            .await?
+            .map(|start| &self.buffer[start..start + self.k]))
+    }
+
+    /// Returns the next canonical kmer as a slice into an internal buffer, without allocating.
+    pub async fn next_canonical_kmer(&mut self) -> Result<Option<&[u8]>, FastaError> {
+        Ok(self.advance().await?.map(|start| {
+            get_canonical_into(&self.buffer[start..start + self.k], &mut self.canonical)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::FastaReader;
+
+    const DATA: &[u8] = b">seq1 first\nACGTN\nacgt\n>seq2\nGGGCCC\n>empty\n>seq3\nTTTTAAAAC\n";
+
+    #[tokio::test]
+    async fn test_records_match_sync_reader() {
+        let mut sync_reader = FastaReader::new(DATA);
+        let mut reader = AsyncFastaReader::new(DATA);
+        while sync_reader.next_record().unwrap() {
+            assert!(reader.next_record().await.unwrap());
+            assert_eq!(reader.id, sync_reader.id);
+            assert_eq!(
+                reader.read_sequence().await.unwrap(),
+                sync_reader.read_sequence().unwrap()
+            );
+            assert_eq!(reader.stats(), sync_reader.stats());
+        }
+        assert!(!reader.next_record().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_kmers_match_sync_reader() {
+        for policy in [KmerPolicy::default(), KmerPolicy::STRICT] {
+            let mut sync_reader = FastaReader::new(DATA).with_kmer_policy(policy);
+            let mut expected = Vec::new();
+            while sync_reader.next_record().unwrap() {
+                let mut kmers = sync_reader.kmers(3);
+                while let Some(kmer) = kmers.next_canonical_kmer().unwrap() {
+                    expected.push(kmer.to_vec());
+                }
+            }
+
+            let mut reader = AsyncFastaReader::new(DATA).with_kmer_policy(policy);
+            let mut kmers_seen = Vec::new();
+            while reader.next_record().await.unwrap() {
+                let mut kmers = reader.kmers(3);
+                while let Some(kmer) = kmers.next_canonical_kmer().await.unwrap() {
+                    kmers_seen.push(kmer.to_vec());
+                }
+            }
+            assert_eq!(kmers_seen, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unread_sequence_is_skipped() {
+        let mut reader = AsyncFastaReader::new(DATA);
+        assert!(reader.next_record().await.unwrap());
+        let mut kmers = reader.kmers(3);
+        assert_eq!(kmers.next_kmer().await.unwrap(), Some(&b"ACG"[..]));
+        assert!(reader.next_record().await.unwrap());
+        assert_eq!(reader.id.as_deref(), Some(&b"seq2"[..]));
+
+        let mut reader = AsyncFastaReader::new(&b"ACGT\n"[..]);
+        assert!(matches!(
+            reader.next_record().await,
+            Err(FastaError::MalformedHeader { line_no: 1 })
+        ));
+    }
+}
@@ -0,0 +1,441 @@
+//! A tiny HTTP service of named HyperLogLog sketches, in the spirit of
+//! Redis' `PFADD`, `PFCOUNT` and `PFMERGE`.
+//!
+//! Endpoints, with names of letters, digits, `-`, `_` and `.`:
+//!
+//! * `GET /sketches`: names of all sketches
+//! * `PUT /sketches/{name}`: creates an empty sketch
+//! * `POST /sketches/{name}/add`: adds every line of the body as an item,
+//!   creating the sketch if missing
+//! * `POST /sketches/{name}/merge?from=a,b`: merges other sketches into it
+//! * `GET /sketches/{name}`: estimate with its 95% confidence interval
+//! * `GET /sketches/{name}/sketch`: the sketch in the format of `hll sketch`
+//! * `DELETE /sketches/{name}`: removes the sketch
+//! * `GET /count?keys=a,b`: estimate of the union of several sketches
+
+use clap::Parser;
+use hll_rust::counters::CounterMap;
+use hll_rust::{Counter, EstimateWithError, HLLCounter, MergeableCounter};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Deterministic, so downloaded sketches can be merged with `hll merge`
+type Hasher = xxhash_rust::xxh64::Xxh64Builder;
+type Sketches = CounterMap<String, HLLCounter<Hasher>>;
+
+// Largest accepted request body
+const MAX_BODY_SIZE: usize = 64 << 20;
+// Longest accepted request or header line, and most header lines
+const MAX_LINE_LEN: u64 = 8 << 10;
+const MAX_HEADERS: usize = 100;
+// Time a client may take for every read or write before it is disconnected
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP service of named HyperLogLog sketches.
+#[derive(Parser, Debug)]
+#[command(name = "hll-server", version, about)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    listen: String,
+    /// HyperLogLog precision of all sketches (number of index bits)
+    #[arg(short, long, default_value_t = 14, value_parser = clap::value_parser!(u8).range(4..=24))]
+    precision: u8,
+    /// Most connections served at once, further ones wait to be accepted
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u16).range(1..))]
+    max_connections: u16,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, format!("{{\"error\": \"{}\"}}", message))
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let precision = args.precision as usize;
+    let sketches = Arc::new(Mutex::new(CounterMap::new(HLLCounter::<Hasher>::new(
+        precision,
+    ))));
+
+    // One token per connection that may be served at once, taken before
+    // accepting and returned when the connection is closed
+    let max_connections = args.max_connections as usize;
+    let (release, slots) = mpsc::sync_channel(max_connections);
+    for _ in 0..max_connections {
+        release.send(()).expect("channel has room for every token");
+    }
+
+    let listener = TcpListener::bind(&args.listen)?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    loop {
+        slots.recv().expect("the sender is kept alive");
+        let slot = Slot(release.clone());
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                continue;
+            }
+        };
+        let sketches = Arc::clone(&sketches);
+        std::thread::spawn(move || {
+            let _slot = slot;
+            if let Err(e) = serve(stream, &sketches, precision) {
+                eprintln!("error: {}", e);
+            }
+        });
+    }
+}
+
+// A connection slot, returned when dropped, even if serving panics
+struct Slot(SyncSender<()>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let _ = self.0.send(());
+    }
+}
+
+// Answers a single request and closes the connection.
+fn serve(stream: TcpStream, sketches: &Mutex<Sketches>, precision: usize) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = respond(&mut reader, sketches, precision)?;
+
+    let mut writer = io::BufWriter::new(stream);
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+// Reads a request from `reader` and answers it.
+fn respond<R: BufRead>(
+    reader: &mut R,
+    sketches: &Mutex<Sketches>,
+    precision: usize,
+) -> io::Result<Response> {
+    Ok(match read_request(reader)? {
+        Some(request) => {
+            let mut sketches = sketches.lock().unwrap_or_else(|e| e.into_inner());
+            handle(&request, &mut sketches, precision)
+        }
+        None => Response::error(400, "malformed request"),
+    })
+}
+
+// Reads a line of at most `MAX_LINE_LEN` bytes into `line`, `false` if it
+// is longer.
+fn read_limited_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<bool> {
+    line.clear();
+    let len = reader.take(MAX_LINE_LEN).read_line(line)?;
+    Ok(len < MAX_LINE_LEN as usize || line.ends_with('\n'))
+}
+
+// Reads the request line, the headers and the body, `None` if malformed.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if !read_limited_line(reader, &mut line)? {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        body: Vec::new(),
+    };
+
+    let mut content_length = 0;
+    for headers in 0.. {
+        if !read_limited_line(reader, &mut line)? || headers == MAX_HEADERS {
+            return Ok(None);
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            match value.trim().parse() {
+                Ok(len) if len <= MAX_BODY_SIZE => content_length = len,
+                _ => return Ok(None),
+            }
+        }
+    }
+    request.body = vec![0; content_length];
+    reader.read_exact(&mut request.body)?;
+    Ok(Some(request))
+}
+
+fn handle(request: &Request, sketches: &mut Sketches, precision: usize) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["sketches"]) => {
+            let mut names: Vec<&String> = sketches.keys().collect();
+            names.sort();
+            let names: Vec<String> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+            Response::json(200, format!("{{\"sketches\": [{}]}}", names.join(", ")))
+        }
+        ("GET", ["count"]) => {
+            let Some(keys) = query_list(&request.query, "keys") else {
+                return Response::error(400, "invalid keys");
+            };
+            let mut union = HLLCounter::<Hasher>::new(precision);
+            for key in &keys {
+                match sketches.get(&key.to_string()) {
+                    Some(sketch) => union.merge(sketch).expect("sketches share the hasher"),
+                    None => return Response::error(404, "no such sketch"),
+                }
+            }
+            Response::json(200, estimate_json(&union))
+        }
+        (method, ["sketches", name, rest @ ..]) => {
+            if !is_valid_name(name) {
+                return Response::error(400, "invalid sketch name");
+            }
+            let name = name.to_string();
+            match (method, rest) {
+                ("PUT", []) => {
+                    if sketches.get(&name).is_some() {
+                        return Response::error(409, "sketch exists");
+                    }
+                    sketches.counter_mut(name);
+                    Response::json(201, "{}".to_string())
+                }
+                ("POST", ["add"]) => {
+                    let sketch = sketches.counter_mut(name);
+                    let mut added = 0;
+                    for item in request.body.split(|&byte| byte == b'\n') {
+                        let item = item.strip_suffix(b"\r").unwrap_or(item);
+                        if !item.is_empty() {
+                            sketch.add(item);
+                            added += 1;
+                        }
+                    }
+                    Response::json(200, format!("{{\"added\": {}}}", added))
+                }
+                ("POST", ["merge"]) => {
+                    let Some(sources) = query_list(&request.query, "from") else {
+                        return Response::error(400, "invalid sources");
+                    };
+                    let mut merged = HLLCounter::<Hasher>::new(precision);
+                    for key in sources.iter().copied().chain([name.as_str()]) {
+                        match sketches.get(&key.to_string()) {
+                            Some(sketch) => {
+                                merged.merge(sketch).expect("sketches share the hasher")
+                            }
+                            // The destination is created like by PFMERGE
+                            None if key == name => {}
+                            None => return Response::error(404, "no such sketch"),
+                        }
+                    }
+                    let body = estimate_json(&merged);
+                    *sketches.counter_mut(name) = merged;
+                    Response::json(200, body)
+                }
+                ("GET", []) => match sketches.get(&name) {
+                    Some(sketch) => Response::json(200, estimate_json(sketch)),
+                    None => Response::error(404, "no such sketch"),
+                },
+                ("GET", ["sketch"]) => match sketches.get(&name) {
+                    Some(sketch) => {
+                        let mut body = Vec::new();
+                        sketch.write_to(&mut body).expect("writing to memory");
+                        Response {
+                            status: 200,
+                            content_type: "application/octet-stream",
+                            body,
+                        }
+                    }
+                    None => Response::error(404, "no such sketch"),
+                },
+                ("DELETE", []) => match sketches.remove(&name) {
+                    Some(_) => Response::json(200, "{}".to_string()),
+                    None => Response::error(404, "no such sketch"),
+                },
+                _ => Response::error(404, "not found"),
+            }
+        }
+        _ => Response::error(404, "not found"),
+    }
+}
+
+// Names are restricted so they never need escaping in paths or JSON
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+}
+
+// The comma separated names of the query parameter `key`.
+fn query_list<'a>(query: &'a str, key: &str) -> Option<Vec<&'a str>> {
+    let value = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))?;
+    let names: Vec<&str> = value.split(',').collect();
+    names
+        .iter()
+        .all(|name| is_valid_name(name))
+        .then_some(names)
+}
+
+fn estimate_json(sketch: &HLLCounter<Hasher>) -> String {
+    let estimate = sketch.estimate_with_error();
+    format!(
+        "{{\"estimate\": {:.0}, \"std_error\": {:.1}, \"ci95\": [{:.0}, {:.0}]}}",
+        estimate.value, estimate.std_error, estimate.ci95.0, estimate.ci95.1
+    )
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers `request` as the server would, and returns the status and body.
+    fn call(sketches: &Mutex<Sketches>, request: &[u8]) -> (u16, String) {
+        let response = respond(&mut &request[..], sketches, 12).unwrap();
+        (
+            response.status,
+            String::from_utf8_lossy(&response.body).into_owned(),
+        )
+    }
+
+    fn post(path: &str, body: &str) -> Vec<u8> {
+        format!(
+            "POST {path} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let sketches = Mutex::new(CounterMap::new(HLLCounter::<Hasher>::new(12)));
+
+        assert_eq!(call(&sketches, b"PUT /sketches/a HTTP/1.1\r\n\r\n").0, 201);
+        assert_eq!(call(&sketches, b"PUT /sketches/a HTTP/1.1\r\n\r\n").0, 409);
+        let (status, body) = call(&sketches, &post("/sketches/a/add", "x\ny\r\nz\n\n"));
+        assert_eq!((status, body.as_str()), (200, "{\"added\": 3}"));
+        call(&sketches, &post("/sketches/b/add", "z\nw\n"));
+
+        let (status, body) = call(&sketches, b"GET /sketches HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            (status, body.as_str()),
+            (200, "{\"sketches\": [\"a\", \"b\"]}")
+        );
+        let (status, body) = call(&sketches, b"GET /sketches/a HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert!(body.starts_with("{\"estimate\": 3,"), "{body}");
+        let (status, body) = call(&sketches, b"GET /count?keys=a,b HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert!(body.starts_with("{\"estimate\": 4,"), "{body}");
+
+        let (status, body) = call(&sketches, &post("/sketches/c/merge?from=a,b", ""));
+        assert_eq!(status, 200);
+        assert!(body.starts_with("{\"estimate\": 4,"), "{body}");
+        let response = respond(
+            &mut &b"GET /sketches/c/sketch HTTP/1.1\r\n\r\n"[..],
+            &sketches,
+            12,
+        )
+        .unwrap();
+        let sketch = HLLCounter::<Hasher>::read_from(response.body.as_slice()).unwrap();
+        assert_eq!(sketch.estimate().round(), 4.0);
+
+        assert_eq!(
+            call(&sketches, b"DELETE /sketches/c HTTP/1.1\r\n\r\n").0,
+            200
+        );
+        assert_eq!(call(&sketches, b"GET /sketches/c HTTP/1.1\r\n\r\n").0, 404);
+        assert_eq!(
+            call(&sketches, b"GET /count?keys=a,c HTTP/1.1\r\n\r\n").0,
+            404
+        );
+        assert_eq!(
+            call(&sketches, b"GET /sketches/a%20b HTTP/1.1\r\n\r\n").0,
+            400
+        );
+        assert_eq!(
+            call(&sketches, b"PATCH /sketches/a HTTP/1.1\r\n\r\n").0,
+            404
+        );
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        let sketches = Mutex::new(CounterMap::new(HLLCounter::<Hasher>::new(12)));
+
+        assert_eq!(call(&sketches, b"\r\n").0, 400);
+        let too_large = format!(
+            "POST /sketches/a/add HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert_eq!(call(&sketches, too_large.as_bytes()).0, 400);
+
+        // A header line without end is cut off instead of read into memory
+        let mut long_line = b"GET /sketches HTTP/1.1\r\nX-Long: ".to_vec();
+        long_line.resize(long_line.len() + 2 * MAX_LINE_LEN as usize, b'a');
+        assert_eq!(call(&sketches, &long_line).0, 400);
+        let mut long_target = b"GET /".to_vec();
+        long_target.resize(2 * MAX_LINE_LEN as usize, b'a');
+        assert_eq!(call(&sketches, &long_target).0, 400);
+
+        let mut many_headers = b"GET /sketches HTTP/1.1\r\n".to_vec();
+        for _ in 0..=MAX_HEADERS {
+            many_headers.extend_from_slice(b"X-Header: 1\r\n");
+        }
+        many_headers.extend_from_slice(b"\r\n");
+        assert_eq!(call(&sketches, &many_headers).0, 400);
+        assert!(sketches.lock().unwrap().keys().next().is_none());
+    }
+}
@@ -0,0 +1,469 @@
+use crate::demo;
+use crate::demo::output::OutputFormat;
+use crate::demo::synthetic::{PlotBackend, PlotConfig};
+use clap::{Parser, Subcommand, ValueEnum};
+use hll_rust::counters::DistinctSampler;
+use hll_rust::counters::hll_counter::SKETCH_MAGIC;
+use hll_rust::distance::{DistanceMatrix, ani_estimate, mash_distance};
+use hll_rust::fasta::decode_kmer;
+use hll_rust::parallel_counting::{self, K_MER_LENGTH, MAX_K_MER_LENGTH, MAX_MULTI_K_MER_LENGTH};
+use hll_rust::{Counter, HLLCounter, MergeableCounter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// The examples use the Xxh64 hash function.
+// Sketch files do not store the hasher, so it must be deterministic
+// (e.g. not `RandomState`) for sketches to be comparable across runs.
+type Hasher = xxhash_rust::xxh64::Xxh64Builder;
+
+const DEFAULT_PRECISION: usize = 14;
+const MIN_PRECISION: usize = 4;
+const MAX_PRECISION: usize = 24;
+
+/// Probabilistic k-mer counting for biological sequences.
+#[derive(Parser, Debug)]
+#[command(name = "hll", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Estimate the number of distinct canonical k-mers in FASTA files.
+    Count {
+        /// FASTA files to analyze (optionally gzip compressed), `-` for standard input
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// k-mer length
+        #[arg(short, default_value_t = K_MER_LENGTH, value_parser = parse_k)]
+        k: usize,
+        /// HyperLogLog precision (number of index bits)
+        #[arg(short, long, default_value_t = DEFAULT_PRECISION, value_parser = parse_precision)]
+        precision: usize,
+        /// Also compute the exact count and report the relative error
+        #[arg(long)]
+        exact: bool,
+        /// Process the files on a single thread
+        #[arg(long)]
+        sequential: bool,
+        /// Print the id of every record and compare the estimates of all counters
+        #[arg(short, long, requires = "sequential")]
+        verbose: bool,
+        /// Output format of the results
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Build an HLL sketch of a FASTA file and write it to disk.
+    Sketch {
+        /// FASTA file to sketch (optionally gzip compressed), `-` for standard input
+        file: PathBuf,
+        /// Output sketch file
+        #[arg(short, long)]
+        output: PathBuf,
+        /// k-mer length
+        #[arg(short, default_value_t = K_MER_LENGTH, value_parser = parse_k)]
+        k: usize,
+        /// HyperLogLog precision (number of index bits)
+        #[arg(short, long, default_value_t = DEFAULT_PRECISION, value_parser = parse_precision)]
+        precision: usize,
+    },
+    /// Merge several sketches into one.
+    Merge {
+        /// Sketch files to merge (must share the same precision)
+        #[arg(required = true)]
+        sketches: Vec<PathBuf>,
+        /// Output sketch file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Compare two sketches: union, intersection and Jaccard index.
+    Compare {
+        /// First sketch file
+        a: PathBuf,
+        /// Second sketch file
+        b: PathBuf,
+    },
+    /// Compare many genomes pairwise and print a distance matrix.
+    Dist {
+        /// Sketch files or FASTA files (optionally gzip compressed) to compare
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+        /// k-mer length, also used by the sketches for the Mash distance
+        #[arg(short, default_value_t = K_MER_LENGTH, value_parser = parse_k)]
+        k: usize,
+        /// HyperLogLog precision of the sketches built from FASTA files
+        #[arg(short, long, default_value_t = DEFAULT_PRECISION, value_parser = parse_precision)]
+        precision: usize,
+        /// Value computed for every pair
+        #[arg(long, value_enum, default_value_t = Metric::Mash)]
+        metric: Metric,
+        /// Output format of the matrix
+        #[arg(long, value_enum, default_value_t = MatrixFormat::Phylip)]
+        format: MatrixFormat,
+    },
+    /// Estimate the distinct k-mers of a FASTA file for a range of k, to
+    /// pick an informative k.
+    Profile {
+        /// FASTA file to analyze (optionally gzip compressed), `-` for standard input
+        file: PathBuf,
+        /// Smallest k-mer length
+        #[arg(long, default_value_t = 11, value_parser = parse_multi_k)]
+        k_min: usize,
+        /// Largest k-mer length
+        #[arg(long, default_value_t = K_MER_LENGTH, value_parser = parse_multi_k)]
+        k_max: usize,
+        /// Difference between consecutive k-mer lengths
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+        k_step: u64,
+        /// HyperLogLog precision (number of index bits)
+        #[arg(short, long, default_value_t = DEFAULT_PRECISION, value_parser = parse_precision)]
+        precision: usize,
+        /// Output format of the results
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        /// Also plot the profile to this file (PNG, or SVG for a `.svg` extension)
+        #[arg(long)]
+        plot: Option<PathBuf>,
+    },
+    /// Print a uniform random sample of the distinct canonical k-mers of a
+    /// FASTA file.
+    Sample {
+        /// FASTA file to sample (optionally gzip compressed), `-` for standard input
+        file: PathBuf,
+        /// k-mer length
+        #[arg(short, default_value_t = K_MER_LENGTH, value_parser = parse_k)]
+        k: usize,
+        /// Number of sampled k-mers
+        #[arg(short = 'n', long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(2..))]
+        size: u64,
+    },
+    /// Plot the synthetic comparison of the counters.
+    Plot {
+        /// Collect the data on a single thread
+        #[arg(long)]
+        sequential: bool,
+        /// Output file
+        #[arg(short, long, default_value = "counter_comparison.png")]
+        output: PathBuf,
+        /// Image format, by default from the extension of the output file
+        #[arg(long, value_enum)]
+        backend: Option<PlotBackend>,
+        /// Width of the image in pixels
+        #[arg(long, default_value_t = 3200, value_parser = clap::value_parser!(u32).range(1..))]
+        width: u32,
+        /// Height of the image in pixels
+        #[arg(long, default_value_t = 800, value_parser = clap::value_parser!(u32).range(1..))]
+        height: u32,
+        /// Title above the charts
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Compare the speed and memory of the HLL register backends.
+    Bench,
+}
+
+/// Pairwise value of the `dist` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Metric {
+    /// Jaccard index of the k-mer sets
+    Jaccard,
+    /// Fraction of the k-mers of the row genome found in the column genome
+    Containment,
+    /// Mash distance, an estimate of the mutation rate per base
+    Mash,
+    /// Average nucleotide identity from the containment of the smaller genome
+    Ani,
+}
+
+/// Output format of the `dist` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MatrixFormat {
+    /// Square PHYLIP distance matrix
+    Phylip,
+    /// Tab-separated values with a header line
+    Tsv,
+}
+
+fn parse_k(s: &str) -> Result<usize, String> {
+    let k: usize = s.parse().map_err(|e| format!("{}", e))?;
+    if (1..=MAX_K_MER_LENGTH).contains(&k) {
+        Ok(k)
+    } else {
+        Err(format!("k must be between 1 and {}", MAX_K_MER_LENGTH))
+    }
+}
+
+fn parse_multi_k(s: &str) -> Result<usize, String> {
+    let k: usize = s.parse().map_err(|e| format!("{}", e))?;
+    if (1..=MAX_MULTI_K_MER_LENGTH).contains(&k) {
+        Ok(k)
+    } else {
+        Err(format!(
+            "k must be between 1 and {}",
+            MAX_MULTI_K_MER_LENGTH
+        ))
+    }
+}
+
+fn parse_precision(s: &str) -> Result<usize, String> {
+    let precision: usize = s.parse().map_err(|e| format!("{}", e))?;
+    if (MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+        Ok(precision)
+    } else {
+        Err(format!(
+            "precision must be between {} and {}",
+            MIN_PRECISION, MAX_PRECISION
+        ))
+    }
+}
+
+fn read_sketch(path: &Path) -> Result<HLLCounter<Hasher>, Box<dyn std::error::Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let sketch = HLLCounter::read_from(BufReader::new(file))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(sketch)
+}
+
+// Reads a sketch file, or sketches a FASTA file if it does not start with
+// the sketch header.
+fn load_or_sketch(
+    path: &Path,
+    k: usize,
+    precision: usize,
+) -> Result<HLLCounter<Hasher>, Box<dyn std::error::Error>> {
+    let mut magic = [0u8; 4];
+    let is_sketch = File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == SKETCH_MAGIC;
+    if is_sketch {
+        return read_sketch(path);
+    }
+    let (_, sketch) =
+        parallel_counting::run_parallel_fasta_analysis_with_k(&path.to_string_lossy(), k, || {
+            HLLCounter::<Hasher>::new(precision)
+        })
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(sketch)
+}
+
+fn write_sketch(path: &Path, sketch: &HLLCounter<Hasher>) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    sketch.write_to(&mut writer)?;
+    writer.flush()
+}
+
+pub fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    match cli.command {
+        Command::Count {
+            files,
+            k,
+            precision,
+            exact,
+            sequential,
+            verbose,
+            format,
+        } => {
+            let stdin_count = files.iter().filter(|f| f.as_os_str() == "-").count();
+            if stdin_count > 1 {
+                return Err("standard input can only be read once".into());
+            }
+            if stdin_count == 1 && exact && !sequential {
+                // The parallel exact count reads every file a second time
+                return Err("--exact with standard input requires --sequential".into());
+            }
+            let names: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+            let dataset: Vec<(&str, &str)> =
+                names.iter().map(|n| (n.as_str(), n.as_str())).collect();
+            if sequential {
+                demo::biological::run_sequential::<Hasher>(
+                    &dataset, k, precision, exact, verbose, format,
+                )?;
+            } else {
+                demo::biological::run_parallel::<Hasher>(&dataset, k, precision, exact, format)?;
+            }
+        }
+        Command::Sketch {
+            file,
+            output,
+            k,
+            precision,
+        } => {
+            let path = file.to_string_lossy();
+            let (total_count, sketch) =
+                parallel_counting::run_parallel_fasta_analysis_with_k(&path, k, || {
+                    HLLCounter::<Hasher>::new(precision)
+                })?;
+            write_sketch(&output, &sketch)?;
+            println!(
+                "{}: {} k-mers, ~{:.0} distinct, sketch written to {}",
+                path,
+                total_count,
+                sketch.estimate(),
+                output.display()
+            );
+        }
+        Command::Merge { sketches, output } => {
+            let mut merged = read_sketch(&sketches[0])?;
+            for path in &sketches[1..] {
+                let sketch = read_sketch(path)?;
+                if sketch.precision() != merged.precision() {
+                    return Err(format!(
+                        "{}: precision {} does not match {}",
+                        path.display(),
+                        sketch.precision(),
+                        merged.precision()
+                    )
+                    .into());
+                }
+                merged.merge(&sketch)?;
+            }
+            write_sketch(&output, &merged)?;
+            println!(
+                "Merged {} sketches, ~{:.0} distinct, written to {}",
+                sketches.len(),
+                merged.estimate(),
+                output.display()
+            );
+        }
+        Command::Compare { a, b } => {
+            let sketch_a = read_sketch(&a)?;
+            let sketch_b = read_sketch(&b)?;
+            if sketch_a.precision() != sketch_b.precision() {
+                return Err(format!(
+                    "precision {} does not match {}",
+                    sketch_a.precision(),
+                    sketch_b.precision()
+                )
+                .into());
+            }
+
+            println!("{:<15} {:>15}", "A", format!("{:.0}", sketch_a.estimate()));
+            println!("{:<15} {:>15}", "B", format!("{:.0}", sketch_b.estimate()));
+            println!(
+                "{:<15} {:>15}",
+                "Union",
+                format!("{:.0}", sketch_a.union_estimate(&sketch_b)?)
+            );
+            println!(
+                "{:<15} {:>15}",
+                "Intersection",
+                format!("{:.0}", sketch_a.intersection_estimate(&sketch_b))
+            );
+            println!(
+                "{:<15} {:>15}",
+                "Jaccard",
+                format!("{:.6}", sketch_a.jaccard(&sketch_b))
+            );
+        }
+        Command::Dist {
+            inputs,
+            k,
+            precision,
+            metric,
+            format,
+        } => {
+            let sketches = inputs
+                .iter()
+                .map(|path| load_or_sketch(path, k, precision))
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some((path, sketch)) = inputs
+                .iter()
+                .zip(&sketches)
+                .find(|(_, sketch)| sketch.precision() != sketches[0].precision())
+            {
+                return Err(format!(
+                    "{}: precision {} does not match {}",
+                    path.display(),
+                    sketch.precision(),
+                    sketches[0].precision()
+                )
+                .into());
+            }
+
+            let names = inputs.iter().map(|f| f.display().to_string()).collect();
+            let matrix = match metric {
+                Metric::Jaccard => DistanceMatrix::pairwise(names, &sketches, |a, b| a.jaccard(b)),
+                Metric::Containment => {
+                    DistanceMatrix::pairwise(names, &sketches, |a, b| a.containment(b))
+                }
+                Metric::Mash => DistanceMatrix::pairwise(names, &sketches, |a, b| {
+                    mash_distance(a.jaccard(b), k)
+                }),
+                Metric::Ani => {
+                    DistanceMatrix::pairwise(names, &sketches, |a, b| ani_estimate(a, b, k).value)
+                }
+            };
+            let stdout = std::io::stdout().lock();
+            match format {
+                MatrixFormat::Phylip => matrix.write_phylip(stdout)?,
+                MatrixFormat::Tsv => matrix.write_tsv(stdout)?,
+            }
+        }
+        Command::Profile {
+            file,
+            k_min,
+            k_max,
+            k_step,
+            precision,
+            format,
+            plot,
+        } => {
+            if k_min > k_max {
+                return Err(format!("--k-min {k_min} is larger than --k-max {k_max}").into());
+            }
+            let ks: Vec<usize> = (k_min..=k_max).step_by(k_step as usize).collect();
+            let plot = plot.map(|path| PlotConfig {
+                backend: PlotBackend::from_path(&path),
+                path,
+                width: 1600,
+                height: 600,
+                title: None,
+            });
+            demo::profile::run_profile::<Hasher>(&file, &ks, precision, format, plot.as_ref())?;
+        }
+        Command::Sample { file, k, size } => {
+            let path = file.to_string_lossy();
+            let (total_count, sampler) =
+                parallel_counting::sample_distinct_kmers(&path, k, || {
+                    DistinctSampler::<u64, Hasher>::new(size as usize)
+                })?;
+            // Keep the k-mers on stdout parseable
+            eprintln!(
+                "{}: {} k-mers, ~{:.0} distinct, {} sampled",
+                path,
+                total_count,
+                sampler.estimate(),
+                sampler.len()
+            );
+            let mut stdout = std::io::stdout().lock();
+            for &kmer in sampler.sample() {
+                stdout.write_all(&decode_kmer(kmer, k))?;
+                stdout.write_all(b"\n")?;
+            }
+        }
+        Command::Plot {
+            sequential,
+            output,
+            backend,
+            width,
+            height,
+            title,
+        } => {
+            let config = PlotConfig {
+                backend: backend.unwrap_or_else(|| PlotBackend::from_path(&output)),
+                path: output,
+                width,
+                height,
+                title,
+            };
+            demo::synthetic::plot_comparison::<Hasher>(!sequential, &config)?;
+        }
+        Command::Bench => {
+            demo::registers::compare_register_backends::<Hasher>();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// Fraction of empty registers below which [`AdaptiveCounter`] switches
+/// from linear counting to LogLog (Cai et al., 2005).
+pub const SWITCH_THRESHOLD: f64 = 0.051;
+
+// Asymptotic LogLog constant (Durand & Flajolet, 2003)
+const ALPHA_INF: f64 = 0.39701;
+
+/// Adaptive Counting (Cai et al., 2005): LogLog registers estimated by
+/// linear counting while many of them are empty.
+///
+/// Uses 2^size registers holding the maximum rank of the hashes routed to
+/// them. While at least [`SWITCH_THRESHOLD`] of the registers are empty,
+/// linear counting over the empty registers is more accurate than LogLog and
+/// is used instead. Beyond that, the LogLog estimate takes over. The switch
+/// is explicit here, while [`HLLCounter`](crate::HLLCounter) folds a similar
+/// small range correction into its estimate.
+pub struct AdaptiveCounter<S = RandomState> {
+    size: usize,
+    registers: Vec<u8>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for AdaptiveCounter<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, 4..=24)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        AdaptiveCounter {
+            size,
+            registers: vec![0; 1 << size],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        if self.uses_linear_counting() {
+            self.linear_estimate()
+        } else {
+            self.loglog_estimate()
+        }
+    }
+
+    fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for AdaptiveCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (register_self, register_other) in self.registers.iter_mut().zip(other.registers.iter())
+        {
+            *register_self = (*register_self).max(*register_other);
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for AdaptiveCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for AdaptiveCounter<S> {
+    /// 1.30 / sqrt(m) for m registers, the error of LogLog. Linear counting
+    /// is more accurate while it is used.
+    fn relative_std_error(&self) -> f64 {
+        1.30 / (self.registers.len() as f64).sqrt()
+    }
+}
+
+impl<S: BuildHasher + Default> AdaptiveCounter<S> {
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        let index = (hash & ((1u64 << self.size) - 1)) as usize;
+        let remainder = hash >> self.size;
+        let rank = std::cmp::min(remainder.trailing_zeros() as usize, 64 - self.size) + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    /// Fraction of registers no item was routed to.
+    pub fn empty_ratio(&self) -> f64 {
+        let empty = self.registers.iter().filter(|&&r| r == 0).count();
+        empty as f64 / self.registers.len() as f64
+    }
+
+    /// Whether [`Counter::estimate`] currently uses linear counting, i.e. at
+    /// least [`SWITCH_THRESHOLD`] of the registers are empty.
+    pub fn uses_linear_counting(&self) -> bool {
+        self.empty_ratio() >= SWITCH_THRESHOLD
+    }
+
+    /// Linear counting estimate m ln(m / V) over the V empty registers.
+    /// Infinite if no register is empty.
+    pub fn linear_estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        m * (1.0 / self.empty_ratio()).ln()
+    }
+
+    /// LogLog estimate alpha_m m 2^(mean rank), biased for small
+    /// cardinalities.
+    pub fn loglog_estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let mean_rank = self.registers.iter().map(|&r| r as f64).sum::<f64>() / m;
+        let alpha = ALPHA_INF
+            - (2.0 * std::f64::consts::PI.powi(2) + std::f64::consts::LN_2.powi(2)) / (48.0 * m);
+        alpha * m * 2f64.powf(mean_rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_adaptive_estimate_and_switch() {
+        let mut counter = AdaptiveCounter::<Xxh64Builder>::new(10);
+        let mut switched_at = None;
+        for i in 0..1_000_000u64 {
+            counter.add_u64(i);
+            if switched_at.is_none() && !counter.uses_linear_counting() {
+                switched_at = Some(i + 1);
+            }
+            let n = i + 1;
+            if [1, 100, 1_000, 3_000, 10_000, 100_000, 1_000_000].contains(&n) {
+                let estimate = counter.estimate();
+                let tolerance = 3.0 * counter.relative_std_error() * n as f64 + 1.0;
+                assert!(
+                    (estimate - n as f64).abs() <= tolerance,
+                    "estimated {estimate} for {n}"
+                );
+            }
+        }
+        // Few registers stay empty after m ln(1 / 0.051) ~ 3 m items
+        let switched_at = switched_at.unwrap() as f64;
+        assert!((2_500.0..3_600.0).contains(&switched_at), "{switched_at}");
+        assert_eq!(counter.estimate(), counter.loglog_estimate());
+    }
+}
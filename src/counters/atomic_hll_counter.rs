@@ -0,0 +1,127 @@
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use crate::counters::{Counter, HLLCounter, MergeError};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Thread-safe HyperLogLog counter with atomic registers.
+///
+/// Items are added through `&self`, so a single counter can be shared between
+/// threads (e.g. rayon workers) instead of merging per-thread counters.
+/// Estimation uses the same estimator as [`HLLCounter`].
+pub struct AtomicHLLCounter<S = RandomState> {
+    size: usize,
+    registers: Vec<AtomicU8>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> AtomicHLLCounter<S> {
+    pub fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    /// Creates a counter that hashes items with `hasher`, see
+    /// [`Counter::with_hasher`].
+    pub fn with_hasher(size: usize, hasher: S) -> Self {
+        HLLCounter::<S>::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        AtomicHLLCounter {
+            size,
+            registers: (0..1 << size).map(|_| AtomicU8::new(0)).collect(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    pub fn add(&self, item: &[u8]) {
+        self.add_item(item);
+    }
+
+    /// Adds any hashable item, see [`Counter::add_item`].
+    pub fn add_item<T: Hash + ?Sized>(&self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    #[inline(always)]
+    pub fn add_u64(&self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    #[inline(always)]
+    fn add_hash(&self, hash: u64) {
+        let index = (hash & ((1u64 << self.size) - 1)) as usize;
+        let remainder = hash >> self.size;
+        let rho = std::cmp::min(remainder.trailing_zeros() + 1, 64 - self.size as u32) as u8;
+
+        // Avoid the read-modify-write (and cache line contention) if the
+        // register is already large enough, which is the common case.
+        if self.registers[index].load(Ordering::Relaxed) < rho {
+            self.registers[index].fetch_max(rho, Ordering::Relaxed);
+        }
+    }
+
+    /// Merges the registers of another counter into this one.
+    ///
+    /// Fails if the counters differ in precision or hash function.
+    pub fn merge(&self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (reg_self, reg_other) in self.registers.iter().zip(other.registers.iter()) {
+            reg_self.fetch_max(reg_other.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Returns a snapshot of the current registers as a regular [`HLLCounter`]
+    /// with the same hasher.
+    pub fn to_counter(&self) -> HLLCounter<S>
+    where
+        S: Clone,
+    {
+        self.snapshot(self.hasher.clone())
+    }
+
+    fn snapshot(&self, hasher: S) -> HLLCounter<S> {
+        HLLCounter::from_dense_registers(
+            self.size,
+            hasher,
+            self.registers.iter().map(|reg| reg.load(Ordering::Relaxed)),
+        )
+    }
+
+    pub fn estimate(&self) -> f64 {
+        // The hasher of the snapshot is never used
+        self.snapshot(S::default()).estimate()
+    }
+
+    pub fn precision(&self) -> usize {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_shared_between_threads_matches_sequential() {
+        let shared = AtomicHLLCounter::<Xxh64Builder>::new(12);
+        let mut sequential = HLLCounter::<Xxh64Builder>::new(12);
+
+        (0..100_000u64)
+            .into_par_iter()
+            .for_each(|i| shared.add_u64(i));
+        for i in 0..100_000u64 {
+            sequential.add_u64(i);
+        }
+
+        assert_eq!(shared.estimate(), sequential.estimate());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, HashCounter, MergeError, MergeableCounter,
+    ShardedCounter,
+};
+use std::hash::{BuildHasher, Hash};
+
+/// A sketch together with an exact [`HashCounter`] fed the same items.
+///
+/// Estimates come from the sketch, while [`AuditCounter::relative_error`]
+/// compares them with the exact count. This measures the real error of the
+/// sketch on a dataset instead of trusting the theoretical bound, at the
+/// memory cost of storing a hash per distinct item. Since it is a
+/// [`MergeableCounter`] itself, it can be passed to all functions of the
+/// `parallel_counting` module.
+///
+/// ```
+/// use hll_rust::{AuditCounter, Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let mut audit = AuditCounter::<HLLCounter<Xxh64Builder>>::new(12);
+/// for i in 0..100_000u64 {
+///     audit.add_u64(i % 50_000);
+/// }
+/// assert_eq!(audit.exact_count(), 50_000.0);
+/// assert!(audit.relative_error().abs() < 0.05);
+/// ```
+pub struct AuditCounter<C: Counter>
+where
+    C::Hasher: BuildHasher + Default,
+{
+    pub sketch: C,
+    pub exact: HashCounter<C::Hasher>,
+}
+
+impl<C: Counter> AuditCounter<C>
+where
+    C::Hasher: BuildHasher + Default,
+{
+    /// Audits an existing sketch, which must be empty. The exact counter
+    /// hashes with `C::Hasher::default()`.
+    pub fn from_sketch(sketch: C) -> Self {
+        AuditCounter {
+            sketch,
+            exact: HashCounter::new(0),
+        }
+    }
+
+    /// The exact number of distinct items, up to 64-bit hash collisions.
+    pub fn exact_count(&self) -> f64 {
+        self.exact.estimate()
+    }
+
+    /// Error of the sketch estimate relative to the exact count, e.g. -0.01
+    /// if the sketch underestimates by 1%. 0 if no items were added.
+    pub fn relative_error(&self) -> f64 {
+        let exact = self.exact_count();
+        if exact == 0.0 {
+            0.0
+        } else {
+            (self.sketch.estimate() - exact) / exact
+        }
+    }
+}
+
+impl<C: Counter> Counter for AuditCounter<C>
+where
+    C::Hasher: BuildHasher + Default + Clone,
+{
+    type Hasher = C::Hasher;
+
+    fn new(size: usize) -> Self {
+        Self::from_sketch(C::new(size))
+    }
+
+    fn with_hasher(size: usize, hasher: C::Hasher) -> Self {
+        AuditCounter {
+            sketch: C::with_hasher(size, hasher.clone()),
+            exact: HashCounter::with_hasher(0, hasher),
+        }
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        C::check_size(size)
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.sketch.add_item(item);
+        self.exact.add_item(item);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        self.sketch.add_u64(item);
+        self.exact.add_u64(item);
+    }
+
+    fn add_u64_batch(&mut self, items: &[u64]) {
+        self.sketch.add_u64_batch(items);
+        self.exact.add_u64_batch(items);
+    }
+
+    /// The estimate of the sketch.
+    fn estimate(&self) -> f64 {
+        self.sketch.estimate()
+    }
+
+    fn clear(&mut self) {
+        self.sketch.clear();
+        self.exact.clear();
+    }
+}
+
+impl<C: MergeableCounter> MergeableCounter for AuditCounter<C>
+where
+    C::Hasher: BuildHasher + Default + Clone,
+{
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        self.sketch.merge(&other.sketch)?;
+        self.exact.merge(&other.exact)
+    }
+}
+
+impl<C: ShardedCounter> ShardedCounter for AuditCounter<C>
+where
+    C::Hasher: BuildHasher + Default + Clone,
+{
+    fn empty_shard(&self) -> Self {
+        AuditCounter {
+            sketch: self.sketch.empty_shard(),
+            exact: self.exact.empty_shard(),
+        }
+    }
+}
+
+impl<C: EstimateWithError> EstimateWithError for AuditCounter<C>
+where
+    C::Hasher: BuildHasher + Default + Clone,
+{
+    /// The theoretical error of the sketch, to compare with
+    /// [`AuditCounter::relative_error`].
+    fn relative_std_error(&self) -> f64 {
+        self.sketch.relative_std_error()
+    }
+}
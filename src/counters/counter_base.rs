@@ -1,5 +1,311 @@
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::ops::RangeInclusive;
+
 pub trait Counter {
+    /// Builder of the hash function applied to the items.
+    type Hasher;
+
+    /// Creates a counter of the given size, e.g. the precision of HyperLogLog.
+    ///
+    /// Panics if the size is not supported, see [`Counter::check_size`].
     fn new(size: usize) -> Self;
-    fn add(&mut self, item: &[u8]);
+
+    /// Creates a counter that hashes items with `hasher` instead of
+    /// `Self::Hasher::default()`.
+    ///
+    /// Only counters with the same hash function can be merged or compared.
+    /// `RandomState` picks a new seed in every process, so pass an explicitly
+    /// seeded hasher such as `Xxh64Builder::new(seed)` for sketches that are
+    /// reproducible and mergeable across runs.
+    fn with_hasher(size: usize, hasher: Self::Hasher) -> Self;
+
+    /// Checks that counters of the given size can be created.
+    fn check_size(_size: usize) -> Result<(), BuildError> {
+        Ok(())
+    }
+
+    /// Returns a builder that validates the configuration instead of
+    /// panicking, e.g. `HLLCounter::<Xxh64Builder>::builder().precision(14).build()`.
+    fn builder() -> CounterBuilder<Self>
+    where
+        Self: Sized,
+    {
+        CounterBuilder {
+            size: None,
+            hasher: None,
+        }
+    }
+
+    /// Adds any hashable item, e.g. a string or a struct deriving `Hash`.
+    ///
+    /// The item is hashed with `Self::Hasher::hash_one`, so `add_item(bytes)`
+    /// counts the same item as `add(bytes)` and `add_item(&x)` the same as
+    /// `add_u64(x)`.
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T);
+
+    /// Adds an item given as raw bytes.
+    fn add(&mut self, item: &[u8]) {
+        self.add_item(item);
+    }
     fn estimate(&self) -> f64;
+
+    /// Resets the counter to the empty state, keeping its size, hasher and
+    /// allocated memory, so it can be reused, e.g. for every window of a
+    /// stream or every chunk of a parallel pipeline.
+    fn clear(&mut self);
+
+    /// Adds an item that is already packed into a u64, e.g. a 2-bit encoded k-mer.
+    ///
+    /// Counts the same item as `add_item(&item)`. Counters that override it
+    /// to hash the u64 directly must keep it that way.
+    #[inline(always)]
+    fn add_u64(&mut self, item: u64) {
+        self.add_item(&item);
+    }
+
+    /// Adds a batch of items. Counters may hash the whole batch before
+    /// updating their state to hide memory latency.
+    fn add_batch(&mut self, items: &[impl AsRef<[u8]>]) {
+        for item in items {
+            self.add(item.as_ref());
+        }
+    }
+
+    /// Adds a batch of items packed into u64s, see [`Counter::add_batch`].
+    fn add_u64_batch(&mut self, items: &[u64]) {
+        for &item in items {
+            self.add_u64(item);
+        }
+    }
+}
+
+/// Builder for counters created by [`Counter::builder`].
+pub struct CounterBuilder<C: Counter> {
+    size: Option<usize>,
+    hasher: Option<C::Hasher>,
+}
+
+impl<C: Counter> CounterBuilder<C> {
+    /// Sets the size of the counter, as passed to [`Counter::new`].
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Same as [`CounterBuilder::size`], for counters sized by their precision.
+    pub fn precision(self, precision: usize) -> Self {
+        self.size(precision)
+    }
+
+    /// Sets the hasher, which defaults to `C::Hasher::default()`.
+    pub fn hasher(mut self, hasher: C::Hasher) -> Self {
+        self.hasher = Some(hasher);
+        self
+    }
+
+    /// Creates the counter, or fails if no size was set or the size is not
+    /// supported by the counter.
+    pub fn build(self) -> Result<C, BuildError>
+    where
+        C::Hasher: Default,
+    {
+        let size = self.size.ok_or(BuildError::MissingSize)?;
+        C::check_size(size)?;
+        Ok(C::with_hasher(size, self.hasher.unwrap_or_default()))
+    }
+}
+
+/// An error raised when building a counter with an invalid configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// Neither `size` nor `precision` was set.
+    MissingSize,
+    /// The size is outside of the range supported by the counter.
+    SizeOutOfRange {
+        /// Name of the size, e.g. "precision".
+        parameter: &'static str,
+        value: usize,
+        min: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::MissingSize => write!(f, "No size or precision given"),
+            BuildError::SizeOutOfRange {
+                parameter,
+                value,
+                min,
+                max,
+            } => {
+                if *max == usize::MAX {
+                    write!(
+                        f,
+                        "The {} must be at least {}, got {}",
+                        parameter, min, value
+                    )
+                } else {
+                    write!(
+                        f,
+                        "The {} must be between {} and {}, got {}",
+                        parameter, min, max, value
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+// Checks that the size parameter named `parameter` lies in `range`.
+pub(crate) fn check_range(
+    parameter: &'static str,
+    value: usize,
+    range: RangeInclusive<usize>,
+) -> Result<(), BuildError> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(BuildError::SizeOutOfRange {
+            parameter,
+            value,
+            min: *range.start(),
+            max: *range.end(),
+        })
+    }
+}
+
+/// A counter whose state can be combined with another counter of the same
+/// configuration, e.g. to merge partial results computed in parallel.
+pub trait MergeableCounter: Counter {
+    /// Merges `other` into `self`, so that `self` counts the union of both inputs.
+    ///
+    /// Fails without changing `self` if the counters differ in size or hash
+    /// the items with different hash functions, e.g. two `RandomState`s.
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError>;
+}
+
+/// Counters that can create empty counters with the same size and hash
+/// function, e.g. one per thread whose results are merged afterwards.
+pub trait ShardedCounter: MergeableCounter {
+    /// An empty counter that can be merged into `self`.
+    fn empty_shard(&self) -> Self;
+}
+
+/// An error raised when merging two sketches that are not compatible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeError {
+    /// The sketches differ in size, e.g. in precision, number of bits or
+    /// number of hash functions.
+    SizeMismatch { left: usize, right: usize },
+    /// The sliding windows of the counters differ.
+    WindowMismatch { left: u64, right: u64 },
+    /// The sketches were built with different hash functions or seeds, so
+    /// their union would be meaningless.
+    HasherMismatch,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::SizeMismatch { left, right } => {
+                write!(f, "Cannot merge sketches of size {} and {}", left, right)
+            }
+            MergeError::WindowMismatch { left, right } => {
+                write!(f, "Cannot merge windows of {} and {}", left, right)
+            }
+            MergeError::HasherMismatch => {
+                write!(
+                    f,
+                    "Cannot merge sketches built with different hash functions"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// An error raised when a sketch is so full that its estimate only bounds
+/// the cardinality from below, e.g. a linear counter with every bit set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaturatedEstimate {
+    /// The clamped estimate returned by `estimate`.
+    pub lower_bound: f64,
+}
+
+impl fmt::Display for SaturatedEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The sketch is saturated, the cardinality is at least {}",
+            self.lower_bound
+        )
+    }
+}
+
+impl std::error::Error for SaturatedEstimate {}
+
+// Hashed to tell hash functions and seeds apart
+const FINGERPRINT_PROBE: &[u8] = b"hll-rust";
+
+// Fingerprint of a hash function, the hash of a fixed probe value.
+pub(crate) fn hasher_fingerprint<S: BuildHasher>(hasher: &S) -> u64 {
+    hasher.hash_one(FINGERPRINT_PROBE)
+}
+
+// Checks that two sketches with the given sizes and hasher fingerprints can
+// be merged.
+pub(crate) fn check_mergeable(
+    (left_size, left_fingerprint): (usize, u64),
+    (right_size, right_fingerprint): (usize, u64),
+) -> Result<(), MergeError> {
+    if left_size != right_size {
+        return Err(MergeError::SizeMismatch {
+            left: left_size,
+            right: right_size,
+        });
+    }
+    if left_fingerprint != right_fingerprint {
+        return Err(MergeError::HasherMismatch);
+    }
+    Ok(())
+}
+
+/// A cardinality estimate together with its uncertainty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    /// Standard error of the estimate, in the same unit as `value`.
+    pub std_error: f64,
+    /// Approximate 95% confidence interval (value ± 1.96 standard errors, clamped at 0).
+    pub ci95: (f64, f64),
+}
+
+impl Estimate {
+    pub fn new(value: f64, std_error: f64) -> Self {
+        Estimate {
+            value,
+            std_error,
+            ci95: (
+                f64::max(value - 1.96 * std_error, 0.0),
+                value + 1.96 * std_error,
+            ),
+        }
+    }
+}
+
+/// A counter that can report the theoretical standard error of its estimate.
+pub trait EstimateWithError: Counter {
+    /// Relative standard error (standard error divided by the cardinality).
+    fn relative_std_error(&self) -> f64;
+
+    fn estimate_with_error(&self) -> Estimate {
+        let value = self.estimate();
+        Estimate::new(value, value * self.relative_std_error())
+    }
 }
@@ -0,0 +1,270 @@
+use crate::counters::{Counter, MergeError, ShardedCounter};
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelExtend, ParallelIterator};
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::hash::Hash;
+
+/// One counter per key, e.g. per chromosome or per sample.
+///
+/// All counters are empty shards of a template counter, so they share its
+/// size and hash function and can be merged with each other, also across
+/// maps created from the same template. With the `parallel` feature, the map
+/// implements `ParallelExtend<(K, T)>` to count keyed items on the rayon
+/// thread pool.
+///
+/// ```
+/// use hll_rust::counters::CounterMap;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let mut map = CounterMap::new(HLLCounter::<Xxh64Builder>::new(12));
+/// for i in 0..1000u64 {
+///     map.add_u64("chr1", i);
+///     map.add_u64("chr2", i + 500);
+/// }
+/// assert_eq!(map.len(), 2);
+/// assert!((map.estimate(&"chr1") - 1000.0).abs() < 50.0);
+/// assert!((map.total_estimate() - 2000.0).abs() < 100.0);
+/// assert!((map.unique_estimate() - 1500.0).abs() < 75.0);
+/// ```
+pub struct CounterMap<K, C> {
+    counters: HashMap<K, C>,
+    template: C,
+}
+
+impl<K: Eq + Hash, C: ShardedCounter> CounterMap<K, C> {
+    /// Creates an empty map whose counters are empty shards of `template`.
+    pub fn new(template: C) -> Self {
+        CounterMap {
+            counters: HashMap::new(),
+            template: template.empty_shard(),
+        }
+    }
+
+    /// The counter of `key`, created if missing.
+    pub fn counter_mut(&mut self, key: K) -> &mut C {
+        let template = &self.template;
+        self.counters
+            .entry(key)
+            .or_insert_with(|| template.empty_shard())
+    }
+
+    /// Adds `item` to the counter of `key`.
+    pub fn add<T: Hash + ?Sized>(&mut self, key: K, item: &T) {
+        self.counter_mut(key).add_item(item);
+    }
+
+    /// Adds `item` to the counter of `key`, see [`Counter::add_u64`].
+    pub fn add_u64(&mut self, key: K, item: u64) {
+        self.counter_mut(key).add_u64(item);
+    }
+
+    pub fn get(&self, key: &K) -> Option<&C> {
+        self.counters.get(key)
+    }
+
+    /// Removes the counter of `key` and returns it.
+    pub fn remove(&mut self, key: &K) -> Option<C> {
+        self.counters.remove(key)
+    }
+
+    /// The estimate of the counter of `key`, 0 for unknown keys.
+    pub fn estimate(&self, key: &K) -> f64 {
+        self.get(key).map_or(0.0, Counter::estimate)
+    }
+
+    /// Number of keys.
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<'_, K, C> {
+        self.counters.keys()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, K, C> {
+        self.counters.iter()
+    }
+
+    /// Sum of the estimates of all keys: items added under several keys are
+    /// counted once per key.
+    pub fn total_estimate(&self) -> f64 {
+        self.counters.values().map(Counter::estimate).sum()
+    }
+
+    /// The union of the counters of all keys.
+    pub fn union(&self) -> C {
+        let mut union = self.template.empty_shard();
+        for counter in self.counters.values() {
+            union
+                .merge(counter)
+                .unwrap_or_else(|e| panic!("counters of a map are mergeable: {e}"));
+        }
+        union
+    }
+
+    /// Estimate of the distinct items over all keys.
+    pub fn unique_estimate(&self) -> f64 {
+        self.union().estimate()
+    }
+
+    /// Merges the counters of `other` into those of the same key, adding the
+    /// keys missing from `self`.
+    ///
+    /// Fails without changing `self` if the maps were created from templates
+    /// that cannot be merged.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError>
+    where
+        K: Clone,
+    {
+        self.check_template(other)?;
+        for (key, counter) in &other.counters {
+            self.counter_mut(key.clone())
+                .merge(counter)
+                .unwrap_or_else(|e| panic!("{e}"));
+        }
+        Ok(())
+    }
+
+    // Like `merge`, but moves the counters of keys missing from `self`
+    #[cfg(feature = "parallel")]
+    fn merge_owned(&mut self, other: Self) -> Result<(), MergeError> {
+        self.check_template(&other)?;
+        for (key, counter) in other.counters {
+            match self.counters.entry(key) {
+                hash_map::Entry::Occupied(mut entry) => entry
+                    .get_mut()
+                    .merge(&counter)
+                    .unwrap_or_else(|e| panic!("{e}")),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(counter);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The counters of both maps are mergeable if their templates are
+    fn check_template(&self, other: &Self) -> Result<(), MergeError> {
+        self.template.empty_shard().merge(&other.template)
+    }
+}
+
+impl<'a, K, C> IntoIterator for &'a CounterMap<K, C> {
+    type Item = (&'a K, &'a C);
+    type IntoIter = hash_map::Iter<'a, K, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.counters.iter()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<K, T, C> ParallelExtend<(K, T)> for CounterMap<K, C>
+where
+    K: Eq + Hash + Send,
+    T: Hash + Send,
+    C: ShardedCounter + Send + Sync,
+{
+    /// Counts the keyed items into maps of empty shards, one per rayon job,
+    /// and merges them into `self`.
+    fn par_extend<I: IntoParallelIterator<Item = (K, T)>>(&mut self, items: I) {
+        let template = &self.template;
+        let counted = items
+            .into_par_iter()
+            .fold(
+                || CounterMap::new(template.empty_shard()),
+                |mut map, (key, item)| {
+                    map.add(key, &item);
+                    map
+                },
+            )
+            .reduce(
+                || CounterMap::new(template.empty_shard()),
+                |mut a, b| {
+                    a.merge_owned(b).unwrap_or_else(|e| panic!("{e}"));
+                    a
+                },
+            );
+        self.merge_owned(counted).unwrap_or_else(|e| panic!("{e}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HLLCounter, HashCounter};
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_counter_map() {
+        let mut map = CounterMap::new(HashCounter::<Xxh64Builder>::new(0));
+        assert!(map.is_empty());
+        for i in 0..100u64 {
+            map.add_u64(i % 3, i);
+            map.add(i % 3, &i);
+        }
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.estimate(&0), 34.0);
+        assert_eq!(map.estimate(&1), 33.0);
+        assert_eq!(map.estimate(&7), 0.0);
+        assert_eq!(map.total_estimate(), 100.0);
+
+        // Items shared by keys count once in the union
+        let mut other = CounterMap::new(HashCounter::<Xxh64Builder>::new(0));
+        for i in 50..150u64 {
+            other.add_u64(3, i);
+        }
+        map.merge(&other).unwrap();
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.total_estimate(), 200.0);
+        assert_eq!(map.unique_estimate(), 150.0);
+        let mut keys: Vec<_> = map.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, [0, 1, 2, 3]);
+        assert_eq!(
+            map.remove(&3).map(|counter| counter.estimate()),
+            Some(100.0)
+        );
+        assert!(map.remove(&3).is_none());
+        assert_eq!(map.len(), 3);
+
+        let mut hll = CounterMap::new(HLLCounter::<Xxh64Builder>::new(10));
+        hll.add_u64(5, 1);
+        assert!(
+            hll.merge(&CounterMap::new(HLLCounter::<Xxh64Builder>::new(12)))
+                .is_err()
+        );
+        assert_eq!(hll.len(), 1);
+
+        #[cfg(feature = "parallel")]
+        {
+            let items: Vec<(u64, u64)> = (0..200_000).map(|i| (i % 4, i % 50_000)).collect();
+            let mut sequential = CounterMap::new(HLLCounter::<Xxh64Builder>::new(10));
+            for (key, item) in &items {
+                sequential.add(*key, item);
+            }
+            sequential.add_u64(5, 1);
+            hll.par_extend(items.into_par_iter());
+            assert_eq!(hll.len(), 5);
+            for (key, counter) in &sequential {
+                assert_eq!(
+                    hll.get(key).unwrap().register_values(),
+                    counter.register_values()
+                );
+            }
+
+            // RandomState shards share the keys of the template
+            let mut random = CounterMap::new(
+                HLLCounter::<std::collections::hash_map::RandomState>::new(10),
+            );
+            random.par_extend((0..10_000u64).into_par_iter().map(|i| (i % 2, i)));
+            assert!((random.unique_estimate() - 10_000.0).abs() < 1_000.0);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::counters::MergeError;
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// Bottom-k sketch that keeps the items themselves: a uniform random sample
+/// of the distinct items of a stream.
+///
+/// Like [`KMVCounter`](crate::KMVCounter), it keeps the `size` distinct
+/// items with the smallest hashes and estimates the cardinality from the
+/// k-th smallest hash. As the hash is independent of how often an item
+/// occurs, every distinct item is equally likely to be sampled, however
+/// skewed the stream, e.g. to inspect representative k-mers of a genome
+/// instead of its most abundant repeats.
+///
+/// Samplers with the same size and hash function can be merged, which
+/// yields the sample of the union.
+///
+/// ```
+/// use hll_rust::counters::DistinctSampler;
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let mut sampler = DistinctSampler::<String, Xxh64Builder>::new(100);
+/// for i in 0..10_000 {
+///     sampler.add(format!("item {}", i % 5_000));
+/// }
+/// assert_eq!(sampler.len(), 100);
+/// assert!((sampler.estimate() - 5_000.0).abs() < 1_000.0);
+/// ```
+pub struct DistinctSampler<T, S = RandomState> {
+    size: usize,
+    // The items with the smallest distinct hashes seen so far
+    sample: BTreeMap<u64, T>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<T: Hash, S: BuildHasher + Default> DistinctSampler<T, S> {
+    /// Creates a sampler keeping up to `size` distinct items.
+    ///
+    /// Panics if `size` is less than 2.
+    pub fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    pub fn with_hasher(size: usize, hasher: S) -> Self {
+        check_range("sample size", size, 2..=usize::MAX).unwrap_or_else(|e| panic!("{e}"));
+        DistinctSampler {
+            size,
+            sample: BTreeMap::new(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Adds `item`. Returns true if it is part of the sample afterwards.
+    pub fn add(&mut self, item: T) -> bool {
+        let hash = self.hasher.hash_one(&item);
+        self.add_hash(hash, item)
+    }
+
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64, item: T) -> bool {
+        if self.sample.len() < self.size {
+            self.sample.entry(hash).or_insert(item);
+            return true;
+        }
+        let largest = *self.sample.last_key_value().unwrap().0;
+        if hash > largest {
+            return false;
+        }
+        if hash < largest && !self.sample.contains_key(&hash) {
+            self.sample.insert(hash, item);
+            self.sample.pop_last();
+        }
+        true
+    }
+
+    /// Estimates the number of distinct items, exactly while fewer than
+    /// `size` were added.
+    pub fn estimate(&self) -> f64 {
+        if self.sample.len() < self.size {
+            return self.sample.len() as f64;
+        }
+        let kth_smallest = *self.sample.last_key_value().unwrap().0;
+        // Normalize the hash to (0, 1]
+        let normalized = (kth_smallest as f64 + 1.0) / 2f64.powi(64);
+        (self.size - 1) as f64 / normalized
+    }
+
+    /// 1 / sqrt(k - 2) once the sample is full, 0 before.
+    pub fn relative_std_error(&self) -> f64 {
+        if self.sample.len() < self.size || self.size <= 2 {
+            0.0
+        } else {
+            1.0 / ((self.size - 2) as f64).sqrt()
+        }
+    }
+
+    /// The sampled items in random order, that of their hashes.
+    pub fn sample(&self) -> impl Iterator<Item = &T> {
+        self.sample.values()
+    }
+
+    pub fn into_sample(self) -> Vec<T> {
+        self.sample.into_values().collect()
+    }
+
+    /// Number of sampled items, at most `size`.
+    pub fn len(&self) -> usize {
+        self.sample.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sample.is_empty()
+    }
+
+    /// Largest number of sampled items.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Merges `other` into `self`, keeping the sample of the union.
+    ///
+    /// Fails if the samplers differ in size or hash function.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError>
+    where
+        T: Clone,
+    {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (&hash, item) in &other.sample {
+            if self.sample.len() == self.size && hash > *self.sample.last_key_value().unwrap().0 {
+                // The remaining hashes of `other` are larger still
+                break;
+            }
+            self.add_hash(hash, item.clone());
+        }
+        Ok(())
+    }
+
+    /// An empty sampler that can be merged into `self`.
+    pub fn empty_shard(&self) -> Self
+    where
+        S: Clone,
+    {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_distinct_sampler() {
+        let mut sampler = DistinctSampler::<u64, Xxh64Builder>::new(256);
+        for i in 0..100u64 {
+            assert!(sampler.add(i));
+        }
+        assert_eq!(sampler.estimate(), 100.0);
+
+        // Item i is added i % 10 + 1 times, without favoring frequent items
+        let mut halves = [sampler.empty_shard(), sampler.empty_shard()];
+        for i in 0..100_000u64 {
+            for _ in 0..i % 10 + 1 {
+                sampler.add(i);
+                halves[(i % 2) as usize].add(i);
+            }
+        }
+        assert_eq!(sampler.len(), 256);
+        assert!((sampler.estimate() / 100_000.0 - 1.0).abs() < 0.2);
+        let mean = sampler.sample().sum::<u64>() as f64 / 256.0;
+        assert!((mean - 50_000.0).abs() < 6_000.0, "{mean}");
+        let mean_repeats = sampler.sample().map(|i| i % 10 + 1).sum::<u64>() as f64 / 256.0;
+        assert!((mean_repeats - 5.5).abs() < 1.0, "{mean_repeats}");
+
+        let [mut merged, other] = halves;
+        merged.merge(&other).unwrap();
+        assert_eq!(merged.into_sample(), sampler.into_sample());
+        assert!(
+            DistinctSampler::<u64, Xxh64Builder>::new(8)
+                .merge(&DistinctSampler::new(16))
+                .is_err()
+        );
+    }
+}
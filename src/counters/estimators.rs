@@ -0,0 +1,426 @@
+//! Cardinality estimators over the registers of HyperLogLog sketches.
+//!
+//! [`HLLState`] holds the observed register values of a sketch and an
+//! [`Estimator`] turns them into a cardinality, so different formulas can be
+//! compared on the same registers without counting the data again:
+//!
+//! ```
+//! use hll_rust::counters::estimators::{Estimator, MleEstimator, RawEstimator};
+//! use hll_rust::{Counter, HLLCounter};
+//! use xxhash_rust::xxh64::Xxh64Builder;
+//!
+//! let mut hll = HLLCounter::<Xxh64Builder>::new(12);
+//! for i in 0..10_000u64 {
+//!     hll.add_u64(i);
+//! }
+//! let state = hll.state();
+//! let raw = RawEstimator.estimate(&state);
+//! let mle = MleEstimator.estimate(&state);
+//! assert!((mle - 10_000.0).abs() < 500.0);
+//! assert!(raw > 0.0);
+//! ```
+
+use crate::counters::hll_bias_data::{
+    BIAS_DATA, LOGLOG_BETA_DATA, RAW_ESTIMATE_DATA, THRESHOLD_DATA,
+};
+use crate::counters::{EstimatorKind, Registers};
+
+const AM_4: f64 = 0.673;
+const AM_5: f64 = 0.697;
+const AM_6: f64 = 0.709;
+
+// Smallest precision covered by the HLL++ bias correction tables
+const BIAS_MIN_PRECISION: usize = 4;
+
+/// The register values of a dense HyperLogLog sketch, the input of every
+/// [`Estimator`].
+///
+/// Returned by [`HLLCounter::state`](crate::HLLCounter::state). The
+/// registers cannot be modified, so every estimator sees the same
+/// observations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HLLState {
+    precision: usize,
+    registers: Vec<u8>,
+}
+
+impl HLLState {
+    /// Creates the state of `2^precision` registers, e.g. read from another
+    /// HyperLogLog implementation. Values above `64 - precision` are capped.
+    ///
+    /// Panics if the precision is not between 4 and 24 or the number of
+    /// registers is not `2^precision`.
+    pub fn new(precision: usize, mut registers: Vec<u8>) -> Self {
+        assert!(
+            (4..=24).contains(&precision),
+            "The precision must be between 4 and 24, got {precision}"
+        );
+        assert_eq!(
+            registers.len(),
+            1 << precision,
+            "expected 2^{precision} registers"
+        );
+        let limit = 64 - precision as u8;
+        for register in &mut registers {
+            *register = (*register).min(limit);
+        }
+        HLLState {
+            precision,
+            registers,
+        }
+    }
+
+    /// Number of index bits, i.e. the number of registers is 2^precision.
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Returns how many registers hold each value `0..=64 - precision`.
+    pub fn histogram(&self) -> Vec<u64> {
+        let mut histogram = vec![0u64; 65 - self.precision];
+        for &register in &self.registers {
+            histogram[register as usize] += 1;
+        }
+        histogram
+    }
+}
+
+/// A formula estimating the cardinality from the registers of a sketch.
+pub trait Estimator {
+    fn estimate(&self, state: &HLLState) -> f64;
+}
+
+/// The harmonic mean estimate of the original HyperLogLog paper, without
+/// any corrections. Strongly biased below about 2.5 times the number of
+/// registers, which makes it a baseline for the other estimators.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawEstimator;
+
+impl Estimator for RawEstimator {
+    fn estimate(&self, state: &HLLState) -> f64 {
+        let num_registers = (1u64 << state.precision) as f64;
+        let (sum, _) = state.registers.harmonic_sum();
+        alpha(state.precision) * num_registers * num_registers / sum
+    }
+}
+
+/// The raw estimate with the HLL++ empirical bias correction and linear
+/// counting for small cardinalities, as used by
+/// [`Counter::estimate`](crate::Counter::estimate) of dense counters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiasCorrectedEstimator;
+
+impl Estimator for BiasCorrectedEstimator {
+    fn estimate(&self, state: &HLLState) -> f64 {
+        dense_estimate(state.precision, alpha(state.precision), &state.registers)
+    }
+}
+
+/// Ertl's improved raw estimator, see [`EstimatorKind::ErtlImproved`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErtlImprovedEstimator;
+
+impl Estimator for ErtlImprovedEstimator {
+    fn estimate(&self, state: &HLLState) -> f64 {
+        ertl_improved_estimate(state.precision, &state.histogram())
+    }
+}
+
+/// Ertl's maximum-likelihood estimator, see [`EstimatorKind::ErtlMle`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MleEstimator;
+
+impl Estimator for MleEstimator {
+    fn estimate(&self, state: &HLLState) -> f64 {
+        ertl_mle_estimate(state.precision, &state.histogram())
+    }
+}
+
+/// The LogLog-Beta estimator, see [`EstimatorKind::LogLogBeta`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BetaEstimator;
+
+impl Estimator for BetaEstimator {
+    fn estimate(&self, state: &HLLState) -> f64 {
+        loglog_beta_estimate(state.precision, &state.registers)
+            .unwrap_or_else(|| BiasCorrectedEstimator.estimate(state))
+    }
+}
+
+impl Estimator for EstimatorKind {
+    fn estimate(&self, state: &HLLState) -> f64 {
+        match self {
+            EstimatorKind::HyperLogLogPlusPlus => BiasCorrectedEstimator.estimate(state),
+            EstimatorKind::ErtlImproved => ErtlImprovedEstimator.estimate(state),
+            EstimatorKind::ErtlMle => MleEstimator.estimate(state),
+            EstimatorKind::LogLogBeta => BetaEstimator.estimate(state),
+            EstimatorKind::Raw => RawEstimator.estimate(state),
+        }
+    }
+}
+
+// Bias correction constant of the raw estimate for 2^size registers
+pub(crate) fn alpha(size: usize) -> f64 {
+    match size {
+        0..=4 => AM_4,
+        5 => AM_5,
+        6 => AM_6,
+        _ => 0.7213 / (1.0 + 1.079 / (1u64 << size) as f64),
+    }
+}
+
+// Raw HyperLogLog estimate of dense registers with the HLL++ bias correction
+// and linear counting for small cardinalities.
+pub(crate) fn dense_estimate<R: Registers>(size: usize, am: f64, registers: &R) -> f64 {
+    let num_registers = (1 << size) as f64;
+
+    let numerator = am * num_registers * num_registers;
+
+    let (denominator, zeros) = registers.harmonic_sum();
+
+    let mut estimate = numerator / denominator;
+
+    if let Some(threshold) = bias_threshold(size) {
+        // HLL++: subtract the empirical bias in the intermediate range and
+        // fall back to linear counting for small cardinalities.
+        if estimate <= 5.0 * num_registers {
+            estimate -= estimate_bias(size, estimate);
+        }
+        if zeros > 0 {
+            let linear = num_registers * (num_registers / zeros as f64).ln();
+            if linear <= threshold {
+                return linear;
+            }
+        }
+        return estimate;
+    }
+
+    // Small range correction
+    if estimate <= 2.5 * num_registers {
+        if zeros > 0 {
+            estimate = num_registers * (num_registers / zeros as f64).ln();
+        }
+    } else if estimate > (2f64.powi(64) / 30f64) {
+        estimate = -2f64.powi(64) * (1f64 - estimate * 2f64.powi(-64)).ln()
+    }
+
+    estimate
+}
+
+fn bias_threshold(precision: usize) -> Option<f64> {
+    precision
+        .checked_sub(BIAS_MIN_PRECISION)
+        .and_then(|i| THRESHOLD_DATA.get(i).copied())
+}
+
+// Interpolates the empirical bias of a raw estimate between the two
+// closest measured raw estimates.
+fn estimate_bias(precision: usize, raw: f64) -> f64 {
+    let estimates = RAW_ESTIMATE_DATA[precision - BIAS_MIN_PRECISION];
+    let biases = BIAS_DATA[precision - BIAS_MIN_PRECISION];
+
+    let idx = estimates.partition_point(|&e| e < raw);
+    if idx == 0 {
+        return biases[0];
+    } else if idx == estimates.len() {
+        return biases[biases.len() - 1];
+    }
+
+    let ratio = (raw - estimates[idx - 1]) / (estimates[idx] - estimates[idx - 1]);
+    biases[idx - 1] + ratio * (biases[idx] - biases[idx - 1])
+}
+
+// LogLog-Beta estimate alpha_inf m (m - z) / (beta(z) + sum 2^-M), or `None`
+// for precisions above 18, for which no beta coefficients are published.
+pub(crate) fn loglog_beta_estimate(precision: usize, registers: &Vec<u8>) -> Option<f64> {
+    let coefficients = LOGLOG_BETA_DATA.get(precision - BIAS_MIN_PRECISION)?;
+    let m = (1u64 << precision) as f64;
+    let (sum, zeros) = registers.harmonic_sum();
+    let z = zeros as f64;
+
+    let zl = z.ln_1p();
+    let mut beta = coefficients[0] * z;
+    let mut power = 1.0;
+    for &coefficient in &coefficients[1..] {
+        power *= zl;
+        beta += coefficient * power;
+    }
+
+    let alpha_inf = 1.0 / (2.0 * std::f64::consts::LN_2);
+    Some(alpha_inf * m * (m - z) / (beta + sum))
+}
+
+// In Ertl's notation, registers take values 0..=q+1 where q + 1 is the
+// saturated value. Since rho is capped at 64 - p, q = 63 - p here.
+
+// Ertl's improved raw estimator (Algorithm 6 in "New cardinality estimation
+// algorithms for HyperLogLog sketches", 2017).
+pub(crate) fn ertl_improved_estimate(precision: usize, histogram: &[u64]) -> f64 {
+    let m = (1u64 << precision) as f64;
+    let q = histogram.len() - 2;
+
+    let mut z = m * ertl_tau(1.0 - histogram[q + 1] as f64 / m);
+    for k in (1..=q).rev() {
+        z = 0.5 * (z + histogram[k] as f64);
+    }
+    z += m * ertl_sigma(histogram[0] as f64 / m);
+
+    m * m / (2.0 * std::f64::consts::LN_2) / z
+}
+
+fn ertl_sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let z_prev = z;
+        z += x * y;
+        y += y;
+        if z == z_prev {
+            return z;
+        }
+    }
+}
+
+fn ertl_tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let z_prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == z_prev {
+            return z / 3.0;
+        }
+    }
+}
+
+// Maximum-likelihood estimate under the Poisson model.
+pub(crate) fn ertl_mle_estimate(precision: usize, histogram: &[u64]) -> f64 {
+    let m = (1u64 << precision) as f64;
+    let q = histogram.len() - 2;
+
+    if histogram[0] as f64 == m {
+        return 0.0;
+    }
+    if histogram[q + 1] as f64 == m {
+        return f64::INFINITY;
+    }
+
+    m * mle_root(q, histogram, -(histogram[0] as f64))
+}
+
+// Maximum-likelihood estimate of the number of items added to a sketch since
+// its registers were `before`, given the registers `after`. Under the Poisson
+// model, a register that kept its value k saw no new item above k, which
+// contributes like the `-2^-k` term of a register of value k above, and a
+// register that grew contributes like a register of its new value. Only the
+// registers that changed carry the estimate, so its error scales with the
+// number of new items rather than with the cardinality.
+pub(crate) fn ertl_mle_added_estimate(precision: usize, before: &[u8], after: &[u8]) -> f64 {
+    let m = (1u64 << precision) as f64;
+    let q = 63 - precision;
+
+    // Histogram of the new values of the changed registers
+    let mut histogram = vec![0u64; q + 2];
+    let mut unchanged = 0.0;
+    for (&old, &new) in before.iter().zip(after) {
+        if new > old {
+            histogram[new as usize] += 1;
+        } else {
+            unchanged += 2f64.powi(-(old as i32));
+        }
+    }
+
+    if histogram.iter().all(|&c| c == 0) {
+        return 0.0;
+    }
+    if unchanged == 0.0 && histogram[..=q].iter().all(|&c| c == 0) {
+        return f64::INFINITY;
+    }
+    m * mle_root(q, &histogram, -unchanged)
+}
+
+// Maximizes over x = n / m the log-likelihood of the nonzero registers in
+// `histogram`, plus `slope * x`. Its derivative is strictly decreasing, so
+// the root is found by bisection on log(x).
+fn mle_root(q: usize, histogram: &[u64], slope: f64) -> f64 {
+    let derivative = |x: f64| {
+        let mut d = slope;
+        for (k, &c) in histogram.iter().enumerate().take(q + 1).skip(1) {
+            let scale = 2f64.powi(-(k as i32));
+            d += c as f64 * scale * (1.0 / (x * scale).exp_m1() - 1.0);
+        }
+        let scale = 2f64.powi(-(q as i32));
+        d + histogram[q + 1] as f64 * scale / (x * scale).exp_m1()
+    };
+
+    let (mut lo, mut hi) = (
+        f64::MIN_POSITIVE.ln(),
+        (q as f64 + 2.0) * std::f64::consts::LN_2,
+    );
+    while derivative(hi.exp()) > 0.0 {
+        hi *= 2.0;
+    }
+    for _ in 0..200 {
+        let mid = 0.5 * (lo + hi);
+        if derivative(mid.exp()) > 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    (0.5 * (lo + hi)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counter, HLLCounter};
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_estimators_share_state() {
+        let estimators: [(&str, &dyn Estimator); 5] = [
+            ("raw", &RawEstimator),
+            ("bias corrected", &BiasCorrectedEstimator),
+            ("Ertl improved", &ErtlImprovedEstimator),
+            ("MLE", &MleEstimator),
+            ("beta", &BetaEstimator),
+        ];
+        for n in [0u64, 100, 100_000] {
+            let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+            counter.to_dense();
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let state = counter.state();
+            assert_eq!(state.histogram().iter().sum::<u64>(), 1 << 12);
+            assert_eq!(BiasCorrectedEstimator.estimate(&state), counter.estimate());
+            for (name, estimator) in estimators {
+                let estimate = estimator.estimate(&state);
+                if name == "raw" && n < 10_000 {
+                    // No small range correction
+                    assert!(estimate > n as f64, "{name} estimated {estimate} for {n}");
+                    continue;
+                }
+                assert!(
+                    (estimate - n as f64).abs() <= 0.05 * n as f64 + 1.0,
+                    "{name} estimated {estimate} for {n}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use crate::counters::{Counter, EstimateWithError, MergeError, MergeableCounter};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of hashes kept in memory if the size is 0 (128 MiB).
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 24;
+
+// Runs on disk before they are merged into one, which bounds the number of
+// files open at once while counting
+const MAX_RUNS: usize = 64;
+
+// Makes the names of run files unique within the process
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Exact counter that spills the hashes of the items to sorted runs on disk.
+///
+/// Up to `size` hashes are buffered in memory (0 for
+/// [`DEFAULT_BUFFER_SIZE`]). A full buffer is sorted, deduplicated and
+/// written to a temporary file, and the estimate merges all runs to count
+/// the distinct hashes. Memory stays bounded independently of the number of
+/// distinct items, so genomes with billions of distinct k-mers can be
+/// counted exactly (up to 64-bit hash collisions) to validate sketches,
+/// using 8 bytes of disk per buffered hash.
+///
+/// The run files are removed when the counter is dropped. Panics if they
+/// cannot be written or read.
+pub struct ExternalHashCounter<S = RandomState> {
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+    buffer_size: usize,
+    buffer: Vec<u64>,
+    dir: PathBuf,
+    // Files of sorted, distinct hashes as little-endian u64s
+    runs: Vec<PathBuf>,
+}
+
+impl<S: BuildHasher + Default> Counter for ExternalHashCounter<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    /// Creates a counter that writes its runs to the system's temporary
+    /// directory, see [`ExternalHashCounter::with_dir_and_hasher`].
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::with_dir_and_hasher(size, std::env::temp_dir(), hasher)
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        self.count()
+            .unwrap_or_else(|e| panic!("Cannot read runs of external counter: {e}")) as f64
+    }
+
+    /// Also deletes the run files written so far.
+    fn clear(&mut self) {
+        self.buffer.clear();
+        for run in self.runs.drain(..) {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for ExternalHashCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((0, self.fingerprint), (0, other.fingerprint))?;
+        let mut other_buffer = other.buffer.clone();
+        other_buffer.sort_unstable();
+        for_each_distinct(other.sources(&other_buffer), |hash| {
+            self.add_hash(hash);
+            Ok(())
+        })
+        .unwrap_or_else(|e| panic!("Cannot read runs of external counter: {e}"));
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for ExternalHashCounter<S> {
+    /// Exact up to 64-bit hash collisions.
+    fn relative_std_error(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<S: BuildHasher + Default> ExternalHashCounter<S> {
+    /// Creates a counter that writes its runs to `dir`.
+    pub fn with_dir<P: Into<PathBuf>>(size: usize, dir: P) -> Self {
+        Self::with_dir_and_hasher(size, dir, S::default())
+    }
+
+    /// Like [`ExternalHashCounter::with_dir`], but hashes items with `hasher`.
+    pub fn with_dir_and_hasher<P: Into<PathBuf>>(size: usize, dir: P, hasher: S) -> Self {
+        let buffer_size = if size == 0 { DEFAULT_BUFFER_SIZE } else { size };
+        ExternalHashCounter {
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+            buffer_size,
+            buffer: Vec::new(),
+            dir: dir.into(),
+            runs: Vec::new(),
+        }
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        self.buffer.push(hash);
+        if self.buffer.len() < self.buffer_size {
+            return;
+        }
+        // Only spill if deduplication does not free enough space
+        self.buffer.sort_unstable();
+        self.buffer.dedup();
+        if self.buffer.len() > self.buffer_size / 2 {
+            self.spill()
+                .unwrap_or_else(|e| panic!("Cannot write run of external counter: {e}"));
+        }
+    }
+
+    // Writes the sorted, deduplicated buffer to a new run
+    fn spill(&mut self) -> io::Result<()> {
+        let path = self.write_run(|writer| {
+            for &hash in &self.buffer {
+                writer.write_all(&hash.to_le_bytes())?;
+            }
+            Ok(())
+        })?;
+        self.buffer.clear();
+        self.runs.push(path);
+
+        if self.runs.len() >= MAX_RUNS {
+            let runs = std::mem::take(&mut self.runs);
+            let path = self.write_run(|writer| {
+                let sources = runs
+                    .iter()
+                    .map(|run| RunReader::open(run))
+                    .collect::<Result<_, _>>()?;
+                for_each_distinct(sources, |hash| writer.write_all(&hash.to_le_bytes()))
+            })?;
+            self.runs.push(path);
+            for run in runs {
+                std::fs::remove_file(run)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Creates a new run file with the hashes written by `write`
+    fn write_run<F>(&self, write: F) -> io::Result<PathBuf>
+    where
+        F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+    {
+        let (path, file) = loop {
+            let path = self.dir.join(format!(
+                "hll_rust_run_{}_{}.bin",
+                std::process::id(),
+                NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed)
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break (path, file),
+                // Left behind by an earlier process with the same id, or
+                // another file that must not be overwritten
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        Ok(path)
+    }
+
+    // The runs and the sorted buffer as sorted sources of hashes
+    fn sources<'a>(&'a self, sorted_buffer: &'a [u64]) -> Vec<Source<'a>> {
+        let mut sources: Vec<Source> = self
+            .runs
+            .iter()
+            .map(|run| Box::new(RunReader::lazy(run)) as Source)
+            .collect();
+        sources.push(Box::new(sorted_buffer.iter().map(|&hash| Ok(hash))));
+        sources
+    }
+
+    /// Counts the distinct hashes of the runs and the buffer.
+    pub fn count(&self) -> io::Result<u64> {
+        let mut buffer = self.buffer.clone();
+        buffer.sort_unstable();
+        let mut count = 0;
+        for_each_distinct(self.sources(&buffer), |_| {
+            count += 1;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// Number of runs currently on disk.
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+}
+
+impl<S> Drop for ExternalHashCounter<S> {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+type Source<'a> = Box<dyn Iterator<Item = io::Result<u64>> + 'a>;
+
+// Reads the hashes of a run file, opening it on the first read
+struct RunReader<'a> {
+    path: &'a Path,
+    reader: Option<BufReader<File>>,
+}
+
+impl<'a> RunReader<'a> {
+    fn lazy(path: &'a Path) -> Self {
+        RunReader { path, reader: None }
+    }
+
+    fn open(path: &'a Path) -> io::Result<Source<'a>> {
+        let mut reader = Self::lazy(path);
+        reader.reader = Some(BufReader::new(File::open(path)?));
+        Ok(Box::new(reader))
+    }
+
+    fn read_hash(&mut self) -> io::Result<Option<u64>> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => self.reader.insert(BufReader::new(File::open(self.path)?)),
+        };
+        let mut bytes = [0u8; 8];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(u64::from_le_bytes(bytes))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Iterator for RunReader<'_> {
+    type Item = io::Result<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_hash().transpose()
+    }
+}
+
+// Calls `f` with every distinct hash of the sorted sources in ascending order
+fn for_each_distinct<F>(mut sources: Vec<Source>, mut f: F) -> io::Result<()>
+where
+    F: FnMut(u64) -> io::Result<()>,
+{
+    let mut heap = BinaryHeap::new();
+    for (i, source) in sources.iter_mut().enumerate() {
+        if let Some(hash) = source.next().transpose()? {
+            heap.push(Reverse((hash, i)));
+        }
+    }
+
+    let mut last = None;
+    while let Some(Reverse((hash, i))) = heap.pop() {
+        if last != Some(hash) {
+            f(hash)?;
+            last = Some(hash);
+        }
+        if let Some(next) = sources[i].next().transpose()? {
+            heap.push(Reverse((next, i)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashCounter;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_spills_and_counts_exactly() {
+        let mut external = ExternalHashCounter::<Xxh64Builder>::new(1_000);
+        let mut exact = HashCounter::<Xxh64Builder>::new(0);
+        for i in 0..200_000u64 {
+            let item = i * 7 % 100_003;
+            external.add_u64(item);
+            exact.add_u64(item);
+        }
+        // About 200 runs were written and compacted
+        assert!(external.num_runs() < MAX_RUNS);
+        assert_eq!(external.estimate(), exact.estimate());
+
+        let runs = external.runs.clone();
+        let mut other = ExternalHashCounter::<Xxh64Builder>::new(1_000);
+        for i in 100_000..150_000u64 {
+            other.add_u64(i);
+            exact.add_u64(i);
+        }
+        external.merge(&other).unwrap();
+        assert_eq!(external.estimate(), exact.estimate());
+
+        drop(external);
+        assert!(runs.iter().all(|run| !run.exists()));
+
+        let mut external = ExternalHashCounter::<Xxh64Builder>::new(1_000);
+        for i in 0..10_000u64 {
+            external.add_u64(i);
+        }
+        let runs = external.runs.clone();
+        external.clear();
+        assert_eq!(external.estimate(), 0.0);
+        assert!(runs.iter().all(|run| !run.exists()));
+    }
+
+    #[test]
+    fn test_existing_files_are_kept() {
+        let dir = std::env::temp_dir().join(format!("hll_rust_runs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Files named like the next runs
+        let next_id = NEXT_RUN_ID.load(Ordering::Relaxed);
+        let existing: Vec<PathBuf> = (next_id..next_id + 4)
+            .map(|id| dir.join(format!("hll_rust_run_{}_{id}.bin", std::process::id())))
+            .collect();
+        for path in &existing {
+            std::fs::write(path, b"keep").unwrap();
+        }
+
+        let mut external = ExternalHashCounter::<Xxh64Builder>::with_dir(100, &dir);
+        for i in 0..1_000u64 {
+            external.add_u64(i);
+        }
+        assert_eq!(external.estimate(), 1_000.0);
+        drop(external);
+        for path in &existing {
+            assert_eq!(std::fs::read(path).unwrap(), b"keep");
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,10 @@
-use crate::counters::Counter;
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, SaturatedEstimate,
+    ShardedCounter,
+};
 use std::collections::hash_map::RandomState;
-use std::hash::BuildHasher;
+use std::hash::{BuildHasher, Hash};
 
 const PHI: f64 = 0.77351;
 
@@ -8,18 +12,32 @@ pub struct FMCounter<S = RandomState> {
     size: usize,
     bitset: Vec<u8>,
     hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
 }
 
 impl<S: BuildHasher + Default> Counter for FMCounter<S> {
+    type Hasher = S;
+
     fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("number of bits", size, 1..=64)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
         FMCounter {
             size,
             bitset: vec![0; size.div_ceil(8)],
-            hasher: S::default(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
         }
     }
 
-    fn add(&mut self, item: &[u8]) {
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
         let hash = self.hasher.hash_one(item);
 
         let num_trailing_zeros = hash.trailing_zeros() as usize;
@@ -27,21 +45,128 @@ impl<S: BuildHasher + Default> Counter for FMCounter<S> {
         self.bitset[index / 8] |= 1 << (index % 8);
     }
 
+    /// Clamped at 2^(num_bits - 1) / phi once every bit is set, see
+    /// [`FMCounter::try_estimate`].
     fn estimate(&self) -> f64 {
-        let first_zero_bit = self
-            .bitset
+        let first_zero_bit = self.first_zero_bit().unwrap_or(self.size - 1);
+
+        (1_u64 << first_zero_bit) as f64 / PHI
+    }
+
+    fn clear(&mut self) {
+        self.bitset.fill(0);
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for FMCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (byte_self, byte_other) in self.bitset.iter_mut().zip(other.bitset.iter()) {
+            *byte_self |= *byte_other;
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for FMCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for FMCounter<S> {
+    /// A single FM sketch has a relative standard error of about 0.78
+    /// (0.78 / sqrt(m) with m = 1 bitmap).
+    fn relative_std_error(&self) -> f64 {
+        0.78
+    }
+}
+
+impl<S: BuildHasher + Default> FMCounter<S> {
+    /// Number of bits of the bitmap. Hashes with `num_bits - 1` or more
+    /// trailing zeros all set the last bit.
+    pub fn num_bits(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` once every bit is set, so the estimate is stuck at its
+    /// maximum and only a lower bound of the cardinality.
+    pub fn is_saturated(&self) -> bool {
+        self.first_zero_bit().is_none()
+    }
+
+    /// Like [`Counter::estimate`], but fails with the clamped estimate as a
+    /// lower bound if the bitmap is saturated.
+    pub fn try_estimate(&self) -> Result<f64, SaturatedEstimate> {
+        if self.is_saturated() {
+            return Err(SaturatedEstimate {
+                lower_bound: self.estimate(),
+            });
+        }
+        Ok(self.estimate())
+    }
+
+    // Index of the lowest unset bit, `None` if all bits are set.
+    fn first_zero_bit(&self) -> Option<usize> {
+        self.bitset
             .iter()
             .enumerate()
-            .filter_map(|(idx, &byte)| {
-                if byte == u8::MAX {
-                    None
-                } else {
-                    Some(idx * 8 + byte.trailing_ones() as usize)
-                }
-            })
-            .next()
-            .unwrap_or(self.size - 1);
-
-        (1_usize << first_zero_bit) as f64 / PHI
+            .find(|&(_, &byte)| byte != u8::MAX)
+            .map(|(idx, &byte)| idx * 8 + byte.trailing_ones() as usize)
+            .filter(|&bit| bit < self.size)
+    }
+
+    /// Returns `true` if `other` can be merged into `self`: both have the
+    /// same number of bits and hash function.
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )
+        .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_compatibility() {
+        let mut a = FMCounter::with_hasher(32, Xxh64Builder::new(1));
+        let b = FMCounter::with_hasher(32, Xxh64Builder::new(1));
+        assert_eq!(a.num_bits(), 32);
+        assert!(a.is_compatible(&b));
+        assert!(a.merge(&b).is_ok());
+        for other in [
+            FMCounter::with_hasher(16, Xxh64Builder::new(1)),
+            FMCounter::with_hasher(32, Xxh64Builder::new(2)),
+        ] {
+            assert!(!a.is_compatible(&other));
+            assert!(a.merge(&other).is_err());
+        }
+    }
+
+    #[test]
+    fn test_saturation() {
+        let mut counter = FMCounter::with_hasher(4, Xxh64Builder::new(1));
+        counter.add_u64(1);
+        assert!(!counter.is_saturated());
+        assert!(counter.try_estimate().is_ok());
+
+        for i in 0..1_000u64 {
+            counter.add_u64(i);
+        }
+        assert!(counter.is_saturated());
+        let lower_bound = 8.0 / PHI;
+        assert_eq!(counter.estimate(), lower_bound);
+        assert_eq!(
+            counter.try_estimate(),
+            Err(SaturatedEstimate { lower_bound })
+        );
     }
 }
@@ -1,27 +1,163 @@
-use crate::counters::Counter;
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use crate::counters::{
+    Counter, EstimateWithError, HLLCounter, MergeError, MergeableCounter, ShardedCounter,
+};
 use std::collections::HashSet;
 use std::collections::hash_map::RandomState;
-use std::hash::BuildHasher;
+use std::hash::{BuildHasher, Hash};
 
+/// Precision of the HyperLogLog counter a capped [`HashCounter`] hands off to.
+pub const FALLBACK_PRECISION: usize = 14;
+
+/// Exact counter that stores the hash of every distinct item.
+///
+/// `size` caps the number of stored hashes, with 0 meaning no cap. Once the
+/// cap is exceeded, the hashes are handed off to an internal [`HLLCounter`]
+/// so memory stays bounded; [`HashCounter::is_exact`] reports whether the
+/// estimate is still exact.
 pub struct HashCounter<S: BuildHasher + Default = RandomState> {
     hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+    cap: usize,
     counter: HashSet<u64>,
+    // Set once the cap was exceeded
+    fallback: Option<HLLCounter<S>>,
 }
 
 impl<S: BuildHasher + Default> Counter for HashCounter<S> {
-    fn new(_size: usize) -> Self {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
         HashCounter {
-            hasher: S::default(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+            cap: size,
             counter: HashSet::new(),
+            fallback: None,
         }
     }
 
-    fn add(&mut self, item: &[u8]) {
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
         let hash = self.hasher.hash_one(item);
-        self.counter.insert(hash);
+        self.add_hash(hash);
     }
 
     fn estimate(&self) -> f64 {
-        self.counter.len() as f64
+        match &self.fallback {
+            Some(hll) => hll.estimate(),
+            None => self.counter.len() as f64,
+        }
+    }
+
+    /// Keeps the capacity of the set, but drops the fallback sketch, so the
+    /// counter is exact again.
+    fn clear(&mut self) {
+        self.counter.clear();
+        self.fallback = None;
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for HashCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((0, self.fingerprint), (0, other.fingerprint))?;
+        match &other.fallback {
+            Some(other_hll) => {
+                self.downgrade();
+                self.fallback.as_mut().unwrap().merge(other_hll)?;
+            }
+            None => {
+                for &hash in &other.counter {
+                    self.add_hash(hash);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for HashCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.cap, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for HashCounter<S> {
+    /// Exact up to 64-bit hash collisions, unless the cap was exceeded.
+    fn relative_std_error(&self) -> f64 {
+        match &self.fallback {
+            Some(hll) => hll.relative_std_error(),
+            None => 0.0,
+        }
+    }
+}
+
+impl<S: BuildHasher + Default> HashCounter<S> {
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        if let Some(hll) = &mut self.fallback {
+            hll.add_hash(hash);
+            return;
+        }
+
+        self.counter.insert(hash);
+        if self.cap > 0 && self.counter.len() > self.cap {
+            self.downgrade();
+        }
+    }
+
+    // Moves the stored hashes into the fallback HLL counter.
+    fn downgrade(&mut self) {
+        if self.fallback.is_some() {
+            return;
+        }
+        // Only receives hashes, so it shares the fingerprint of this counter
+        let mut hll = HLLCounter::new(FALLBACK_PRECISION);
+        hll.fingerprint = self.fingerprint;
+        for hash in std::mem::take(&mut self.counter) {
+            hll.add_hash(hash);
+        }
+        self.fallback = Some(hll);
+    }
+
+    /// Returns `true` as long as the cap was not exceeded and the count is exact.
+    pub fn is_exact(&self) -> bool {
+        self.fallback.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_cap_hands_off_to_hll() {
+        let mut counter = HashCounter::<Xxh64Builder>::new(1000);
+        for i in 0..1000u64 {
+            counter.add_u64(i);
+        }
+        assert!(counter.is_exact());
+        assert_eq!(counter.estimate(), 1000.0);
+
+        for i in 0..100_000u64 {
+            counter.add_u64(i);
+        }
+        assert!(!counter.is_exact());
+        assert!((counter.estimate() - 100_000.0).abs() / 100_000.0 < 0.05);
+
+        // Merging an exact counter into a downgraded one keeps it approximate
+        let mut exact = HashCounter::<Xxh64Builder>::new(0);
+        for i in 100_000..200_000u64 {
+            exact.add_u64(i);
+        }
+        assert!(exact.is_exact());
+        exact.merge(&counter).unwrap();
+        assert!(!exact.is_exact());
+        assert!((exact.estimate() - 200_000.0).abs() / 200_000.0 < 0.05);
     }
 }
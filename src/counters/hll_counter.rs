@@ -1,77 +1,280 @@
-use crate::counters::Counter;
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::estimators::{
+    Estimator, HLLState, RawEstimator, alpha, dense_estimate, ertl_improved_estimate,
+    ertl_mle_added_estimate, ertl_mle_estimate, loglog_beta_estimate,
+};
+use crate::counters::{
+    BuildError, Counter, Estimate, EstimateWithError, MergeError, MergeableCounter,
+    PackedRegisters, Registers, ShardedCounter,
+};
 use std::collections::hash_map::RandomState;
-use std::hash::BuildHasher;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
 
-const AM_4: f64 = 0.673;
-const AM_5: f64 = 0.697;
-const AM_6: f64 = 0.709;
+// Precision used while the counter is in sparse mode (HLL++ p').
+// Sparse entries are encoded as (index << 6) | rho in a u32, so the index
+// takes 25 bits and rho (at most 64 - 25 = 39) fits into the lower 6 bits.
+const SPARSE_PRECISION: usize = 25;
+const SPARSE_RHO_BITS: u32 = 6;
+// Largest number of sparse entries, the size of the sparse list of a counter
+// at precision 14. Every insert shifts the sorted list, so counters of higher
+// precision switch to the dense array before that takes quadratic time.
+const MAX_SPARSE_ENTRIES: usize = 1 << 12;
 
-pub struct HLLCounter<S = RandomState> {
+// Number of items hashed ahead of their register updates in batched adds
+const BATCH_SIZE: usize = 8;
+
+// The registers are split into at most 2^6 groups for bootstrapping
+const BOOTSTRAP_GROUP_BITS: usize = 6;
+
+/// First bytes of sketches written by [`HLLCounter::write_to`].
+pub const SKETCH_MAGIC: &[u8; 4] = b"HLLR";
+// Version 1 sketches store no hasher fingerprint
+const SKETCH_VERSION: u8 = 2;
+
+/// First bytes of sketches encoded by [`HLLCounter::to_compressed_bytes`].
+pub const COMPRESSED_SKETCH_MAGIC: &[u8; 4] = b"HLLZ";
+const COMPRESSED_SKETCH_VERSION: u8 = 2;
+// Dense register values take the lowest bits of a run, the run length minus
+// one the bits above
+const RUN_VALUE_BITS: u32 = 6;
+
+// Header of Redis' HyperLogLog strings: magic, encoding, 3 unused bytes and
+// the cached cardinality, whose most significant bit marks it as stale.
+const REDIS_MAGIC: &[u8; 4] = b"HYLL";
+const REDIS_HEADER_SIZE: usize = 16;
+const REDIS_DENSE: u8 = 0;
+const REDIS_SPARSE: u8 = 1;
+/// Precision of Redis' HyperLogLog (16384 registers).
+pub const REDIS_PRECISION: usize = 14;
+const REDIS_REGISTER_BITS: usize = 6;
+const REDIS_DENSE_SIZE: usize =
+    REDIS_HEADER_SIZE + ((1 << REDIS_PRECISION) * REDIS_REGISTER_BITS).div_ceil(8);
+
+/// Cardinality estimator used by [`HLLCounter::estimate_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EstimatorKind {
+    /// Raw HyperLogLog estimate with the HLL++ bias correction and linear
+    /// counting for small cardinalities. This is what `estimate` uses.
+    #[default]
+    HyperLogLogPlusPlus,
+    /// Ertl's improved raw estimator, which corrects the harmonic mean using
+    /// the number of empty and saturated registers.
+    ErtlImproved,
+    /// Ertl's maximum-likelihood estimator over the register histogram.
+    ErtlMle,
+    /// LogLog-Beta (Qin et al., 2016), which adds a correction term of the
+    /// number of empty registers to the harmonic mean. A single formula
+    /// covers all cardinalities, without switching to linear counting.
+    /// The correction term is only published for precisions up to 18, so
+    /// higher precisions fall back to the HLL++ estimate.
+    LogLogBeta,
+    /// The uncorrected harmonic mean estimate of the original HyperLogLog
+    /// paper, strongly biased for small cardinalities.
+    Raw,
+}
+
+/// HyperLogLog counter.
+///
+/// The register storage is selected with `R`: one byte per register
+/// (`Vec<u8>`, the default) or [`PackedRegisters`] with 6 bits per register.
+#[derive(Clone)]
+pub struct HLLCounter<S = RandomState, R = Vec<u8>> {
     size: usize,
     am: f64,
-    registers: Vec<u8>,
+    registers: R,
+    // Sorted list of encoded (index, rho) pairs at SPARSE_PRECISION.
+    // `None` once the counter has been converted to the dense representation.
+    sparse: Option<Vec<u32>>,
     hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    pub(crate) fingerprint: u64,
+}
+
+/// The registers and cardinality of an [`HLLCounter`] at some point of a
+/// stream, taken by [`HLLCounter::snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HLLSnapshot {
+    size: usize,
+    fingerprint: u64,
+    registers: Vec<u8>,
+    estimate: f64,
 }
 
-impl<S: BuildHasher + Default> Counter for HLLCounter<S> {
+impl HLLSnapshot {
+    /// The maximum-likelihood estimate of the cardinality at the snapshot.
+    pub fn estimate(&self) -> f64 {
+        self.estimate
+    }
+}
+
+/// HyperLogLog counter with 6-bit packed registers.
+pub type PackedHLLCounter<S = RandomState> = HLLCounter<S, PackedRegisters>;
+
+impl<S: BuildHasher + Default, R: Registers> Counter for HLLCounter<S, R> {
+    type Hasher = S;
+
     fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, 4..=24)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
         let num_registers = 1 << size;
-        let am = match size {
-            0..=4 => AM_4,
-            5 => AM_5,
-            6 => AM_6,
-            _ => 0.7213 / (1.0 + 1.079 / num_registers as f64),
-        };
+        let am = alpha(size);
+        // The sparse representation only pays off if the dense array is
+        // larger than a handful of sparse entries, and needs registers
+        // that can start out empty.
+        let (registers, sparse) =
+            if size < SPARSE_PRECISION && num_registers >= 64 && R::FIXED_LEN.is_none() {
+                (R::with_len(0), Some(Vec::new()))
+            } else {
+                (R::with_len(num_registers), None)
+            };
         HLLCounter {
             size,
             am,
-            registers: vec![u8::MIN; num_registers],
-            hasher: S::default(),
+            registers,
+            sparse,
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
         }
     }
 
-    fn add(&mut self, item: &[u8]) {
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    // Specialized high-performance method
+    #[inline(always)]
+    fn add_u64(&mut self, item: u64) {
         let hash = self.hasher.hash_one(item);
         self.add_hash(hash);
     }
 
+    fn add_batch(&mut self, items: &[impl AsRef<[u8]>]) {
+        let mut hashes = [0u64; BATCH_SIZE];
+        for batch in items.chunks(BATCH_SIZE) {
+            for (hash, item) in hashes.iter_mut().zip(batch) {
+                *hash = self.hasher.hash_one(item.as_ref());
+            }
+            self.add_hashes(&hashes[..batch.len()]);
+        }
+    }
+
+    fn add_u64_batch(&mut self, items: &[u64]) {
+        let mut hashes = [0u64; BATCH_SIZE];
+        for batch in items.chunks(BATCH_SIZE) {
+            for (hash, &item) in hashes.iter_mut().zip(batch) {
+                *hash = self.hasher.hash_one(item);
+            }
+            self.add_hashes(&hashes[..batch.len()]);
+        }
+    }
+
     fn estimate(&self) -> f64 {
-        let num_registers = (1 << self.size) as f64;
+        if let Some(sparse) = &self.sparse {
+            // Linear counting at the sparse precision
+            let num_registers = (1u64 << SPARSE_PRECISION) as f64;
+            let zeros = num_registers - sparse.len() as f64;
+            return num_registers * (num_registers / zeros).ln();
+        }
 
-        let numerator = self.am * num_registers * num_registers;
+        dense_estimate(self.size, self.am, &self.registers)
+    }
 
-        let denominator: f64 = self
-            .registers
-            .iter()
-            .map(|&reg| 2f64.powi(-(reg as i32)))
-            .sum();
+    /// A sparse counter stays sparse, a dense one keeps its registers.
+    fn clear(&mut self) {
+        match &mut self.sparse {
+            Some(sparse) => sparse.clear(),
+            None => self.registers.reset(),
+        }
+    }
+}
 
-        let mut estimate = numerator / denominator;
+impl<S: BuildHasher + Default, R: Registers> MergeableCounter for HLLCounter<S, R> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
 
-        // Small range correction
-        if estimate <= 2.5 * num_registers {
-            let zeros = self.registers.iter().filter(|&&reg| reg == 0).count();
-            if zeros > 0 {
-                estimate = num_registers * (num_registers / zeros as f64).ln();
+        if let Some(other_sparse) = &other.sparse {
+            self.merge_sparse_entries(other_sparse);
+        } else {
+            self.to_dense();
+            for (index, reg_other) in other.registers.iter().enumerate() {
+                self.registers.update_max(index, reg_other);
             }
-        } else if estimate > (2f64.powi(64) / 30f64) {
-            estimate = -2f64.powi(64) * (1f64 - estimate * 2f64.powi(-64)).ln()
         }
+        Ok(())
+    }
+}
 
-        estimate
+impl<S: BuildHasher + Default + Clone, R: Registers> ShardedCounter for HLLCounter<S, R> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
     }
 }
 
-impl<S: BuildHasher + Default> HLLCounter<S> {
-    // Some specialized high-performance methods
-    #[inline(always)]
-    pub fn add_u64(&mut self, item: u64) {
-        let hash = self.hasher.hash_one(item);
-        self.add_hash(hash);
+impl<S: BuildHasher + Default + Clone, R: Registers> HLLCounter<S, R> {
+    /// Returns a copy of the counter with the lower `precision`, e.g. to merge
+    /// chunks counted at precision 16 into a long-lived sketch at precision 12.
+    ///
+    /// The index bits above the new precision become the lowest bits of the
+    /// remainder, so the result equals a counter built at `precision` from
+    /// the same items with the same hasher. Fails if `precision` is below 4
+    /// or above the precision of the counter.
+    pub fn fold_to(&self, precision: usize) -> Result<Self, BuildError> {
+        check_range("precision", precision, 4..=self.size)?;
+        let mut folded = Self::with_hasher(precision, self.hasher.clone());
+
+        match &self.sparse {
+            // Sparse entries are kept at SPARSE_PRECISION for every precision
+            Some(sparse) => folded.merge_sparse_entries(sparse),
+            None => {
+                folded.to_dense();
+                let extra_precision = (self.size - precision) as u8;
+                for (index, reg) in self.registers.iter().enumerate() {
+                    if reg == 0 {
+                        continue;
+                    }
+                    let extra_bits = index >> precision;
+                    let rho = if extra_bits != 0 {
+                        extra_bits.trailing_zeros() as u8 + 1
+                    } else {
+                        (reg + extra_precision).min(folded.rho_limit())
+                    };
+                    folded
+                        .registers
+                        .update_max(index & ((1 << precision) - 1), rho);
+                }
+            }
+        }
+        Ok(folded)
+    }
+}
+
+impl<S: BuildHasher + Default, R: Registers> EstimateWithError for HLLCounter<S, R> {
+    /// 1.04 / sqrt(m) for m registers. Sparse counters are at least as accurate.
+    fn relative_std_error(&self) -> f64 {
+        1.04 / ((1u64 << self.size) as f64).sqrt()
     }
+}
 
+impl<S: BuildHasher + Default, R: Registers> HLLCounter<S, R> {
     #[inline(always)]
-    fn add_hash(&mut self, hash: u64) {
+    pub(crate) fn add_hash(&mut self, hash: u64) {
+        if self.sparse.is_some() {
+            self.add_hash_sparse(hash);
+            return;
+        }
+
         let index = (hash & ((1u64 << self.size) - 1)) as usize;
         let remainder = hash >> self.size;
         // trailing_zeros() will usually be compiled to a single instruction
@@ -79,13 +282,1534 @@ impl<S: BuildHasher + Default> HLLCounter<S> {
         // see this example: https://godbolt.org/z/eGejof3Kz
         let rho = std::cmp::min(remainder.trailing_zeros() + 1, 64 - self.size as u32) as u8;
 
-        self.registers[index] = std::cmp::max(self.registers[index], rho);
+        self.registers.update_max(index, rho);
+    }
+
+    // Prefetches the registers of all hashes before updating them, so the
+    // cache misses of a batch overlap.
+    fn add_hashes(&mut self, hashes: &[u64]) {
+        if self.sparse.is_none() {
+            let mask = (1u64 << self.size) - 1;
+            for &hash in hashes {
+                self.registers.prefetch((hash & mask) as usize);
+            }
+        }
+        for &hash in hashes {
+            self.add_hash(hash);
+        }
+    }
+
+    fn add_hash_sparse(&mut self, hash: u64) {
+        let index = (hash & ((1u64 << SPARSE_PRECISION) - 1)) as u32;
+        let remainder = hash >> SPARSE_PRECISION;
+        let rho = std::cmp::min(remainder.trailing_zeros() + 1, 64 - SPARSE_PRECISION as u32);
+        self.insert_sparse((index << SPARSE_RHO_BITS) | rho);
+    }
+
+    fn insert_sparse(&mut self, entry: u32) {
+        let sparse = self.sparse.as_mut().expect("counter is not sparse");
+        let index = entry >> SPARSE_RHO_BITS;
+        match sparse.binary_search_by_key(&index, |&e| e >> SPARSE_RHO_BITS) {
+            Ok(pos) => sparse[pos] = std::cmp::max(sparse[pos], entry),
+            Err(pos) => sparse.insert(pos, entry),
+        }
+
+        // Switch to the dense array once the sparse list takes up more memory
+        if sparse.len() * std::mem::size_of::<u32>() > (1 << self.size)
+            || sparse.len() > MAX_SPARSE_ENTRIES
+        {
+            self.to_dense();
+        }
+    }
+
+    // Adds encoded sparse entries, folding them if the counter is dense.
+    fn merge_sparse_entries(&mut self, entries: &[u32]) {
+        for &entry in entries {
+            if self.sparse.is_some() {
+                self.insert_sparse(entry);
+            } else {
+                let (index, rho) = self.fold_sparse_entry(entry);
+                self.registers.update_max(index, rho);
+            }
+        }
+    }
+
+    // Creates a dense counter from the given register values.
+    pub(crate) fn from_dense_registers<I: IntoIterator<Item = u8>>(
+        size: usize,
+        hasher: S,
+        registers: I,
+    ) -> Self {
+        let mut counter = Self::with_hasher(size, hasher);
+        counter.to_dense();
+        for (index, reg) in registers.into_iter().enumerate() {
+            counter.registers.set(index, reg.min(counter.rho_limit()));
+        }
+        counter
+    }
+
+    // Returns the dense register values, folding sparse entries.
+    pub(crate) fn register_values(&self) -> Vec<u8> {
+        match &self.sparse {
+            Some(sparse) => {
+                let mut registers = vec![0u8; 1 << self.size];
+                for &entry in sparse {
+                    let (index, rho) = self.fold_sparse_entry(entry);
+                    registers[index] = registers[index].max(rho);
+                }
+                registers
+            }
+            None => self.registers.iter().collect(),
+        }
+    }
+
+    // Returns the hasher, e.g. to read a saved sketch of the same hash
+    // function.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn into_hasher(self) -> S {
+        self.hasher
+    }
+
+    // Raises a register to at least `rho`, switching to the dense representation.
+    pub(crate) fn update_register(&mut self, index: usize, rho: u8) {
+        self.to_dense();
+        self.registers.update_max(index, rho.min(self.rho_limit()));
+    }
+
+    // Largest register value, reached when all remaining hash bits are zero.
+    // Other sketch formats may use a few more bits.
+    fn rho_limit(&self) -> u8 {
+        64 - self.size as u8
+    }
+
+    /// Number of index bits, i.e. the counter uses 2^precision registers.
+    pub fn precision(&self) -> usize {
+        self.size
+    }
+
+    /// Number of registers, 2^precision.
+    pub fn num_registers(&self) -> usize {
+        1 << self.size
+    }
+
+    /// Returns `true` if `other` can be merged into `self`: both have the
+    /// same precision and hash function.
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )
+        .is_ok()
+    }
+
+    /// Heap memory used by the sketch in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.sparse {
+            Some(sparse) => sparse.len() * std::mem::size_of::<u32>(),
+            None => self.registers.size_in_bytes(),
+        }
+    }
+
+    /// Memory of the whole counter in bytes, the struct itself included.
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.size_in_bytes()
+    }
+
+    /// Number of registers of each value 0 to 64, folding sparse entries to
+    /// the precision of the counter.
+    ///
+    /// With a good hash function, every value above 0 is reached by about
+    /// half as many registers as the one before, once the cardinality is
+    /// well above the number of registers. Registers clumped at a few values
+    /// or far too many high values point to a poor or correlated hash.
+    pub fn register_histogram(&self) -> [u64; 65] {
+        let mut histogram = [0u64; 65];
+        match &self.sparse {
+            Some(_) => {
+                for reg in self.register_values() {
+                    histogram[reg as usize] += 1;
+                }
+            }
+            None => {
+                for reg in self.registers.iter() {
+                    histogram[reg as usize] += 1;
+                }
+            }
+        }
+        histogram
+    }
+
+    /// Largest register value, 0 for an empty counter. It grows with the
+    /// logarithm of the cardinality per register; values close to
+    /// 64 - precision mean the hash space is nearly exhausted.
+    pub fn max_rho(&self) -> u8 {
+        match &self.sparse {
+            Some(sparse) => sparse
+                .iter()
+                .map(|&entry| self.fold_sparse_entry(entry).1)
+                .max()
+                .unwrap_or(0),
+            None => self.registers.iter().max().unwrap_or(0),
+        }
+    }
+
+    /// Number of registers that are still 0. Linear counting is used while
+    /// there are enough of them, and an empty register is left with
+    /// probability (1 - 1/m)^n after n distinct items.
+    pub fn zero_registers(&self) -> usize {
+        match &self.sparse {
+            Some(sparse) => {
+                let mut indices: Vec<usize> = sparse
+                    .iter()
+                    .map(|&entry| self.fold_sparse_entry(entry).0)
+                    .collect();
+                indices.sort_unstable();
+                indices.dedup();
+                (1 << self.size) - indices.len()
+            }
+            None => self.registers.iter().filter(|&reg| reg == 0).count(),
+        }
+    }
+
+    /// Returns `true` while the counter still uses the sparse representation.
+    pub fn is_sparse(&self) -> bool {
+        self.sparse.is_some()
+    }
+
+    /// Converts a sparse counter to the dense register array.
+    ///
+    /// Does nothing if the counter is already dense.
+    pub fn to_dense(&mut self) {
+        let Some(sparse) = self.sparse.take() else {
+            return;
+        };
+        self.registers = R::with_len(1 << self.size);
+        for entry in sparse {
+            let (index, rho) = self.fold_sparse_entry(entry);
+            self.registers.update_max(index, rho);
+        }
+    }
+
+    // Maps a sparse entry at SPARSE_PRECISION to a dense (index, rho) pair.
+    fn fold_sparse_entry(&self, entry: u32) -> (usize, u8) {
+        let sparse_index = entry >> SPARSE_RHO_BITS;
+        let sparse_rho = entry & ((1 << SPARSE_RHO_BITS) - 1);
+
+        let index = (sparse_index & ((1 << self.size) - 1)) as usize;
+        // The bits between the dense and the sparse precision are the lowest
+        // bits of the dense remainder.
+        let extra_bits = sparse_index >> self.size;
+        let rho = if extra_bits != 0 {
+            extra_bits.trailing_zeros() + 1
+        } else {
+            std::cmp::min(
+                (SPARSE_PRECISION - self.size) as u32 + sparse_rho,
+                64 - self.size as u32,
+            )
+        };
+        (index, rho as u8)
+    }
+
+    /// Serializes the sketch.
+    ///
+    /// The format stores the precision, a fingerprint of the hasher and
+    /// either the sparse entries or the dense registers. The hasher itself
+    /// is not stored, so sketches must be read back with the same
+    /// deterministic `BuildHasher`; reading with another hash function or
+    /// seed fails instead of silently mixing hashes.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(SKETCH_MAGIC)?;
+        writer.write_all(&[SKETCH_VERSION, self.size as u8])?;
+        writer.write_all(&[self.sparse.is_some() as u8])?;
+        writer.write_all(&self.fingerprint.to_le_bytes())?;
+        match &self.sparse {
+            Some(sparse) => {
+                writer.write_all(&(sparse.len() as u32).to_le_bytes())?;
+                for entry in sparse {
+                    writer.write_all(&entry.to_le_bytes())?;
+                }
+            }
+            None => {
+                let registers: Vec<u8> = self.registers.iter().collect();
+                writer.write_all(&registers)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserializes a sketch written by [`HLLCounter::write_to`] with the
+    /// default hasher of `S`.
+    pub fn read_from<Rd: Read>(reader: Rd) -> io::Result<Self> {
+        Self::read_from_with_hasher(reader, S::default())
     }
 
-    pub fn merge(&mut self, other: &HLLCounter<S>) {
-        assert_eq!(self.size, other.size);
-        for (reg_self, reg_other) in self.registers.iter_mut().zip(other.registers.iter()) {
-            *reg_self = std::cmp::max(*reg_self, *reg_other);
+    /// Deserializes a sketch written by [`HLLCounter::write_to`], hashing
+    /// further items with `hasher`.
+    ///
+    /// Fails with an `InvalidData` error if the sketch was written with
+    /// another hash function or seed. Sketches of the first version of the
+    /// format store no fingerprint and are assumed to match `hasher`.
+    pub fn read_from_with_hasher<Rd: Read>(mut reader: Rd, hasher: S) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut header = [0u8; 7];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != SKETCH_MAGIC {
+            return Err(invalid("Not an HLL sketch file."));
+        }
+        if !(1..=SKETCH_VERSION).contains(&header[4]) {
+            return Err(invalid("Unsupported HLL sketch version."));
+        }
+        let size = header[5] as usize;
+        if Self::check_size(size).is_err() {
+            return Err(invalid("Invalid HLL sketch precision."));
+        }
+        if header[4] >= 2 {
+            let mut fingerprint = [0u8; 8];
+            reader.read_exact(&mut fingerprint)?;
+            check_stored_fingerprint(u64::from_le_bytes(fingerprint), &hasher)?;
+        }
+
+        let mut counter = Self::with_hasher(size, hasher);
+        match header[6] {
+            1 => {
+                let mut len = [0u8; 4];
+                reader.read_exact(&mut len)?;
+                let len = u32::from_le_bytes(len) as usize;
+                if len > 1 << SPARSE_PRECISION {
+                    return Err(invalid("Too many sparse HLL entries."));
+                }
+                // Grown while reading, so a corrupt length fails at the end
+                // of the data instead of allocating gigabytes up front
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    let mut entry = [0u8; 4];
+                    reader.read_exact(&mut entry)?;
+                    entries.push(u32::from_le_bytes(entry));
+                }
+                check_sparse_entries(&entries)?;
+                if counter.sparse.is_none() {
+                    return Err(invalid("Sparse HLL sketch with unsupported precision."));
+                }
+                counter.sparse = Some(entries);
+            }
+            0 => {
+                let mut registers = vec![0u8; 1 << size];
+                reader.read_exact(&mut registers)?;
+                counter = Self::from_dense_registers(size, counter.hasher, registers);
+            }
+            _ => return Err(invalid("Invalid HLL sketch representation.")),
+        }
+        Ok(counter)
+    }
+
+    /// Serializes the sketch like [`HLLCounter::write_to`], but compressed
+    /// for storing many sketches.
+    ///
+    /// Sparse entries are stored as varint deltas, which take 2 to 3 instead
+    /// of 4 bytes each. Dense registers are run-length encoded, every run of
+    /// equal registers as one varint of its value and length, so the empty
+    /// registers of sketches with fewer items than registers take a few
+    /// bytes. Registers of saturated sketches rarely repeat, which leaves
+    /// them at about one byte each.
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        let mut bytes = COMPRESSED_SKETCH_MAGIC.to_vec();
+        bytes.extend_from_slice(&[
+            COMPRESSED_SKETCH_VERSION,
+            self.size as u8,
+            self.sparse.is_some() as u8,
+        ]);
+        bytes.extend_from_slice(&self.fingerprint.to_le_bytes());
+        match &self.sparse {
+            Some(sparse) => {
+                write_varint(&mut bytes, sparse.len() as u64);
+                let mut previous = 0;
+                for &entry in sparse {
+                    write_varint(&mut bytes, (entry - previous) as u64);
+                    previous = entry;
+                }
+            }
+            None => {
+                let mut registers = self.registers.iter().peekable();
+                while let Some(value) = registers.next() {
+                    let mut run = 1u64;
+                    while registers.next_if_eq(&value).is_some() {
+                        run += 1;
+                    }
+                    write_varint(&mut bytes, ((run - 1) << RUN_VALUE_BITS) | value as u64);
+                }
+            }
         }
+        bytes
+    }
+
+    /// Deserializes a sketch encoded by [`HLLCounter::to_compressed_bytes`]
+    /// with the default hasher of `S`.
+    pub fn from_compressed_bytes(bytes: &[u8]) -> io::Result<Self> {
+        Self::from_compressed_bytes_with_hasher(bytes, S::default())
+    }
+
+    /// Deserializes a sketch encoded by [`HLLCounter::to_compressed_bytes`],
+    /// hashing further items with `hasher`.
+    ///
+    /// Fails with an `InvalidData` error if the sketch was encoded with
+    /// another hash function or seed, like
+    /// [`HLLCounter::read_from_with_hasher`].
+    pub fn from_compressed_bytes_with_hasher(bytes: &[u8], hasher: S) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if bytes.len() < 7 || &bytes[..4] != COMPRESSED_SKETCH_MAGIC {
+            return Err(invalid("Not a compressed HLL sketch."));
+        }
+        if !(1..=COMPRESSED_SKETCH_VERSION).contains(&bytes[4]) {
+            return Err(invalid("Unsupported compressed HLL sketch version."));
+        }
+        let size = bytes[5] as usize;
+        if Self::check_size(size).is_err() {
+            return Err(invalid("Invalid HLL sketch precision."));
+        }
+
+        let mut data = &bytes[7..];
+        if bytes[4] >= 2 {
+            let (fingerprint, rest) = data
+                .split_first_chunk()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            check_stored_fingerprint(u64::from_le_bytes(*fingerprint), &hasher)?;
+            data = rest;
+        }
+        let mut counter = Self::with_hasher(size, hasher);
+        match bytes[6] {
+            1 => {
+                let len = read_varint(&mut data)?;
+                if len > 1 << SPARSE_PRECISION {
+                    return Err(invalid("Too many sparse HLL entries."));
+                }
+                // Every entry takes at least one byte
+                let mut entries = Vec::with_capacity((len as usize).min(data.len()));
+                let mut previous = 0u64;
+                for _ in 0..len {
+                    let delta = read_varint(&mut data)?;
+                    previous = previous
+                        .checked_add(delta)
+                        .filter(|&entry| entry <= u32::MAX as u64)
+                        .ok_or_else(|| invalid("Sparse HLL entry out of range."))?;
+                    entries.push(previous as u32);
+                }
+                check_sparse_entries(&entries)?;
+                if counter.sparse.is_none() {
+                    return Err(invalid("Sparse HLL sketch with unsupported precision."));
+                }
+                counter.sparse = Some(entries);
+            }
+            0 => {
+                let num_registers = 1 << size;
+                let mut registers = Vec::with_capacity(num_registers);
+                while registers.len() < num_registers {
+                    let token = read_varint(&mut data)?;
+                    let run = (token >> RUN_VALUE_BITS) as usize + 1;
+                    if run > num_registers - registers.len() {
+                        return Err(invalid("HLL register runs exceed the sketch."));
+                    }
+                    let value = (token & ((1 << RUN_VALUE_BITS) - 1)) as u8;
+                    registers.extend(std::iter::repeat_n(value, run));
+                }
+                counter = Self::from_dense_registers(size, counter.hasher, registers);
+            }
+            _ => return Err(invalid("Invalid HLL sketch representation.")),
+        }
+        if !data.is_empty() {
+            return Err(invalid("Trailing bytes after the compressed HLL sketch."));
+        }
+        Ok(counter)
+    }
+
+    /// Exports the sketch in the dense encoding of Redis' `PFADD`/`PFMERGE`.
+    ///
+    /// The result can be stored with `SET` and merged into other keys with
+    /// `PFMERGE`. Only counters with precision [`REDIS_PRECISION`] can be
+    /// exported, and they must use the same hash as Redis, see
+    /// [`RedisHashBuilder`](crate::counters::RedisHashBuilder).
+    pub fn to_redis_bytes(&self) -> io::Result<Vec<u8>> {
+        if self.size != REDIS_PRECISION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Redis sketches require precision {}.", REDIS_PRECISION),
+            ));
+        }
+
+        let mut bytes = vec![0u8; REDIS_DENSE_SIZE];
+        bytes[..4].copy_from_slice(REDIS_MAGIC);
+        bytes[4] = REDIS_DENSE;
+        // Invalidate the cached cardinality so Redis recomputes it
+        bytes[15] = 0x80;
+
+        let mut set_register = |index: usize, value: u8| {
+            let bit = index * REDIS_REGISTER_BITS;
+            let (byte, shift) = (REDIS_HEADER_SIZE + bit / 8, bit % 8);
+            let value = value as u16 & 0x3f;
+            bytes[byte] |= (value << shift) as u8;
+            if shift > 8 - REDIS_REGISTER_BITS {
+                bytes[byte + 1] |= (value >> (8 - shift)) as u8;
+            }
+        };
+        for (index, reg) in self.register_values().into_iter().enumerate() {
+            set_register(index, reg);
+        }
+        Ok(bytes)
+    }
+
+    /// Imports a sketch from a Redis HyperLogLog string (as returned by `GET`
+    /// on a `PFADD` key), in either the dense or the sparse encoding.
+    pub fn from_redis_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if bytes.len() < REDIS_HEADER_SIZE || &bytes[..4] != REDIS_MAGIC {
+            return Err(invalid("Not a Redis HyperLogLog."));
+        }
+        let data = &bytes[REDIS_HEADER_SIZE..];
+        let num_registers = 1 << REDIS_PRECISION;
+
+        let registers = match bytes[4] {
+            REDIS_DENSE => {
+                if bytes.len() != REDIS_DENSE_SIZE {
+                    return Err(invalid("Invalid size of a dense Redis HyperLogLog."));
+                }
+                (0..num_registers)
+                    .map(|index| {
+                        let bit = index * REDIS_REGISTER_BITS;
+                        let (byte, shift) = (bit / 8, bit % 8);
+                        let next = data.get(byte + 1).copied().unwrap_or(0) as u16;
+                        let value = ((data[byte] as u16) | (next << 8)) >> shift;
+                        (value & 0x3f) as u8
+                    })
+                    .collect()
+            }
+            REDIS_SPARSE => {
+                // Run-length encoded opcodes:
+                // ZERO 00xxxxxx, XZERO 01xxxxxx yyyyyyyy, VAL 1vvvvvxx
+                let mut registers = Vec::with_capacity(num_registers);
+                let mut i = 0;
+                while i < data.len() {
+                    let op = data[i];
+                    let (value, run) = match op >> 6 {
+                        0b00 => (0, (op & 0x3f) as usize + 1),
+                        0b01 => {
+                            let low = *data
+                                .get(i + 1)
+                                .ok_or_else(|| invalid("Truncated sparse Redis HyperLogLog."))?;
+                            i += 1;
+                            (0, ((((op & 0x3f) as usize) << 8) | low as usize) + 1)
+                        }
+                        _ => (((op >> 2) & 0x1f) + 1, (op & 0x3) as usize + 1),
+                    };
+                    registers.extend(std::iter::repeat_n(value, run));
+                    i += 1;
+                }
+                if registers.len() != num_registers {
+                    return Err(invalid("Sparse Redis HyperLogLog has the wrong length."));
+                }
+                registers
+            }
+            _ => return Err(invalid("Unsupported Redis HyperLogLog encoding.")),
+        };
+
+        Ok(Self::from_dense_registers(
+            REDIS_PRECISION,
+            S::default(),
+            registers,
+        ))
+    }
+
+    /// Estimates the cardinality using the given estimator.
+    ///
+    /// The Ertl estimators work on the full register histogram and need no
+    /// small or large range corrections.
+    pub fn estimate_with(&self, kind: EstimatorKind) -> f64 {
+        match kind {
+            EstimatorKind::HyperLogLogPlusPlus => self.estimate(),
+            EstimatorKind::ErtlImproved => {
+                let (precision, histogram) = self.histogram();
+                ertl_improved_estimate(precision, &histogram)
+            }
+            EstimatorKind::ErtlMle => {
+                let (precision, histogram) = self.histogram();
+                ertl_mle_estimate(precision, &histogram)
+            }
+            EstimatorKind::LogLogBeta => {
+                let registers = self.register_values();
+                loglog_beta_estimate(self.size, &registers).unwrap_or_else(|| self.estimate())
+            }
+            EstimatorKind::Raw => RawEstimator.estimate(&self.state()),
+        }
+    }
+
+    /// Returns the register values for estimating the cardinality with any
+    /// [`Estimator`]. Sparse entries are folded into the registers at the
+    /// precision of the counter, so unlike [`HLLCounter::estimate_with`],
+    /// the estimators do not see the higher sparse precision.
+    pub fn state(&self) -> HLLState {
+        HLLState::new(self.size, self.register_values())
+    }
+
+    /// Records the current registers, for estimating with
+    /// [`HLLCounter::estimate_added_since`] how many distinct items arrive
+    /// later in the stream, e.g. per chromosome of a genome.
+    ///
+    /// The snapshot takes one byte per register, also of a sparse counter.
+    pub fn snapshot(&self) -> HLLSnapshot {
+        HLLSnapshot {
+            size: self.size,
+            fingerprint: self.fingerprint,
+            registers: self.register_values(),
+            estimate: self.estimate_with(EstimatorKind::ErtlMle),
+        }
+    }
+
+    /// Estimates the number of distinct items added since `snapshot` was
+    /// taken of this counter, or of a counter merged into it.
+    ///
+    /// This is the maximum-likelihood estimate of the new items given the
+    /// registers at the snapshot and now. It is based on the registers that
+    /// changed, so the error is relative to the number of new items, not to
+    /// the cardinality: a few percent once a few hundred registers changed.
+    /// Fails if the snapshot is of a counter with another precision or hash
+    /// function.
+    pub fn estimate_added_since(&self, snapshot: &HLLSnapshot) -> Result<f64, MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (snapshot.size, snapshot.fingerprint),
+        )?;
+        Ok(ertl_mle_added_estimate(
+            self.size,
+            &snapshot.registers,
+            &self.register_values(),
+        ))
+    }
+
+    /// Estimates the cardinality with an empirical confidence interval.
+    ///
+    /// The registers are split into up to 64 groups, and `n_resamples`
+    /// times as many groups are drawn with replacement and estimated with
+    /// [`EstimatorKind::ErtlImproved`]. The standard error and the 95%
+    /// confidence interval are the standard deviation and the 2.5% and 97.5%
+    /// percentiles of these estimates. Unlike
+    /// [`EstimateWithError::estimate_with_error`], this does not assume an
+    /// ideal hash function, so a hasher that spreads the items unevenly over
+    /// the registers shows up as a wider interval.
+    ///
+    /// The resampling is deterministic, so repeated calls return the same
+    /// interval.
+    ///
+    /// Panics if `n_resamples` is 0.
+    pub fn estimate_with_bootstrap(&self, n_resamples: usize) -> Estimate {
+        assert!(n_resamples > 0, "bootstrap needs at least one resample");
+
+        let num_groups = 1 << self.size.min(BOOTSTRAP_GROUP_BITS);
+        let group_size = (1 << self.size) / num_groups;
+        let histogram_len = 65 - self.size;
+        let groups: Vec<Vec<u64>> = self
+            .register_values()
+            .chunks(group_size)
+            .map(|group| {
+                let mut histogram = vec![0u64; histogram_len];
+                for &reg in group {
+                    histogram[reg as usize] += 1;
+                }
+                histogram
+            })
+            .collect();
+
+        // SplitMix64 with a fixed seed
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next_group = || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            ((z ^ (z >> 31)) % num_groups as u64) as usize
+        };
+
+        let mut estimates: Vec<f64> = (0..n_resamples)
+            .map(|_| {
+                let mut histogram = vec![0u64; histogram_len];
+                for _ in 0..num_groups {
+                    for (count, &group_count) in histogram.iter_mut().zip(&groups[next_group()]) {
+                        *count += group_count;
+                    }
+                }
+                ertl_improved_estimate(self.size, &histogram)
+            })
+            .collect();
+        estimates.sort_unstable_by(f64::total_cmp);
+
+        let mean = estimates.iter().sum::<f64>() / n_resamples as f64;
+        let variance = estimates
+            .iter()
+            .map(|estimate| (estimate - mean).powi(2))
+            .sum::<f64>()
+            / n_resamples as f64;
+        let percentile = |q: f64| estimates[((n_resamples - 1) as f64 * q).round() as usize];
+
+        Estimate {
+            value: self.estimate(),
+            std_error: variance.sqrt(),
+            ci95: (percentile(0.025), percentile(0.975)),
+        }
+    }
+
+    // Returns the precision and the register value histogram. Sparse counters
+    // report the histogram at the sparse precision.
+    fn histogram(&self) -> (usize, Vec<u64>) {
+        match &self.sparse {
+            Some(sparse) => {
+                let mut histogram = vec![0u64; 65 - SPARSE_PRECISION];
+                histogram[0] = (1u64 << SPARSE_PRECISION) - sparse.len() as u64;
+                for &entry in sparse {
+                    histogram[(entry & ((1 << SPARSE_RHO_BITS) - 1)) as usize] += 1;
+                }
+                (SPARSE_PRECISION, histogram)
+            }
+            None => {
+                let mut histogram = vec![0u64; 65 - self.size];
+                for reg in self.registers.iter() {
+                    histogram[reg as usize] += 1;
+                }
+                (self.size, histogram)
+            }
+        }
+    }
+}
+
+impl<S: BuildHasher + Default + Clone, R: Registers + Clone> HLLCounter<S, R> {
+    /// Estimates the cardinality of the union of both counters.
+    ///
+    /// Fails if the counters cannot be merged, see [`MergeableCounter::merge`].
+    pub fn union_estimate(&self, other: &Self) -> Result<f64, MergeError> {
+        let mut union = self.clone();
+        union.merge(other)?;
+        Ok(union.estimate())
+    }
+
+    /// Estimates the cardinality of the intersection of both counters using
+    /// the inclusion-exclusion principle, clamped at zero.
+    ///
+    /// Panics if the counters cannot be merged, see [`Self::union_estimate`].
+    pub fn intersection_estimate(&self, other: &Self) -> f64 {
+        let intersection = self.estimate() + other.estimate() - self.expect_union(other);
+        intersection.max(0.0)
+    }
+
+    /// Estimates the Jaccard index |A ∩ B| / |A ∪ B| of both counters.
+    ///
+    /// Returns 0 if both counters are empty. Panics if the counters cannot
+    /// be merged, see [`Self::union_estimate`].
+    pub fn jaccard(&self, other: &Self) -> f64 {
+        let union = self.expect_union(other);
+        if union == 0.0 {
+            return 0.0;
+        }
+        let intersection = (self.estimate() + other.estimate() - union).max(0.0);
+        f64::clamp(intersection / union, 0.0, 1.0)
+    }
+
+    /// Estimates the containment |A ∩ B| / |A| of `self` in `other`, which
+    /// unlike the Jaccard index stays high for a small genome contained in a
+    /// large one.
+    ///
+    /// Returns 0 if `self` is empty. Panics if the counters cannot be merged,
+    /// see [`Self::union_estimate`].
+    pub fn containment(&self, other: &Self) -> f64 {
+        let estimate = self.estimate();
+        if estimate == 0.0 {
+            return 0.0;
+        }
+        f64::clamp(self.intersection_estimate(other) / estimate, 0.0, 1.0)
+    }
+
+    fn expect_union(&self, other: &Self) -> f64 {
+        self.union_estimate(other).unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
+// Checks the sparse entries of a serialized sketch: sorted by distinct
+// indices at SPARSE_PRECISION, with rho values that hashing can produce, so
+// folding and the histogram stay in bounds.
+fn check_sparse_entries(entries: &[u32]) -> io::Result<()> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let max_rho = 64 - SPARSE_PRECISION as u32;
+    for &entry in entries {
+        if entry >> SPARSE_RHO_BITS >= 1 << SPARSE_PRECISION {
+            return Err(invalid("Sparse HLL entry index out of range."));
+        }
+        if !(1..=max_rho).contains(&(entry & ((1 << SPARSE_RHO_BITS) - 1))) {
+            return Err(invalid("Sparse HLL entry value out of range."));
+        }
+    }
+    if !entries
+        .windows(2)
+        .all(|w| w[0] >> SPARSE_RHO_BITS < w[1] >> SPARSE_RHO_BITS)
+    {
+        return Err(invalid("Sparse HLL entries are not sorted."));
+    }
+    Ok(())
+}
+
+// Checks the hasher fingerprint stored in a serialized sketch against the
+// hasher it is read with.
+fn check_stored_fingerprint<S: BuildHasher>(stored: u64, hasher: &S) -> io::Result<()> {
+    if stored != hasher_fingerprint(hasher) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "HLL sketch was written with a different hash function or seed.",
+        ));
+    }
+    Ok(())
+}
+
+// Appends `value` in LEB128, 7 bits per byte with the high bit set on all
+// but the last byte.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// Reads a varint written by `write_varint` from the start of `data`.
+fn read_varint(data: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data
+            .split_first()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Varint longer than 64 bits.",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_batch_add_matches_single_add() {
+        for size in [10, 16] {
+            let mut single = HLLCounter::<Xxh64Builder>::new(size);
+            let mut batched = HLLCounter::<Xxh64Builder>::new(size);
+            let mut batched_bytes = HLLCounter::<Xxh64Builder>::new(size);
+
+            let items: Vec<u64> = (0..20_003).collect();
+            let byte_items: Vec<[u8; 8]> = items.iter().map(|i| i.to_le_bytes()).collect();
+            for &item in &items {
+                single.add_u64(item);
+            }
+            batched.add_u64_batch(&items);
+            batched_bytes.add_batch(&byte_items);
+
+            assert_eq!(single.register_values(), batched.register_values());
+            assert_eq!(single.estimate(), batched.estimate());
+            // add_batch hashes the bytes like add
+            let mut single_bytes = HLLCounter::<Xxh64Builder>::new(size);
+            for item in &byte_items {
+                single_bytes.add(item);
+            }
+            assert_eq!(
+                single_bytes.register_values(),
+                batched_bytes.register_values()
+            );
+        }
+    }
+
+    #[test]
+    fn test_sparse_to_dense_conversion() {
+        let mut counter = HLLCounter::<Xxh64Builder>::new(10);
+        assert!(counter.is_sparse());
+
+        for i in 0..100u64 {
+            counter.add_u64(i);
+        }
+        assert!(counter.is_sparse());
+        let sparse_estimate = counter.estimate();
+        assert!((sparse_estimate - 100.0).abs() < 1.0);
+
+        for i in 100..10_000u64 {
+            counter.add_u64(i);
+        }
+        assert!(!counter.is_sparse());
+        let estimate = counter.estimate();
+        assert!((estimate - 10_000.0).abs() / 10_000.0 < 0.1);
+    }
+
+    #[test]
+    fn test_high_precision_sparse_list_is_capped() {
+        // A quarter of the 2^20 registers would be inserted into the sorted
+        // sparse list one by one
+        let mut counter = HLLCounter::<Xxh64Builder>::new(20);
+        for i in 0..MAX_SPARSE_ENTRIES as u64 {
+            counter.add_u64(i);
+        }
+        assert!(counter.is_sparse());
+        for i in MAX_SPARSE_ENTRIES as u64..200_000 {
+            counter.add_u64(i);
+        }
+        assert!(!counter.is_sparse());
+        let estimate = counter.estimate();
+        assert!((estimate - 200_000.0).abs() / 200_000.0 < 0.01);
+    }
+
+    #[test]
+    fn test_sparse_fold_matches_dense() {
+        let mut sparse = HLLCounter::<Xxh64Builder>::new(8);
+        let mut dense = HLLCounter::<Xxh64Builder>::new(8);
+        dense.to_dense();
+
+        for i in 0..50u64 {
+            sparse.add_u64(i);
+            dense.add_u64(i);
+        }
+        assert!(sparse.is_sparse());
+        sparse.to_dense();
+        assert_eq!(sparse.registers, dense.registers);
+    }
+
+    #[test]
+    fn test_register_introspection() {
+        let mut counter = HLLCounter::<Xxh64Builder>::new(10);
+        assert_eq!(counter.max_rho(), 0);
+        assert_eq!(counter.zero_registers(), 1024);
+        assert_eq!(counter.register_histogram()[0], 1024);
+
+        for n in [100u64, 100_000] {
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let histogram = counter.register_histogram();
+            assert_eq!(histogram.iter().sum::<u64>(), 1024);
+            assert_eq!(histogram[0] as usize, counter.zero_registers());
+            let max = histogram.iter().rposition(|&count| count > 0).unwrap();
+            assert_eq!(counter.max_rho() as usize, max);
+            let registers = counter.register_values();
+            assert_eq!(
+                counter.zero_registers(),
+                registers.iter().filter(|&reg| reg == 0).count()
+            );
+        }
+        assert!(!counter.is_sparse());
+        // A register holds rho with probability e^(-l 2^-rho) - e^(-l 2^(1-rho))
+        // for l = n / m items per register
+        let histogram = counter.register_histogram();
+        let items_per_register = 100_000.0 / 1024.0;
+        for rho in 4..=10 {
+            let p = (-items_per_register / 2f64.powi(rho)).exp()
+                - (-items_per_register / 2f64.powi(rho - 1)).exp();
+            let expected = 1024.0 * p;
+            let observed = histogram[rho as usize] as f64;
+            assert!(
+                (observed - expected).abs() < 5.0 * expected.sqrt(),
+                "rho {rho}: {observed} registers, expected {expected:.0}"
+            );
+        }
+        assert_eq!(
+            counter.memory_bytes(),
+            std::mem::size_of_val(&counter) + 1024
+        );
+    }
+
+    #[test]
+    fn test_merge_sparse_and_dense() {
+        let mut a = HLLCounter::<Xxh64Builder>::new(12);
+        let mut b = HLLCounter::<Xxh64Builder>::new(12);
+        let mut all = HLLCounter::<Xxh64Builder>::new(12);
+        for i in 0..20_000u64 {
+            a.add_u64(i);
+            all.add_u64(i);
+        }
+        for i in 20_000..20_050u64 {
+            b.add_u64(i);
+            all.add_u64(i);
+        }
+        assert!(!a.is_sparse());
+        assert!(b.is_sparse());
+
+        a.merge(&b).unwrap();
+        assert_eq!(a.registers, all.registers);
+    }
+
+    #[test]
+    fn test_fold_to_lower_precision() {
+        for n in [50u64, 100_000] {
+            let mut high = HLLCounter::<Xxh64Builder>::new(16);
+            let mut low = HLLCounter::<Xxh64Builder>::new(12);
+            for i in 0..n {
+                high.add_u64(i);
+                low.add_u64(i);
+            }
+            let folded = high.fold_to(12).unwrap();
+            assert_eq!(folded.precision(), 12);
+            assert_eq!(folded.is_sparse(), low.is_sparse());
+            assert_eq!(folded.register_values(), low.register_values());
+            assert!(folded.is_compatible(&low));
+        }
+
+        let mut long_lived = HLLCounter::<Xxh64Builder>::new(12);
+        let mut chunk = HLLCounter::<Xxh64Builder>::new(16);
+        for i in 0..10_000u64 {
+            long_lived.add_u64(i);
+            chunk.add_u64(i + 10_000);
+        }
+        long_lived.merge(&chunk.fold_to(12).unwrap()).unwrap();
+        assert!((long_lived.estimate() - 20_000.0).abs() / 20_000.0 < 0.05);
+
+        assert_eq!(
+            long_lived.fold_to(14).err(),
+            Some(BuildError::SizeOutOfRange {
+                parameter: "precision",
+                value: 14,
+                min: 4,
+                max: 12
+            })
+        );
+    }
+
+    #[test]
+    fn test_bias_corrected_intermediate_range() {
+        // Around 2.5m the raw estimate is biased upwards by several percent.
+        let n = 2_500u64;
+        let trials = 50u64;
+        let mut mean = 0.0;
+        for t in 0..trials {
+            let mut counter = HLLCounter::<Xxh64Builder>::new(10);
+            counter.to_dense();
+            for i in 0..n {
+                counter.add_u64(t * n + i);
+            }
+            mean += counter.estimate() / trials as f64;
+        }
+        assert!((mean - n as f64).abs() / (n as f64) < 0.01);
+    }
+
+    #[test]
+    fn test_ertl_estimators() {
+        for n in [0u64, 10, 1_000, 100_000] {
+            let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            for kind in [
+                EstimatorKind::ErtlImproved,
+                EstimatorKind::ErtlMle,
+                EstimatorKind::LogLogBeta,
+            ] {
+                let estimate = counter.estimate_with(kind);
+                assert!(
+                    (estimate - n as f64).abs() <= 0.05 * n as f64 + 1.0,
+                    "{kind:?} estimated {estimate} for {n}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_loglog_beta_precisions() {
+        for precision in 4..=18 {
+            let m = 1u64 << precision;
+            // Four standard errors
+            let tolerance = 4.0 * 1.04 / (m as f64).sqrt();
+            for n in [m / 2, 4 * m] {
+                let mut counter = HLLCounter::<Xxh64Builder>::new(precision);
+                counter.to_dense();
+                for i in 0..n {
+                    counter.add_u64(i);
+                }
+                let estimate = counter.estimate_with(EstimatorKind::LogLogBeta);
+                assert!(
+                    (estimate - n as f64).abs() <= tolerance * n as f64 + 2.0,
+                    "precision {precision} estimated {estimate} for {n}"
+                );
+            }
+        }
+
+        // No coefficients are published above precision 18
+        let mut counter = HLLCounter::<Xxh64Builder>::new(20);
+        for i in 0..10_000 {
+            counter.add_u64(i);
+        }
+        assert_eq!(
+            counter.estimate_with(EstimatorKind::LogLogBeta),
+            counter.estimate()
+        );
+    }
+
+    #[test]
+    fn test_clear_and_reuse() {
+        fn check<R: Registers>(dense: bool) {
+            let fresh = || {
+                let mut counter = HLLCounter::<Xxh64Builder, R>::new(12);
+                if dense {
+                    counter.to_dense();
+                }
+                counter
+            };
+            // Few enough items to keep a sparse counter sparse
+            let items = if dense { 50_000 } else { 500 };
+            let mut counter = fresh();
+            for i in 0..items {
+                counter.add_u64(i);
+            }
+            counter.clear();
+            assert_eq!(counter.estimate(), 0.0);
+            assert!(counter.register_values().iter().all(|reg| reg == 0));
+            assert_eq!(counter.is_sparse(), !dense);
+
+            let mut expected = fresh();
+            for i in 100_000..100_300u64 {
+                counter.add_u64(i);
+                expected.add_u64(i);
+            }
+            assert_eq!(counter.estimate(), expected.estimate());
+            assert_eq!(counter.register_values(), expected.register_values());
+        }
+        check::<Vec<u8>>(false);
+        check::<Vec<u8>>(true);
+        check::<PackedRegisters>(true);
+        check::<[u8; 4096]>(true);
+    }
+
+    #[test]
+    fn test_estimate_added_since_snapshot() {
+        let mut counter = HLLCounter::<Xxh64Builder>::new(14);
+        let mut snapshot = counter.snapshot();
+        assert_eq!(snapshot.estimate(), 0.0);
+        // Chromosomes of 10k, 50k and 200k new items, each repeating the
+        // items of the previous ones
+        let mut end = 0u64;
+        for new_items in [10_000u64, 50_000, 200_000] {
+            for i in 0..end + new_items {
+                counter.add_u64(i);
+            }
+            end += new_items;
+            let added = counter.estimate_added_since(&snapshot).unwrap();
+            assert!(
+                (added - new_items as f64).abs() < 0.03 * new_items as f64,
+                "estimated {added} new items of {new_items}"
+            );
+            snapshot = counter.snapshot();
+        }
+        assert_eq!(counter.estimate_added_since(&snapshot), Ok(0.0));
+
+        // Few new items of a large sketch, far below the error of its estimate
+        for i in end..end + 5_000 {
+            counter.add_u64(i);
+        }
+        let added = counter.estimate_added_since(&snapshot).unwrap();
+        assert!(
+            (added - 5_000.0).abs() < 0.15 * 5_000.0,
+            "estimated {added} new items of 5000"
+        );
+
+        let other = HLLCounter::<Xxh64Builder>::new(12);
+        assert!(other.estimate_added_since(&snapshot).is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_estimate() {
+        let n = 100_000;
+        let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+        for i in 0..n {
+            counter.add_u64(i);
+        }
+        let estimate = counter.estimate_with_bootstrap(200);
+        let expected_error = counter.estimate_with_error().std_error;
+        assert_eq!(estimate.value, counter.estimate());
+        assert!(estimate.ci95.0 < n as f64 && estimate.ci95.1 > n as f64);
+        assert!(
+            (0.5..2.0).contains(&(estimate.std_error / expected_error)),
+            "bootstrap error {} vs {expected_error}",
+            estimate.std_error
+        );
+        assert_eq!(counter.estimate_with_bootstrap(200), estimate);
+    }
+
+    #[test]
+    fn test_add_item() {
+        #[derive(Hash)]
+        struct Read<'a> {
+            name: &'a str,
+            position: u32,
+        }
+
+        let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+        for position in 0..1_000 {
+            for name in ["read1", "read2"] {
+                counter.add_item(&Read { name, position });
+            }
+        }
+        assert!((counter.estimate() - 2_000.0).abs() < 40.0);
+
+        // Bytes and u64s are hashed the same way by add and add_u64
+        let mut items = HLLCounter::<Xxh64Builder>::new(12);
+        let mut raw = HLLCounter::<Xxh64Builder>::new(12);
+        for i in 0..1_000u64 {
+            items.add_item(&i);
+            items.add_item(format!("item{i}").as_bytes());
+            raw.add_u64(i);
+            raw.add(format!("item{i}").as_bytes());
+        }
+        assert_eq!(items.register_values(), raw.register_values());
+    }
+
+    #[test]
+    fn test_seeded_hasher() {
+        let counter_with_seed = |seed| {
+            let mut counter: HLLCounter<_> = HLLCounter::with_hasher(12, Xxh64Builder::new(seed));
+            for i in 0..10_000u64 {
+                counter.add_u64(i);
+            }
+            counter
+        };
+        assert_eq!(
+            counter_with_seed(42).register_values(),
+            counter_with_seed(42).register_values()
+        );
+        assert_ne!(
+            counter_with_seed(42).register_values(),
+            counter_with_seed(7).register_values()
+        );
+        // Xxh64Builder::default() uses seed 0
+        assert_eq!(
+            counter_with_seed(0).register_values(),
+            {
+                let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+                for i in 0..10_000u64 {
+                    counter.add_u64(i);
+                }
+                counter
+            }
+            .register_values()
+        );
+    }
+
+    #[test]
+    fn test_builder_validates_precision() {
+        let counter = HLLCounter::<Xxh64Builder>::builder()
+            .precision(12)
+            .hasher(Xxh64Builder::new(7))
+            .build()
+            .unwrap();
+        assert_eq!(counter.precision(), 12);
+        assert_eq!(
+            counter.fingerprint,
+            HLLCounter::<Xxh64Builder>::with_hasher(12, Xxh64Builder::new(7)).fingerprint
+        );
+
+        let error = HLLCounter::<Xxh64Builder>::builder()
+            .precision(64)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            BuildError::SizeOutOfRange {
+                parameter: "precision",
+                value: 64,
+                min: 4,
+                max: 24
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "The precision must be between 4 and 24, got 64"
+        );
+        assert!(matches!(
+            HLLCounter::<Xxh64Builder>::builder().build(),
+            Err(BuildError::MissingSize)
+        ));
+        assert!(matches!(
+            crate::LinearCounter::<Xxh64Builder>::builder()
+                .size(0)
+                .build(),
+            Err(BuildError::SizeOutOfRange { min: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_checks_hasher_and_precision() {
+        let mut a = HLLCounter::<Xxh64Builder>::with_hasher(12, Xxh64Builder::new(1));
+        let same_seed = HLLCounter::<Xxh64Builder>::with_hasher(12, Xxh64Builder::new(1));
+        let other_seed = HLLCounter::<Xxh64Builder>::with_hasher(12, Xxh64Builder::new(2));
+        let other_precision = HLLCounter::<Xxh64Builder>::with_hasher(10, Xxh64Builder::new(1));
+        assert!(a.is_compatible(&same_seed));
+        assert!(!a.is_compatible(&other_seed) && !a.is_compatible(&other_precision));
+        assert_eq!(other_precision.num_registers(), 1024);
+        assert_eq!(a.merge(&same_seed), Ok(()));
+        assert_eq!(a.merge(&other_seed), Err(MergeError::HasherMismatch));
+        assert_eq!(
+            a.merge(&other_precision),
+            Err(MergeError::SizeMismatch {
+                left: 12,
+                right: 10
+            })
+        );
+
+        // Every RandomState has its own keys
+        let mut random = HLLCounter::<RandomState>::new(12);
+        let other_random = HLLCounter::<RandomState>::new(12);
+        assert_eq!(random.merge(&other_random), Err(MergeError::HasherMismatch));
+    }
+
+    #[test]
+    fn test_jaccard() {
+        let mut a = HLLCounter::<Xxh64Builder>::new(14);
+        let mut b = HLLCounter::<Xxh64Builder>::new(14);
+        // |A| = |B| = 100k, |A ∩ B| = 50k, |A ∪ B| = 150k
+        for i in 0..100_000u64 {
+            a.add_u64(i);
+            b.add_u64(i + 50_000);
+        }
+
+        let union = a.union_estimate(&b).unwrap();
+        let intersection = a.intersection_estimate(&b);
+        assert!((union - 150_000.0).abs() / 150_000.0 < 0.03);
+        assert!((intersection - 50_000.0).abs() / 50_000.0 < 0.1);
+        assert!((a.jaccard(&b) - 1.0 / 3.0).abs() < 0.03);
+        assert!((a.jaccard(&a) - 1.0).abs() < 1e-9);
+
+        // Every RandomState has its own keys
+        let random = HLLCounter::<RandomState>::new(14);
+        assert_eq!(
+            random.union_estimate(&HLLCounter::new(14)),
+            Err(MergeError::HasherMismatch)
+        );
+    }
+
+    #[test]
+    fn test_packed_registers_match_bytes() {
+        let mut bytes = HLLCounter::<Xxh64Builder>::new(10);
+        let mut packed = PackedHLLCounter::<Xxh64Builder>::new(10);
+        for i in 0..50_000u64 {
+            bytes.add_u64(i);
+            packed.add_u64(i);
+        }
+        assert!(bytes.registers.iter().eq(packed.registers.iter()));
+        assert_eq!(bytes.estimate(), packed.estimate());
+        assert_eq!(packed.size_in_bytes(), 1024 * 6 / 8 + 1);
+    }
+
+    #[test]
+    fn test_array_registers_are_dense() {
+        let mut array = HLLCounter::<Xxh64Builder, [u8; 4096]>::new(12);
+        let mut bytes = HLLCounter::<Xxh64Builder>::new(12);
+        bytes.to_dense();
+        assert!(!array.is_sparse());
+        for i in 0..1000u64 {
+            array.add_u64(i);
+            bytes.add_u64(i);
+        }
+        assert_eq!(array.estimate(), bytes.estimate());
+        array.clear();
+        assert_eq!(array.estimate(), 0.0);
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        for n in [10u64, 10_000] {
+            let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let mut bytes = Vec::new();
+            counter.write_to(&mut bytes).unwrap();
+
+            let restored = HLLCounter::<Xxh64Builder>::read_from(bytes.as_slice()).unwrap();
+            assert_eq!(restored.is_sparse(), counter.is_sparse());
+            assert_eq!(restored.estimate(), counter.estimate());
+        }
+        assert!(HLLCounter::<Xxh64Builder>::read_from(&b"nope"[..]).is_err());
+        // Sparse length of 2^32 - 1 entries without the entries
+        let huge = b"HLLR\x01\x0e\x01\xff\xff\xff\xff";
+        let err = HLLCounter::<Xxh64Builder>::read_from(&huge[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let truncated = b"HLLR\x01\x0e\x01\x00\x00\x00\x01";
+        let err = HLLCounter::<Xxh64Builder>::read_from(&truncated[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_corrupt_sparse_entries_are_rejected() {
+        // Version 1 sketches of precision 12 with one sparse entry
+        let sketch = |entries: &[u32]| {
+            let mut bytes = b"HLLR\x01\x0c\x01".to_vec();
+            bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+            for entry in entries {
+                bytes.extend_from_slice(&entry.to_le_bytes());
+            }
+            HLLCounter::<Xxh64Builder>::read_from(bytes.as_slice())
+        };
+        let valid = sketch(&[(5 << SPARSE_RHO_BITS) | 39]).unwrap();
+        assert_eq!(valid.estimate_with(EstimatorKind::ErtlMle).round(), 1.0);
+
+        for entries in [
+            // rho of 0 and above 64 - SPARSE_PRECISION
+            vec![5 << SPARSE_RHO_BITS],
+            vec![(5 << SPARSE_RHO_BITS) | 40],
+            vec![(5 << SPARSE_RHO_BITS) | 63],
+            // Index beyond SPARSE_PRECISION bits
+            vec![(1 << (SPARSE_PRECISION as u32 + SPARSE_RHO_BITS)) | 1],
+            // Unsorted and repeated indices
+            vec![(6 << SPARSE_RHO_BITS) | 1, (5 << SPARSE_RHO_BITS) | 1],
+            vec![(5 << SPARSE_RHO_BITS) | 1, (5 << SPARSE_RHO_BITS) | 2],
+        ] {
+            let err = sketch(&entries).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{entries:?}");
+        }
+    }
+
+    #[test]
+    fn test_serialized_hasher_is_checked() {
+        let seeded = || Xxh64Builder::new(42);
+        for n in [10u64, 10_000] {
+            let mut counter = HLLCounter::<Xxh64Builder>::with_hasher(12, seeded());
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let mut bytes = Vec::new();
+            counter.write_to(&mut bytes).unwrap();
+            let compressed = counter.to_compressed_bytes();
+
+            let restored: [HLLCounter<Xxh64Builder>; 2] = [
+                HLLCounter::read_from_with_hasher(bytes.as_slice(), seeded()).unwrap(),
+                HLLCounter::from_compressed_bytes_with_hasher(&compressed, seeded()).unwrap(),
+            ];
+            for mut restored in restored {
+                let mut live = HLLCounter::with_hasher(12, seeded());
+                live.add_u64(n);
+                assert!(live.merge(&restored).is_ok());
+                // Items added after reading hash with the seed they were
+                // counted with
+                restored.add_u64(0);
+                assert_eq!(restored.estimate(), counter.estimate());
+            }
+
+            let err = HLLCounter::<Xxh64Builder>::read_from(bytes.as_slice())
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let err = HLLCounter::<Xxh64Builder>::from_compressed_bytes(&compressed)
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        // Sketches of the first version have no fingerprint
+        let legacy = b"HLLR\x01\x0c\x01\x01\x00\x00\x00\x41\x00\x00\x00";
+        let restored =
+            HLLCounter::<Xxh64Builder>::read_from_with_hasher(&legacy[..], seeded()).unwrap();
+        assert_eq!(restored.estimate().round(), 1.0);
+    }
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        for (n, dense, min_ratio) in [
+            (0u64, false, 1.0),
+            (1_000, false, 1.1),
+            (1_000, true, 5.0),
+            (1_000_000, true, 1.0),
+        ] {
+            let mut counter = HLLCounter::<Xxh64Builder>::new(14);
+            if dense {
+                counter.to_dense();
+            }
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let mut plain = Vec::new();
+            counter.write_to(&mut plain).unwrap();
+            let bytes = counter.to_compressed_bytes();
+            let ratio = plain.len() as f64 / bytes.len() as f64;
+            assert!(ratio >= min_ratio, "{n} items: compressed {ratio:.2}x");
+
+            let restored = HLLCounter::<Xxh64Builder>::from_compressed_bytes(&bytes).unwrap();
+            assert_eq!(restored.is_sparse(), counter.is_sparse());
+            assert!(restored.register_values() == counter.register_values());
+            assert_eq!(restored.estimate(), counter.estimate());
+            for len in [0, 6, bytes.len() - 1] {
+                assert!(HLLCounter::<Xxh64Builder>::from_compressed_bytes(&bytes[..len]).is_err());
+            }
+        }
+        assert!(
+            HLLCounter::<Xxh64Builder>::from_compressed_bytes(b"HLLZ\x01\x0e\x00\xff").is_err()
+        );
+        // Sparse entries of precision 12 sketches, as varint deltas
+        let sparse = |deltas: &[u8]| {
+            let mut bytes = b"HLLZ\x01\x0c\x01".to_vec();
+            bytes.push(deltas.len() as u8);
+            bytes.extend_from_slice(deltas);
+            HLLCounter::<Xxh64Builder>::from_compressed_bytes(&bytes)
+        };
+        // One entry of index 1 and rho 39
+        let valid = sparse(&[0x67]).unwrap();
+        assert_eq!(valid.estimate_with(EstimatorKind::ErtlMle).round(), 1.0);
+        // rho of 0 and 40, and a repeated index
+        for deltas in [&[0x40][..], &[0x68], &[0x41, 0x01]] {
+            let err = sparse(deltas).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{deltas:?}");
+        }
+        // Sparse length of 2^25 entries without the entries
+        let err =
+            HLLCounter::<Xxh64Builder>::from_compressed_bytes(b"HLLZ\x01\x0e\x01\x80\x80\x80\x10")
+                .err()
+                .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_redis_roundtrip() {
+        use crate::counters::RedisHashBuilder;
+        use std::hash::BuildHasher;
+
+        // Reference values of Redis' MurmurHash64A
+        let hasher = RedisHashBuilder::default();
+        assert_eq!(hasher.hash_one(&b"abc"[..]), 0x77ec90aeb374e502);
+        assert_eq!(hasher.hash_one(&b"hello world"[..]), 0xa919bc3051f624b7);
+
+        let mut counter = HLLCounter::<RedisHashBuilder>::new(REDIS_PRECISION);
+        for i in 0..50_000u64 {
+            counter.add(i.to_string().as_bytes());
+        }
+        let bytes = counter.to_redis_bytes().unwrap();
+        assert_eq!(bytes.len(), REDIS_DENSE_SIZE);
+        let restored = HLLCounter::<RedisHashBuilder>::from_redis_bytes(&bytes).unwrap();
+        assert_eq!(restored.estimate(), counter.estimate());
+
+        // `PFADD key a` in the sparse encoding: "a" sets register 12711 to 2
+        let mut sparse = b"HYLL\x01\0\0\0\x01\0\0\0\0\0\0\0".to_vec();
+        sparse.extend_from_slice(&[0x71, 0xA6, 0x84, 0x4E, 0x57]);
+        let restored = HLLCounter::<RedisHashBuilder>::from_redis_bytes(&sparse).unwrap();
+        let mut counter = HLLCounter::<RedisHashBuilder>::new(REDIS_PRECISION);
+        counter.add(b"a");
+        assert_eq!(
+            restored.to_redis_bytes().unwrap(),
+            counter.to_redis_bytes().unwrap()
+        );
+
+        assert!(
+            HLLCounter::<RedisHashBuilder>::new(12)
+                .to_redis_bytes()
+                .is_err()
+        );
+        assert!(HLLCounter::<RedisHashBuilder>::from_redis_bytes(&sparse[..18]).is_err());
     }
 }
@@ -0,0 +1,171 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::estimators::{alpha, dense_estimate};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, HLLCounter, MergeError, MergeableCounter, Registers,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// HyperLogLog counter whose precision `P` is known at compile time.
+///
+/// The register count and the index mask are constants, so the compiler can
+/// fold them into `add`. The counter is always dense. With `[u8; 1 << P]`
+/// registers (see [`StackHLLCounter`]) it does not allocate at all, which
+/// suits small per-chunk sketches that are merged afterwards.
+///
+/// Registers and estimates are identical to an [`HLLCounter`] of the same
+/// precision and hasher.
+#[derive(Clone)]
+pub struct HLLCounterConst<const P: usize, S = RandomState, R = Vec<u8>> {
+    registers: R,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+/// [`HLLCounterConst`] with its `M = 1 << P` registers stored inline.
+pub type StackHLLCounter<const P: usize, const M: usize, S = RandomState> =
+    HLLCounterConst<P, S, [u8; M]>;
+
+impl<const P: usize, S, R> HLLCounterConst<P, S, R> {
+    const NUM_REGISTERS: usize = 1 << P;
+    const INDEX_MASK: u64 = (1 << P) - 1;
+    const MAX_RHO: u32 = 64 - P as u32;
+    const CHECK_PRECISION: () = assert!(P >= 4 && P <= 24, "precision must be between 4 and 24");
+}
+
+impl<const P: usize, S: BuildHasher + Default, R: Registers> HLLCounterConst<P, S, R> {
+    /// Creates an empty counter.
+    pub fn empty() -> Self {
+        Self::empty_with_hasher(S::default())
+    }
+
+    /// Creates an empty counter that hashes items with `hasher`.
+    pub fn empty_with_hasher(hasher: S) -> Self {
+        let () = Self::CHECK_PRECISION;
+        HLLCounterConst {
+            registers: R::with_len(Self::NUM_REGISTERS),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        let index = (hash & Self::INDEX_MASK) as usize;
+        let rho = std::cmp::min((hash >> P).trailing_zeros() + 1, Self::MAX_RHO) as u8;
+        self.registers.update_max(index, rho);
+    }
+
+    pub fn precision(&self) -> usize {
+        P
+    }
+
+    /// Memory used by the registers in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.registers.size_in_bytes()
+    }
+
+    /// Converts the counter to a dynamically sized [`HLLCounter`] with the
+    /// same hasher.
+    pub fn to_hll_counter(&self) -> HLLCounter<S>
+    where
+        S: Clone,
+    {
+        HLLCounter::from_dense_registers(P, self.hasher.clone(), self.registers.iter())
+    }
+}
+
+impl<const P: usize, S: BuildHasher + Default, R: Registers> Counter for HLLCounterConst<P, S, R> {
+    type Hasher = S;
+
+    /// `size` must equal the precision `P`; prefer [`HLLCounterConst::empty`].
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, P..=P)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        Self::empty_with_hasher(hasher)
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    #[inline(always)]
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        dense_estimate(P, alpha(P), &self.registers)
+    }
+
+    fn clear(&mut self) {
+        self.registers.reset();
+    }
+}
+
+impl<const P: usize, S: BuildHasher + Default, R: Registers> MergeableCounter
+    for HLLCounterConst<P, S, R>
+{
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((P, self.fingerprint), (P, other.fingerprint))?;
+        for (index, reg) in other.registers.iter().enumerate() {
+            self.registers.update_max(index, reg);
+        }
+        Ok(())
+    }
+}
+
+impl<const P: usize, S: BuildHasher + Default, R: Registers> EstimateWithError
+    for HLLCounterConst<P, S, R>
+{
+    /// 1.04 / sqrt(m) for m = 2^P registers.
+    fn relative_std_error(&self) -> f64 {
+        1.04 / (Self::NUM_REGISTERS as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_const_matches_dynamic() {
+        let mut dynamic: HLLCounter<Xxh64Builder> = HLLCounter::new(10);
+        let mut heap: HLLCounterConst<10, Xxh64Builder> = HLLCounterConst::empty();
+        let mut left: StackHLLCounter<10, 1024, Xxh64Builder> = StackHLLCounter::empty();
+        let mut right: StackHLLCounter<10, 1024, Xxh64Builder> = StackHLLCounter::empty();
+
+        for i in 0..50_000u64 {
+            dynamic.add_u64(i);
+            heap.add_u64(i);
+            if i % 2 == 0 {
+                left.add_u64(i);
+            } else {
+                right.add_u64(i);
+            }
+        }
+        left.merge(&right).unwrap();
+
+        assert_eq!(heap.estimate(), dynamic.estimate());
+        assert_eq!(left.estimate(), dynamic.estimate());
+        assert_eq!(left.to_hll_counter().estimate(), dynamic.estimate());
+        assert_eq!(left.size_in_bytes(), 1024);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_stack_length_mismatch() {
+        let _: StackHLLCounter<10, 512> = StackHLLCounter::empty();
+    }
+}
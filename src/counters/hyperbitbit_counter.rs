@@ -0,0 +1,248 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// Initial level of Sedgewick's implementation, the usual size of a
+/// [`HyperBitBit`].
+pub const DEFAULT_LEVEL: usize = 5;
+
+// Largest initial level; an item reaches level l with probability 2^-(l + 1)
+// and only the 58 hash bits above the bucket index are looked at.
+const MAX_LEVEL: usize = 56;
+
+/// HyperBitBit (Sedgewick, 2016): a counter of two machine words.
+///
+/// Hashes are routed to one of 64 buckets. `sketch` marks the buckets that
+/// received a hash with more than `level` trailing ones, `sketch2` those with
+/// more than `level + 1`. Once half of `sketch` is set, the level is raised
+/// and `sketch2` becomes the new `sketch`.
+///
+/// As `sketch2` only records the hashes since the last raise, the new
+/// `sketch` misses the items before it. Sedgewick's estimate
+/// 2^(level + 5.4 + bits / 32) is calibrated for that loss, but still off by
+/// 10-20% for larger cardinalities. Here the estimate is linear counting over
+/// the buckets of `sketch`, scaled by the probability 2^-(level + 1) of a
+/// hash reaching the level, plus the expected number of missed items.
+///
+/// With a relative error of roughly 15% it is far less accurate than
+/// [`HLLCounter`](crate::HLLCounter), but small enough to keep millions of
+/// them, e.g. one per contig or per key.
+pub struct HyperBitBit<S = RandomState> {
+    sketch: u64,
+    sketch2: u64,
+    level: u8,
+    // Level of an empty counter, compared when merging
+    initial_level: u8,
+    hasher: S,
+}
+
+impl<S: BuildHasher + Default> Counter for HyperBitBit<S> {
+    type Hasher = S;
+
+    /// Creates a counter starting at level `size`, usually
+    /// [`DEFAULT_LEVEL`]. Higher levels waste less of the range on small
+    /// cardinalities, lower ones estimate small cardinalities more accurately.
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("initial level", size, 0..=MAX_LEVEL)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        HyperBitBit {
+            sketch: 0,
+            sketch2: 0,
+            level: size as u8,
+            initial_level: size as u8,
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        let empty = 64 - self.sketch.count_ones();
+        if empty == 0 {
+            // Cannot happen after `add_hash`, only after merging
+            return f64::INFINITY;
+        }
+        let scale = (1u64 << (self.level as u32 + 1)) as f64;
+        64.0 * scale * (64.0 / empty as f64).ln() + self.missed()
+    }
+
+    fn clear(&mut self) {
+        self.sketch = 0;
+        self.sketch2 = 0;
+        self.level = self.initial_level;
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for HyperBitBit<S> {
+    /// Merges the sketches of the same level. If the levels differ, only the
+    /// buckets of the higher level are kept, as the lower counter has no
+    /// record of the hashes above its `sketch2`.
+    ///
+    /// The items missed by either counter when raising its level are not
+    /// recovered, so merged estimates are only approximate.
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (
+                self.initial_level as usize,
+                hasher_fingerprint(&self.hasher),
+            ),
+            (
+                other.initial_level as usize,
+                hasher_fingerprint(&other.hasher),
+            ),
+        )?;
+        let (high, low) = if self.level >= other.level {
+            ((self.sketch, self.sketch2, self.level), other)
+        } else {
+            ((other.sketch, other.sketch2, other.level), &*self)
+        };
+        let (mut sketch, mut sketch2, level) = high;
+        if low.level == level {
+            sketch |= low.sketch;
+            sketch2 |= low.sketch2;
+        } else if low.level + 1 == level {
+            sketch |= low.sketch2;
+        }
+        self.sketch = sketch;
+        self.sketch2 = sketch2;
+        self.level = level;
+        self.raise_level();
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for HyperBitBit<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.initial_level as usize, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for HyperBitBit<S> {
+    /// Error of linear counting over the 64 buckets, sqrt(m (e^t - t - 1)) /
+    /// (m t) for the estimated load t (Whang et al., 1990), between about 8%
+    /// and 13% once the first level is passed. The variation of the missed
+    /// items adds to it, the measured error is closer to 15%.
+    fn relative_std_error(&self) -> f64 {
+        let empty = 64 - self.sketch.count_ones();
+        if self.sketch == 0 || empty == 0 {
+            return 1.0;
+        }
+        let t = (64.0 / empty as f64).ln();
+        (64.0 * (t.exp() - t - 1.0)).sqrt() / (64.0 * t)
+    }
+}
+
+impl<S: BuildHasher + Default> HyperBitBit<S> {
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        let bucket = hash & 63;
+        let rank = (hash >> 6).trailing_ones() as u8;
+        if rank > self.level {
+            self.sketch |= 1 << bucket;
+            if rank > self.level + 1 {
+                self.sketch2 |= 1 << bucket;
+            }
+            self.raise_level();
+        }
+    }
+
+    // Moves up one level while more than half of the buckets are set
+    fn raise_level(&mut self) {
+        while self.sketch.count_ones() > 31 && (self.level as usize) < MAX_LEVEL + 1 {
+            self.sketch = self.sketch2;
+            self.sketch2 = 0;
+            self.level += 1;
+        }
+    }
+
+    // Expected number of items missing from `sketch`: the level is raised
+    // after about 128 ln 2 * 2^level items reached `sketch`, which itself
+    // lacks the items before the previous raise.
+    fn missed(&self) -> f64 {
+        let (mut before, mut last) = (0.0, 0.0);
+        for level in self.initial_level..self.level {
+            let raised = before + 128.0 * std::f64::consts::LN_2 * 2f64.powi(level as i32);
+            before = last;
+            last = raised;
+        }
+        before
+    }
+
+    /// Current level: the sketch records hashes with more than this many
+    /// trailing ones.
+    pub fn level(&self) -> usize {
+        self.level as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_hyperbitbit() {
+        assert_eq!(
+            HyperBitBit::<Xxh64Builder>::new(DEFAULT_LEVEL).estimate(),
+            0.0
+        );
+        assert!(std::mem::size_of::<HyperBitBit<RandomState>>() <= 40);
+
+        // Average the relative error over independent hash functions
+        let n = 1_000_000u64;
+        let runs = 20;
+        let mut mean_error = 0.0;
+        for seed in 0..runs {
+            let mut counter = HyperBitBit::with_hasher(DEFAULT_LEVEL, Xxh64Builder::new(seed));
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let error = counter.estimate() / n as f64 - 1.0;
+            assert!(error.abs() < 0.5, "relative error {error}");
+            mean_error += error / runs as f64;
+        }
+        assert!(mean_error.abs() < 0.1, "bias {mean_error}");
+
+        // Merging counters of disjoint halves
+        let hasher = Xxh64Builder::new(1);
+        let mut a = HyperBitBit::with_hasher(DEFAULT_LEVEL, hasher);
+        let mut b = HyperBitBit::with_hasher(DEFAULT_LEVEL, hasher);
+        for i in 0..n {
+            if i % 2 == 0 {
+                a.add_u64(i);
+            } else {
+                b.add_u64(i);
+            }
+        }
+        let half = a.estimate();
+        a.merge(&b).unwrap();
+        assert!(a.estimate() > 1.4 * half);
+        assert!((a.estimate() / n as f64 - 1.0).abs() < 0.5);
+        assert!(
+            a.merge(&HyperBitBit::with_hasher(6, Xxh64Builder::new(1)))
+                .is_err()
+        );
+
+        a.clear();
+        assert_eq!(a.estimate(), 0.0);
+        assert!(a.merge(&b).is_ok());
+    }
+}
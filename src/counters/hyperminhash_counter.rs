@@ -0,0 +1,241 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::estimators::{alpha, dense_estimate};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+// Number of min-hash bits kept next to the LogLog value of every register
+const SUBBUCKET_BITS: usize = 10;
+const SUBBUCKET_MASK: u16 = (1 << SUBBUCKET_BITS) - 1;
+
+/// HyperMinHash counter (Yu and Weber, 2017).
+///
+/// Every register stores the HyperLogLog value of its bucket together with
+/// `SUBBUCKET_BITS` bits of the smallest hash in the bucket, i.e. a
+/// compressed MinHash. Cardinalities are estimated like HyperLogLog, and the
+/// Jaccard index is estimated directly from the number of matching registers
+/// instead of by inclusion-exclusion, which stays accurate for small
+/// overlaps. Registers take 2 bytes.
+pub struct HyperMinHashCounter<S = RandomState> {
+    size: usize,
+    // (rho << SUBBUCKET_BITS) | !min-hash bits, so the larger register holds
+    // the smaller hash
+    registers: Vec<u16>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for HyperMinHashCounter<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, 4..=24)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        HyperMinHashCounter {
+            size,
+            registers: vec![0; 1 << size],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        let rhos: Vec<u8> = self.registers.iter().map(|&reg| Self::rho(reg)).collect();
+        dense_estimate(self.size, alpha(self.size), &rhos)
+    }
+
+    fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for HyperMinHashCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (reg, &other_reg) in self.registers.iter_mut().zip(&other.registers) {
+            *reg = std::cmp::max(*reg, other_reg);
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for HyperMinHashCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for HyperMinHashCounter<S> {
+    /// 1.04 / sqrt(m) for m registers, as for HyperLogLog.
+    fn relative_std_error(&self) -> f64 {
+        1.04 / ((1u64 << self.size) as f64).sqrt()
+    }
+}
+
+impl<S: BuildHasher + Default> HyperMinHashCounter<S> {
+    // The lowest bits select the register, the highest bits are the min-hash
+    // bits and the bits in between give rho.
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        let index = (hash & ((1u64 << self.size) - 1)) as usize;
+        let subbucket = (hash >> (64 - SUBBUCKET_BITS)) as u16;
+        let remainder = hash >> self.size;
+        let rho = std::cmp::min(remainder.trailing_zeros() + 1, self.max_rho() as u32) as u16;
+
+        let reg = (rho << SUBBUCKET_BITS) | (!subbucket & SUBBUCKET_MASK);
+        self.registers[index] = std::cmp::max(self.registers[index], reg);
+    }
+
+    fn max_rho(&self) -> u8 {
+        (64 - self.size - SUBBUCKET_BITS) as u8
+    }
+
+    fn rho(reg: u16) -> u8 {
+        (reg >> SUBBUCKET_BITS) as u8
+    }
+
+    pub fn precision(&self) -> usize {
+        self.size
+    }
+
+    /// Estimates the Jaccard index |A ∩ B| / |A ∪ B| of both counters.
+    ///
+    /// Counts the non-empty registers that match in both counters and
+    /// corrects for the matches expected by chance for sets of these
+    /// cardinalities. Returns 0 if both counters are empty.
+    pub fn jaccard(&self, other: &Self) -> f64 {
+        assert_eq!(self.size, other.size);
+        let (mut matching, mut occupied) = (0usize, 0usize);
+        for (&a, &b) in self.registers.iter().zip(&other.registers) {
+            if a != 0 || b != 0 {
+                occupied += 1;
+                if a == b {
+                    matching += 1;
+                }
+            }
+        }
+        if occupied == 0 {
+            return 0.0;
+        }
+
+        // A fraction c = expected / occupied of the registers that differ in
+        // their minimum also match, so matching / occupied = J + (1 - J) c.
+        let expected = self.expected_collisions(self.estimate(), other.estimate());
+        if expected >= occupied as f64 {
+            return 0.0;
+        }
+        f64::clamp(
+            (matching as f64 - expected) / (occupied as f64 - expected),
+            0.0,
+            1.0,
+        )
+    }
+
+    /// Estimates the cardinality of the union of both counters.
+    ///
+    /// Panics if the counters cannot be merged.
+    pub fn union_estimate(&self, other: &Self) -> f64 {
+        let mut union = Self::new(self.size);
+        union.fingerprint = self.fingerprint;
+        for counter in [self, other] {
+            union.merge(counter).unwrap_or_else(|e| panic!("{e}"));
+        }
+        union.estimate()
+    }
+
+    /// Estimates the cardinality of the intersection as Jaccard index times
+    /// the union cardinality.
+    pub fn intersection_estimate(&self, other: &Self) -> f64 {
+        self.jaccard(other) * self.union_estimate(other)
+    }
+
+    // Expected number of registers that match by chance for two disjoint sets
+    // with n and m items (ApproxExpectedCollisions in the paper).
+    fn expected_collisions(&self, n: f64, m: f64) -> f64 {
+        let (n, m) = if n < m { (m, n) } else { (n, m) };
+        if m < 1.0 {
+            return 0.0;
+        }
+        let p = self.size as i32;
+        let r = SUBBUCKET_BITS as i32;
+
+        if n > 2f64.powi(p + 5) {
+            // Asymptotic approximation for large sets
+            let ratio = n / m;
+            let phi = 4.0 * ratio / (1.0 + ratio).powi(2);
+            return 0.169_919_487_159_739_1 * 2f64.powi(p - r) * phi;
+        }
+
+        // Sum over all register values of the probability that both sets
+        // produce it. A register has value (i, j) if the smallest hash of its
+        // bucket lies in [b1, b2).
+        let mut x = 0.0;
+        for i in 1..=self.max_rho() as i32 {
+            let scale = 2f64.powi(-(p + r + i));
+            for j in 0..(1 << r) {
+                let b1 = ((1 << r) + j) as f64 * scale;
+                let b2 = ((1 << r) + j + 1) as f64 * scale;
+                let prob_x = (1.0 - b2).powf(n) - (1.0 - b1).powf(n);
+                let prob_y = (1.0 - b2).powf(m) - (1.0 - b1).powf(m);
+                x += prob_x * prob_y;
+            }
+        }
+        x * 2f64.powi(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    fn counter_of(items: std::ops::Range<u64>) -> HyperMinHashCounter<Xxh64Builder> {
+        let mut counter = HyperMinHashCounter::new(12);
+        for i in items {
+            counter.add_u64(i);
+        }
+        counter
+    }
+
+    #[test]
+    fn test_estimate_and_jaccard() {
+        let a = counter_of(0..20_000);
+        let b = counter_of(10_000..30_000);
+        assert!((a.estimate() - 20_000.0).abs() / 20_000.0 < 0.05);
+        assert!((a.union_estimate(&b) - 30_000.0).abs() / 30_000.0 < 0.05);
+        assert!((a.jaccard(&b) - 1.0 / 3.0).abs() < 0.03);
+        assert_eq!(a.jaccard(&a), 1.0);
+
+        // Small overlap between sets of very different size
+        let large = counter_of(0..100_000);
+        let small = counter_of(99_000..101_000);
+        let expected = 1_000.0 / 101_000.0;
+        assert!((large.jaccard(&small) - expected).abs() < 0.005);
+
+        let disjoint = counter_of(200_000..220_000);
+        assert!(a.jaccard(&disjoint) < 0.01);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::BTreeSet;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// K minimum values (bottom-k) counter.
+///
+/// Keeps the `size` smallest distinct hash values. The cardinality is estimated
+/// from the k-th smallest hash, and two sketches can be compared with an
+/// unbiased Jaccard estimate, like MinHash.
+pub struct KMVCounter<S = RandomState> {
+    size: usize,
+    // The smallest distinct hash values seen so far
+    hashes: BTreeSet<u64>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for KMVCounter<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("number of kept hashes", size, 2..=usize::MAX)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        KMVCounter {
+            size,
+            hashes: BTreeSet::new(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        if self.hashes.len() < self.size {
+            // Fewer distinct items than the sketch size: the count is exact
+            return self.hashes.len() as f64;
+        }
+
+        let kth_smallest = *self.hashes.last().unwrap();
+        // Normalize the hash to (0, 1]
+        let normalized = (kth_smallest as f64 + 1.0) / 2f64.powi(64);
+        (self.size - 1) as f64 / normalized
+    }
+
+    fn clear(&mut self) {
+        self.hashes.clear();
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for KMVCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for &hash in &other.hashes {
+            self.add_hash(hash);
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for KMVCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for KMVCounter<S> {
+    /// 1 / sqrt(k - 2) once the sketch is full, exact before.
+    fn relative_std_error(&self) -> f64 {
+        if self.hashes.len() < self.size || self.size <= 2 {
+            0.0
+        } else {
+            1.0 / ((self.size - 2) as f64).sqrt()
+        }
+    }
+}
+
+impl<S: BuildHasher + Default> KMVCounter<S> {
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        if self.hashes.len() < self.size {
+            self.hashes.insert(hash);
+        } else if hash < *self.hashes.last().unwrap() && self.hashes.insert(hash) {
+            self.hashes.pop_last();
+        }
+    }
+
+    /// Estimates the Jaccard index |A ∩ B| / |A ∪ B| of both sketches.
+    ///
+    /// Uses the bottom-k sketch of the union and counts how many of its hashes
+    /// occur in both sketches. Returns 0 if both sketches are empty.
+    pub fn jaccard(&self, other: &Self) -> f64 {
+        assert_eq!(self.size, other.size);
+        let union_bottom_k: Vec<u64> = self
+            .hashes
+            .union(&other.hashes)
+            .take(self.size)
+            .copied()
+            .collect();
+        if union_bottom_k.is_empty() {
+            return 0.0;
+        }
+
+        let shared = union_bottom_k
+            .iter()
+            .filter(|hash| self.hashes.contains(hash) && other.hashes.contains(hash))
+            .count();
+        shared as f64 / union_bottom_k.len() as f64
+    }
+
+    /// Estimates the cardinality of the union of both sketches.
+    ///
+    /// Panics if the sketches cannot be merged.
+    pub fn union_estimate(&self, other: &Self) -> f64 {
+        let mut union = Self::new(self.size);
+        union.fingerprint = self.fingerprint;
+        for counter in [self, other] {
+            union.merge(counter).unwrap_or_else(|e| panic!("{e}"));
+        }
+        union.estimate()
+    }
+
+    /// Estimates the cardinality of the intersection as Jaccard index times
+    /// the union cardinality.
+    pub fn intersection_estimate(&self, other: &Self) -> f64 {
+        self.jaccard(other) * self.union_estimate(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_kmv_estimate_and_jaccard() {
+        let mut a = KMVCounter::<Xxh64Builder>::new(1024);
+        let mut b = KMVCounter::<Xxh64Builder>::new(1024);
+        for i in 0..100u64 {
+            a.add_u64(i);
+        }
+        // Exact below the sketch size
+        assert_eq!(a.estimate(), 100.0);
+
+        for i in 100..100_000u64 {
+            a.add_u64(i);
+            b.add_u64(i + 50_000);
+        }
+        assert!((a.estimate() - 100_000.0).abs() / 100_000.0 < 0.1);
+        assert!((a.jaccard(&b) - 1.0 / 3.0).abs() < 0.05);
+        assert_eq!(a.jaccard(&a), 1.0);
+    }
+}
@@ -1,38 +1,244 @@
-use crate::counters::Counter;
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, SaturatedEstimate,
+    ShardedCounter,
+};
 use std::collections::hash_map::RandomState;
-use std::hash::BuildHasher;
+use std::hash::{BuildHasher, Hash};
+
+// Smallest expected number of unset bits after adding the expected number of
+// items. The number of unset bits is about Poisson distributed, so the bitmap
+// fills up with probability e^-5 < 1%.
+const MIN_EXPECTED_UNSET_BITS: f64 = 5.0;
 
 pub struct LinearCounter<S = RandomState> {
     bit_array: Vec<u8>,
     size: usize,
     hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
 }
 
 impl<S: BuildHasher + Default> Counter for LinearCounter<S> {
+    type Hasher = S;
+
     fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("number of bits", size, 1..=usize::MAX)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
         LinearCounter {
             bit_array: vec![0; size.div_ceil(8)],
             size,
-            hasher: S::default(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
         }
     }
 
-    fn add(&mut self, item: &[u8]) {
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
         let hash = self.hasher.hash_one(item);
 
         let index = (hash % self.size as u64) as usize;
         self.bit_array[index / 8] |= 1 << (index % 8);
     }
 
+    /// Clamped at m ln(m) once every bit is set, see
+    /// [`LinearCounter::try_estimate`].
     fn estimate(&self) -> f64 {
-        let num_unset_bits = std::cmp::max(
-            1,
-            self.bit_array
-                .iter()
-                .map(|byte| byte.count_zeros() as usize)
-                .sum::<usize>(),
-        );
+        let num_unset_bits = std::cmp::max(1, self.num_unset_bits());
 
         self.size as f64 * (self.size as f64 / num_unset_bits as f64).ln()
     }
+
+    fn clear(&mut self) {
+        self.bit_array.fill(0);
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for LinearCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (byte_self, byte_other) in self.bit_array.iter_mut().zip(other.bit_array.iter()) {
+            *byte_self |= *byte_other;
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for LinearCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for LinearCounter<S> {
+    /// sqrt(m (e^t - t - 1)) / n with load factor t = n / m (Whang et al., 1990).
+    fn relative_std_error(&self) -> f64 {
+        let n = self.estimate();
+        if n == 0.0 {
+            return 0.0;
+        }
+        let m = self.size as f64;
+        let t = n / m;
+        (m * (t.exp() - t - 1.0)).sqrt() / n
+    }
+}
+
+impl<S: BuildHasher + Default> LinearCounter<S> {
+    /// Creates a counter with the smallest bitmap that counts `expected_n`
+    /// distinct items with a relative standard error of at most
+    /// `target_error`.
+    ///
+    /// This is the computation behind the load factor tables of Whang et al.
+    /// (1990): the standard error sqrt(m (e^t - t - 1)) / n must not exceed
+    /// the target, and the bitmap must be large enough not to fill up.
+    pub fn for_cardinality(expected_n: u64, target_error: f64) -> Self {
+        Self::new(bits_for_cardinality(expected_n, target_error))
+    }
+
+    /// Number of bits of the bitmap.
+    pub fn num_bits(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` once every bit is set. The estimate is then only a
+    /// lower bound of the cardinality, and a larger bitmap is needed.
+    pub fn is_saturated(&self) -> bool {
+        self.num_unset_bits() == 0
+    }
+
+    /// Like [`Counter::estimate`], but fails with the clamped estimate as a
+    /// lower bound if the bitmap is saturated.
+    pub fn try_estimate(&self) -> Result<f64, SaturatedEstimate> {
+        if self.is_saturated() {
+            return Err(SaturatedEstimate {
+                lower_bound: self.estimate(),
+            });
+        }
+        Ok(self.estimate())
+    }
+
+    // Number of unset bits, not counting the padding of the last byte.
+    fn num_unset_bits(&self) -> usize {
+        let num_set_bits: usize = self
+            .bit_array
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum();
+        self.size - num_set_bits
+    }
+
+    /// Returns `true` if `other` can be merged into `self`: both have the
+    /// same number of bits and hash function.
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )
+        .is_ok()
+    }
+}
+
+// Smallest number of bits that meets the error target and the fill-up bound
+// for `expected_n` items. Both only improve with more bits, so the smallest
+// size is found by binary search.
+fn bits_for_cardinality(expected_n: u64, target_error: f64) -> usize {
+    assert!(
+        target_error > 0.0 && target_error < 1.0,
+        "target error must be between 0 and 1"
+    );
+    let n = expected_n.max(1) as f64;
+    let meets_target = |m: usize| {
+        let m = m as f64;
+        let t = n / m;
+        (m * (t.exp() - t - 1.0)).sqrt() / n <= target_error
+            && m * (-t).exp() >= MIN_EXPECTED_UNSET_BITS
+    };
+
+    let mut high = expected_n.max(1) as usize;
+    while !meets_target(high) {
+        high *= 2;
+    }
+    let mut low = 1;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if meets_target(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_compatibility() {
+        let mut a = LinearCounter::with_hasher(1000, Xxh64Builder::new(1));
+        let b = LinearCounter::with_hasher(1000, Xxh64Builder::new(1));
+        assert_eq!(a.num_bits(), 1000);
+        assert!(a.is_compatible(&b));
+        assert!(a.merge(&b).is_ok());
+        for other in [
+            LinearCounter::with_hasher(1001, Xxh64Builder::new(1)),
+            LinearCounter::with_hasher(1000, Xxh64Builder::new(2)),
+        ] {
+            assert!(!a.is_compatible(&other));
+            assert!(a.merge(&other).is_err());
+        }
+    }
+
+    #[test]
+    fn test_for_cardinality() {
+        // The smallest size meets the target, one bit less does not
+        let bits = bits_for_cardinality(1_000_000, 0.01);
+        let error = |m: usize| {
+            let t = 1_000_000.0 / m as f64;
+            (m as f64 * (t.exp() - t - 1.0)).sqrt() / 1_000_000.0
+        };
+        assert!(error(bits) <= 0.01 && error(bits - 1) > 0.01);
+        assert!(bits < 1_000_000 / 5);
+        // Less error needs more bits
+        assert!(bits_for_cardinality(1_000_000, 0.001) > bits);
+
+        let n = 200_000u64;
+        let mut counter = LinearCounter::<Xxh64Builder>::for_cardinality(n, 0.01);
+        for i in 0..n {
+            counter.add_u64(i);
+        }
+        assert!((counter.estimate() - n as f64).abs() / (n as f64) < 0.05);
+        assert!(counter.relative_std_error() <= 0.011);
+    }
+
+    #[test]
+    fn test_saturation() {
+        // 13 bits leave 3 bits of padding in the second byte
+        let mut counter = LinearCounter::with_hasher(13, Xxh64Builder::new(1));
+        counter.add_u64(1);
+        assert!(!counter.is_saturated());
+        assert!(counter.try_estimate().is_ok());
+
+        for i in 0..1_000u64 {
+            counter.add_u64(i);
+        }
+        assert!(counter.is_saturated());
+        let lower_bound = 13.0 * 13f64.ln();
+        assert_eq!(counter.estimate(), lower_bound);
+        assert_eq!(
+            counter.try_estimate(),
+            Err(SaturatedEstimate { lower_bound })
+        );
+    }
 }
@@ -0,0 +1,181 @@
+use crate::counters::{MergeError, MergeableCounter};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Counters merged sequentially into one new counter at the leaves of
+// `MergeTree::merge_refs`
+#[cfg(feature = "parallel")]
+const LEAF_SIZE: usize = 16;
+
+/// Merges many counters pairwise in a balanced binary tree.
+///
+/// Pushed counters are combined like the digits of a binary counter: level
+/// i holds the union of 2^i counters, and two counters of the same level
+/// are merged into the next level. Only O(log n) counters are alive at any
+/// time and every counter takes part in O(log n) merges of equally sized
+/// counters, instead of folding everything into one counter whose merges
+/// grow with the input, e.g. for sparse HyperLogLog counters.
+///
+/// With the `parallel` feature, [`MergeTree::merge_all`] and
+/// [`MergeTree::merge_refs`] reduce a whole collection on the rayon thread
+/// pool.
+pub struct MergeTree<C> {
+    levels: Vec<Option<C>>,
+    len: usize,
+}
+
+impl<C: MergeableCounter> Default for MergeTree<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: MergeableCounter> MergeTree<C> {
+    pub fn new() -> Self {
+        MergeTree {
+            levels: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Adds a counter to the tree, merging it with the subtrees of equal size.
+    ///
+    /// Fails if the counter cannot be merged with the counters pushed before.
+    pub fn push(&mut self, counter: C) -> Result<(), MergeError> {
+        let mut carry = counter;
+        for level in self.levels.iter_mut() {
+            match level.take() {
+                Some(mut subtree) => {
+                    subtree.merge(&carry)?;
+                    carry = subtree;
+                }
+                None => {
+                    *level = Some(carry);
+                    self.len += 1;
+                    return Ok(());
+                }
+            }
+        }
+        self.levels.push(Some(carry));
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Number of counters pushed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Merges the remaining subtrees, from the smallest to the largest.
+    /// Returns `None` if no counter was pushed.
+    pub fn finish(self) -> Result<Option<C>, MergeError> {
+        let mut merged: Option<C> = None;
+        for subtree in self.levels.into_iter().flatten() {
+            merged = Some(match merged {
+                Some(smaller) => {
+                    let mut subtree = subtree;
+                    subtree.merge(&smaller)?;
+                    subtree
+                }
+                None => subtree,
+            });
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<C: MergeableCounter + Send + Sync> MergeTree<C> {
+    /// Merges all counters in a balanced parallel reduction, reusing the
+    /// counters themselves for the intermediate results. Returns `None` if
+    /// `counters` is empty.
+    pub fn merge_all(counters: Vec<C>) -> Result<Option<C>, MergeError> {
+        counters
+            .into_par_iter()
+            .map(Ok)
+            .try_reduce_with(|mut a, b| {
+                a.merge(&b)?;
+                Ok(a)
+            })
+            .transpose()
+    }
+
+    /// Like [`MergeTree::merge_all`], but leaves the counters untouched.
+    /// A new counter from `make_counter` is created for every group of a few
+    /// counters at the leaves of the tree.
+    pub fn merge_refs<F>(counters: &[&C], make_counter: &F) -> Result<C, MergeError>
+    where
+        F: Fn() -> C + Sync,
+    {
+        if counters.len() <= LEAF_SIZE {
+            let mut merged = make_counter();
+            for counter in counters {
+                merged.merge(counter)?;
+            }
+            return Ok(merged);
+        }
+        let (left, right) = counters.split_at(counters.len() / 2);
+        let (left, right) = rayon::join(
+            || Self::merge_refs(left, make_counter),
+            || Self::merge_refs(right, make_counter),
+        );
+        let mut merged = left?;
+        merged.merge(&right?)?;
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counter, HLLCounter};
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    fn counters(n: u64) -> Vec<HLLCounter<Xxh64Builder>> {
+        (0..n)
+            .map(|i| {
+                let mut counter = HLLCounter::new(10);
+                for item in i * 100..i * 100 + 150 {
+                    counter.add_u64(item);
+                }
+                counter
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_tree_matches_fold() {
+        let mut expected = HLLCounter::<Xxh64Builder>::new(10);
+        for counter in counters(1000) {
+            expected.merge(&counter).unwrap();
+        }
+
+        let mut tree = MergeTree::new();
+        for counter in counters(1000) {
+            tree.push(counter).unwrap();
+        }
+        assert_eq!(tree.len(), 1000);
+        let merged = tree.finish().unwrap().unwrap();
+        assert_eq!(merged.register_values(), expected.register_values());
+        assert!(MergeTree::<HLLCounter>::new().finish().unwrap().is_none());
+
+        #[cfg(feature = "parallel")]
+        {
+            let merged = MergeTree::merge_all(counters(1000)).unwrap().unwrap();
+            assert_eq!(merged.register_values(), expected.register_values());
+
+            let owned = counters(1000);
+            let refs: Vec<_> = owned.iter().collect();
+            let merged = MergeTree::merge_refs(&refs, &|| HLLCounter::new(10)).unwrap();
+            assert_eq!(merged.register_values(), expected.register_values());
+        }
+
+        let mut tree = MergeTree::new();
+        tree.push(HLLCounter::<Xxh64Builder>::new(10)).unwrap();
+        assert!(tree.push(HLLCounter::new(12)).is_err());
+    }
+}
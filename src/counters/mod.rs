@@ -1,11 +1,56 @@
+pub mod adaptive_counter;
+pub mod atomic_hll_counter;
+pub mod audit_counter;
 pub mod counter_base;
+pub mod counter_map;
+pub mod distinct_sampler;
+pub mod estimators;
+#[cfg(feature = "fs")]
+pub mod external_hash_counter;
 pub mod fm_counter;
 pub mod hash_counter;
+mod hll_bias_data;
 pub mod hll_counter;
+pub mod hll_counter_const;
+pub mod hyperbitbit_counter;
+pub mod hyperminhash_counter;
+pub mod kmv_counter;
 pub mod linear_counter;
+pub mod merge_tree;
+#[cfg(feature = "parallel")]
+pub mod par_extend;
+pub mod pcsa_counter;
+pub mod redis_hash;
+pub mod registers;
+mod simd;
+pub mod sliding_hll_counter;
+pub mod theta_sketch;
+pub mod ull_counter;
 
-pub use counter_base::Counter;
+pub use adaptive_counter::AdaptiveCounter;
+pub use atomic_hll_counter::AtomicHLLCounter;
+pub use audit_counter::AuditCounter;
+pub use counter_base::{
+    BuildError, Counter, CounterBuilder, Estimate, EstimateWithError, MergeError, MergeableCounter,
+    SaturatedEstimate, ShardedCounter,
+};
+pub use counter_map::CounterMap;
+pub use distinct_sampler::DistinctSampler;
+pub use estimators::{Estimator, HLLState};
+#[cfg(feature = "fs")]
+pub use external_hash_counter::ExternalHashCounter;
 pub use fm_counter::FMCounter;
 pub use hash_counter::HashCounter;
-pub use hll_counter::HLLCounter;
+pub use hll_counter::{EstimatorKind, HLLCounter, HLLSnapshot, PackedHLLCounter};
+pub use hll_counter_const::{HLLCounterConst, StackHLLCounter};
+pub use hyperbitbit_counter::HyperBitBit;
+pub use hyperminhash_counter::HyperMinHashCounter;
+pub use kmv_counter::KMVCounter;
 pub use linear_counter::LinearCounter;
+pub use merge_tree::MergeTree;
+pub use pcsa_counter::PCSACounter;
+pub use redis_hash::{RedisHashBuilder, RedisHasher};
+pub use registers::{PackedRegisters, Registers};
+pub use sliding_hll_counter::SlidingHLLCounter;
+pub use theta_sketch::ThetaSketch;
+pub use ull_counter::ULLCounter;
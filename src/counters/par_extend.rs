@@ -0,0 +1,156 @@
+use crate::counters::{
+    AdaptiveCounter, AuditCounter, FMCounter, HLLCounter, HashCounter, HyperBitBit,
+    HyperMinHashCounter, KMVCounter, LinearCounter, MergeableCounter, PCSACounter, Registers,
+    ShardedCounter, ThetaSketch, ULLCounter,
+};
+use rayon::iter::{IntoParallelIterator, ParallelExtend, ParallelIterator};
+use std::hash::{BuildHasher, Hash};
+
+/// Counts the items of a parallel iterator: every rayon job adds its items
+/// to its own counter created by `make_counter`, and the counters are merged.
+///
+/// Panics if the counters cannot be merged, so `make_counter` must create
+/// counters with the same hash function: `RandomState` draws new keys for
+/// every counter.
+///
+/// ```
+/// use hll_rust::counters::par_extend::par_count;
+/// use hll_rust::{Counter, HLLCounter};
+/// use rayon::prelude::*;
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let words = vec!["to", "be", "or", "not", "to", "be"];
+/// let hll = par_count(words.par_iter(), || HLLCounter::<Xxh64Builder>::new(12));
+/// assert_eq!(hll.estimate().round(), 4.0);
+/// ```
+pub fn par_count<C, F, I>(items: I, make_counter: F) -> C
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+    I: IntoParallelIterator,
+    I::Item: Hash,
+{
+    items
+        .into_par_iter()
+        .fold(&make_counter, |mut counter, item| {
+            counter.add_item(&item);
+            counter
+        })
+        .reduce(&make_counter, |mut a, b| {
+            a.merge(&b).unwrap_or_else(|e| {
+                panic!(
+                    "counters created by make_counter must be mergeable, e.g. not use RandomState: {e}"
+                )
+            });
+            a
+        })
+}
+
+// Counts the items into empty shards of `counter` and merges them into it
+fn par_extend_counter<C, I>(counter: &mut C, items: I)
+where
+    C: ShardedCounter + Send + Sync,
+    I: IntoParallelIterator,
+    I::Item: Hash,
+{
+    let template = counter.empty_shard();
+    let counted = par_count(items, || template.empty_shard());
+    counter.merge(&counted).unwrap_or_else(|e| panic!("{e}"));
+}
+
+// `ParallelExtend` is implemented per counter, as the orphan rule forbids a
+// blanket implementation for all `ShardedCounter`s.
+macro_rules! impl_par_extend {
+    ($($counter:ident),*) => {$(
+        impl<T, S> ParallelExtend<T> for $counter<S>
+        where
+            T: Hash + Send,
+            S: BuildHasher + Default + Clone + Send + Sync,
+        {
+            /// Counts the items in parallel, see [`par_count`].
+            fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, items: I) {
+                par_extend_counter(self, items);
+            }
+        }
+    )*};
+}
+
+impl_par_extend!(
+    AdaptiveCounter,
+    FMCounter,
+    HashCounter,
+    HyperBitBit,
+    HyperMinHashCounter,
+    KMVCounter,
+    LinearCounter,
+    PCSACounter,
+    ThetaSketch,
+    ULLCounter
+);
+
+impl<T, S, R> ParallelExtend<T> for HLLCounter<S, R>
+where
+    T: Hash + Send,
+    S: BuildHasher + Default + Clone + Send + Sync,
+    R: Registers + Send + Sync,
+{
+    /// Counts the items in parallel, see [`par_count`].
+    ///
+    /// ```
+    /// use hll_rust::{Counter, HLLCounter};
+    /// use rayon::prelude::*;
+    /// use xxhash_rust::xxh64::Xxh64Builder;
+    ///
+    /// let items: Vec<u64> = (0..100_000).collect();
+    /// let mut hll = HLLCounter::<Xxh64Builder>::new(14);
+    /// hll.par_extend(items.par_iter());
+    /// assert!((hll.estimate() - 100_000.0).abs() < 3_000.0);
+    /// ```
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, items: I) {
+        par_extend_counter(self, items);
+    }
+}
+
+impl<T, C> ParallelExtend<T> for AuditCounter<C>
+where
+    T: Hash + Send,
+    C: ShardedCounter + Send + Sync,
+    C::Hasher: BuildHasher + Default + Clone + Send + Sync,
+{
+    /// Counts the items in parallel, see [`par_count`].
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, items: I) {
+        par_extend_counter(self, items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Counter;
+    use rayon::prelude::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_par_extend_matches_sequential() {
+        let items: Vec<u64> = (0..200_000).map(|i| i % 150_000).collect();
+
+        let mut sequential = HLLCounter::<Xxh64Builder>::new(12);
+        for item in &items {
+            sequential.add_item(item);
+        }
+        let mut parallel = HLLCounter::<Xxh64Builder>::new(12);
+        parallel.add_u64(1_000_000);
+        parallel.par_extend(items.par_iter());
+        sequential.add_u64(1_000_000);
+        assert_eq!(parallel.register_values(), sequential.register_values());
+
+        let mut exact = HashCounter::<Xxh64Builder>::new(0);
+        exact.par_extend(items.par_iter().map(|i| i * 2));
+        assert_eq!(exact.estimate(), 150_000.0);
+
+        // RandomState shards share the keys of the extended counter
+        let mut random = ULLCounter::<std::collections::hash_map::RandomState>::new(10);
+        random.par_extend(0..10_000u64);
+        assert!((random.estimate() - 10_000.0).abs() < 1_000.0);
+    }
+}
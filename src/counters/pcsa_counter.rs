@@ -0,0 +1,137 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+const PHI: f64 = 0.77351;
+// Bias correction of Scheuermann and Mauve (2007)
+const KAPPA: f64 = 1.75;
+
+/// Probabilistic Counting with Stochastic Averaging (Flajolet & Martin, 1985).
+///
+/// Uses 2^size FM bitmaps. Every item is routed to one bitmap by the lowest
+/// `size` bits of its hash, and the estimate averages the position of the
+/// lowest unset bit over all bitmaps.
+pub struct PCSACounter<S = RandomState> {
+    size: usize,
+    bitmaps: Vec<u64>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for PCSACounter<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, 0..=24)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        PCSACounter {
+            size,
+            bitmaps: vec![0; 1 << size],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        let m = self.bitmaps.len() as f64;
+        let mean_r = self
+            .bitmaps
+            .iter()
+            .map(|bitmap| bitmap.trailing_ones() as f64)
+            .sum::<f64>()
+            / m;
+
+        // Small range correction: linear counting over the empty bitmaps
+        let empty = self.bitmaps.iter().filter(|&&bitmap| bitmap == 0).count();
+        if empty > 0 {
+            let linear = m * (m / empty as f64).ln();
+            if linear <= 2.0 * m {
+                return linear;
+            }
+        }
+
+        m / (PHI * (1.0 + 0.31 / m)) * (2f64.powf(mean_r) - 2f64.powf(-KAPPA * mean_r))
+    }
+
+    fn clear(&mut self) {
+        self.bitmaps.fill(0);
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for PCSACounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (bitmap_self, bitmap_other) in self.bitmaps.iter_mut().zip(other.bitmaps.iter()) {
+            *bitmap_self |= *bitmap_other;
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for PCSACounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for PCSACounter<S> {
+    /// 0.78 / sqrt(m) for m bitmaps.
+    fn relative_std_error(&self) -> f64 {
+        0.78 / (self.bitmaps.len() as f64).sqrt()
+    }
+}
+
+impl<S: BuildHasher + Default> PCSACounter<S> {
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        let index = (hash & ((1u64 << self.size) - 1)) as usize;
+        let remainder = hash >> self.size;
+        let rho = std::cmp::min(remainder.trailing_zeros(), 63);
+        self.bitmaps[index] |= 1 << rho;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_pcsa_estimate() {
+        for n in [1u64, 100, 1_000, 10_000, 1_000_000] {
+            let mut counter = PCSACounter::<Xxh64Builder>::new(10);
+            for i in 0..n {
+                counter.add_u64(i);
+            }
+            let estimate = counter.estimate();
+            assert!(
+                (estimate - n as f64).abs() <= 0.1 * n as f64 + 1.0,
+                "estimated {estimate} for {n}"
+            );
+        }
+    }
+}
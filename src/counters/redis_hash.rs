@@ -0,0 +1,66 @@
+use std::hash::{BuildHasherDefault, Hasher};
+
+// Seed of the MurmurHash64A calls in Redis' hyperloglog.c
+const REDIS_SEED: u64 = 0xadc83b19;
+
+/// MurmurHash64A as used by Redis to hash `PFADD` elements.
+///
+/// Counters using [`RedisHashBuilder`] produce the same registers as Redis for
+/// the same elements, so their [`to_redis_bytes`] export can be merged with
+/// live Redis keys. The length prefix written by the `Hash` impl of byte
+/// slices is skipped, so adding `b"abc"` hashes exactly the bytes of
+/// `PFADD key abc`.
+///
+/// [`to_redis_bytes`]: crate::HLLCounter::to_redis_bytes
+#[derive(Default, Clone)]
+pub struct RedisHasher {
+    buffer: Vec<u8>,
+}
+
+/// `BuildHasher` for [`RedisHasher`].
+pub type RedisHashBuilder = BuildHasherDefault<RedisHasher>;
+
+impl Hasher for RedisHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // Length prefix of slices
+    fn write_usize(&mut self, _len: usize) {}
+
+    fn finish(&self) -> u64 {
+        murmur_hash_64a(&self.buffer, REDIS_SEED)
+    }
+}
+
+/// MurmurHash64A by Austin Appleby, reading blocks as little endian.
+pub fn murmur_hash_64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+
+    let mut blocks = data.chunks_exact(8);
+    for block in &mut blocks {
+        let mut k = u64::from_le_bytes(block.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
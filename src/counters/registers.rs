@@ -0,0 +1,208 @@
+use crate::counters::simd;
+
+/// Storage backend for the registers of an [`HLLCounter`](crate::HLLCounter).
+pub trait Registers {
+    /// The only length of backends whose length is fixed by their type,
+    /// `None` for growable ones. Counters with fixed-size registers are
+    /// always dense, since they cannot start out without registers.
+    const FIXED_LEN: Option<usize> = None;
+
+    /// Creates `len` registers initialized to zero.
+    fn with_len(len: usize) -> Self;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn get(&self, index: usize) -> u8;
+    fn set(&mut self, index: usize, value: u8);
+
+    /// Sets the register at `index` to `value` if that is larger than its current value.
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) {
+        if value > self.get(index) {
+            self.set(index, value);
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_;
+
+    /// Sets all registers to zero. Not named `clear`, which would be
+    /// shadowed by `Vec::clear`.
+    fn reset(&mut self) {
+        for index in 0..self.len() {
+            self.set(index, 0);
+        }
+    }
+
+    /// Hints that the register at `index` will be updated soon.
+    #[inline(always)]
+    fn prefetch(&self, _index: usize) {}
+
+    /// Returns the sum of 2^-r over all registers r and the number of zero registers.
+    fn harmonic_sum(&self) -> (f64, usize) {
+        self.iter().fold((0.0, 0), |(sum, zeros), reg| {
+            (sum + 2f64.powi(-(reg as i32)), zeros + (reg == 0) as usize)
+        })
+    }
+
+    /// Memory used by the registers in bytes.
+    fn size_in_bytes(&self) -> usize;
+}
+
+/// One byte per register. This is the fastest backend.
+impl Registers for Vec<u8> {
+    fn with_len(len: usize) -> Self {
+        vec![u8::MIN; len]
+    }
+
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        self[index]
+    }
+
+    #[inline(always)]
+    fn set(&mut self, index: usize, value: u8) {
+        self[index] = value;
+    }
+
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) {
+        self[index] = std::cmp::max(self[index], value);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        <[u8]>::iter(self).copied()
+    }
+
+    fn reset(&mut self) {
+        self.fill(0);
+    }
+
+    fn harmonic_sum(&self) -> (f64, usize) {
+        simd::harmonic_sum(self)
+    }
+
+    #[inline(always)]
+    fn prefetch(&self, index: usize) {
+        simd::prefetch(&self[index]);
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        <[u8]>::len(self)
+    }
+}
+
+/// One byte per register stored inline, e.g. on the stack. The length is
+/// fixed, so `with_len` panics for any length other than `N`. Meant for
+/// [`HLLCounterConst`](crate::HLLCounterConst); an [`HLLCounter`](crate::HLLCounter)
+/// with these registers skips the sparse representation.
+impl<const N: usize> Registers for [u8; N] {
+    const FIXED_LEN: Option<usize> = Some(N);
+
+    fn with_len(len: usize) -> Self {
+        assert_eq!(len, N, "array registers hold exactly {} registers", N);
+        [u8::MIN; N]
+    }
+
+    fn len(&self) -> usize {
+        N
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        self[index]
+    }
+
+    #[inline(always)]
+    fn set(&mut self, index: usize, value: u8) {
+        self[index] = value;
+    }
+
+    #[inline(always)]
+    fn update_max(&mut self, index: usize, value: u8) {
+        self[index] = std::cmp::max(self[index], value);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        <[u8]>::iter(self).copied()
+    }
+
+    fn reset(&mut self) {
+        self.fill(0);
+    }
+
+    fn harmonic_sum(&self) -> (f64, usize) {
+        simd::harmonic_sum(self)
+    }
+
+    #[inline(always)]
+    fn prefetch(&self, index: usize) {
+        simd::prefetch(&self[index]);
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        N
+    }
+}
+
+const PACKED_BITS: usize = 6;
+const PACKED_MASK: u16 = (1 << PACKED_BITS) - 1;
+
+/// Registers packed into 6 bits each, saving 25% of memory compared to one
+/// byte per register at the cost of some bit twiddling on every access.
+///
+/// Values larger than 63 are saturated, which only matters for precision 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedRegisters {
+    len: usize,
+    // One extra trailing byte, so every register can be read as a u16
+    bytes: Vec<u8>,
+}
+
+impl Registers for PackedRegisters {
+    fn with_len(len: usize) -> Self {
+        PackedRegisters {
+            len,
+            bytes: vec![0; (len * PACKED_BITS).div_ceil(8) + 1],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> u8 {
+        let bit = index * PACKED_BITS;
+        let word = u16::from_le_bytes([self.bytes[bit / 8], self.bytes[bit / 8 + 1]]);
+        ((word >> (bit % 8)) & PACKED_MASK) as u8
+    }
+
+    #[inline(always)]
+    fn set(&mut self, index: usize, value: u8) {
+        let bit = index * PACKED_BITS;
+        let shift = bit % 8;
+        let mut word = u16::from_le_bytes([self.bytes[bit / 8], self.bytes[bit / 8 + 1]]);
+        word &= !(PACKED_MASK << shift);
+        word |= (std::cmp::min(value as u16, PACKED_MASK)) << shift;
+        let [lo, hi] = word.to_le_bytes();
+        self.bytes[bit / 8] = lo;
+        self.bytes[bit / 8 + 1] = hi;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|index| self.get(index))
+    }
+
+    fn reset(&mut self) {
+        self.bytes.fill(0);
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.bytes.len()
+    }
+}
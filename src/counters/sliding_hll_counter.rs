@@ -0,0 +1,257 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::estimators::{alpha, dense_estimate};
+use crate::counters::{BuildError, Counter, EstimateWithError, MergeError, MergeableCounter};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// HyperLogLog counter over a sliding window (Chabchoub and Hébrail, 2010).
+///
+/// Instead of a single maximum, every register keeps its List of Future
+/// Possible Maxima: the `(timestamp, rho)` pairs that are the maximum of the
+/// register for some window ending now. Older pairs with a smaller or equal
+/// rho can never become a maximum again and are dropped, so each list stays
+/// short (O(log n) entries on average).
+///
+/// Timestamps are plain `u64` in any unit and must not decrease. [`add`]
+/// advances the clock by one per item, which makes the window a number of
+/// items; [`add_at`] takes explicit timestamps, e.g. seconds. The estimate
+/// covers the items with a timestamp in `(now - window, now]`.
+///
+/// [`add`]: Counter::add
+/// [`add_at`]: SlidingHLLCounter::add_at
+pub struct SlidingHLLCounter<S = RandomState> {
+    size: usize,
+    window: u64,
+    now: u64,
+    // (timestamp, rho) pairs, timestamps increasing and rho decreasing
+    registers: Vec<Vec<(u64, u8)>>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for SlidingHLLCounter<S> {
+    type Hasher = S;
+
+    /// Creates a counter whose items never expire; see [`SlidingHLLCounter::with_window`].
+    fn new(size: usize) -> Self {
+        Self::with_window(size, u64::MAX)
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, 4..=24)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::with_window_and_hasher(size, u64::MAX, hasher)
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.now += 1;
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        self.now += 1;
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    /// Estimates the number of distinct items in the whole window.
+    fn estimate(&self) -> f64 {
+        self.estimate_window(self.window)
+    }
+
+    /// Also resets the clock, so timestamps may start over.
+    fn clear(&mut self) {
+        self.now = 0;
+        for list in &mut self.registers {
+            list.clear();
+        }
+    }
+}
+
+impl<S: BuildHasher + Default> SlidingHLLCounter<S> {
+    /// Creates a counter with 2^size registers covering the last `window`
+    /// time steps.
+    pub fn with_window(size: usize, window: u64) -> Self {
+        Self::with_window_and_hasher(size, window, S::default())
+    }
+
+    /// Like [`SlidingHLLCounter::with_window`], but hashes items with `hasher`.
+    pub fn with_window_and_hasher(size: usize, window: u64, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        assert!(window > 0, "window must be positive");
+        SlidingHLLCounter {
+            size,
+            window,
+            now: 0,
+            registers: vec![Vec::new(); 1 << size],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Adds an item seen at `timestamp`, which must not be before the
+    /// timestamp of the previous item.
+    pub fn add_at(&mut self, item: &[u8], timestamp: u64) {
+        assert!(timestamp >= self.now, "timestamps must not decrease");
+        self.now = timestamp;
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    /// Moves the clock forward to `timestamp` without adding an item, so items
+    /// age out while the stream is idle.
+    pub fn advance_to(&mut self, timestamp: u64) {
+        self.now = self.now.max(timestamp);
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        let index = (hash & ((1u64 << self.size) - 1)) as usize;
+        let remainder = hash >> self.size;
+        let rho = std::cmp::min(remainder.trailing_zeros() + 1, 64 - self.size as u32) as u8;
+
+        let oldest = self.oldest_timestamp(self.window);
+        let list = &mut self.registers[index];
+        while list.last().is_some_and(|&(_, r)| r <= rho) {
+            list.pop();
+        }
+        list.push((self.now, rho));
+        // Expired pairs can only be at the front
+        let expired = list.partition_point(|&(t, _)| t < oldest);
+        list.drain(..expired);
+    }
+
+    // Smallest timestamp inside a window of the given length ending now
+    fn oldest_timestamp(&self, window: u64) -> u64 {
+        (self.now + 1).saturating_sub(window)
+    }
+
+    /// Estimates the number of distinct items in the last `window` time
+    /// steps. Windows longer than the counter's window are truncated to it.
+    pub fn estimate_window(&self, window: u64) -> f64 {
+        let oldest = self.oldest_timestamp(window.min(self.window));
+        let registers: Vec<u8> = self
+            .registers
+            .iter()
+            .map(|list| {
+                // The first pair inside the window has the largest rho
+                let pos = list.partition_point(|&(t, _)| t < oldest);
+                list.get(pos).map_or(0, |&(_, rho)| rho)
+            })
+            .collect();
+        dense_estimate(self.size, alpha(self.size), &registers)
+    }
+
+    /// Timestamp of the latest item or `advance_to` call.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    pub fn precision(&self) -> usize {
+        self.size
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for SlidingHLLCounter<S> {
+    /// Merges two counters over the same time axis, e.g. the same stream
+    /// split across threads. The merged clock is the later of both.
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        if self.window != other.window {
+            return Err(MergeError::WindowMismatch {
+                left: self.window,
+                right: other.window,
+            });
+        }
+
+        self.now = self.now.max(other.now);
+        let oldest = self.oldest_timestamp(self.window);
+        for (list, other_list) in self.registers.iter_mut().zip(&other.registers) {
+            if other_list.is_empty() {
+                continue;
+            }
+            let mut pairs: Vec<(u64, u8)> = list.iter().chain(other_list).copied().collect();
+            pairs.sort_unstable();
+
+            list.clear();
+            for (t, rho) in pairs.into_iter().filter(|&(t, _)| t >= oldest) {
+                while list.last().is_some_and(|&(_, r)| r <= rho) {
+                    list.pop();
+                }
+                list.push((t, rho));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for SlidingHLLCounter<S> {
+    /// 1.04 / sqrt(m) for m registers, as for a plain HyperLogLog.
+    fn relative_std_error(&self) -> f64 {
+        1.04 / ((1u64 << self.size) as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HLLCounter;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_window_matches_hll_of_recent_items() {
+        let window = 20_000;
+        let mut sliding = SlidingHLLCounter::<Xxh64Builder>::with_window(12, window);
+        for i in 0..100_000u64 {
+            sliding.add_u64(i);
+        }
+
+        // Registers of the window are exactly those of an HLL over its items
+        let mut recent = HLLCounter::<Xxh64Builder>::new(12);
+        recent.to_dense();
+        for i in 100_000 - window..100_000 {
+            recent.add_u64(i);
+        }
+        assert_eq!(sliding.estimate(), recent.estimate());
+
+        let last_thousand = sliding.estimate_window(1_000);
+        assert!((last_thousand - 1_000.0).abs() / 1_000.0 < 0.1);
+    }
+
+    #[test]
+    fn test_timestamps_and_merge() {
+        let mut a = SlidingHLLCounter::<Xxh64Builder>::with_window(10, 60);
+        let mut b = SlidingHLLCounter::<Xxh64Builder>::with_window(10, 60);
+        for second in 0..120u64 {
+            for i in 0..50u64 {
+                let item = (second * 50 + i).to_le_bytes();
+                if i % 2 == 0 {
+                    a.add_at(&item, second);
+                } else {
+                    b.add_at(&item, second);
+                }
+            }
+        }
+        a.merge(&b).unwrap();
+        assert!((a.estimate() - 3_000.0).abs() / 3_000.0 < 0.1);
+
+        // Everything expires once the stream goes quiet
+        a.advance_to(1_000);
+        assert_eq!(a.estimate(), 0.0);
+
+        // Clearing resets the clock
+        a.clear();
+        a.add_at(b"item", 0);
+        assert!(a.estimate() > 0.0);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::BTreeSet;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// Theta sketch (Dasgupta et al., 2016).
+///
+/// Keeps every hash below the threshold theta, lowering theta whenever more
+/// than `size` hashes would be kept. Every retained hash stands for
+/// 2^64 / theta items, so the estimate is unbiased, and unlike HyperLogLog
+/// the sketch supports [`union`], [`intersection`] and [`a_not_b`] with
+/// unbiased results: both inputs are cut to the smaller theta and the set
+/// operation is applied to the retained hashes.
+///
+/// [`union`]: ThetaSketch::union
+/// [`intersection`]: ThetaSketch::intersection
+/// [`a_not_b`]: ThetaSketch::a_not_b
+pub struct ThetaSketch<S = RandomState> {
+    size: usize,
+    // Only hashes below theta are kept. u64::MAX until the sketch is full,
+    // which makes the sketch exact.
+    theta: u64,
+    hashes: BTreeSet<u64>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for ThetaSketch<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("number of kept hashes", size, 1..=usize::MAX)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        ThetaSketch {
+            size,
+            theta: u64::MAX,
+            hashes: BTreeSet::new(),
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        self.hashes.len() as f64 / self.theta_fraction()
+    }
+
+    fn clear(&mut self) {
+        self.theta = u64::MAX;
+        self.hashes.clear();
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for ThetaSketch<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        self.theta = self.theta.min(other.theta);
+        let theta = self.theta;
+        self.hashes.retain(|&hash| hash < theta);
+        for &hash in other.hashes.range(..theta) {
+            self.add_hash(hash);
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for ThetaSketch<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for ThetaSketch<S> {
+    /// 1 / sqrt(k) for k retained hashes, exact while theta was never lowered.
+    fn relative_std_error(&self) -> f64 {
+        if self.is_exact() || self.hashes.is_empty() {
+            0.0
+        } else {
+            1.0 / (self.hashes.len() as f64).sqrt()
+        }
+    }
+}
+
+impl<S: BuildHasher + Default> ThetaSketch<S> {
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        if hash >= self.theta || !self.hashes.insert(hash) {
+            return;
+        }
+        if self.hashes.len() > self.size {
+            self.theta = self.hashes.pop_last().unwrap();
+        }
+    }
+
+    // Theta as a sampling probability in (0, 1]
+    fn theta_fraction(&self) -> f64 {
+        if self.is_exact() {
+            1.0
+        } else {
+            self.theta as f64 / 2f64.powi(64)
+        }
+    }
+
+    /// Returns `true` while every distinct item is still retained.
+    pub fn is_exact(&self) -> bool {
+        self.theta == u64::MAX
+    }
+
+    /// Number of retained hashes.
+    pub fn retained(&self) -> usize {
+        self.hashes.len()
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ThetaSketch<S> {
+    // Builds a sketch with the same hasher from hashes that are all below `theta`
+    fn with_hashes(&self, theta: u64, hashes: BTreeSet<u64>) -> Self {
+        ThetaSketch {
+            size: self.size,
+            theta,
+            hashes,
+            hasher: self.hasher.clone(),
+            fingerprint: self.fingerprint,
+        }
+    }
+
+    /// Sketch of the union of both sketches.
+    ///
+    /// Panics if the sketches cannot be merged.
+    pub fn union(&self, other: &Self) -> Self {
+        let mut union = self.with_hashes(self.theta, self.hashes.clone());
+        union.merge(other).unwrap_or_else(|e| panic!("{e}"));
+        union
+    }
+
+    /// Sketch of the intersection of both sketches.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let theta = self.theta.min(other.theta);
+        let hashes = self
+            .hashes
+            .range(..theta)
+            .filter(|hash| other.hashes.contains(hash))
+            .copied()
+            .collect();
+        self.with_hashes(theta, hashes)
+    }
+
+    /// Sketch of the items in `self` that are not in `other`.
+    pub fn a_not_b(&self, other: &Self) -> Self {
+        let theta = self.theta.min(other.theta);
+        let hashes = self
+            .hashes
+            .range(..theta)
+            .filter(|hash| !other.hashes.contains(hash))
+            .copied()
+            .collect();
+        self.with_hashes(theta, hashes)
+    }
+
+    /// Estimates the Jaccard index |A ∩ B| / |A ∪ B| of both sketches.
+    ///
+    /// Returns 0 if both sketches are empty.
+    pub fn jaccard(&self, other: &Self) -> f64 {
+        let union = self.union(other);
+        if union.hashes.is_empty() {
+            return 0.0;
+        }
+        let shared = union
+            .hashes
+            .iter()
+            .filter(|hash| self.hashes.contains(hash) && other.hashes.contains(hash))
+            .count();
+        shared as f64 / union.hashes.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    fn sketch_of(items: std::ops::Range<u64>) -> ThetaSketch<Xxh64Builder> {
+        let mut sketch = ThetaSketch::new(4096);
+        for i in items {
+            sketch.add_u64(i);
+        }
+        sketch
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = sketch_of(0..40_000);
+        let b = sketch_of(20_000..60_000);
+
+        let close = |estimate: f64, truth: f64| (estimate - truth).abs() / truth < 0.1;
+        assert!(close(a.estimate(), 40_000.0));
+        assert!(close(a.union(&b).estimate(), 60_000.0));
+        assert!(close(a.intersection(&b).estimate(), 20_000.0));
+        assert!(close(a.a_not_b(&b).estimate(), 20_000.0));
+        assert!((a.jaccard(&b) - 1.0 / 3.0).abs() < 0.05);
+        assert_eq!(a.intersection(&sketch_of(100_000..140_000)).estimate(), 0.0);
+    }
+
+    #[test]
+    fn test_exact_below_size() {
+        let a = sketch_of(0..1_000);
+        let b = sketch_of(500..1_500);
+        assert!(a.is_exact());
+        assert_eq!(a.estimate(), 1_000.0);
+        assert_eq!(a.intersection(&b).estimate(), 500.0);
+        assert_eq!(a.a_not_b(&b).estimate(), 500.0);
+        assert_eq!(a.relative_std_error(), 0.0);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::counters::counter_base::{check_mergeable, check_range, hasher_fingerprint};
+use crate::counters::{
+    BuildError, Counter, EstimateWithError, MergeError, MergeableCounter, ShardedCounter,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// UltraLogLog counter (Ertl, 2024).
+///
+/// Like HyperLogLog every register belongs to a bucket of the hash space and
+/// records the largest update value u seen in it, but the two lowest bits of
+/// the byte additionally record whether the values u - 1 and u - 2 were seen.
+/// The extra information lowers the error at the same number of registers,
+/// so for the same accuracy a sketch needs about a quarter less memory than
+/// a HyperLogLog with 6-bit registers. The estimate is the maximum-likelihood
+/// estimate over all recorded update values.
+pub struct ULLCounter<S = RandomState> {
+    size: usize,
+    // 4 * u + (bit for u - 1) * 2 + (bit for u - 2), or 0 if empty
+    registers: Vec<u8>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> Counter for ULLCounter<S> {
+    type Hasher = S;
+
+    fn new(size: usize) -> Self {
+        Self::with_hasher(size, S::default())
+    }
+
+    fn check_size(size: usize) -> Result<(), BuildError> {
+        check_range("precision", size, 4..=24)
+    }
+
+    fn with_hasher(size: usize, hasher: S) -> Self {
+        Self::check_size(size).unwrap_or_else(|e| panic!("{e}"));
+        ULLCounter {
+            size,
+            registers: vec![0; 1 << size],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    fn add_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash);
+    }
+
+    fn estimate(&self) -> f64 {
+        let p = self.size;
+        // Histogram of the update values that were seen in some register and
+        // the total probability of the update values that were not.
+        let mut seen = [0u64; 64];
+        let mut missing_rate = 0.0;
+        for &reg in &self.registers {
+            if reg == 0 {
+                missing_rate += 1.0;
+                continue;
+            }
+            let bits = unpack(reg);
+            for (level, count) in seen.iter_mut().enumerate().skip(p - 1) {
+                if bits & (1 << level) != 0 {
+                    *count += 1;
+                } else if level >= (reg >> 2) as usize - 2 {
+                    missing_rate += level_probability(p, level);
+                }
+            }
+        }
+        if missing_rate == self.registers.len() as f64 {
+            return 0.0;
+        }
+        if missing_rate == 0.0 {
+            return f64::INFINITY;
+        }
+
+        // Derivative of the Poisson log-likelihood with respect to the
+        // per-register rate x, which decreases monotonically.
+        let derivative = |x: f64| {
+            let mut d = -missing_rate;
+            for (level, &count) in seen.iter().enumerate().skip(p - 1) {
+                if count > 0 {
+                    let rate = level_probability(p, level);
+                    d += count as f64 * rate / (x * rate).exp_m1();
+                }
+            }
+            d
+        };
+
+        let (mut lo, mut hi) = (f64::MIN_POSITIVE.ln(), 64.0 * std::f64::consts::LN_2);
+        while derivative(hi.exp()) > 0.0 {
+            hi *= 2.0;
+        }
+        for _ in 0..200 {
+            let mid = 0.5 * (lo + hi);
+            if derivative(mid.exp()) > 0.0 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        self.registers.len() as f64 * (0.5 * (lo + hi)).exp()
+    }
+
+    fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+}
+
+impl<S: BuildHasher + Default> MergeableCounter for ULLCounter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.size, self.fingerprint),
+            (other.size, other.fingerprint),
+        )?;
+        for (reg, &other_reg) in self.registers.iter_mut().zip(&other.registers) {
+            if other_reg != 0 {
+                *reg = pack(unpack(*reg) | unpack(other_reg));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> ShardedCounter for ULLCounter<S> {
+    fn empty_shard(&self) -> Self {
+        Self::with_hasher(self.size, self.hasher.clone())
+    }
+}
+
+impl<S: BuildHasher + Default> EstimateWithError for ULLCounter<S> {
+    /// 0.76 / sqrt(m) for m registers, the asymptotic error of the
+    /// maximum-likelihood estimate.
+    fn relative_std_error(&self) -> f64 {
+        0.76 / ((1u64 << self.size) as f64).sqrt()
+    }
+}
+
+impl<S: BuildHasher + Default> ULLCounter<S> {
+    // The highest bits select the register. The number of leading zeros t of
+    // the remaining bits gives the update value p - 1 + t.
+    #[inline(always)]
+    fn add_hash(&mut self, hash: u64) {
+        let p = self.size as u32;
+        let index = (hash >> (64 - p)) as usize;
+        let nlz = (!(!hash << p)).leading_zeros();
+        let reg = &mut self.registers[index];
+        *reg = pack(unpack(*reg) | 1 << (nlz + p - 1));
+    }
+
+    pub fn precision(&self) -> usize {
+        self.size
+    }
+
+    /// Memory used by the registers in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.registers.len()
+    }
+}
+
+// Probability that an item gets the update value `level` (p - 1 to 63)
+fn level_probability(p: usize, level: usize) -> f64 {
+    if level == 63 {
+        2f64.powi(-(64 - p as i32))
+    } else {
+        2f64.powi(-((level + 2 - p) as i32))
+    }
+}
+
+// Expands a register into a bit set of the update values seen
+fn unpack(reg: u8) -> u64 {
+    if reg == 0 {
+        return 0;
+    }
+    ((4 | (reg & 3)) as u64) << ((reg >> 2) - 2)
+}
+
+// Keeps the largest update value and the two below it
+fn pack(bits: u64) -> u8 {
+    let u = 63 - bits.leading_zeros();
+    ((u << 2) as u8) | ((bits >> (u - 2)) & 3) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_pack_roundtrip() {
+        for reg in (12..=255u8).filter(|reg| reg >> 2 >= 3) {
+            assert_eq!(pack(unpack(reg)), reg);
+        }
+    }
+
+    #[test]
+    fn test_estimate_and_merge() {
+        let mut all = ULLCounter::<Xxh64Builder>::new(12);
+        let mut a = ULLCounter::<Xxh64Builder>::new(12);
+        let mut b = ULLCounter::<Xxh64Builder>::new(12);
+        assert_eq!(all.estimate(), 0.0);
+
+        for i in 0..200_000u64 {
+            all.add_u64(i);
+            if i % 2 == 0 {
+                a.add_u64(i)
+            } else {
+                b.add_u64(i)
+            }
+            if i == 99 {
+                assert!((all.estimate() - 100.0).abs() < 5.0);
+            }
+        }
+        a.merge(&b).unwrap();
+        assert_eq!(a.registers, all.registers);
+        assert!((all.estimate() - 200_000.0).abs() / 200_000.0 < 3.0 * all.relative_std_error());
+    }
+}
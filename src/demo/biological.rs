@@ -1,55 +1,95 @@
-use hll_rust::fasta::FastaReader;
+use hll_rust::fasta::{FastaError, FastaReader, open_sequence_file};
 use hll_rust::parallel_counting;
-use hll_rust::{Counter, FMCounter, HLLCounter, HashCounter, LinearCounter};
-use std::fs::File;
-use std::io::{self, BufReader};
+use hll_rust::{Counter, EstimateWithError, FMCounter, HLLCounter, HashCounter, LinearCounter};
+use std::path::Path;
+
+use super::output::{OutputFormat, ResultRow, write_results};
+
+// Relative standard error of the linear counter at the file's k-mer bound
+const LINEAR_TARGET_ERROR: f64 = 0.01;
+// K-mer bound of inputs of unknown length, e.g. standard input: about the
+// size of a human genome
+const DEFAULT_MAX_KMERS: u64 = 1 << 32;
 
 pub fn run_sequential<S: std::hash::BuildHasher + Default>(
     dataset: &[(&str, &str)],
+    k: usize,
+    precision: usize,
+    exact: bool,
     verbose: bool,
-) -> io::Result<()> {
-    // Store (length)
-    let mut hll_estimated_complexity: Vec<f64> = Vec::new();
+    format: OutputFormat,
+) -> Result<(), FastaError> {
+    let mut rows = Vec::new();
+    // Structured output only prints the results
+    let table = format == OutputFormat::Table;
 
     for (name, path) in dataset.iter() {
-        println!("Processing dataset: {}", name);
+        if table {
+            println!("Processing dataset: {}", name);
+        }
+        let start = std::time::Instant::now();
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+        let reader = open_sequence_file(path)?;
         let mut fasta_reader = FastaReader::new(reader);
 
-        let mut linear_counter: LinearCounter<S> = LinearCounter::new(1_000_000);
+        // A file holds at most as many k-mers as bases, about four per byte
+        // when gzip compressed
+        let max_kmers = match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() && path.ends_with(".gz") => metadata.len() * 4,
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => DEFAULT_MAX_KMERS,
+        };
+        let mut linear_counter: LinearCounter<S> =
+            LinearCounter::for_cardinality(max_kmers, LINEAR_TARGET_ERROR);
         let mut hash_counter: HashCounter<S> = HashCounter::new(0);
         let mut fm_counter: FMCounter<S> = FMCounter::new(32);
-        let mut hll_counter: HLLCounter<S> = HLLCounter::new(14);
+        let mut hll_counter: HLLCounter<S> = HLLCounter::new(precision);
 
         let mut total_kmers_seen: u64 = 0;
 
         while fasta_reader.next_record()? {
             if let Some(id) = &fasta_reader.id
                 && verbose
+                && table
             {
                 println!(">{}", String::from_utf8_lossy(id));
             }
 
-            for kmer_result in fasta_reader.canonical_kmers(31) {
-                let kmer = kmer_result?;
+            let mut kmers = fasta_reader.kmers(k);
+            while let Some(kmer) = kmers.next_canonical_kmer()? {
                 if verbose {
-                    linear_counter.add(&kmer);
-                    fm_counter.add(&kmer);
-                    hash_counter.add(&kmer);
+                    linear_counter.add(kmer);
+                    fm_counter.add(kmer);
                 }
-                hll_counter.add(&kmer);
+                // The comparison of the counters is against the exact count
+                if exact || verbose {
+                    hash_counter.add(kmer);
+                }
+                hll_counter.add(kmer);
 
                 total_kmers_seen += 1;
             }
         }
 
-        let hll_estimate = hll_counter.estimate();
-        hll_estimated_complexity.push(hll_estimate / total_kmers_seen as f64);
-
-        if verbose {
-            let linear_estimate = linear_counter.estimate();
+        let duration = start.elapsed();
+        let estimate = hll_counter.estimate_with_error();
+        let hll_estimate = estimate.value;
+        rows.push(ResultRow {
+            dataset: name.to_string(),
+            k,
+            total_kmers: total_kmers_seen,
+            estimate: hll_estimate,
+            ci95: estimate.ci95,
+            exact: exact.then(|| hash_counter.estimate()),
+            runtime: Some(duration.as_secs_f64()),
+        });
+
+        if verbose && table {
+            // A saturated bitmap only gives a lower bound
+            let (linear_name, linear_estimate) = match linear_counter.try_estimate() {
+                Ok(estimate) => ("Linear", estimate),
+                Err(saturated) => ("Linear (sat.)", saturated.lower_bound),
+            };
             let fm_estimate = fm_counter.estimate();
             let hash_estimate = hash_counter.estimate();
 
@@ -60,7 +100,7 @@ pub fn run_sequential<S: std::hash::BuildHasher + Default>(
             println!("{:-<65}", "");
             println!(
                 "{:<15} {:<15.6} {:<20.0} {:<15.4}",
-                "Linear",
+                linear_name,
                 linear_estimate / total_kmers_seen as f64,
                 linear_estimate.round(),
                 100f64 * (linear_estimate - hash_estimate) / hash_estimate
@@ -90,8 +130,15 @@ pub fn run_sequential<S: std::hash::BuildHasher + Default>(
         }
     }
 
-    for (est, (name, _)) in hll_estimated_complexity.iter().zip(dataset.iter()) {
-        println!("Dataset: {}, HLL estimated complexity: {:.6}", name, est);
+    if !table {
+        return Ok(write_results(std::io::stdout().lock(), format, &rows)?);
+    }
+    for row in &rows {
+        println!(
+            "Dataset: {}, HLL estimated complexity: {:.6}",
+            row.dataset,
+            row.estimate / row.total_kmers as f64
+        );
     }
 
     Ok(())
@@ -99,28 +146,88 @@ pub fn run_sequential<S: std::hash::BuildHasher + Default>(
 
 pub fn run_parallel<S: std::hash::BuildHasher + Default + Send + Sync>(
     dataset: &[(&str, &str)],
-    _verbose: bool,
-) -> io::Result<()> {
-    println!(
-        "\n{:<20} | {:<15} | {:<15} | {:<15}",
-        "Dataset", "Complexity", "Total K-mers", "Time"
-    );
-    println!("{:-<80}", "");
-
-    for (name, path) in dataset.iter() {
-        // println!("Processing dataset: {}", name);
-        let start = std::time::Instant::now();
-        let (total_count, counter) = parallel_counting::run_parallel_fasta_analysis::<S>(path)?;
-        let duration = start.elapsed();
+    k: usize,
+    precision: usize,
+    exact: bool,
+    format: OutputFormat,
+) -> Result<(), FastaError> {
+    // All datasets share the thread pool, so small genomes are counted side by side
+    let paths: Vec<&Path> = dataset.iter().map(|(_, path)| Path::new(path)).collect();
+    let start = std::time::Instant::now();
+    let result = parallel_counting::run_parallel_dataset_analysis(&paths, k, || {
+        HLLCounter::<S>::new(precision)
+    })?;
+    let duration = start.elapsed();
+
+    let exact = if exact {
+        // Validate against the exact count
+        let exact = parallel_counting::run_parallel_dataset_analysis(&paths, k, || {
+            HashCounter::<S>::new(0)
+        })?;
+        Some(exact)
+    } else {
+        None
+    };
+
+    let names = dataset.iter().map(|(name, _)| *name).chain(["Combined"]);
+    let counted = result.files.iter().chain([&result.combined]);
+    let rows: Vec<ResultRow> = names
+        .zip(counted)
+        .enumerate()
+        .map(|(i, (name, (total_count, counter)))| {
+            let estimate = counter.estimate_with_error();
+            ResultRow {
+                dataset: name.to_string(),
+                k,
+                total_kmers: *total_count,
+                estimate: estimate.value,
+                ci95: estimate.ci95,
+                exact: exact.as_ref().map(|exact| {
+                    let (_, exact_counter) = exact.files.get(i).unwrap_or(&exact.combined);
+                    exact_counter.estimate()
+                }),
+                // The files are counted concurrently, so only the total time is known
+                runtime: (i == dataset.len()).then_some(duration.as_secs_f64()),
+            }
+        })
+        .collect();
 
-        let unique_count_estimate = counter.estimate();
-        let complexity_estimate =
-            f64::clamp(unique_count_estimate / (total_count as f64), 0.0, 1.0);
+    if format != OutputFormat::Table {
+        return Ok(write_results(std::io::stdout().lock(), format, &rows)?);
+    }
 
+    println!(
+        "\n{:<20} | {:<15} | {:<15} | {:<25} | {:<15}",
+        "Dataset", "Complexity", "Total K-mers", "Distinct (95% CI)", "Time"
+    );
+    println!("{:-<108}", "");
+    for (i, row) in rows.iter().enumerate() {
+        if i == dataset.len() {
+            println!("{:-<108}", "");
+        }
         println!(
-            "{:<20} | {:<15.4} | {:<15} | {:?}",
-            name, complexity_estimate, total_count, duration
+            "{:<20} | {:<15.4} | {:<15} | {:<25} | {}",
+            row.dataset,
+            row.complexity(),
+            row.total_kmers,
+            format!("{:.0} - {:.0}", row.ci95.0, row.ci95.1),
+            if i == dataset.len() {
+                format!("{:?}", duration)
+            } else {
+                String::new()
+            }
         );
+
+        if let (Some(exact_count), Some(relative_error)) = (row.exact, row.relative_error()) {
+            println!(
+                "{:<20} | {:<15.4} | {:<15} | {:<25.0} | Rel Error: {:.4}%",
+                "  True (Hash)",
+                exact_count / row.total_kmers as f64,
+                row.total_kmers,
+                exact_count,
+                100f64 * relative_error
+            );
+        }
     }
     println!();
     Ok(())
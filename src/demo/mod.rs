@@ -1,2 +1,5 @@
 pub mod biological;
+pub mod output;
+pub mod profile;
+pub mod registers;
 pub mod synthetic;
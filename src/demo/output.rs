@@ -0,0 +1,230 @@
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+/// How the `count` command reports its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable table
+    #[default]
+    Table,
+    /// Comma-separated values with a header line
+    Csv,
+    /// Tab-separated values with a header line
+    Tsv,
+    /// JSON array with one object per dataset
+    Json,
+}
+
+/// The k-mer counts of one dataset.
+#[derive(Debug, Clone)]
+pub struct ResultRow {
+    pub dataset: String,
+    pub k: usize,
+    pub total_kmers: u64,
+    pub estimate: f64,
+    /// Approximate 95% confidence interval of the estimate
+    pub ci95: (f64, f64),
+    /// Exact number of distinct k-mers, if it was computed
+    pub exact: Option<f64>,
+    /// Runtime in seconds, if it was measured for this dataset
+    pub runtime: Option<f64>,
+}
+
+impl ResultRow {
+    /// Fraction of distinct k-mers, between 0 and 1.
+    pub fn complexity(&self) -> f64 {
+        f64::clamp(self.estimate / self.total_kmers as f64, 0.0, 1.0)
+    }
+
+    /// Error of the estimate relative to the exact count, if known.
+    pub fn relative_error(&self) -> Option<f64> {
+        self.exact.map(|exact| (self.estimate - exact) / exact)
+    }
+}
+
+const COLUMNS: [&str; 10] = [
+    "dataset",
+    "k",
+    "total_kmers",
+    "estimate",
+    "ci95_low",
+    "ci95_high",
+    "complexity",
+    "exact",
+    "relative_error",
+    "runtime_s",
+];
+
+/// Writes the rows as CSV, TSV or JSON. Nothing is written for
+/// [`OutputFormat::Table`], which the callers print themselves.
+pub fn write_results<W: Write>(
+    mut writer: W,
+    format: OutputFormat,
+    rows: &[ResultRow],
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Table => {}
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let separator = if format == OutputFormat::Csv {
+                ","
+            } else {
+                "\t"
+            };
+            writeln!(writer, "{}", COLUMNS.join(separator))?;
+            for row in rows {
+                let dataset = if format == OutputFormat::Csv {
+                    csv_field(&row.dataset)
+                } else {
+                    row.dataset.replace(['\t', '\n', '\r'], " ")
+                };
+                let fields: Vec<String> = std::iter::once(dataset)
+                    .chain(numbers(row).map(|number| number.unwrap_or_default()))
+                    .collect();
+                writeln!(writer, "{}", fields.join(separator))?;
+            }
+        }
+        OutputFormat::Json => {
+            writeln!(writer, "[")?;
+            for (i, row) in rows.iter().enumerate() {
+                let mut fields = vec![format!("\"dataset\": {}", json_string(&row.dataset))];
+                for (column, number) in COLUMNS[1..].iter().zip(numbers(row)) {
+                    let number = number.unwrap_or_else(|| "null".to_string());
+                    fields.push(format!("\"{}\": {}", column, number));
+                }
+                let comma = if i + 1 < rows.len() { "," } else { "" };
+                writeln!(writer, "  {{{}}}{}", fields.join(", "), comma)?;
+            }
+            writeln!(writer, "]")?;
+        }
+    }
+    writer.flush()
+}
+
+// The numeric columns of a row, `None` if unknown or not finite
+fn numbers(row: &ResultRow) -> impl Iterator<Item = Option<String>> {
+    let float = |x: Option<f64>| x.filter(|x| x.is_finite()).map(|x| x.to_string());
+    [
+        Some(row.k.to_string()),
+        Some(row.total_kmers.to_string()),
+        float(Some(row.estimate)),
+        float(Some(row.ci95.0)),
+        float(Some(row.ci95.1)),
+        float(Some(row.complexity())),
+        float(row.exact),
+        float(row.relative_error()),
+        float(row.runtime),
+    ]
+    .into_iter()
+}
+
+// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(dataset: &str, estimate: f64) -> ResultRow {
+        ResultRow {
+            dataset: dataset.to_string(),
+            k: 31,
+            total_kmers: 1000,
+            estimate,
+            ci95: (estimate * 0.99, estimate * 1.01),
+            exact: None,
+            runtime: Some(0.5),
+        }
+    }
+
+    fn write(format: OutputFormat, rows: &[ResultRow]) -> String {
+        let mut out = Vec::new();
+        write_results(&mut out, format, rows).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("genome.fa"), "genome.fa");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\r\nlines"), "\"two\r\nlines\"");
+        // Tabs are not special in CSV
+        assert_eq!(csv_field("a\tb"), "a\tb");
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("genome.fa"), "\"genome.fa\"");
+        assert_eq!(json_string("a,\"b\"\\c"), r#""a,\"b\"\\c""#);
+        assert_eq!(json_string("a\tb\nc\rd"), r#""a\tb\nc\rd""#);
+        assert_eq!(json_string("\u{0}\u{1b}\u{7f}"), "\"\\u0000\\u001b\u{7f}\"");
+        assert_eq!(json_string("génome"), "\"génome\"");
+    }
+
+    #[test]
+    fn test_names_keep_rows_intact() {
+        let name = "odd, \"name\"\twith\r\nbreaks";
+        let rows = [row(name, 990.0)];
+
+        let csv = write(OutputFormat::Csv, &rows);
+        assert!(csv.starts_with("dataset,k,"));
+        assert!(csv.contains("\"odd, \"\"name\"\"\twith\r\nbreaks\",31,1000,990,"));
+
+        let tsv = write(OutputFormat::Tsv, &rows);
+        assert_eq!(tsv.lines().count(), 2);
+        let fields: Vec<&str> = tsv.lines().nth(1).unwrap().split('\t').collect();
+        assert_eq!(fields.len(), COLUMNS.len());
+        assert_eq!(fields[0], "odd, \"name\" with  breaks");
+
+        let json = write(OutputFormat::Json, &rows);
+        assert!(json.contains(r#""dataset": "odd, \"name\"\twith\r\nbreaks""#));
+        assert_eq!(json.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_non_finite_values_are_null() {
+        let mut infinite = row("a", f64::INFINITY);
+        infinite.exact = Some(0.0);
+        let rows = [infinite, row("b", f64::NAN)];
+
+        let json = write(OutputFormat::Json, &rows);
+        for column in ["estimate", "ci95_low", "ci95_high"] {
+            assert_eq!(json.matches(&format!("\"{column}\": null")).count(), 2);
+        }
+        // The complexity is clamped to 1
+        assert!(json.contains("\"complexity\": 1,"));
+        assert!(json.contains("\"complexity\": null,"));
+        // The exact count of 0 makes the relative error infinite
+        assert!(json.contains("\"exact\": 0, \"relative_error\": null"));
+        assert!(!json.contains("inf") && !json.contains("NaN"));
+
+        let csv = write(OutputFormat::Csv, &rows);
+        assert_eq!(csv.lines().nth(1).unwrap(), "a,31,1000,,,,1,0,,0.5");
+        assert_eq!(csv.lines().nth(2).unwrap(), "b,31,1000,,,,,,,0.5");
+    }
+}
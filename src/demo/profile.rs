@@ -0,0 +1,149 @@
+use super::output::{OutputFormat, ResultRow, write_results};
+use super::synthetic::{PlotBackend, PlotConfig};
+use hll_rust::parallel_counting;
+use hll_rust::{Counter, Estimate, EstimateWithError, HLLCounter};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::path::Path;
+
+/// Estimates the distinct canonical k-mers of a FASTA file for every k in
+/// `ks` and prints them in `format`, optionally plotting them as configured
+/// by `plot`.
+pub fn run_profile<S: std::hash::BuildHasher + Default + Send + Sync>(
+    path: &Path,
+    ks: &[usize],
+    precision: usize,
+    format: OutputFormat,
+    plot: Option<&PlotConfig>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = path.display().to_string();
+    let profile =
+        parallel_counting::complexity_profile(&path.to_string_lossy(), ks.iter().copied(), || {
+            HLLCounter::<S>::new(precision)
+        })
+        .map_err(|e| format!("{name}: {e}"))?;
+
+    let relative_error = HLLCounter::<S>::new(precision).relative_std_error();
+    let rows: Vec<ResultRow> = profile
+        .iter()
+        .map(|&(k, distinct, total)| ResultRow {
+            dataset: name.clone(),
+            k,
+            total_kmers: total,
+            estimate: distinct,
+            ci95: Estimate::new(distinct, distinct * relative_error).ci95,
+            exact: None,
+            runtime: None,
+        })
+        .collect();
+
+    if format == OutputFormat::Table {
+        println!(
+            "{:>3} {:>15} {:>15} {:>10}",
+            "k", "total k-mers", "distinct", "complexity"
+        );
+        for row in &rows {
+            println!(
+                "{:>3} {:>15} {:>15.0} {:>10.4}",
+                row.k,
+                row.total_kmers,
+                row.estimate,
+                row.complexity()
+            );
+        }
+    } else {
+        write_results(std::io::stdout().lock(), format, &rows)?;
+    }
+
+    if let Some(config) = plot {
+        let size = (config.width, config.height);
+        let title = config.title.as_deref().unwrap_or(&name);
+        match config.backend {
+            PlotBackend::Png => draw_profile(
+                BitMapBackend::new(&config.path, size).into_drawing_area(),
+                &rows,
+                title,
+            )?,
+            PlotBackend::Svg => draw_profile(
+                SVGBackend::new(&config.path, size).into_drawing_area(),
+                &rows,
+                title,
+            )?,
+        }
+        // Keep structured output on stdout parseable
+        eprintln!("Plot saved to {}", config.path.display());
+    }
+    Ok(())
+}
+
+// Draws the distinct k-mers and the complexity against k side by side
+fn draw_profile<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    rows: &[ResultRow],
+    title: &str,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let root = root.titled(title, ("sans-serif", 32))?;
+    let (left, right) = root.split_horizontally(root.dim_in_pixel().0 / 2);
+
+    let k_min = rows.iter().map(|row| row.k).min().unwrap_or(1) as f64;
+    let k_max = rows.iter().map(|row| row.k).max().unwrap_or(1) as f64;
+    // Leave room around a single k
+    let k_range = (k_min - 1.0)..(k_max + 1.0);
+    let blue = RGBColor(31, 119, 180);
+
+    let max_distinct = rows.iter().map(|row| row.ci95.1).fold(1.0f64, f64::max);
+    let mut chart = ChartBuilder::on(&left)
+        .caption("Distinct k-mers", ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(90)
+        .build_cartesian_2d(k_range.clone(), 0.0..max_distinct * 1.1)?;
+    chart
+        .configure_mesh()
+        .x_desc("k")
+        .y_desc("distinct k-mers")
+        .label_style(("sans-serif", 16))
+        .draw()?;
+    chart.draw_series(rows.iter().map(|row| {
+        ErrorBar::new_vertical(
+            row.k as f64,
+            row.ci95.0,
+            row.estimate,
+            row.ci95.1,
+            blue.filled(),
+            8,
+        )
+    }))?;
+    chart.draw_series(LineSeries::new(
+        rows.iter().map(|row| (row.k as f64, row.estimate)),
+        blue.stroke_width(2),
+    ))?;
+
+    let mut chart = ChartBuilder::on(&right)
+        .caption("Complexity", ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(70)
+        .build_cartesian_2d(k_range, 0.0..1.05)?;
+    chart
+        .configure_mesh()
+        .x_desc("k")
+        .y_desc("distinct / total")
+        .label_style(("sans-serif", 16))
+        .draw()?;
+    chart.draw_series(LineSeries::new(
+        rows.iter().map(|row| (row.k as f64, row.complexity())),
+        blue.stroke_width(2),
+    ))?;
+    chart.draw_series(
+        rows.iter()
+            .map(|row| Circle::new((row.k as f64, row.complexity()), 4, blue.filled())),
+    )?;
+
+    root.present()?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use hll_rust::counters::{PackedRegisters, Registers};
+use hll_rust::{Counter, HLLCounter};
+use std::time::{Duration, Instant};
+
+const NUM_ITEMS: u64 = 10_000_000;
+
+fn bench_backend<S: std::hash::BuildHasher + Default, R: Registers>(
+    size: usize,
+) -> (usize, Duration, Duration) {
+    let mut counter: HLLCounter<S, R> = HLLCounter::new(size);
+    counter.to_dense();
+
+    let start = Instant::now();
+    for i in 0..NUM_ITEMS {
+        counter.add_u64(i);
+    }
+    let add_duration = start.elapsed();
+
+    let start = Instant::now();
+    std::hint::black_box(counter.estimate());
+    let estimate_duration = start.elapsed();
+
+    (counter.size_in_bytes(), add_duration, estimate_duration)
+}
+
+/// Compares memory usage and speed of byte and 6-bit packed HLL registers.
+pub fn compare_register_backends<S: std::hash::BuildHasher + Default>() {
+    println!(
+        "\n{:<10} | {:<8} | {:<12} | {:<15} | {:<15}",
+        "Precision", "Backend", "Memory (B)", "Add (ns/item)", "Estimate"
+    );
+    println!("{:-<72}", "");
+
+    for size in [12, 14, 16, 18] {
+        let results = [
+            ("u8", bench_backend::<S, Vec<u8>>(size)),
+            ("packed", bench_backend::<S, PackedRegisters>(size)),
+        ];
+        for (backend, (memory, add_duration, estimate_duration)) in results {
+            println!(
+                "{:<10} | {:<8} | {:<12} | {:<15.2} | {:?}",
+                size,
+                backend,
+                memory,
+                add_duration.as_nanos() as f64 / NUM_ITEMS as f64,
+                estimate_duration
+            );
+        }
+    }
+    println!();
+}
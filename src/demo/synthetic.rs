@@ -1,20 +1,50 @@
-use hll_rust::{Counter, FMCounter, HLLCounter, LinearCounter};
+use clap::ValueEnum;
+use hll_rust::{Counter, EstimateWithError, FMCounter, HLLCounter, LinearCounter, PCSACounter};
+use plotters::coord::Shift;
 use plotters::prelude::*;
 use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+// Sizes of the compared counters
+const LINEAR_BITS: usize = 1 << 20;
+const FM_BITS: usize = 32;
+// 2^17 64-bit bitmaps use the same memory as the 2^20 HLL registers
+const PCSA_PRECISION: usize = 17;
+const HLL_PRECISION: usize = 20;
+
+// Largest cardinality of the test data
+const MAX_N: f64 = 16777216.0;
+
+// Colors for each seed (matching matplotlib default colors)
+const COLORS: [RGBColor; 9] = [
+    RGBColor(31, 119, 180),  // blue
+    RGBColor(255, 127, 14),  // orange
+    RGBColor(44, 160, 44),   // green
+    RGBColor(214, 39, 40),   // red
+    RGBColor(148, 103, 189), // purple
+    RGBColor(140, 86, 75),   // brown
+    RGBColor(227, 119, 194), // pink
+    RGBColor(127, 127, 127), // gray
+    RGBColor(188, 189, 34),  // olive
+];
 
 pub type SeedData = (u64, Vec<(f64, f64)>);
 
-fn process_seed<S: std::hash::BuildHasher + Default>(
-    seed: u64,
-    ns: &[u64],
-) -> (SeedData, SeedData, SeedData) {
+// Linear, FM, PCSA and HLL data for one seed
+type SeedResults = (SeedData, SeedData, SeedData, SeedData);
+// Linear, FM, PCSA and HLL data for all seeds
+type TestData = (Vec<SeedData>, Vec<SeedData>, Vec<SeedData>, Vec<SeedData>);
+
+fn process_seed<S: std::hash::BuildHasher + Default>(seed: u64, ns: &[u64]) -> SeedResults {
     let mut linear_points = Vec::new();
     let mut fm_points = Vec::new();
+    let mut pcsa_points = Vec::new();
     let mut hll_points = Vec::new();
 
-    let mut linear_counter: LinearCounter<S> = LinearCounter::new(1 << 20);
-    let mut fm_counter: FMCounter<S> = FMCounter::new(32);
-    let mut hll_counter: HLLCounter<S> = HLLCounter::new(20);
+    let mut linear_counter: LinearCounter<S> = LinearCounter::new(LINEAR_BITS);
+    let mut fm_counter: FMCounter<S> = FMCounter::new(FM_BITS);
+    let mut pcsa_counter: PCSACounter<S> = PCSACounter::new(PCSA_PRECISION);
+    let mut hll_counter: HLLCounter<S> = HLLCounter::new(HLL_PRECISION);
 
     let mut last_n = 0;
     for &n in ns {
@@ -23,39 +53,46 @@ fn process_seed<S: std::hash::BuildHasher + Default>(
             let bytes = value.to_le_bytes();
             linear_counter.add(&bytes);
             fm_counter.add(&bytes);
+            pcsa_counter.add(&bytes);
             hll_counter.add(&bytes);
         }
         last_n = n;
 
         linear_points.push((n as f64, linear_counter.estimate()));
         fm_points.push((n as f64, fm_counter.estimate()));
+        pcsa_points.push((n as f64, pcsa_counter.estimate()));
         hll_points.push((n as f64, hll_counter.estimate()));
     }
 
-    ((seed, linear_points), (seed, fm_points), (seed, hll_points))
+    (
+        (seed, linear_points),
+        (seed, fm_points),
+        (seed, pcsa_points),
+        (seed, hll_points),
+    )
 }
 
-pub fn collect_test_data_sequential<S: std::hash::BuildHasher + Default>()
--> (Vec<SeedData>, Vec<SeedData>, Vec<SeedData>) {
+pub fn collect_test_data_sequential<S: std::hash::BuildHasher + Default>() -> TestData {
     let seeds: Vec<u64> = (1..=9).collect();
     let ns: Vec<u64> = (0..25).map(|i| 1u64 << i).collect();
 
     let mut linear_data = Vec::new();
     let mut fm_data = Vec::new();
+    let mut pcsa_data = Vec::new();
     let mut hll_data = Vec::new();
 
     for &seed in &seeds {
-        let (l, f, h) = process_seed::<S>(seed, &ns);
+        let (l, f, p, h) = process_seed::<S>(seed, &ns);
         linear_data.push(l);
         fm_data.push(f);
+        pcsa_data.push(p);
         hll_data.push(h);
     }
 
-    (linear_data, fm_data, hll_data)
+    (linear_data, fm_data, pcsa_data, hll_data)
 }
 
-pub fn collect_test_data_parallel<S: std::hash::BuildHasher + Default + Send + Sync>()
--> (Vec<SeedData>, Vec<SeedData>, Vec<SeedData>) {
+pub fn collect_test_data_parallel<S: std::hash::BuildHasher + Default + Send + Sync>() -> TestData {
     let seeds: Vec<u64> = (1..=9).collect();
     let ns: Vec<u64> = (0..25).map(|i| 1u64 << i).collect();
 
@@ -66,29 +103,285 @@ pub fn collect_test_data_parallel<S: std::hash::BuildHasher + Default + Send + S
 
     let mut linear_data = Vec::new();
     let mut fm_data = Vec::new();
+    let mut pcsa_data = Vec::new();
     let mut hll_data = Vec::new();
 
-    for (l, f, h) in results {
+    for (l, f, p, h) in results {
         linear_data.push(l);
         fm_data.push(f);
+        pcsa_data.push(p);
         hll_data.push(h);
     }
 
-    (linear_data, fm_data, hll_data)
+    (linear_data, fm_data, pcsa_data, hll_data)
+}
+
+/// File format of the comparison plot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum PlotBackend {
+    /// PNG bitmap
+    #[default]
+    Png,
+    /// Scalable vector graphics
+    Svg,
+}
+
+impl PlotBackend {
+    /// SVG for paths ending in `.svg` (in any case), PNG otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("svg") => PlotBackend::Svg,
+            _ => PlotBackend::Png,
+        }
+    }
+}
+
+/// Where and how [`plot_comparison`] draws the plots.
+#[derive(Debug, Clone)]
+pub struct PlotConfig {
+    pub path: PathBuf,
+    pub backend: PlotBackend,
+    /// Width in pixels, split evenly between the four counters
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Caption above the charts, if any
+    pub title: Option<String>,
+}
+
+impl PlotConfig {
+    /// Path of the relative error plot: `path` with `_error` appended to
+    /// the file stem.
+    pub fn error_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut name = format!("{stem}_error");
+        if let Some(ext) = self.path.extension() {
+            name = format!("{name}.{}", ext.to_string_lossy());
+        }
+        self.path.with_file_name(name)
+    }
 }
 
+impl Default for PlotConfig {
+    fn default() -> Self {
+        PlotConfig {
+            path: PathBuf::from("counter_comparison.png"),
+            backend: PlotBackend::Png,
+            width: 3200,
+            height: 800,
+            title: None,
+        }
+    }
+}
+
+/// Collects the synthetic test data and plots the estimates of the counters
+/// as configured by `config`, and their relative errors next to it at
+/// [`PlotConfig::error_path`]. Returns the paths of both plots.
 pub fn plot_comparison<S: std::hash::BuildHasher + Default + Send + Sync>(
     parallel: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
+    config: &PlotConfig,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
     println!("Collecting test data (parallel={})...", parallel);
-    let (linear_data, fm_data, hll_data) = if parallel {
+    let data = if parallel {
         collect_test_data_parallel::<S>()
     } else {
         collect_test_data_sequential::<S>()
     };
 
+    let size = (config.width, config.height);
+    match config.backend {
+        PlotBackend::Png => draw_comparison(
+            BitMapBackend::new(&config.path, size).into_drawing_area(),
+            &data,
+            config.title.as_deref(),
+        )?,
+        PlotBackend::Svg => draw_comparison(
+            SVGBackend::new(&config.path, size).into_drawing_area(),
+            &data,
+            config.title.as_deref(),
+        )?,
+    }
+    println!("Plot saved to {}", config.path.display());
+
+    let error_path = config.error_path();
+    let bands = error_bands::<S>(&data.3);
+    match config.backend {
+        PlotBackend::Png => draw_relative_error(
+            BitMapBackend::new(&error_path, size).into_drawing_area(),
+            &data,
+            &bands,
+            config.title.as_deref(),
+        )?,
+        PlotBackend::Svg => draw_relative_error(
+            SVGBackend::new(&error_path, size).into_drawing_area(),
+            &data,
+            &bands,
+            config.title.as_deref(),
+        )?,
+    }
+    println!("Relative error plot saved to {}", error_path.display());
+
+    Ok(vec![config.path.clone(), error_path])
+}
+
+// Theoretical relative standard error of the Linear, FM, PCSA and HLL
+// counters at the cardinalities of `seeds`
+fn error_bands<S: std::hash::BuildHasher + Default>(seeds: &[SeedData]) -> [Vec<(f64, f64)>; 4] {
+    let ns: Vec<f64> = seeds
+        .first()
+        .map(|(_, points)| points.iter().map(|&(n, _)| n).collect())
+        .unwrap_or_default();
+    // Linear counting depends on the load factor t = n / m, see
+    // `LinearCounter::relative_std_error` (Whang et al., 1990)
+    let m = LINEAR_BITS as f64;
+    let linear = ns
+        .iter()
+        .map(|&n| {
+            let t = n / m;
+            (n, (m * (t.exp() - t - 1.0)).sqrt() / n)
+        })
+        .collect();
+    let constant = |error: f64| ns.iter().map(|&n| (n, error)).collect();
+    [
+        linear,
+        constant(FMCounter::<S>::new(FM_BITS).relative_std_error()),
+        constant(PCSACounter::<S>::new(PCSA_PRECISION).relative_std_error()),
+        constant(HLLCounter::<S>::new(HLL_PRECISION).relative_std_error()),
+    ]
+}
+
+// Draws estimate / n - 1 for every seed with the theoretical 1 sigma band
+// shaded around zero.
+fn draw_relative_error<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    (linear_data, fm_data, pcsa_data, hll_data): &TestData,
+    bands: &[Vec<(f64, f64)>; 4],
+    title: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let root = match title {
+        Some(title) => root.titled(title, ("sans-serif", 40))?,
+        None => root,
+    };
+
+    let areas = root.split_evenly((1, 4));
+    let datasets = [
+        ("LinearCounting", linear_data),
+        ("FM", fm_data),
+        ("PCSA", pcsa_data),
+        ("HLL", hll_data),
+    ];
+
+    for (idx, ((area, (title, data)), band)) in
+        areas.iter().zip(datasets.iter()).zip(bands).enumerate()
+    {
+        let errors: Vec<Vec<(f64, f64)>> = data
+            .iter()
+            .map(|(_, points)| points.iter().map(|&(n, y)| (n, y / n - 1.0)).collect())
+            .collect();
+        // Scale to the errors and the band, but not beyond 100% so small
+        // errors stay visible next to a saturated counter
+        let limit = errors
+            .iter()
+            .flatten()
+            .chain(band)
+            .map(|(_, e)| e.abs())
+            .filter(|e| e.is_finite())
+            .fold(0.0f64, f64::max)
+            .clamp(1e-3, 1.0)
+            * 1.1;
+        let clamp = |e: f64| {
+            if e.is_nan() {
+                e
+            } else {
+                e.clamp(-limit, limit)
+            }
+        };
+
+        let mut chart = ChartBuilder::on(area)
+            .caption(
+                format!("{title} relative error"),
+                ("sans-serif", 32).into_font(),
+            )
+            .margin(15)
+            .x_label_area_size(50)
+            .y_label_area_size(80)
+            .build_cartesian_2d((1.0f64..MAX_N).log_scale(), -limit..limit)?;
+
+        chart
+            .configure_mesh()
+            .x_desc("n")
+            .y_desc("estimate / n - 1")
+            .label_style(("sans-serif", 18))
+            .draw()?;
+
+        // Shade the theoretical 1 sigma band
+        let upper = band.iter().map(|&(n, e)| (n, clamp(e)));
+        let lower = band.iter().rev().map(|&(n, e)| (n, clamp(-e)));
+        chart
+            .draw_series(std::iter::once(Polygon::new(
+                upper.chain(lower).collect::<Vec<_>>(),
+                BLACK.mix(0.15).filled(),
+            )))?
+            .label("1 sigma")
+            .legend(|(x, y)| {
+                Rectangle::new([(x, y - 6), (x + 30, y + 6)], BLACK.mix(0.15).filled())
+            });
+
+        chart.draw_series(LineSeries::new(
+            vec![(1.0, 0.0), (MAX_N, 0.0)],
+            ShapeStyle::from(&BLACK).stroke_width(2),
+        ))?;
+
+        for (i, ((seed, _), points)) in data.iter().zip(&errors).enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            let points = points
+                .iter()
+                .filter(|(_, e)| !e.is_nan())
+                .map(|&(n, e)| (n, clamp(e)));
+            let series = LineSeries::new(points, color.stroke_width(3));
+
+            // Only add legend for the last (rightmost) chart
+            if idx == datasets.len() - 1 {
+                chart
+                    .draw_series(series)?
+                    .label(format!("seed {}", seed))
+                    .legend(move |(x, y)| {
+                        PathElement::new(vec![(x, y), (x + 30, y)], color.stroke_width(3))
+                    });
+            } else {
+                chart.draw_series(series)?;
+            }
+        }
+
+        if idx == datasets.len() - 1 {
+            chart
+                .configure_series_labels()
+                .position(SeriesLabelPosition::LowerLeft)
+                .label_font(("sans-serif", 18))
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.8))
+                .draw()?;
+        }
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+fn draw_comparison<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    (linear_data, fm_data, pcsa_data, hll_data): &TestData,
+    title: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     // Find the max value across all data for consistent scaling
-    let max_val = [&linear_data, &fm_data, &hll_data]
+    let max_val = [linear_data, fm_data, pcsa_data, hll_data]
         .iter()
         .flat_map(|data| {
             data.iter()
@@ -96,31 +389,19 @@ pub fn plot_comparison<S: std::hash::BuildHasher + Default + Send + Sync>(
         })
         .fold(0.0f64, f64::max);
 
-    let max_n = 16777216.0f64;
-
-    // Define colors for each seed (matching matplotlib default colors)
-    let colors = [
-        RGBColor(31, 119, 180),  // blue
-        RGBColor(255, 127, 14),  // orange
-        RGBColor(44, 160, 44),   // green
-        RGBColor(214, 39, 40),   // red
-        RGBColor(148, 103, 189), // purple
-        RGBColor(140, 86, 75),   // brown
-        RGBColor(227, 119, 194), // pink
-        RGBColor(127, 127, 127), // gray
-        RGBColor(188, 189, 34),  // olive
-    ];
-
-    // Create the plot with higher resolution
-    let root = BitMapBackend::new("counter_comparison.png", (2400, 800)).into_drawing_area();
     root.fill(&WHITE)?;
+    let root = match title {
+        Some(title) => root.titled(title, ("sans-serif", 40))?,
+        None => root,
+    };
 
-    let areas = root.split_evenly((1, 3));
+    let areas = root.split_evenly((1, 4));
 
     let datasets = [
-        ("LinearCounting", &linear_data),
-        ("FM", &fm_data),
-        ("HLL", &hll_data),
+        ("LinearCounting", linear_data),
+        ("FM", fm_data),
+        ("PCSA", pcsa_data),
+        ("HLL", hll_data),
     ];
 
     for (idx, (area, (title, data))) in areas.iter().zip(datasets.iter()).enumerate() {
@@ -130,7 +411,7 @@ pub fn plot_comparison<S: std::hash::BuildHasher + Default + Send + Sync>(
             .x_label_area_size(50)
             .y_label_area_size(80)
             .build_cartesian_2d(
-                (1.0f64..max_n).log_scale(),
+                (1.0f64..MAX_N).log_scale(),
                 (1.0f64..max_val * 1.5).log_scale(),
             )?;
 
@@ -143,18 +424,18 @@ pub fn plot_comparison<S: std::hash::BuildHasher + Default + Send + Sync>(
 
         // Draw the perfect counter line (y = x)
         chart.draw_series(LineSeries::new(
-            vec![(1.0, 1.0), (max_n, max_n)],
+            vec![(1.0, 1.0), (MAX_N, MAX_N)],
             ShapeStyle::from(&BLACK).stroke_width(2),
         ))?;
 
         // Draw each seed's data
         for (i, (seed, points)) in data.iter().enumerate() {
-            let color = colors[i % colors.len()];
+            let color = COLORS[i % COLORS.len()];
 
             let series = LineSeries::new(points.clone(), color.stroke_width(3));
 
             // Only add legend for the last (rightmost) chart
-            if idx == 2 {
+            if idx == datasets.len() - 1 {
                 chart
                     .draw_series(series)?
                     .label(format!("seed {}", seed))
@@ -167,7 +448,7 @@ pub fn plot_comparison<S: std::hash::BuildHasher + Default + Send + Sync>(
         }
 
         // Configure legend only for the last chart
-        if idx == 2 {
+        if idx == datasets.len() - 1 {
             chart
                 .configure_series_labels()
                 .position(SeriesLabelPosition::UpperLeft)
@@ -179,7 +460,5 @@ pub fn plot_comparison<S: std::hash::BuildHasher + Default + Send + Sync>(
     }
 
     root.present()?;
-    println!("Plot saved to counter_comparison.png");
-
     Ok(())
 }
@@ -0,0 +1,308 @@
+//! Pairwise comparison of many sketches, e.g. to build a tree of genomes,
+//! and screening of samples against reference genomes.
+
+use crate::counters::{Counter, Estimate, EstimateWithError, HLLCounter, Registers};
+use std::hash::BuildHasher;
+use std::io::{self, Write};
+
+/// Mash distance of two sequences with the given Jaccard index of their
+/// k-mers (Ondov et al., 2016).
+///
+/// Estimates the mutation rate per base as -1/k ln(2J / (1 + J)). Returns 1
+/// for sequences without shared k-mers.
+pub fn mash_distance(jaccard: f64, k: usize) -> f64 {
+    if jaccard <= 0.0 {
+        return 1.0;
+    }
+    let distance = ((1.0 + jaccard) / (2.0 * jaccard)).ln() / k as f64;
+    distance.clamp(0.0, 1.0)
+}
+
+/// Estimates the average nucleotide identity (ANI) of two genomes from their
+/// sketches of k-mers, as `sourmash` does from the containment C of the
+/// smaller genome in the larger one: ANI = C^(1/k).
+///
+/// A base mutated with probability 1 - ANI leaves a k-mer intact with
+/// probability ANI^k. The confidence interval transforms the 95% interval of
+/// the containment, whose standard error is derived from the relative error
+/// of the sketches and the three cardinalities of the inclusion-exclusion
+/// estimate. Returns an ANI of 0 for genomes without shared k-mers.
+///
+/// Panics if the sketches cannot be merged.
+pub fn ani_estimate<S, R>(a: &HLLCounter<S, R>, b: &HLLCounter<S, R>, k: usize) -> Estimate
+where
+    S: BuildHasher + Default + Clone,
+    R: Registers + Clone,
+{
+    let (size_a, size_b) = (a.estimate(), b.estimate());
+    let union = a.union_estimate(b).unwrap_or_else(|e| panic!("{e}"));
+    let smaller = size_a.min(size_b);
+    if smaller == 0.0 {
+        return Estimate::new(0.0, 0.0);
+    }
+
+    let intersection = (size_a + size_b - union).max(0.0);
+    let containment = f64::clamp(intersection / smaller, 0.0, 1.0);
+    let intersection_error =
+        a.relative_std_error() * (size_a * size_a + size_b * size_b + union * union).sqrt();
+    let containment_error = intersection_error / smaller;
+
+    let exponent = 1.0 / k as f64;
+    let ani = containment.powf(exponent);
+    // Delta method: d/dC C^(1/k) = C^(1/k) / (k C)
+    let std_error = if containment > 0.0 {
+        ani * exponent / containment * containment_error
+    } else {
+        0.0
+    };
+    let low = (containment - 1.96 * containment_error).max(0.0);
+    let high = (containment + 1.96 * containment_error).min(1.0);
+    Estimate {
+        value: ani,
+        std_error,
+        ci95: (low.powf(exponent), high.powf(exponent)),
+    }
+}
+
+/// Ranks reference genomes by the containment of their k-mers in a sample,
+/// e.g. to screen a metagenome against a database of genomes like a simple
+/// `sourmash gather`.
+///
+/// Returns the name of every reference with the estimated fraction of its
+/// k-mers found in `query`, highest first; references with equal
+/// containment keep their order. Unlike `gather`, k-mers shared by several
+/// references are counted for each of them.
+///
+/// Panics if a reference cannot be merged with `query`.
+///
+/// ```
+/// use hll_rust::distance::contain;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let sketch = |kmers: std::ops::Range<u64>| {
+///     let mut sketch = HLLCounter::<Xxh64Builder>::new(12);
+///     kmers.for_each(|kmer| sketch.add_u64(kmer));
+///     sketch
+/// };
+/// let sample = sketch(0..10_000);
+/// let references = vec![
+///     ("absent".to_string(), sketch(50_000..53_000)),
+///     ("present".to_string(), sketch(2_000..5_000)),
+/// ];
+/// let ranking = contain(&sample, &references);
+/// assert_eq!(ranking[0].0, "present");
+/// assert!(ranking[0].1 > 0.9 && ranking[1].1 < 0.1);
+/// ```
+pub fn contain<'a, S, R>(
+    query: &HLLCounter<S, R>,
+    references: &'a [(String, HLLCounter<S, R>)],
+) -> Vec<(&'a str, f64)>
+where
+    S: BuildHasher + Default + Clone,
+    R: Registers + Clone,
+{
+    let mut ranking: Vec<(&str, f64)> = references
+        .iter()
+        .map(|(name, reference)| (name.as_str(), reference.containment(query)))
+        .collect();
+    ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranking
+}
+
+/// A square matrix of pairwise values with one row and column per sketch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix {
+    pub names: Vec<String>,
+    /// `values[i][j]` compares sketch i with sketch j.
+    pub values: Vec<Vec<f64>>,
+}
+
+impl DistanceMatrix {
+    /// Compares all pairs of sketches with `compare`, which does not need to
+    /// be symmetric, e.g. the containment of one sketch in the other.
+    ///
+    /// ```
+    /// use hll_rust::distance::{DistanceMatrix, mash_distance};
+    /// use hll_rust::{Counter, HLLCounter};
+    /// use xxhash_rust::xxh64::Xxh64Builder;
+    ///
+    /// let sketches: Vec<HLLCounter<Xxh64Builder>> = (0..3u64)
+    ///     .map(|i| {
+    ///         let mut sketch = HLLCounter::new(12);
+    ///         for kmer in i * 1_000..i * 1_000 + 5_000 {
+    ///             sketch.add_u64(kmer);
+    ///         }
+    ///         sketch
+    ///     })
+    ///     .collect();
+    /// let names = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+    /// let jaccard = DistanceMatrix::pairwise(names, &sketches, |a, b| a.jaccard(b));
+    /// let distances = jaccard.map(|j| mash_distance(j, 21));
+    /// assert_eq!(distances.values[0][0], 0.0);
+    /// assert!(distances.values[0][1] < distances.values[0][2]);
+    /// ```
+    pub fn pairwise<T, F>(names: Vec<String>, sketches: &[T], compare: F) -> Self
+    where
+        F: Fn(&T, &T) -> f64,
+    {
+        assert_eq!(names.len(), sketches.len(), "every sketch needs a name");
+        let values = sketches
+            .iter()
+            .map(|a| sketches.iter().map(|b| compare(a, b)).collect())
+            .collect();
+        DistanceMatrix { names, values }
+    }
+
+    /// Applies `f` to every value, e.g. to turn Jaccard indexes into
+    /// distances with [`mash_distance`].
+    pub fn map<F: Fn(f64) -> f64>(&self, f: F) -> Self {
+        DistanceMatrix {
+            names: self.names.clone(),
+            values: self
+                .values
+                .iter()
+                .map(|row| row.iter().map(|&value| f(value)).collect())
+                .collect(),
+        }
+    }
+
+    /// Writes the matrix in the square PHYLIP format read by tree building
+    /// tools like PHYLIP's `neighbor`: the number of sequences, then one line
+    /// per sequence with its name and values. Whitespace in names is replaced
+    /// by underscores.
+    pub fn write_phylip<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", self.names.len())?;
+        for (name, row) in self.names.iter().zip(&self.values) {
+            let name = name.replace(char::is_whitespace, "_");
+            write!(writer, "{}", name)?;
+            for value in row {
+                write!(writer, " {:.6}", value)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the matrix as tab-separated values with the names as header
+    /// line and first column.
+    pub fn write_tsv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for name in &self.names {
+            write!(writer, "\t{}", name)?;
+        }
+        writeln!(writer)?;
+        for (name, row) in self.names.iter().zip(&self.values) {
+            write!(writer, "{}", name)?;
+            for value in row {
+                write!(writer, "\t{:.6}", value)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ani_estimate() {
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        // Random genome and a copy with 2% of the bases changed
+        let mut state = 1u64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let codes: Vec<usize> = (0..200_000).map(|_| random() as usize % 4).collect();
+        let genome: Vec<u8> = codes.iter().map(|&code| b"ACGT"[code]).collect();
+        let mutated: Vec<u8> = codes
+            .iter()
+            .map(|&code| {
+                if random() % 50 == 0 {
+                    b"ACGT"[(code + 1 + random() as usize % 3) % 4]
+                } else {
+                    b"ACGT"[code]
+                }
+            })
+            .collect();
+
+        let k = 21;
+        let sketch = |seq: &[u8]| {
+            let mut sketch = HLLCounter::<Xxh64Builder>::new(14);
+            for kmer in seq.windows(k) {
+                sketch.add(kmer);
+            }
+            sketch
+        };
+        let a = sketch(&genome);
+        let b = sketch(&mutated);
+
+        let ani = ani_estimate(&a, &b, k);
+        assert!((ani.value - 0.98).abs() < 0.003, "ANI {}", ani.value);
+        assert!(ani.ci95.0 < 0.98 && ani.ci95.1 > 0.98, "{:?}", ani.ci95);
+        assert!(ani.std_error > 0.0 && ani.std_error < 0.005);
+
+        assert_eq!(ani_estimate(&a, &a, k).value, 1.0);
+        let empty = HLLCounter::<Xxh64Builder>::new(14);
+        assert_eq!(ani_estimate(&a, &empty, k).value, 0.0);
+    }
+
+    #[test]
+    fn test_contain_ranks_references() {
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        fn sketch(kmers: impl Iterator<Item = u64>) -> HLLCounter<Xxh64Builder> {
+            let mut sketch = HLLCounter::new(14);
+            kmers.for_each(|kmer| sketch.add_u64(kmer));
+            sketch
+        }
+        // A sample of all of genome a, half of b and none of c
+        let sample = sketch((0..20_000).chain(100_000..105_000).chain(500_000..600_000));
+        let references = vec![
+            ("c".to_string(), sketch(200_000..230_000)),
+            ("b".to_string(), sketch(100_000..110_000)),
+            ("a".to_string(), sketch(0..20_000)),
+        ];
+        let ranking = contain(&sample, &references);
+        let names: Vec<&str> = ranking.iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(ranking[0].1 > 0.95, "{:?}", ranking);
+        assert!((ranking[1].1 - 0.5).abs() < 0.1, "{:?}", ranking);
+        assert!(ranking[2].1 < 0.05, "{:?}", ranking);
+
+        assert!(contain(&sample, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_mash_distance_and_output() {
+        assert_eq!(mash_distance(1.0, 21), 0.0);
+        assert_eq!(mash_distance(0.0, 21), 1.0);
+        // 2J / (1 + J) = 1/3
+        assert!((mash_distance(0.2, 21) - 3f64.ln() / 21.0).abs() < 1e-12);
+
+        let sets = [vec![1, 2, 3, 4], vec![3, 4, 5, 6]];
+        let names = vec!["genome a".to_string(), "b".to_string()];
+        let matrix = DistanceMatrix::pairwise(names, &sets, |a, b| {
+            a.iter().filter(|x| b.contains(x)).count() as f64 / a.len() as f64
+        });
+        assert_eq!(matrix.values, vec![vec![1.0, 0.5], vec![0.5, 1.0]]);
+
+        let mut phylip = Vec::new();
+        matrix.write_phylip(&mut phylip).unwrap();
+        assert_eq!(
+            String::from_utf8(phylip).unwrap(),
+            "2\ngenome_a 1.000000 0.500000\nb 0.500000 1.000000\n"
+        );
+        let mut tsv = Vec::new();
+        matrix.map(|x| 1.0 - x).write_tsv(&mut tsv).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "\tgenome a\tb\ngenome a\t0.000000\t0.500000\nb\t0.500000\t0.000000\n"
+        );
+    }
+}
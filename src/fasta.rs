@@ -1,5 +1,298 @@
 use std::collections::VecDeque;
-use std::io::{self, BufRead};
+use std::fmt;
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+#[cfg(feature = "fs")]
+use std::path::Path;
+use xxhash_rust::xxh64::xxh64;
+
+// Magic bytes at the start of every gzip (and thus bgzip) member
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Opens a sequence file for buffered reading, or standard input if `path`
+/// is `-`.
+///
+/// Gzip and bgzip compressed files are detected by their magic bytes and
+/// decompressed transparently. Decompression requires the `gzip` feature;
+/// without it, compressed files are rejected with an error.
+#[cfg(feature = "fs")]
+pub fn open_sequence_file<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead + Send>> {
+    let path = path.as_ref();
+    if path == Path::new("-") {
+        open_sequence_reader(io::stdin())
+    } else {
+        open_sequence_reader(File::open(path)?)
+    }
+}
+
+/// Wraps a reader of sequence data, e.g. a socket or a pipe, for buffered
+/// reading.
+///
+/// Compressed data is handled as by [`open_sequence_file`].
+pub fn open_sequence_reader<R: Read + Send + 'static>(
+    reader: R,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    // A single read may return fewer bytes than the magic, e.g. from a pipe
+    let mut reader = reader;
+    let mut magic = [0; GZIP_MAGIC.len()];
+    let mut len = 0;
+    while len < magic.len() {
+        match reader.read(&mut magic[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let is_gzip = magic[..len] == GZIP_MAGIC;
+    let reader = BufReader::new(io::Cursor::new(magic).take(len as u64).chain(reader));
+
+    if !is_gzip {
+        return Ok(Box::new(reader));
+    }
+
+    #[cfg(feature = "gzip")]
+    {
+        // bgzip files consist of many concatenated gzip members
+        let decoder = flate2::bufread::MultiGzDecoder::new(reader);
+        Ok(Box::new(BufReader::new(decoder)))
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Compressed input requires the `gzip` feature.",
+        ))
+    }
+}
+
+/// An error raised while reading a FASTA or FASTQ file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FastaError {
+    /// The underlying reader failed.
+    Io(io::Error),
+    /// A record does not start with the expected header line (1-based line number).
+    MalformedHeader { line_no: usize },
+    /// A sequence contains a byte that is not a residue, at the given
+    /// 0-based position within the record's sequence.
+    InvalidCharacter { byte: u8, position: usize },
+    /// A record ended before all of its lines were read.
+    TruncatedRecord,
+    /// A kmer contains an IUPAC ambiguity code, at the given 0-based
+    /// position within the record's sequence. Only raised with
+    /// [`KmerPolicy::reject_ambiguous`].
+    AmbiguousBase { byte: u8, position: usize },
+    /// A `.fai` index line is malformed, or a FASTA file cannot be indexed
+    /// because its lines have different lengths (1-based line number).
+    MalformedIndex { line_no: usize },
+    /// The index has no sequence with this name.
+    UnknownSequence(String),
+    /// A region is empty or extends past the end of its sequence.
+    InvalidRegion { start: u64, end: u64, length: u64 },
+    /// The mates of a read pair have different names, or one FASTQ file of
+    /// a pair has more reads than the other (1-based pair number).
+    MismatchedMates { pair: usize },
+    /// A SAM or BAM record is malformed (1-based record number).
+    MalformedAlignment { record: usize },
+    /// A FASTQ quality line does not have one score per base (1-based line
+    /// number).
+    QualityLengthMismatch { line_no: usize },
+}
+
+impl fmt::Display for FastaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FastaError::Io(e) => write!(f, "I/O error: {}", e),
+            FastaError::MalformedHeader { line_no } => {
+                write!(f, "malformed record header on line {}", line_no)
+            }
+            FastaError::InvalidCharacter { byte, position } => write!(
+                f,
+                "invalid character {:?} at sequence position {}",
+                char::from(*byte),
+                position
+            ),
+            FastaError::TruncatedRecord => write!(f, "truncated record"),
+            FastaError::AmbiguousBase { byte, position } => write!(
+                f,
+                "ambiguous base {:?} at sequence position {}",
+                char::from(*byte),
+                position
+            ),
+            FastaError::MalformedIndex { line_no } => {
+                write!(f, "cannot index line {}", line_no)
+            }
+            FastaError::UnknownSequence(name) => write!(f, "unknown sequence {:?}", name),
+            FastaError::InvalidRegion { start, end, length } => write!(
+                f,
+                "invalid region {}-{} of a sequence of length {}",
+                start, end, length
+            ),
+            FastaError::MismatchedMates { pair } => {
+                write!(f, "mates of read pair {} do not match", pair)
+            }
+            FastaError::MalformedAlignment { record } => {
+                write!(f, "malformed alignment record {}", record)
+            }
+            FastaError::QualityLengthMismatch { line_no } => {
+                write!(f, "quality and sequence lengths differ on line {}", line_no)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FastaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FastaError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FastaError {
+    fn from(e: io::Error) -> Self {
+        FastaError::Io(e)
+    }
+}
+
+// Residues are letters, plus '*' (stop) and '-' (gap)
+#[inline(always)]
+pub(crate) fn is_residue(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || byte == b'*' || byte == b'-'
+}
+
+// Checks that a sequence line starting at `offset` within its record only
+// contains residues.
+pub(crate) fn validate_sequence(line: &[u8], offset: usize) -> Result<(), FastaError> {
+    match line.iter().position(|&byte| !is_residue(byte)) {
+        Some(i) => Err(FastaError::InvalidCharacter {
+            byte: line[i],
+            position: offset + i,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// How kmer iterators treat bases other than `ACGT`.
+///
+/// The default keeps every kmer unchanged. [`KmerPolicy::STRICT`] yields the
+/// same kmers as the 2-bit encoded pipeline in `parallel_counting`, which
+/// ignores case and skips kmers with any other base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KmerPolicy {
+    /// Skip kmers containing IUPAC ambiguity codes such as `N`.
+    pub skip_ambiguous: bool,
+    /// Convert soft-masked (lowercase) bases to uppercase.
+    pub uppercase: bool,
+    /// Skip kmers containing any base other than `A`, `C`, `G` and `T`,
+    /// checked after `uppercase` is applied.
+    pub require_acgt: bool,
+    /// Return [`FastaError::AmbiguousBase`] once for every IUPAC ambiguity
+    /// code instead of yielding or skipping the kmers containing it. Takes
+    /// precedence over the options that skip kmers.
+    pub reject_ambiguous: bool,
+}
+
+impl KmerPolicy {
+    pub const STRICT: KmerPolicy = KmerPolicy {
+        skip_ambiguous: true,
+        uppercase: true,
+        require_acgt: true,
+        reject_ambiguous: false,
+    };
+
+    // Whether any kmers are skipped at all
+    fn skips_bases(&self) -> bool {
+        self.skip_ambiguous || self.require_acgt
+    }
+
+    #[inline(always)]
+    fn rejects(&self, base: u8) -> bool {
+        (self.require_acgt && !matches!(base, b'A' | b'C' | b'G' | b'T'))
+            || (self.skip_ambiguous && is_ambiguous(base))
+    }
+
+    // Position of the first ambiguous base of a kmer, if those are errors
+    pub(crate) fn first_ambiguous(&self, kmer: &[u8]) -> Option<usize> {
+        if !self.reject_ambiguous {
+            return None;
+        }
+        kmer.iter().position(|&base| is_ambiguous(base))
+    }
+
+    // Position of the last rejected base of a kmer
+    pub(crate) fn last_rejected(&self, kmer: &[u8]) -> Option<usize> {
+        if !self.skips_bases() {
+            return None;
+        }
+        kmer.iter().rposition(|&base| self.rejects(base))
+    }
+}
+
+// IUPAC nucleotide ambiguity codes
+fn is_ambiguous(base: u8) -> bool {
+    matches!(
+        base.to_ascii_uppercase(),
+        b'N' | b'R' | b'Y' | b'S' | b'W' | b'K' | b'M' | b'B' | b'D' | b'H' | b'V'
+    )
+}
+
+/// Statistics of the sequence of a FASTA record, see [`FastaReader::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecordStats {
+    /// Number of residues.
+    pub length: usize,
+    /// Number of `G`, `C`, `g` and `c` bases.
+    pub gc_count: usize,
+    /// Number of `A`, `T`, `a` and `t` bases.
+    pub at_count: usize,
+    /// Number of `N` and `n` bases, e.g. assembly gaps.
+    pub n_count: usize,
+    /// Number of sequence lines.
+    pub lines: usize,
+}
+
+impl RecordStats {
+    /// Computes the statistics of a sequence on a single line.
+    pub fn from_sequence(seq: &[u8]) -> Self {
+        let mut stats = RecordStats::default();
+        stats.add_line(seq);
+        stats
+    }
+
+    pub(crate) fn add_line(&mut self, line: &[u8]) {
+        self.lines += 1;
+        self.length += line.len();
+        for &base in line {
+            match base.to_ascii_uppercase() {
+                b'G' | b'C' => self.gc_count += 1,
+                b'A' | b'T' => self.at_count += 1,
+                b'N' => self.n_count += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Fraction of G and C among the unambiguous bases, 0 if there are none.
+    pub fn gc_content(&self) -> f64 {
+        let acgt = self.gc_count + self.at_count;
+        if acgt == 0 {
+            0.0
+        } else {
+            self.gc_count as f64 / acgt as f64
+        }
+    }
+
+    /// Number of residues that are not `N`, the length to normalize k-mer
+    /// counts of assemblies with gaps by.
+    pub fn effective_length(&self) -> usize {
+        self.length - self.n_count
+    }
+}
 
 /// A simple FASTA reader that reads records one by one.
 ///
@@ -7,7 +300,11 @@ use std::io::{self, BufRead};
 pub struct FastaReader<R: BufRead> {
     reader: R,
     line: String,
+    // Number of lines read so far, for error messages
+    line_no: usize,
     finished: bool,
+    kmer_policy: KmerPolicy,
+    stats: RecordStats,
     pub id: Option<Vec<u8>>,
 }
 
@@ -17,35 +314,51 @@ impl<R: BufRead> FastaReader<R> {
         FastaReader {
             reader,
             line: String::new(),
+            line_no: 0,
             finished: false,
+            kmer_policy: KmerPolicy::default(),
+            stats: RecordStats::default(),
             id: None,
         }
     }
 
+    /// Applies the given policy to the kmers of all iterators.
+    pub fn with_kmer_policy(mut self, policy: KmerPolicy) -> Self {
+        self.kmer_policy = policy;
+        self
+    }
+
+    // Reads the next line into `self.line`, returning the number of bytes read.
+    fn read_line(&mut self) -> Result<usize, FastaError> {
+        self.line.clear();
+        let bytes_read = self.reader.read_line(&mut self.line)?;
+        if bytes_read > 0 {
+            self.line_no += 1;
+        }
+        Ok(bytes_read)
+    }
+
     /// Advances the reader to the next record.
     ///
     /// Returns `Ok(true)` if a record was found, `Ok(false)` if EOF was reached.
     /// The record ID is stored in `self.id`.
-    pub fn next_record(&mut self) -> io::Result<bool> {
+    pub fn next_record(&mut self) -> Result<bool, FastaError> {
         if self.finished {
             return Ok(false);
         }
 
-        if self.line.is_empty() {
-            self.line.clear();
-            if self.reader.read_line(&mut self.line)? == 0 {
-                self.finished = true;
-                return Ok(false);
-            }
+        if self.line.is_empty() && self.read_line()? == 0 {
+            self.finished = true;
+            return Ok(false);
         }
 
         if !self.line.starts_with('>') {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Expected '>' at the start of a fasta record.",
-            ));
+            return Err(FastaError::MalformedHeader {
+                line_no: self.line_no,
+            });
         }
 
+        self.stats = RecordStats::default();
         self.id = Some(
             self.line
                 .trim_start_matches('>')
@@ -58,6 +371,33 @@ impl<R: BufRead> FastaReader<R> {
         Ok(true)
     }
 
+    // Number of bytes consumed from the reader that belong to the next
+    // record: its header line, read ahead to find the end of the current one.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn lookahead_len(&self) -> usize {
+        self.line.len()
+    }
+
+    // The wrapped reader.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Statistics of the sequence lines of the current record read so far.
+    ///
+    /// They are complete once the record was consumed, by
+    /// [`FastaReader::read_sequence`] or by a kmer iterator that was
+    /// exhausted or dropped.
+    pub fn stats(&self) -> &RecordStats {
+        &self.stats
+    }
+
+    /// Returns a streaming iterator over the records, see [`Records`].
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records { reader: self }
+    }
+
     /// Returns an iterator over the kmers of the current record.
     pub fn kmers<'a>(&'a mut self, k: usize) -> KmerStream<'a, R> {
         KmerStream::new(self, k)
@@ -70,49 +410,284 @@ impl<R: BufRead> FastaReader<R> {
         CanonicalKmerStream::new(self.kmers(k))
     }
 
+    /// Returns an iterator over the 2-bit encoded canonical kmers of the
+    /// current record, as `u64` for k up to 32 or `u128` for k up to 64.
+    ///
+    /// Kmers with bases other than `ACGT` (in any case) are skipped, like in
+    /// `parallel_counting`. This avoids allocating and is much faster than
+    /// [`FastaReader::canonical_kmers`].
+    pub fn encoded_kmers<'a, T: EncodedKmer>(
+        &'a mut self,
+        k: usize,
+    ) -> EncodedKmerStream<'a, R, T> {
+        EncodedKmerStream::new(self, k)
+    }
+
+    /// Returns an iterator over the canonical ntHash values of the kmers of
+    /// the current record, see [`NtHash`].
+    ///
+    /// Unlike the encoded kmers, this works for any `k` and does not compute
+    /// reverse complements.
+    pub fn nthashes<'a>(&'a mut self, k: usize) -> NtHashStream<'a, R> {
+        NtHashStream::new(self, k)
+    }
+
+    /// Returns an iterator over the 5-bit encoded protein kmers of the current
+    /// record, see [`ProteinKmerStream`].
+    pub fn protein_kmers<'a>(
+        &'a mut self,
+        k: usize,
+        alphabet: ProteinAlphabet,
+    ) -> ProteinKmerStream<'a, R> {
+        ProteinKmerStream::new(self, k, alphabet)
+    }
+
+    /// Returns an iterator over the spaced kmers of the current record, see
+    /// [`SpacedSeed`].
+    pub fn spaced_kmers<'a>(
+        &'a mut self,
+        seed: &SpacedSeed,
+    ) -> SpacedKmerStream<KmerStream<'a, R>> {
+        SpacedKmerStream::new(self.kmers(seed.span()), seed.clone())
+    }
+
+    /// Returns an iterator over the canonical spaced kmers of the current
+    /// record, see [`SpacedSeed::apply_canonical`].
+    pub fn canonical_spaced_kmers<'a>(
+        &'a mut self,
+        seed: &SpacedSeed,
+    ) -> SpacedKmerStream<KmerStream<'a, R>> {
+        SpacedKmerStream::canonical(self.kmers(seed.span()), seed.clone())
+    }
+
+    /// Returns an iterator over the closed `(k, s)` syncmers of the current
+    /// record, see [`SyncmerStream`].
+    pub fn syncmers<'a>(
+        &'a mut self,
+        k: usize,
+        s: usize,
+    ) -> SyncmerStream<EncodedKmerStream<'a, R, u64>> {
+        SyncmerStream::new(self.encoded_kmers(k), k, s, SyncmerKind::Closed)
+    }
+
+    /// Returns an iterator over the `(k, w)` minimizers of the current record.
+    ///
+    /// See [`MinimizerStream`] for details.
+    pub fn minimizers<'a>(
+        &'a mut self,
+        k: usize,
+        w: usize,
+    ) -> MinimizerStream<CanonicalKmerStream<KmerStream<'a, R>>> {
+        MinimizerStream::new(self.canonical_kmers(k), w)
+    }
+
+    // Consumes the rest of the lines of the current sequence until the next
+    // record or EOF.
+    fn skip_sequence(&mut self) {
+        loop {
+            if let Ok(bytes_read) = self.read_line() {
+                if bytes_read == 0 {
+                    self.finished = true;
+                    break;
+                }
+                if self.line.starts_with('>') {
+                    break;
+                }
+                self.stats.add_line(self.line.trim().as_bytes());
+            } else {
+                // On an IO error, we can't do much but stop.
+                self.finished = true;
+                break;
+            }
+        }
+    }
+
     /// Reads the full sequence of the current record.
     ///
     /// This consumes the rest of the current record.
-    pub fn read_sequence(&mut self) -> io::Result<Vec<u8>> {
+    pub fn read_sequence(&mut self) -> Result<Vec<u8>, FastaError> {
         let mut sequence = Vec::new();
         loop {
-            self.line.clear();
-            let bytes_read = self.reader.read_line(&mut self.line)?;
-            if bytes_read == 0 {
+            if self.read_line()? == 0 {
                 self.finished = true;
                 break;
             }
             if self.line.starts_with('>') {
                 break;
             }
-            sequence.extend_from_slice(self.line.trim().as_bytes());
+            let line = self.line.trim().as_bytes();
+            validate_sequence(line, sequence.len())?;
+            self.stats.add_line(line);
+            sequence.extend_from_slice(line);
         }
         Ok(sequence)
     }
 }
 
+/// A streaming iterator over the records of a [`FastaReader`].
+///
+/// Every [`Record`] borrows the reader, so this is not an `Iterator`:
+/// records are visited one at a time with `while let` and
+/// [`Records::next_record`] instead of a `for` loop. A record whose sequence was not
+/// read is skipped when it is dropped, so the sequence is only read as far
+/// as needed and never buffered as a whole unless requested.
+///
+/// ```
+/// use hll_rust::fasta::FastaReader;
+///
+/// let data: &[u8] = b">a\nACGTAC\n>b\nGGG\nCC\n";
+/// let mut reader = FastaReader::new(data);
+/// let mut records = reader.records();
+/// while let Some(record) = records.next_record() {
+///     let mut record = record.unwrap();
+///     let kmers = record.kmers(3).count();
+///     println!("{}: {kmers} kmers", String::from_utf8_lossy(record.id()));
+/// }
+/// ```
+pub struct Records<'a, R: BufRead> {
+    reader: &'a mut FastaReader<R>,
+}
+
+impl<'a, R: BufRead> Records<'a, R> {
+    /// Advances to the next record, skipping the rest of the previous one.
+    ///
+    /// Returns `None` at EOF.
+    pub fn next_record(&mut self) -> Option<Result<Record<'_, R>, FastaError>> {
+        match self.reader.next_record() {
+            Ok(true) => {
+                let id = self.reader.id.clone().unwrap_or_default();
+                Some(Ok(Record {
+                    reader: &mut *self.reader,
+                    id,
+                }))
+            }
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A record of a FASTA file, see [`Records`].
+///
+/// The ID is owned, while the sequence is read lazily by one of the methods
+/// below. Each of them consumes the sequence, so only one of them can be
+/// used per record.
+pub struct Record<'a, R: BufRead> {
+    reader: &'a mut FastaReader<R>,
+    id: Vec<u8>,
+}
+
+impl<'a, R: BufRead> Record<'a, R> {
+    /// The header line without the leading `>`.
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// Takes the ID out of the record.
+    pub fn into_id(mut self) -> Vec<u8> {
+        std::mem::take(&mut self.id)
+    }
+
+    /// Reads the full sequence, see [`FastaReader::read_sequence`].
+    pub fn sequence(&mut self) -> Result<Vec<u8>, FastaError> {
+        self.reader.read_sequence()
+    }
+
+    /// Statistics of the sequence read so far, see [`FastaReader::stats`].
+    pub fn stats(&self) -> &RecordStats {
+        self.reader.stats()
+    }
+
+    /// Returns an iterator over the canonical kmer hashes, see
+    /// [`FastaReader::nthashes`].
+    pub fn nthashes(&mut self, k: usize) -> NtHashStream<'_, R> {
+        self.reader.nthashes(k)
+    }
+
+    /// Returns an iterator over the kmers, see [`FastaReader::kmers`].
+    pub fn kmers(&mut self, k: usize) -> KmerStream<'_, R> {
+        self.reader.kmers(k)
+    }
+
+    /// Returns an iterator over the canonical kmers, see
+    /// [`FastaReader::canonical_kmers`].
+    pub fn canonical_kmers(&mut self, k: usize) -> CanonicalKmerStream<KmerStream<'_, R>> {
+        self.reader.canonical_kmers(k)
+    }
+
+    /// Returns an iterator over the 2-bit encoded canonical kmers, see
+    /// [`FastaReader::encoded_kmers`].
+    pub fn encoded_kmers<T: EncodedKmer>(&mut self, k: usize) -> EncodedKmerStream<'_, R, T> {
+        self.reader.encoded_kmers(k)
+    }
+
+    /// Returns an iterator over the `(k, w)` minimizers, see
+    /// [`FastaReader::minimizers`].
+    pub fn minimizers(
+        &mut self,
+        k: usize,
+        w: usize,
+    ) -> MinimizerStream<CanonicalKmerStream<KmerStream<'_, R>>> {
+        self.reader.minimizers(k, w)
+    }
+}
+
+impl<'a, R: BufRead> Drop for Record<'a, R> {
+    fn drop(&mut self) {
+        // Right after the header, neither the next header nor EOF was read
+        if self.reader.line.is_empty() && !self.reader.finished {
+            self.reader.skip_sequence();
+        }
+    }
+}
+
 /// An iterator over the kmers of a FASTA record.
+///
+/// Besides the allocating `Iterator` implementation, [`KmerStream::next_kmer`]
+/// and [`KmerStream::next_canonical_kmer`] return slices into an internal
+/// buffer without allocating per kmer.
 pub struct KmerStream<'a, R: BufRead> {
     reader: &'a mut FastaReader<R>,
     k: usize,
-    buffer: VecDeque<u8>,
+    // Contiguous buffer of the sequence; the current kmer starts at `start`
+    buffer: Vec<u8>,
+    start: usize,
+    // Scratch space for the reverse complement of the current kmer
+    canonical: Vec<u8>,
+    // Number of bases of the record read so far
+    bases_read: usize,
+    policy: KmerPolicy,
     stream_finished: bool,
 }
 
 impl<'a, R: BufRead> KmerStream<'a, R> {
     fn new(reader: &'a mut FastaReader<R>, k: usize) -> Self {
+        let policy = reader.kmer_policy;
         KmerStream {
             reader,
             k,
-            buffer: VecDeque::with_capacity(k * 2),
+            buffer: Vec::with_capacity(k * 2),
+            start: 0,
+            canonical: vec![0; k],
+            bases_read: 0,
+            policy,
             stream_finished: false,
         }
     }
 
-    fn fill_buffer(&mut self) -> io::Result<()> {
-        while self.buffer.len() < self.k && !self.stream_finished {
-            self.reader.line.clear();
-            let bytes_read = self.reader.reader.read_line(&mut self.reader.line)?;
+    // Number of buffered bases from the start of the current kmer
+    fn available(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    fn fill_buffer(&mut self) -> Result<(), FastaError> {
+        while self.available() < self.k && !self.stream_finished {
+            // Move the (fewer than k) remaining bases to the front, so the
+            // buffer never grows beyond one line plus k bases.
+            self.buffer.drain(..self.start);
+            self.start = 0;
+
+            let bytes_read = self.reader.read_line()?;
 
             if bytes_read == 0 || self.reader.line.starts_with('>') {
                 self.stream_finished = true;
@@ -122,130 +697,1403 @@ impl<'a, R: BufRead> KmerStream<'a, R> {
                 break;
             }
 
-            self.buffer.extend(self.reader.line.trim().as_bytes());
+            let line = self.reader.line.trim().as_bytes();
+            validate_sequence(line, self.bases_read)?;
+            self.reader.stats.add_line(line);
+            self.bases_read += line.len();
+            let appended = self.buffer.len();
+            self.buffer.extend_from_slice(line);
+            if self.policy.uppercase {
+                self.buffer[appended..].make_ascii_uppercase();
+            }
         }
         Ok(())
     }
-}
-
-impl<'a, R: BufRead> Drop for KmerStream<'a, R> {
-    fn drop(&mut self) {
-        if self.stream_finished {
-            return;
-        }
 
-        // Consume the rest of the lines of the current sequence until the next record or EOF
+    // Advances to the next kmer and returns its start in the buffer.
+    fn advance(&mut self) -> Result<Option<usize>, FastaError> {
         loop {
-            self.reader.line.clear();
-            if let Ok(bytes_read) = self.reader.reader.read_line(&mut self.reader.line) {
-                if bytes_read == 0 {
-                    self.reader.finished = true;
-                    break;
-                }
-                if self.reader.line.starts_with('>') {
-                    break;
-                }
-            } else {
-                // On an IO error, we can't do much but stop.
-                self.reader.finished = true;
-                break;
+            if self.stream_finished && self.available() < self.k {
+                return Ok(None);
             }
-        }
-    }
-}
 
-impl<'a, R: BufRead> Iterator for KmerStream<'a, R> {
-    type Item = io::Result<Vec<u8>>;
+            self.fill_buffer()?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.stream_finished && self.buffer.len() < self.k {
-            return None;
-        }
+            if self.available() < self.k {
+                return Ok(None);
+            }
 
-        if let Err(e) = self.fill_buffer() {
-            return Some(Err(e));
+            let start = self.start;
+            let kmer = &self.buffer[start..start + self.k];
+            if let Some(i) = self.policy.first_ambiguous(kmer) {
+                let error = FastaError::AmbiguousBase {
+                    byte: kmer[i],
+                    // The buffer ends with the last base read
+                    position: self.bases_read - (self.buffer.len() - start - i),
+                };
+                // Report every ambiguous base once
+                self.start = start + i + 1;
+                return Err(error);
+            }
+            if let Some(rejected) = self.policy.last_rejected(kmer) {
+                // No kmer containing this base passes the policy
+                self.start = start + rejected + 1;
+                continue;
+            }
+            self.start += 1;
+            return Ok(Some(start));
         }
+    }
 
-        if self.buffer.len() < self.k {
-            return None;
+    /// Returns the next kmer as a slice into the internal buffer, without allocating.
+    pub fn next_kmer(&mut self) -> Result<Option<&[u8]>, FastaError> {
+        Ok(self
+            .advance()?
+            .map(|start| &self.buffer[start..start + self.k]))
+    }
+
+    /// Returns the next canonical kmer as a slice into an internal buffer, without allocating.
+    pub fn next_canonical_kmer(&mut self) -> Result<Option<&[u8]>, FastaError> {
+        Ok(self.advance()?.map(|start| {
+            get_canonical_into(&self.buffer[start..start + self.k], &mut self.canonical)
+        }))
+    }
+}
+
+impl<'a, R: BufRead> Drop for KmerStream<'a, R> {
+    fn drop(&mut self) {
+        if !self.stream_finished {
+            self.reader.skip_sequence();
         }
+    }
+}
 
-        let kmer: Vec<u8> = self.buffer.iter().take(self.k).cloned().collect();
-        self.buffer.pop_front();
+impl<'a, R: BufRead> Iterator for KmerStream<'a, R> {
+    type Item = Result<Vec<u8>, FastaError>;
 
-        Some(Ok(kmer))
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_kmer()
+            .map(|kmer| kmer.map(|kmer| kmer.to_vec()))
+            .transpose()
     }
 }
 
-/// An iterator over the canonical kmers of a FASTA record.
+/// An iterator over the 2-bit encoded canonical kmers of a FASTA record.
 ///
-/// Wraps another iterator yielding kmers and converts them to canonical form.
-pub struct CanonicalKmerStream<I> {
-    iter: I,
+/// Created by [`FastaReader::encoded_kmers`]. Bases are encoded while the
+/// lines are read, so no kmer is materialized as bytes.
+pub struct EncodedKmerStream<'a, R: BufRead, T = u64> {
+    reader: &'a mut FastaReader<R>,
+    k: usize,
+    kmer: T,
+    // Number of valid bases at the end of `kmer`
+    valid_len: usize,
+    // Unread part of the current line in `reader.line`
+    line_pos: usize,
+    line_end: usize,
+    bases_read: usize,
+    stream_finished: bool,
 }
 
-impl<I> CanonicalKmerStream<I> {
-    pub fn new(iter: I) -> Self {
-        CanonicalKmerStream { iter }
+impl<'a, R: BufRead, T: EncodedKmer> EncodedKmerStream<'a, R, T> {
+    fn new(reader: &'a mut FastaReader<R>, k: usize) -> Self {
+        assert!(
+            (1..=T::MAX_K).contains(&k),
+            "k-mer length must be between 1 and {}",
+            T::MAX_K
+        );
+        EncodedKmerStream {
+            reader,
+            k,
+            kmer: T::ZERO,
+            valid_len: 0,
+            line_pos: 0,
+            line_end: 0,
+            bases_read: 0,
+            stream_finished: false,
+        }
     }
-}
 
-impl<I> Iterator for CanonicalKmerStream<I>
-where
-    I: Iterator<Item = io::Result<Vec<u8>>>,
-{
-    type Item = io::Result<Vec<u8>>;
+    /// Returns the next canonical kmer.
+    pub fn next_kmer(&mut self) -> Result<Option<T>, FastaError> {
+        loop {
+            let line = self.reader.line.as_bytes();
+            while self.line_pos < self.line_end {
+                let code = ENCODING[line[self.line_pos] as usize];
+                self.line_pos += 1;
+                if code == 0xFF {
+                    self.valid_len = 0;
+                    self.kmer = T::ZERO;
+                    continue;
+                }
+                self.kmer = self.kmer.push(code, self.k);
+                self.valid_len += 1;
+                if self.valid_len >= self.k {
+                    return Ok(Some(self.kmer.canonical(self.k)));
+                }
+            }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(Ok(kmer)) => Some(Ok(get_canonical(&kmer))),
-            other => other,
+            if self.stream_finished {
+                return Ok(None);
+            }
+            let bytes_read = self.reader.read_line()?;
+            if bytes_read == 0 || self.reader.line.starts_with('>') {
+                self.stream_finished = true;
+                if bytes_read == 0 {
+                    self.reader.finished = true;
+                }
+                return Ok(None);
+            }
+
+            let line = self.reader.line.as_bytes();
+            let trimmed = line.trim_ascii();
+            validate_sequence(trimmed, self.bases_read)?;
+            self.reader.stats.add_line(trimmed);
+            self.bases_read += trimmed.len();
+            self.line_pos = line.len() - line.trim_ascii_start().len();
+            self.line_end = self.line_pos + trimmed.len();
         }
     }
 }
 
-pub fn get_canonical(kmer: &[u8]) -> Vec<u8> {
-    let rc = reverse_complement(kmer);
-    if kmer <= &rc { kmer.to_vec() } else { rc }
+impl<'a, R: BufRead, T> Drop for EncodedKmerStream<'a, R, T> {
+    fn drop(&mut self) {
+        if !self.stream_finished {
+            self.reader.skip_sequence();
+        }
+    }
 }
 
-pub fn get_canonical_into<'a>(kmer: &'a [u8], buffer: &'a mut [u8]) -> &'a [u8] {
-    reverse_complement_into(kmer, buffer);
-    if kmer <= buffer { kmer } else { buffer }
-}
+impl<'a, R: BufRead, T: EncodedKmer> Iterator for EncodedKmerStream<'a, R, T> {
+    type Item = Result<T, FastaError>;
 
-pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
-    seq.iter()
-        .rev()
-        .map(|&base| match base {
-            b'A' => b'T',
-            b'T' => b'A',
-            b'C' => b'G',
-            b'G' => b'C',
-            b'a' => b't',
-            b't' => b'a',
-            b'c' => b'g',
-            b'g' => b'c',
-            x => x,
-        })
-        .collect()
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_kmer().transpose()
+    }
 }
 
-pub fn reverse_complement_into(seq: &[u8], out: &mut [u8]) {
+/// An iterator over the encoded kmers of a protein FASTA record.
+///
+/// Created by [`FastaReader::protein_kmers`]. Every residue is encoded with
+/// 5 bits by its [`ProteinAlphabet`], with the first residue in the most
+/// significant bits. Proteins have no reverse complement, so the kmers are
+/// not canonicalized. Kmers with residues outside the alphabet, e.g. `X`,
+/// stops or gaps, are skipped.
+pub struct ProteinKmerStream<'a, R: BufRead> {
+    reader: &'a mut FastaReader<R>,
+    k: usize,
+    encoding: &'static [u8; 256],
+    kmer: u64,
+    // Number of valid residues at the end of `kmer`
+    valid_len: usize,
+    // Unread part of the current line in `reader.line`
+    line_pos: usize,
+    line_end: usize,
+    bases_read: usize,
+    stream_finished: bool,
+}
+
+impl<'a, R: BufRead> ProteinKmerStream<'a, R> {
+    fn new(reader: &'a mut FastaReader<R>, k: usize, alphabet: ProteinAlphabet) -> Self {
+        assert!(
+            (1..=MAX_PROTEIN_K_MER_LENGTH).contains(&k),
+            "protein k-mer length must be between 1 and {MAX_PROTEIN_K_MER_LENGTH}"
+        );
+        ProteinKmerStream {
+            reader,
+            k,
+            encoding: alphabet.encoding(),
+            kmer: 0,
+            valid_len: 0,
+            line_pos: 0,
+            line_end: 0,
+            bases_read: 0,
+            stream_finished: false,
+        }
+    }
+
+    /// Returns the next encoded kmer.
+    pub fn next_kmer(&mut self) -> Result<Option<u64>, FastaError> {
+        loop {
+            let line = self.reader.line.as_bytes();
+            while self.line_pos < self.line_end {
+                let code = self.encoding[line[self.line_pos] as usize];
+                self.line_pos += 1;
+                if code == 0xFF {
+                    self.valid_len = 0;
+                    self.kmer = 0;
+                    continue;
+                }
+                self.kmer = push_residue(self.kmer, code, self.k);
+                self.valid_len += 1;
+                if self.valid_len >= self.k {
+                    return Ok(Some(self.kmer));
+                }
+            }
+
+            if self.stream_finished {
+                return Ok(None);
+            }
+            let bytes_read = self.reader.read_line()?;
+            if bytes_read == 0 || self.reader.line.starts_with('>') {
+                self.stream_finished = true;
+                if bytes_read == 0 {
+                    self.reader.finished = true;
+                }
+                return Ok(None);
+            }
+
+            let line = self.reader.line.as_bytes();
+            let trimmed = line.trim_ascii();
+            validate_sequence(trimmed, self.bases_read)?;
+            self.reader.stats.add_line(trimmed);
+            self.bases_read += trimmed.len();
+            self.line_pos = line.len() - line.trim_ascii_start().len();
+            self.line_end = self.line_pos + trimmed.len();
+        }
+    }
+}
+
+impl<'a, R: BufRead> Drop for ProteinKmerStream<'a, R> {
+    fn drop(&mut self) {
+        if !self.stream_finished {
+            self.reader.skip_sequence();
+        }
+    }
+}
+
+impl<'a, R: BufRead> Iterator for ProteinKmerStream<'a, R> {
+    type Item = Result<u64, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_kmer().transpose()
+    }
+}
+
+/// An iterator over the canonical ntHash values of the kmers of a FASTA
+/// record.
+///
+/// Created by [`FastaReader::nthashes`], see [`NtHash`]. Kmers with bases
+/// other than `ACGT` (in any case) are skipped.
+pub struct NtHashStream<'a, R: BufRead> {
+    reader: &'a mut FastaReader<R>,
+    hasher: NtHash,
+    // Unread part of the current line in `reader.line`
+    line_pos: usize,
+    line_end: usize,
+    bases_read: usize,
+    stream_finished: bool,
+}
+
+impl<'a, R: BufRead> NtHashStream<'a, R> {
+    fn new(reader: &'a mut FastaReader<R>, k: usize) -> Self {
+        NtHashStream {
+            reader,
+            hasher: NtHash::new(k),
+            line_pos: 0,
+            line_end: 0,
+            bases_read: 0,
+            stream_finished: false,
+        }
+    }
+
+    /// Returns the canonical hash of the next kmer.
+    pub fn next_hash(&mut self) -> Result<Option<u64>, FastaError> {
+        loop {
+            let line = self.reader.line.as_bytes();
+            while self.line_pos < self.line_end {
+                let base = line[self.line_pos];
+                self.line_pos += 1;
+                if let Some(hash) = self.hasher.push(base) {
+                    return Ok(Some(hash));
+                }
+            }
+
+            if self.stream_finished {
+                return Ok(None);
+            }
+            let bytes_read = self.reader.read_line()?;
+            if bytes_read == 0 || self.reader.line.starts_with('>') {
+                self.stream_finished = true;
+                if bytes_read == 0 {
+                    self.reader.finished = true;
+                }
+                return Ok(None);
+            }
+
+            let line = self.reader.line.as_bytes();
+            let trimmed = line.trim_ascii();
+            validate_sequence(trimmed, self.bases_read)?;
+            self.reader.stats.add_line(trimmed);
+            self.bases_read += trimmed.len();
+            self.line_pos = line.len() - line.trim_ascii_start().len();
+            self.line_end = self.line_pos + trimmed.len();
+        }
+    }
+}
+
+impl<'a, R: BufRead> Drop for NtHashStream<'a, R> {
+    fn drop(&mut self) {
+        if !self.stream_finished {
+            self.reader.skip_sequence();
+        }
+    }
+}
+
+impl<'a, R: BufRead> Iterator for NtHashStream<'a, R> {
+    type Item = Result<u64, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_hash().transpose()
+    }
+}
+
+/// Which kmers are selected as syncmers, by the position of their smallest
+/// s-mer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncmerKind {
+    /// The smallest s-mer is the first one.
+    Open,
+    /// The smallest s-mer is the first or the last one.
+    #[default]
+    Closed,
+}
+
+impl SyncmerKind {
+    /// Expected fraction of random kmers that are syncmers.
+    pub fn density(self, k: usize, s: usize) -> f64 {
+        let smers = (k - s + 1) as f64;
+        match self {
+            SyncmerKind::Open => 1.0 / smers,
+            SyncmerKind::Closed => f64::min(2.0 / smers, 1.0),
+        }
+    }
+}
+
+/// Whether a 2-bit encoded kmer is a `(k, s)` syncmer (Edgar, 2021).
+///
+/// The s-mers of the kmer are compared by the hash of their canonical form,
+/// so the decision for a canonical kmer does not depend on the strand.
+pub fn is_syncmer(kmer: u64, k: usize, s: usize, kind: SyncmerKind) -> bool {
+    let mut min_pos = 0;
+    let mut min_hash = u64::MAX;
+    for pos in 0..=k - s {
+        // The first base is in the most significant bits
+        let smer = (kmer >> (2 * (k - s - pos))) & kmer_mask(s);
+        let hash = xxh64(&get_canonical_u64(smer, s).to_le_bytes(), 0);
+        if hash < min_hash {
+            min_hash = hash;
+            min_pos = pos;
+        }
+    }
+    match kind {
+        SyncmerKind::Open => min_pos == 0,
+        SyncmerKind::Closed => min_pos == 0 || min_pos == k - s,
+    }
+}
+
+/// An iterator over the syncmers of a stream of 2-bit encoded canonical
+/// kmers, e.g. from [`FastaReader::encoded_kmers`].
+///
+/// Like minimizers, syncmers sample a fraction of the kmers to shrink
+/// sketches, see [`SyncmerKind::density`]. Unlike minimizers, whether a kmer
+/// is selected depends only on the kmer itself and not on its neighbors, so
+/// a mutation changes fewer selected kmers.
+pub struct SyncmerStream<I> {
+    iter: I,
+    k: usize,
+    s: usize,
+    kind: SyncmerKind,
+}
+
+impl<I> SyncmerStream<I> {
+    /// Panics if `s` is not in `1..=k` or `k` is larger than 32.
+    pub fn new(iter: I, k: usize, s: usize, kind: SyncmerKind) -> Self {
+        assert!(
+            k <= u64::MAX_K,
+            "k-mer length must be at most {}",
+            u64::MAX_K
+        );
+        assert!(
+            (1..=k).contains(&s),
+            "s-mer length must be between 1 and the k-mer length"
+        );
+        SyncmerStream { iter, k, s, kind }
+    }
+}
+
+impl<I> Iterator for SyncmerStream<I>
+where
+    I: Iterator<Item = Result<u64, FastaError>>,
+{
+    type Item = Result<u64, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.find(|kmer| match kmer {
+            Ok(kmer) => is_syncmer(*kmer, self.k, self.s, self.kind),
+            Err(_) => true,
+        })
+    }
+}
+
+/// An iterator over the canonical kmers of a FASTA record.
+///
+/// Wraps another iterator yielding kmers and converts them to canonical form.
+pub struct CanonicalKmerStream<I> {
+    iter: I,
+}
+
+impl<I> CanonicalKmerStream<I> {
+    pub fn new(iter: I) -> Self {
+        CanonicalKmerStream { iter }
+    }
+}
+
+impl<I> Iterator for CanonicalKmerStream<I>
+where
+    I: Iterator<Item = Result<Vec<u8>, FastaError>>,
+{
+    type Item = Result<Vec<u8>, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            Some(Ok(kmer)) => Some(Ok(get_canonical(&kmer))),
+            other => other,
+        }
+    }
+}
+
+/// Largest span of a [`SpacedSeed`].
+pub const MAX_SEED_SPAN: usize = 64;
+
+/// Largest weight of a [`SpacedSeed`], so spaced kmers fit into a u64.
+pub const MAX_SEED_WEIGHT: usize = 32;
+
+/// A spaced seed such as `1101101`: of every window of `span` bases, only
+/// the bases at the `1` ("care") positions are kept and the bases at the `0`
+/// positions are ignored.
+///
+/// Spaced kmers of two sequences still match if the sequences differ only at
+/// ignored positions, so they find more matches between diverged sequences
+/// than contiguous kmers of the same weight (Ma et al., 2002).
+///
+/// ```
+/// use hll_rust::fasta::SpacedSeed;
+///
+/// let seed = SpacedSeed::new("1101");
+/// assert_eq!((seed.span(), seed.weight()), (4, 3));
+/// assert_eq!(seed.apply(b"ACGT"), b"ACT");
+/// assert_eq!(seed.apply(b"ACTT"), b"ACT");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpacedSeed {
+    // Positions of the care bases within the window
+    care: Vec<usize>,
+    span: usize,
+}
+
+impl SpacedSeed {
+    /// Parses a pattern of `1`s (care) and `0`s (don't care).
+    ///
+    /// Panics if the pattern has other characters, does not start and end
+    /// with `1`, is longer than [`MAX_SEED_SPAN`] or has more than
+    /// [`MAX_SEED_WEIGHT`] care positions.
+    pub fn new(pattern: &str) -> Self {
+        let bytes = pattern.as_bytes();
+        assert!(
+            bytes.iter().all(|&c| c == b'0' || c == b'1'),
+            "spaced seed patterns consist of 0s and 1s, got {pattern:?}"
+        );
+        assert!(
+            bytes.first() == Some(&b'1') && bytes.last() == Some(&b'1'),
+            "spaced seed patterns must start and end with 1, got {pattern:?}"
+        );
+        let care: Vec<usize> = (0..bytes.len()).filter(|&i| bytes[i] == b'1').collect();
+        assert!(
+            bytes.len() <= MAX_SEED_SPAN && care.len() <= MAX_SEED_WEIGHT,
+            "spaced seeds can span at most {MAX_SEED_SPAN} bases with at most {MAX_SEED_WEIGHT} care positions"
+        );
+        SpacedSeed {
+            care,
+            span: bytes.len(),
+        }
+    }
+
+    /// A seed without ignored positions, equivalent to contiguous kmers.
+    pub fn contiguous(k: usize) -> Self {
+        Self::new(&"1".repeat(k))
+    }
+
+    /// Length of the windows the seed is applied to.
+    pub fn span(&self) -> usize {
+        self.span
+    }
+
+    /// Number of care positions, the length of the spaced kmers.
+    pub fn weight(&self) -> usize {
+        self.care.len()
+    }
+
+    /// The bases at the care positions of `window`.
+    ///
+    /// Panics if `window` is not `span` bases long.
+    pub fn apply(&self, window: &[u8]) -> Vec<u8> {
+        assert_eq!(
+            window.len(),
+            self.span,
+            "window length must match the seed span"
+        );
+        self.care.iter().map(|&i| window[i]).collect()
+    }
+
+    /// The smaller of the spaced kmers of `window` and of its reverse
+    /// complement, which is the same for both strands.
+    ///
+    /// Unlike the spaced kmer of the canonical window, this does not depend
+    /// on the ignored bases.
+    pub fn apply_canonical(&self, window: &[u8]) -> Vec<u8> {
+        let forward = self.apply(window);
+        let reverse = self.apply(&reverse_complement(window));
+        if forward <= reverse { forward } else { reverse }
+    }
+
+    /// The 2-bit encoded bases at the care positions of a 2-bit encoded
+    /// window of `span` bases, with the first base in the most significant
+    /// bits.
+    #[inline(always)]
+    pub fn apply_encoded(&self, window: u128) -> u64 {
+        self.care.iter().fold(0, |kmer, &i| {
+            let code = (window >> (2 * (self.span - 1 - i))) & 0b11;
+            (kmer << 2) | code as u64
+        })
+    }
+
+    /// Returns an iterator over the canonical 2-bit encoded spaced kmers of
+    /// a sequence, skipping windows with bases other than `ACGT` (in any
+    /// case), like [`FastaReader::encoded_kmers`].
+    pub fn encoded_kmers<'a>(&'a self, seq: &'a [u8]) -> impl Iterator<Item = u64> + 'a {
+        let mask = u128::MAX >> (128 - 2 * self.span);
+        let mut window: u128 = 0;
+        let mut valid_len = 0;
+        let mut bases = seq.iter();
+        std::iter::from_fn(move || {
+            for &base in bases.by_ref() {
+                let code = ENCODING[base as usize];
+                if code == 0xFF {
+                    valid_len = 0;
+                    window = 0;
+                    continue;
+                }
+                window = ((window << 2) & mask) | code as u128;
+                valid_len += 1;
+                if valid_len >= self.span {
+                    let forward = self.apply_encoded(window);
+                    let reverse = self.apply_encoded(reverse_complement_u128(window, self.span));
+                    return Some(forward.min(reverse));
+                }
+            }
+            None
+        })
+    }
+}
+
+impl fmt::Display for SpacedSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pattern = vec![b'0'; self.span];
+        for &i in &self.care {
+            pattern[i] = b'1';
+        }
+        write!(f, "{}", String::from_utf8_lossy(&pattern))
+    }
+}
+
+/// An iterator over the spaced kmers of a stream of windows of the seed's
+/// span, e.g. from [`FastaReader::kmers`].
+pub struct SpacedKmerStream<I> {
+    iter: I,
+    seed: SpacedSeed,
+    canonical: bool,
+}
+
+impl<I> SpacedKmerStream<I> {
+    /// Yields [`SpacedSeed::apply`] of every window.
+    pub fn new(iter: I, seed: SpacedSeed) -> Self {
+        SpacedKmerStream {
+            iter,
+            seed,
+            canonical: false,
+        }
+    }
+
+    /// Yields [`SpacedSeed::apply_canonical`] of every window.
+    pub fn canonical(iter: I, seed: SpacedSeed) -> Self {
+        SpacedKmerStream {
+            iter,
+            seed,
+            canonical: true,
+        }
+    }
+}
+
+impl<I> Iterator for SpacedKmerStream<I>
+where
+    I: Iterator<Item = Result<Vec<u8>, FastaError>>,
+{
+    type Item = Result<Vec<u8>, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let window = match self.iter.next()? {
+            Ok(window) => window,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Ok(if self.canonical {
+            self.seed.apply_canonical(&window)
+        } else {
+            self.seed.apply(&window)
+        }))
+    }
+}
+
+/// An iterator over the window minimizers of a stream of kmers.
+///
+/// For every window of `w` consecutive kmers, the kmer with the smallest hash
+/// is selected. Each selected kmer is yielded once, even if it is the minimizer
+/// of several consecutive windows. Streams with fewer than `w` kmers yield
+/// their single smallest kmer.
+pub struct MinimizerStream<I> {
+    iter: I,
+    w: usize,
+    // Candidates as (hash, position, kmer) with strictly increasing hashes
+    window: VecDeque<(u64, usize, Vec<u8>)>,
+    pos: usize,
+    last_emitted: Option<usize>,
+}
+
+impl<I> MinimizerStream<I> {
+    pub fn new(iter: I, w: usize) -> Self {
+        assert!(w > 0, "window size must be positive");
+        MinimizerStream {
+            iter,
+            w,
+            window: VecDeque::with_capacity(w),
+            pos: 0,
+            last_emitted: None,
+        }
+    }
+
+    // Yields the current minimizer unless it was already yielded.
+    fn emit_front(&mut self) -> Option<Vec<u8>> {
+        let (_, pos, kmer) = self.window.front()?;
+        if self.last_emitted == Some(*pos) {
+            return None;
+        }
+        self.last_emitted = Some(*pos);
+        Some(kmer.clone())
+    }
+}
+
+impl<I> Iterator for MinimizerStream<I>
+where
+    I: Iterator<Item = Result<Vec<u8>, FastaError>>,
+{
+    type Item = Result<Vec<u8>, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let kmer = match self.iter.next() {
+                Some(Ok(kmer)) => kmer,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    // A short stream never filled a whole window
+                    if self.pos < self.w {
+                        return self.emit_front().map(Ok);
+                    }
+                    return None;
+                }
+            };
+
+            let hash = xxh64(&kmer, 0);
+            let pos = self.pos;
+            self.pos += 1;
+
+            // Keep the earliest kmer on ties
+            while self.window.back().is_some_and(|&(h, _, _)| h > hash) {
+                self.window.pop_back();
+            }
+            self.window.push_back((hash, pos, kmer));
+            while self
+                .window
+                .front()
+                .is_some_and(|&(_, p, _)| p + self.w <= pos)
+            {
+                self.window.pop_front();
+            }
+
+            if pos + 1 >= self.w
+                && let Some(minimizer) = self.emit_front()
+            {
+                return Some(Ok(minimizer));
+            }
+        }
+    }
+}
+
+/// Offset of the Phred quality scores in FASTQ files (Sanger / Illumina 1.8+).
+pub const PHRED_OFFSET: u8 = 33;
+
+/// A simple FASTQ reader that reads 4-line records one by one.
+///
+/// It provides the same kmer iterators as [`FastaReader`], optionally skipping
+/// kmers that contain a base below a minimum Phred quality.
+pub struct FastqReader<R: BufRead> {
+    reader: R,
+    line: String,
+    // Number of lines read so far, for error messages
+    line_no: usize,
+    min_quality: Option<u8>,
+    kmer_policy: KmerPolicy,
+    pub id: Option<Vec<u8>>,
+    pub sequence: Vec<u8>,
+    pub quality: Vec<u8>,
+}
+
+impl<R: BufRead> FastqReader<R> {
+    /// Creates a new `FastqReader` from a type implementing `BufRead`.
+    pub fn new(reader: R) -> Self {
+        FastqReader {
+            reader,
+            line: String::new(),
+            line_no: 0,
+            min_quality: None,
+            kmer_policy: KmerPolicy::default(),
+            id: None,
+            sequence: Vec::new(),
+            quality: Vec::new(),
+        }
+    }
+
+    /// Only yield kmers whose bases all have at least the given Phred quality.
+    pub fn with_min_quality(mut self, min_quality: u8) -> Self {
+        self.min_quality = Some(min_quality);
+        self
+    }
+
+    /// Applies the given policy to the kmers of all iterators.
+    pub fn with_kmer_policy(mut self, policy: KmerPolicy) -> Self {
+        self.kmer_policy = policy;
+        self
+    }
+
+    // Reads the next line into `self.line`, returning `false` on EOF.
+    fn read_line(&mut self) -> Result<bool, FastaError> {
+        self.line.clear();
+        if self.reader.read_line(&mut self.line)? == 0 {
+            return Ok(false);
+        }
+        self.line_no += 1;
+        Ok(true)
+    }
+
+    /// Advances the reader to the next record.
+    ///
+    /// Returns `Ok(true)` if a record was found, `Ok(false)` if EOF was reached.
+    /// The record ID, sequence and quality string are stored in `self.id`,
+    /// `self.sequence` and `self.quality`.
+    pub fn next_record(&mut self) -> Result<bool, FastaError> {
+        // Skip blank lines between records
+        loop {
+            if !self.read_line()? {
+                return Ok(false);
+            }
+            if !self.line.trim().is_empty() {
+                break;
+            }
+        }
+
+        if !self.line.starts_with('@') {
+            return Err(FastaError::MalformedHeader {
+                line_no: self.line_no,
+            });
+        }
+        self.id = Some(
+            self.line
+                .trim_start_matches('@')
+                .trim_end()
+                .as_bytes()
+                .to_vec(),
+        );
+
+        if !self.read_line()? {
+            return Err(FastaError::TruncatedRecord);
+        }
+        let line = self.line.trim().as_bytes();
+        validate_sequence(line, 0)?;
+        self.sequence.clear();
+        self.sequence.extend_from_slice(line);
+
+        if !self.read_line()? {
+            return Err(FastaError::TruncatedRecord);
+        }
+        if !self.line.starts_with('+') {
+            return Err(FastaError::MalformedHeader {
+                line_no: self.line_no,
+            });
+        }
+
+        if !self.read_line()? {
+            return Err(FastaError::TruncatedRecord);
+        }
+        self.quality.clear();
+        self.quality.extend_from_slice(self.line.trim().as_bytes());
+
+        // One quality score per base, so a quality line of another length
+        // was cut off or belongs to another sequence
+        if self.quality.len() != self.sequence.len() {
+            return Err(FastaError::QualityLengthMismatch {
+                line_no: self.line_no,
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the full sequence of the current record.
+    pub fn read_sequence(&mut self) -> Result<Vec<u8>, FastaError> {
+        Ok(self.sequence.clone())
+    }
+
+    /// Returns an iterator over the kmers of the current record.
+    pub fn kmers(&self, k: usize) -> FastqKmerStream<'_> {
+        FastqKmerStream {
+            sequence: &self.sequence,
+            quality: &self.quality,
+            k,
+            pos: 0,
+            min_quality: self.min_quality,
+            policy: self.kmer_policy,
+        }
+    }
+
+    /// Returns an iterator over the canonical kmers of the current record.
+    ///
+    /// A canonical kmer is the lexicographically smaller of the kmer and its reverse complement.
+    pub fn canonical_kmers(&self, k: usize) -> CanonicalKmerStream<FastqKmerStream<'_>> {
+        CanonicalKmerStream::new(self.kmers(k))
+    }
+
+    /// Returns an iterator over the `(k, w)` minimizers of the current record.
+    pub fn minimizers(
+        &self,
+        k: usize,
+        w: usize,
+    ) -> MinimizerStream<CanonicalKmerStream<FastqKmerStream<'_>>> {
+        MinimizerStream::new(self.canonical_kmers(k), w)
+    }
+}
+
+/// An iterator over the kmers of a sequence in memory.
+pub struct SequenceKmerStream<'a> {
+    sequence: &'a [u8],
+    k: usize,
+    pos: usize,
+}
+
+impl<'a> SequenceKmerStream<'a> {
+    pub fn new(sequence: &'a [u8], k: usize) -> Self {
+        SequenceKmerStream {
+            sequence,
+            k,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for SequenceKmerStream<'a> {
+    type Item = Result<Vec<u8>, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kmer = self.sequence.get(self.pos..self.pos + self.k)?;
+        self.pos += 1;
+        Some(Ok(kmer.to_vec()))
+    }
+}
+
+/// An iterator over the kmers of a FASTQ record.
+pub struct FastqKmerStream<'a> {
+    sequence: &'a [u8],
+    quality: &'a [u8],
+    k: usize,
+    pos: usize,
+    min_quality: Option<u8>,
+    policy: KmerPolicy,
+}
+
+impl<'a> Iterator for FastqKmerStream<'a> {
+    type Item = Result<Vec<u8>, FastaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos + self.k <= self.sequence.len() {
+            let start = self.pos;
+            self.pos += 1;
+
+            if let Some(min_quality) = self.min_quality
+                && let Some(low) = self.quality[start..start + self.k]
+                    .iter()
+                    .rposition(|&q| q.saturating_sub(PHRED_OFFSET) < min_quality)
+            {
+                // No window containing this base can pass the filter
+                self.pos = start + low + 1;
+                continue;
+            }
+
+            let mut kmer = self.sequence[start..start + self.k].to_vec();
+            if self.policy.uppercase {
+                kmer.make_ascii_uppercase();
+            }
+            if let Some(i) = self.policy.first_ambiguous(&kmer) {
+                self.pos = start + i + 1;
+                return Some(Err(FastaError::AmbiguousBase {
+                    byte: kmer[i],
+                    position: start + i,
+                }));
+            }
+            if let Some(rejected) = self.policy.last_rejected(&kmer) {
+                self.pos = start + rejected + 1;
+                continue;
+            }
+            return Some(Ok(kmer));
+        }
+        None
+    }
+}
+
+pub fn get_canonical(kmer: &[u8]) -> Vec<u8> {
+    let rc = reverse_complement(kmer);
+    if kmer <= rc.as_slice() {
+        kmer.to_vec()
+    } else {
+        rc
+    }
+}
+
+pub fn get_canonical_into<'a>(kmer: &'a [u8], buffer: &'a mut [u8]) -> &'a [u8] {
+    reverse_complement_into(kmer, buffer);
+    if kmer <= &*buffer { kmer } else { buffer }
+}
+
+/// Reverse complement of a nucleotide sequence.
+///
+/// IUPAC ambiguity codes are complemented as well, e.g. `R` (A or G) becomes
+/// `Y` (C or T), and `U` becomes `A`. Case is preserved and other bytes are
+/// passed through unchanged.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().map(|&base| complement(base)).collect()
+}
+
+/// The bases of a 2-bit encoded k-mer (A=00, C=01, G=10, T=11), with the
+/// first base in the most significant bits, e.g. to print sampled k-mers.
+///
+/// Panics if `k` is not in `1..=32`.
+pub fn decode_kmer(kmer: u64, k: usize) -> Vec<u8> {
+    assert!((1..=32).contains(&k), "k must be between 1 and 32");
+    (0..k)
+        .rev()
+        .map(|i| b"ACGT"[(kmer >> (2 * i) & 3) as usize])
+        .collect()
+}
+
+/// Like [`reverse_complement`], but writes into `out`, which must have the
+/// same length as `seq`.
+pub fn reverse_complement_into(seq: &[u8], out: &mut [u8]) {
     assert_eq!(seq.len(), out.len());
     for (i, &base) in seq.iter().enumerate() {
-        out[out.len() - 1 - i] = match base {
-            b'A' => b'T',
-            b'T' => b'A',
-            b'C' => b'G',
-            b'G' => b'C',
-            b'a' => b't',
-            b't' => b'a',
-            b'c' => b'g',
-            b'g' => b'c',
-            x => x,
+        out[out.len() - 1 - i] = complement(base);
+    }
+}
+
+/// The IUPAC complement of a nucleotide, see [`reverse_complement`].
+#[inline(always)]
+pub fn complement(base: u8) -> u8 {
+    COMPLEMENT[base as usize]
+}
+
+const COMPLEMENT: [u8; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = i as u8;
+        i += 1;
+    }
+    let pairs: [(u8, u8); 11] = [
+        (b'A', b'T'),
+        (b'C', b'G'),
+        (b'U', b'A'),
+        // A or G <-> C or T
+        (b'R', b'Y'),
+        // A or C <-> G or T
+        (b'M', b'K'),
+        // Not A <-> not T, not C <-> not G
+        (b'B', b'V'),
+        (b'D', b'H'),
+        // Self-complementary
+        (b'S', b'S'),
+        (b'W', b'W'),
+        (b'N', b'N'),
+        (b'-', b'-'),
+    ];
+    let mut i = 0;
+    while i < pairs.len() {
+        let (base, comp) = pairs[i];
+        table[base as usize] = comp;
+        table[base.to_ascii_lowercase() as usize] = comp.to_ascii_lowercase();
+        // U only appears on the left, T stays complemented to A
+        if base != b'U' {
+            table[comp as usize] = base;
+            table[comp.to_ascii_lowercase() as usize] = base.to_ascii_lowercase();
+        }
+        i += 1;
+    }
+    table
+};
+
+// A=00, C=01, G=10, T=11
+pub(crate) const ENCODING: [u8; 256] = {
+    let mut table = [0xFF; 256];
+    table[b'A' as usize] = 0;
+    table[b'C' as usize] = 1;
+    table[b'G' as usize] = 2;
+    table[b'T' as usize] = 3;
+    // Handle lowercase as well, though we uppercase the sequence
+    table[b'a' as usize] = 0;
+    table[b'c' as usize] = 1;
+    table[b'g' as usize] = 2;
+    table[b't' as usize] = 3;
+    table
+};
+
+// Mask for the lower 2 * k bits (62 bits for a 31-mer)
+#[inline(always)]
+pub(crate) fn kmer_mask(k: usize) -> u64 {
+    u64::MAX >> (64 - 2 * k)
+}
+
+#[inline(always)]
+pub(crate) fn get_canonical_u64(kmer: u64, k: usize) -> u64 {
+    // Reverse complement for 2-bit encoding (A=00, C=01, G=10, T=11)
+    // 1. Reverse bits
+    // 2. Shift right by 64 - 2k (i.e. by 2 for a 31-mer)
+    // 3. Swap adjacent bits (to fix 2-bit chunk order)
+    // 4. XOR with mask (to complement)
+
+    let mut r = kmer.reverse_bits();
+    r >>= 64 - 2 * k; // Align to LSB (64 - 2*k)
+
+    // Swap adjacent bits: (r >> 1) & 0x55... | (r & 0x55...) << 1
+    // 0x5555... is the mask 0101..., allowing us to select every 2nd bit
+    r = ((r >> 1) & 0x5555555555555555) | ((r & 0x5555555555555555) << 1);
+
+    // Complement: XOR with 11...11 (2k bits)
+    // 11 binary is 3 decimal. We want to XOR each 2-bit pair with 11.
+    // So we XOR with all ones (masked to 2k bits).
+    r ^= kmer_mask(k);
+
+    if kmer < r { kmer } else { r }
+}
+
+// Reverse complement of a 2-bit encoded k-mer with up to 64 bases, see
+// `get_canonical_u64` for the individual steps.
+#[inline(always)]
+fn reverse_complement_u128(kmer: u128, k: usize) -> u128 {
+    let mask = u128::MAX >> (128 - 2 * k);
+    let mut r = kmer.reverse_bits() >> (128 - 2 * k);
+    const ODD_BITS: u128 = 0x5555_5555_5555_5555_5555_5555_5555_5555;
+    r = ((r >> 1) & ODD_BITS) | ((r & ODD_BITS) << 1);
+    r ^ mask
+}
+
+#[inline(always)]
+pub(crate) fn get_canonical_u128(kmer: u128, k: usize) -> u128 {
+    let r = reverse_complement_u128(kmer, k);
+    if kmer < r { kmer } else { r }
+}
+
+/// Integer holding a 2-bit encoded kmer (A=00, C=01, G=10, T=11), with the
+/// first base in the most significant bits.
+pub trait EncodedKmer: Copy {
+    /// Largest kmer length that fits.
+    const MAX_K: usize;
+    const ZERO: Self;
+
+    /// Appends the 2-bit `code` of a base, keeping the last `k` bases.
+    fn push(self, code: u8, k: usize) -> Self;
+
+    /// The smaller of the kmer and its reverse complement.
+    fn canonical(self, k: usize) -> Self;
+}
+
+impl EncodedKmer for u64 {
+    const MAX_K: usize = 32;
+    const ZERO: Self = 0;
+
+    #[inline(always)]
+    fn push(self, code: u8, k: usize) -> Self {
+        ((self << 2) & kmer_mask(k)) | code as u64
+    }
+
+    #[inline(always)]
+    fn canonical(self, k: usize) -> Self {
+        get_canonical_u64(self, k)
+    }
+}
+
+impl EncodedKmer for u128 {
+    const MAX_K: usize = 64;
+    const ZERO: Self = 0;
+
+    #[inline(always)]
+    fn push(self, code: u8, k: usize) -> Self {
+        ((self << 2) & (u128::MAX >> (128 - 2 * k))) | code as u128
+    }
+
+    #[inline(always)]
+    fn canonical(self, k: usize) -> Self {
+        get_canonical_u128(self, k)
+    }
+}
+
+/// Largest protein kmer length whose 5-bit encoding fits into a u64.
+pub const MAX_PROTEIN_K_MER_LENGTH: usize = 12;
+
+/// How protein residues are encoded, optionally merging similar amino acids
+/// into one letter of a reduced alphabet.
+///
+/// Reduced alphabets make kmers robust to conservative substitutions, which
+/// helps comparing distantly related proteomes, at the cost of more random
+/// kmer matches. The groups follow those of `sourmash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProteinAlphabet {
+    /// The 20 standard amino acids.
+    #[default]
+    Standard,
+    /// Six groups of amino acids with similar substitution patterns:
+    /// `C`, `AGPST`, `DENQ`, `HKR`, `ILMV` and `FWY`.
+    Dayhoff,
+    /// Hydrophobic (`AFGILMPVWY`) and polar (`CDEHKNQRST`) residues.
+    Hp,
+}
+
+impl ProteinAlphabet {
+    /// The 5-bit code of a residue in any case, or `None` for residues
+    /// outside the alphabet such as `X`, `B`, stops and gaps.
+    pub fn encode(self, residue: u8) -> Option<u8> {
+        match self.encoding()[residue as usize] {
+            0xFF => None,
+            code => Some(code),
+        }
+    }
+
+    /// Number of distinct codes.
+    pub fn size(self) -> usize {
+        match self {
+            ProteinAlphabet::Standard => 20,
+            ProteinAlphabet::Dayhoff => 6,
+            ProteinAlphabet::Hp => 2,
+        }
+    }
+
+    fn encoding(self) -> &'static [u8; 256] {
+        match self {
+            ProteinAlphabet::Standard => &STANDARD_ENCODING,
+            ProteinAlphabet::Dayhoff => &DAYHOFF_ENCODING,
+            ProteinAlphabet::Hp => &HP_ENCODING,
+        }
+    }
+}
+
+// Maps the residues of group i to code i, in upper and lower case
+const fn protein_encoding(groups: &[&[u8]]) -> [u8; 256] {
+    let mut table = [0xFF; 256];
+    let mut code = 0;
+    while code < groups.len() {
+        let group = groups[code];
+        let mut i = 0;
+        while i < group.len() {
+            table[group[i] as usize] = code as u8;
+            table[group[i].to_ascii_lowercase() as usize] = code as u8;
+            i += 1;
+        }
+        code += 1;
+    }
+    table
+}
+
+const STANDARD_ENCODING: [u8; 256] = protein_encoding(&[
+    b"A", b"C", b"D", b"E", b"F", b"G", b"H", b"I", b"K", b"L", b"M", b"N", b"P", b"Q", b"R", b"S",
+    b"T", b"V", b"W", b"Y",
+]);
+const DAYHOFF_ENCODING: [u8; 256] =
+    protein_encoding(&[b"C", b"AGPST", b"DENQ", b"HKR", b"ILMV", b"FWY"]);
+const HP_ENCODING: [u8; 256] = protein_encoding(&[b"AFGILMPVWY", b"CDEHKNQRST"]);
+
+// Appends the 5-bit `code` of a residue, keeping the last `k` residues
+#[inline(always)]
+fn push_residue(kmer: u64, code: u8, k: usize) -> u64 {
+    ((kmer << 5) & (u64::MAX >> (64 - 5 * k))) | code as u64
+}
+
+/// Calls `f` with the 5-bit encoded kmers of a protein sequence, skipping
+/// kmers with residues outside `alphabet`. Returns the number of kmers seen.
+///
+/// Panics if `k` is not in `1..=MAX_PROTEIN_K_MER_LENGTH`.
+pub fn for_each_protein_kmer<F: FnMut(u64)>(
+    seq: &[u8],
+    k: usize,
+    alphabet: ProteinAlphabet,
+    mut f: F,
+) -> u64 {
+    assert!(
+        (1..=MAX_PROTEIN_K_MER_LENGTH).contains(&k),
+        "protein k-mer length must be between 1 and {MAX_PROTEIN_K_MER_LENGTH}"
+    );
+    let encoding = alphabet.encoding();
+    let mut kmers_seen = 0;
+    let mut kmer = 0;
+    let mut valid_len = 0;
+    for &residue in seq {
+        let code = encoding[residue as usize];
+        if code == 0xFF {
+            valid_len = 0;
+            kmer = 0;
+            continue;
+        }
+        kmer = push_residue(kmer, code, k);
+        valid_len += 1;
+        if valid_len >= k {
+            f(kmer);
+            kmers_seen += 1;
+        }
+    }
+    kmers_seen
+}
+
+// Random 64-bit seeds of A, C, G and T, in the order of `ENCODING`, as
+// used by ntHash
+const NTHASH_SEEDS: [u64; 4] = [
+    0x3c8b_fbb3_95c6_0474,
+    0x3193_c185_62a0_2b4c,
+    0x2032_3ed0_8257_2324,
+    0x2955_49f5_4be2_4456,
+];
+
+/// Rolling canonical kmer hash after ntHash (Mohamadi et al., 2016).
+///
+/// The hashes of a kmer and of its reverse complement are both updated in
+/// O(1) per base with rotations and XORs of per-base seeds, and the smaller
+/// one is the canonical hash. No kmer is encoded, reverse complemented or
+/// compared, so kmers of any length are hashed at the same speed, and the
+/// hashes can be passed to any counter with [`Counter::add_u64`](crate::Counter::add_u64).
+///
+/// The hashes are not the 2-bit encoded kmers, so counters fed with them
+/// must not be merged with counters fed with encoded kmers.
+#[derive(Debug, Clone)]
+pub struct NtHash {
+    k: usize,
+    forward: u64,
+    reverse: u64,
+    // Codes of the last k bases, starting at `pos`
+    window: Vec<u8>,
+    pos: usize,
+    // Number of valid bases at the end of the window
+    valid_len: usize,
+}
+
+impl NtHash {
+    /// Panics if `k` is 0.
+    pub fn new(k: usize) -> Self {
+        assert!(k > 0, "k-mer length must be positive");
+        NtHash {
+            k,
+            forward: 0,
+            reverse: 0,
+            window: vec![0; k],
+            pos: 0,
+            valid_len: 0,
+        }
+    }
+
+    /// Appends a base and returns the canonical hash of the kmer ending in
+    /// it, or `None` until k bases other than `ACGT` (in any case) were
+    /// pushed in a row.
+    #[inline(always)]
+    pub fn push(&mut self, base: u8) -> Option<u64> {
+        let code = ENCODING[base as usize];
+        if code == 0xFF {
+            self.reset();
+            return None;
+        }
+        let k = self.k as u32;
+        let seed = NTHASH_SEEDS[code as usize];
+        let seed_rc = NTHASH_SEEDS[3 - code as usize];
+        if self.valid_len < self.k {
+            // Base i of the first kmer is rotated by k - 1 - i in the forward
+            // and by i in the reverse complement hash
+            self.forward = self.forward.rotate_left(1) ^ seed;
+            self.reverse ^= seed_rc.rotate_left(self.valid_len as u32);
+            self.valid_len += 1;
+        } else {
+            let out = self.window[self.pos];
+            self.forward =
+                self.forward.rotate_left(1) ^ NTHASH_SEEDS[out as usize].rotate_left(k) ^ seed;
+            self.reverse = self.reverse.rotate_right(1)
+                ^ NTHASH_SEEDS[3 - out as usize].rotate_right(1)
+                ^ seed_rc.rotate_left(k - 1);
+        }
+        self.window[self.pos] = code;
+        self.pos = if self.pos + 1 == self.k {
+            0
+        } else {
+            self.pos + 1
         };
+        (self.valid_len == self.k).then(|| self.canonical())
+    }
+
+    /// Forgets all pushed bases, e.g. at the start of a new sequence.
+    pub fn reset(&mut self) {
+        self.forward = 0;
+        self.reverse = 0;
+        self.valid_len = 0;
+    }
+
+    /// Hash of the current kmer.
+    pub fn forward(&self) -> u64 {
+        self.forward
+    }
+
+    /// Hash of the reverse complement of the current kmer.
+    pub fn reverse(&self) -> u64 {
+        self.reverse
+    }
+
+    /// Hash of the current kmer that is equal for its reverse complement.
+    pub fn canonical(&self) -> u64 {
+        self.forward.min(self.reverse)
+    }
+}
+
+/// Calls `f` with the canonical ntHash of every kmer of a sequence, skipping
+/// kmers with bases other than `ACGT`. Returns the number of kmers seen.
+///
+/// Panics if `k` is 0.
+pub fn for_each_nthash<F: FnMut(u64)>(seq: &[u8], k: usize, mut f: F) -> u64 {
+    let mut hasher = NtHash::new(k);
+    let mut kmers_seen = 0;
+    for &base in seq {
+        if let Some(hash) = hasher.push(base) {
+            f(hash);
+            kmers_seen += 1;
+        }
     }
+    kmers_seen
 }
 
 #[cfg(test)]
@@ -284,6 +2132,360 @@ mod tests {
         assert_eq!(kmers, vec![b"atc".to_vec(), b"nga".to_vec()]);
     }
 
+    #[test]
+    fn test_kmer_policy() {
+        let data = b">seq1\nACGN\nacgtA\n";
+        let mut reader = FastaReader::new(Cursor::new(data)).with_kmer_policy(KmerPolicy::STRICT);
+        reader.next_record().unwrap();
+        let kmers: Vec<Vec<u8>> = reader.canonical_kmers(3).map(|r| r.unwrap()).collect();
+        // Same as the 2-bit path: ACG, (CGN, GNa, Nac skipped), ACG, CGT -> ACG, GTA -> GTA
+        assert_eq!(
+            kmers,
+            vec![
+                b"ACG".to_vec(),
+                b"ACG".to_vec(),
+                b"ACG".to_vec(),
+                b"GTA".to_vec()
+            ]
+        );
+
+        let policy = KmerPolicy {
+            skip_ambiguous: true,
+            ..KmerPolicy::default()
+        };
+        let mut reader = FastaReader::new(Cursor::new(data)).with_kmer_policy(policy);
+        reader.next_record().unwrap();
+        let kmers: Vec<Vec<u8>> = reader.kmers(3).map(|r| r.unwrap()).collect();
+        assert_eq!(
+            kmers,
+            vec![
+                b"ACG".to_vec(),
+                b"acg".to_vec(),
+                b"cgt".to_vec(),
+                b"gtA".to_vec()
+            ]
+        );
+
+        let data = b"@r1\nACGNacg\n+\nIIIIIII\n";
+        let mut reader = FastqReader::new(Cursor::new(data)).with_kmer_policy(KmerPolicy::STRICT);
+        reader.next_record().unwrap();
+        let kmers: Vec<Vec<u8>> = reader.kmers(3).map(|r| r.unwrap()).collect();
+        assert_eq!(kmers, vec![b"ACG".to_vec(), b"ACG".to_vec()]);
+    }
+
+    #[test]
+    fn test_canonical_u128_matches_u64() {
+        for kmer in [0u64, 1, 0x1234_5678, (1 << 62) - 1, 0x2AAA_AAAA_AAAA_AAAA] {
+            let kmer = kmer & kmer_mask(31);
+            assert_eq!(
+                get_canonical_u128(kmer as u128, 31),
+                get_canonical_u64(kmer, 31) as u128
+            );
+        }
+    }
+
+    #[test]
+    fn test_encoded_kmers() {
+        let data = b">seq1\nACGTN\nacg\n>seq2\nTTTT\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+
+        reader.next_record().unwrap();
+        let kmers: Vec<u64> = reader.encoded_kmers(3).map(|r| r.unwrap()).collect();
+        // ACG (= CGT), CGT -> ACG, acg
+        assert_eq!(kmers, vec![0b000110, 0b000110, 0b000110]);
+
+        reader.next_record().unwrap();
+        let kmers: Vec<u128> = reader.encoded_kmers(3).map(|r| r.unwrap()).collect();
+        // TTT -> AAA
+        assert_eq!(kmers, vec![0, 0]);
+        assert!(!reader.next_record().unwrap());
+
+        assert_eq!(decode_kmer(0b000110, 3), b"ACG");
+        assert_eq!(decode_kmer(u64::MAX, 32), [b'T'; 32]);
+    }
+
+    #[test]
+    fn test_protein_kmers() {
+        let data = b">prot1\nMKV\nlXAC*\n>prot2\nMKVL\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+
+        reader.next_record().unwrap();
+        let kmers: Vec<u64> = reader
+            .protein_kmers(2, ProteinAlphabet::Standard)
+            .map(|r| r.unwrap())
+            .collect();
+        // MK, KV, VL, AC with M=10, K=8, V=17, L=9, A=0, C=1
+        assert_eq!(kmers, vec![10 << 5 | 8, 8 << 5 | 17, 17 << 5 | 9, 1]);
+
+        reader.next_record().unwrap();
+        let dayhoff: Vec<u64> = reader
+            .protein_kmers(4, ProteinAlphabet::Dayhoff)
+            .map(|r| r.unwrap())
+            .collect();
+        // MKVL -> ILMV, HKR, ILMV, ILMV
+        assert_eq!(dayhoff, vec![4 << 15 | 3 << 10 | 4 << 5 | 4]);
+        assert!(!reader.next_record().unwrap());
+
+        let mut hp = Vec::new();
+        let seen = for_each_protein_kmer(b"MKVL", 4, ProteinAlphabet::Hp, |kmer| hp.push(kmer));
+        assert_eq!((seen, hp), (1, vec![1 << 10]));
+        assert_eq!(ProteinAlphabet::Hp.encode(b'x'), None);
+        assert_eq!(ProteinAlphabet::Standard.encode(b'y'), Some(19));
+    }
+
+    #[test]
+    fn test_iupac_reverse_complement() {
+        assert_eq!(reverse_complement(b"ACGTRYMKBVDHSWN"), b"NWSDHBVMKRYACGT");
+        assert_eq!(reverse_complement(b"acgu-ryn"), b"nry-acgt");
+        assert_eq!(
+            reverse_complement(&reverse_complement(b"RYMKBVDH")),
+            b"RYMKBVDH"
+        );
+        assert_eq!(get_canonical(b"YAA"), b"TTR");
+
+        let policy = KmerPolicy {
+            reject_ambiguous: true,
+            ..KmerPolicy::default()
+        };
+        let data = b">seq1\nACGT\nACRT\n";
+        let mut reader = FastaReader::new(Cursor::new(data)).with_kmer_policy(policy);
+        reader.next_record().unwrap();
+        let kmers: Vec<_> = reader.kmers(3).collect();
+        assert_eq!(kmers.len(), 5);
+        assert!(matches!(
+            kmers[4],
+            Err(FastaError::AmbiguousBase {
+                byte: b'R',
+                position: 6
+            })
+        ));
+
+        let data = b"@r1\nACGYAC\n+\nIIIIII\n";
+        let mut reader = FastqReader::new(Cursor::new(data)).with_kmer_policy(policy);
+        reader.next_record().unwrap();
+        let kmers: Vec<_> = reader.kmers(3).collect();
+        assert_eq!(kmers.len(), 2);
+        assert!(matches!(
+            kmers[1],
+            Err(FastaError::AmbiguousBase {
+                byte: b'Y',
+                position: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_spaced_seeds() {
+        let seed = SpacedSeed::new("11011");
+        assert_eq!(seed.to_string(), "11011");
+        assert_eq!(SpacedSeed::contiguous(3).to_string(), "111");
+
+        let data = b">seq1\nACGTTGCA\n>seq2\nACCTTGNCA\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let kmers: Vec<Vec<u8>> = reader.spaced_kmers(&seed).map(|r| r.unwrap()).collect();
+        assert_eq!(
+            kmers,
+            vec![
+                b"ACTT".to_vec(),
+                b"CGTG".to_vec(),
+                b"GTGC".to_vec(),
+                b"TTCA".to_vec()
+            ]
+        );
+
+        // The mutation G -> C at an ignored position keeps the first kmer
+        reader.next_record().unwrap();
+        let kmers: Vec<Vec<u8>> = reader
+            .canonical_spaced_kmers(&seed)
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(kmers[0], seed.apply_canonical(b"ACGTT"));
+
+        // The 2-bit path yields the same canonical kmers
+        let seq = b"ACGTTGCAacgtNGGATCCA";
+        let encode = |kmer: &[u8]| {
+            kmer.iter().fold(0u64, |acc, &base| {
+                (acc << 2) | ENCODING[base as usize] as u64
+            })
+        };
+        let expected: Vec<u64> = seq
+            .windows(seed.span())
+            .filter(|window| window.iter().all(|&base| ENCODING[base as usize] != 0xFF))
+            .map(|window| encode(&seed.apply_canonical(&window.to_ascii_uppercase())))
+            .collect();
+        assert_eq!(seed.encoded_kmers(seq).collect::<Vec<_>>(), expected);
+        assert_eq!(expected.len(), 11);
+    }
+
+    #[test]
+    fn test_syncmers() {
+        let mut state = 7u64;
+        let seq: Vec<u8> = (0..20_000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect();
+        let mut data = b">seq1\n".to_vec();
+        data.extend_from_slice(&seq);
+        data.extend_from_slice(b"\n>seq2\n");
+        data.extend_from_slice(&reverse_complement(&seq));
+        data.push(b'\n');
+
+        let (k, s) = (21, 11);
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let forward: Vec<u64> = reader.syncmers(k, s).map(|r| r.unwrap()).collect();
+        reader.next_record().unwrap();
+        let mut reverse: Vec<u64> = reader.syncmers(k, s).map(|r| r.unwrap()).collect();
+        // Both strands select the same canonical kmers
+        reverse.reverse();
+        assert_eq!(forward, reverse);
+
+        let density = forward.len() as f64 / (seq.len() - k + 1) as f64;
+        let expected = SyncmerKind::Closed.density(k, s);
+        assert!(
+            (density - expected).abs() < 0.2 * expected,
+            "density {density}"
+        );
+
+        let open = SyncmerStream::new(
+            [0b11_10_01_00u64, 0].into_iter().map(Ok),
+            4,
+            4,
+            SyncmerKind::Open,
+        );
+        // With s = k, every kmer is its own smallest s-mer
+        assert_eq!(open.count(), 2);
+    }
+
+    #[test]
+    fn test_nthash() {
+        let seq = b"ACGTTGCAAGGCTTAGCNNACGTATTAGGCCATGCAAACGTTGACATGCAGTACCGT";
+        for k in [1, 5, 31, 40] {
+            // Rolling hashes match hashing every kmer from scratch
+            let mut hashes = Vec::new();
+            let seen = for_each_nthash(seq, k, |hash| hashes.push(hash));
+            let expected: Vec<u64> = seq
+                .windows(k)
+                .filter(|kmer| !kmer.contains(&b'N'))
+                .map(|kmer| {
+                    let mut hasher = NtHash::new(k);
+                    kmer.iter()
+                        .filter_map(|&base| hasher.push(base))
+                        .last()
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(hashes, expected);
+            assert_eq!(seen, expected.len() as u64);
+
+            // Canonical: the reverse complement has the same hashes, reversed
+            let mut rc_hashes = Vec::new();
+            for_each_nthash(&reverse_complement(seq), k, |hash| rc_hashes.push(hash));
+            rc_hashes.reverse();
+            assert_eq!(hashes, rc_hashes);
+        }
+
+        let mut hasher = NtHash::new(3);
+        assert_eq!(hasher.push(b'A'), None);
+        assert_eq!(hasher.push(b'c'), None);
+        let hash = hasher.push(b'G').unwrap();
+        assert_eq!(hash, hasher.canonical());
+        let mut rc_hasher = NtHash::new(3);
+        rc_hasher.push(b'C');
+        rc_hasher.push(b'G');
+        rc_hasher.push(b'T');
+        assert_eq!(rc_hasher.reverse(), hasher.forward());
+        assert_eq!(rc_hasher.forward(), hasher.reverse());
+        assert_eq!(hasher.push(b'N'), None);
+
+        // The stream hashes across line breaks like the whole sequence
+        let data = b">seq1\nACGTTGCAAGG\nCTTAGCNNACG\n>seq2\nAC\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let streamed: Vec<u64> = reader.nthashes(5).map(Result::unwrap).collect();
+        let mut expected = Vec::new();
+        for_each_nthash(b"ACGTTGCAAGGCTTAGCNNACG", 5, |hash| expected.push(hash));
+        assert_eq!(streamed, expected);
+        reader.next_record().unwrap();
+        assert_eq!(reader.nthashes(5).count(), 0);
+    }
+
+    #[test]
+    fn test_records() {
+        let data = b">seq1 first\nACGTAC\nGT\n>seq2\nTTTT\n>seq3\nGGGCC\n>seq4\nAC\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+        let mut records = reader.records();
+
+        let mut record = records.next_record().unwrap().unwrap();
+        assert_eq!(record.id(), b"seq1 first");
+        // Stopping early skips the rest of the record
+        assert_eq!(record.kmers(3).next().unwrap().unwrap(), b"ACG");
+        drop(record);
+
+        // Records are skipped without touching the sequence
+        let record = records.next_record().unwrap().unwrap();
+        assert_eq!(record.into_id(), b"seq2");
+
+        let mut record = records.next_record().unwrap().unwrap();
+        assert_eq!(record.sequence().unwrap(), b"GGGCC");
+        assert_eq!(record.stats().gc_count, 5);
+        drop(record);
+
+        let mut record = records.next_record().unwrap().unwrap();
+        let encoded: Vec<u64> = record.encoded_kmers(2).map(Result::unwrap).collect();
+        assert_eq!(encoded.len(), 1);
+        drop(record);
+        assert!(records.next_record().is_none());
+
+        let mut reader = FastaReader::new(Cursor::new(b"ACGT\n"));
+        assert!(matches!(
+            reader.records().next_record(),
+            Some(Err(FastaError::MalformedHeader { line_no: 1 }))
+        ));
+    }
+
+    #[test]
+    fn test_record_stats() {
+        let data = b">seq1\nACGTNN\nggcn\n>seq2\nATAT\nAT\nGC\n>seq3\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+
+        reader.next_record().unwrap();
+        reader.read_sequence().unwrap();
+        let stats = *reader.stats();
+        assert_eq!(
+            stats,
+            RecordStats {
+                length: 10,
+                gc_count: 5,
+                at_count: 2,
+                n_count: 3,
+                lines: 2
+            }
+        );
+        assert_eq!(stats.effective_length(), 7);
+        assert!((stats.gc_content() - 5.0 / 7.0).abs() < 1e-12);
+
+        // Dropping a kmer stream early still consumes the whole record
+        reader.next_record().unwrap();
+        let _ = reader.encoded_kmers::<u64>(3).next();
+        assert_eq!(reader.stats().lines, 3);
+        assert_eq!(reader.stats().gc_content(), 2.0 / 8.0);
+        assert_eq!(
+            *reader.stats(),
+            RecordStats {
+                lines: 3,
+                ..RecordStats::from_sequence(b"ATATATGC")
+            }
+        );
+
+        reader.next_record().unwrap();
+        assert_eq!(reader.kmers(3).count(), 0);
+        assert_eq!(*reader.stats(), RecordStats::default());
+    }
+
     #[test]
     fn test_canonical_kmers_palindromes() {
         let data = b">seq1\nGCGC\n";
@@ -334,4 +2536,194 @@ mod tests {
         let kmers: Vec<Vec<u8>> = reader.canonical_kmers(3).map(|r| r.unwrap()).collect();
         assert!(kmers.is_empty());
     }
+
+    #[test]
+    fn test_fastq_canonical_kmers() {
+        let data = b"@read1\nATCG\n+\nIIII\n@read2\nAAA\n+read2\nIII\n";
+        let mut reader = FastqReader::new(Cursor::new(data));
+
+        assert!(reader.next_record().unwrap());
+        assert_eq!(reader.id, Some(b"read1".to_vec()));
+        let kmers: Vec<Vec<u8>> = reader.canonical_kmers(3).map(|r| r.unwrap()).collect();
+        assert_eq!(kmers, vec![b"ATC".to_vec(), b"CGA".to_vec()]);
+
+        assert!(reader.next_record().unwrap());
+        let kmers: Vec<Vec<u8>> = reader.canonical_kmers(3).map(|r| r.unwrap()).collect();
+        assert_eq!(kmers, vec![b"AAA".to_vec()]);
+
+        assert!(!reader.next_record().unwrap());
+    }
+
+    #[test]
+    fn test_fastq_min_quality() {
+        // '#' is Phred 2, 'I' is Phred 40
+        let data = b"@read1\nACGTACG\n+\nIII#III\n";
+        let mut reader = FastqReader::new(Cursor::new(data)).with_min_quality(20);
+        reader.next_record().unwrap();
+
+        let kmers: Vec<Vec<u8>> = reader.kmers(3).map(|r| r.unwrap()).collect();
+        assert_eq!(kmers, vec![b"ACG".to_vec(), b"ACG".to_vec()]);
+    }
+
+    #[test]
+    fn test_fastq_malformed() {
+        let data = b"@read1\nACGT\n+\nIII\n";
+        let mut reader = FastqReader::new(Cursor::new(data));
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::QualityLengthMismatch { line_no: 4 })
+        ));
+
+        let data = b"@read1\nACGT\n+\nIIIII\n";
+        let mut reader = FastqReader::new(Cursor::new(data));
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::QualityLengthMismatch { line_no: 4 })
+        ));
+
+        let data = b"@read1\nACGT\n+\n";
+        let mut reader = FastqReader::new(Cursor::new(data));
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::TruncatedRecord)
+        ));
+
+        let data = b"@read1\nACGT\nIIII\n";
+        let mut reader = FastqReader::new(Cursor::new(data));
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::MalformedHeader { line_no: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_fasta_errors() {
+        let data = b">seq1\nACGT\nAC\nAC1T\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let result: Result<Vec<Vec<u8>>, FastaError> = reader.kmers(3).collect();
+        assert!(matches!(
+            result,
+            Err(FastaError::InvalidCharacter {
+                byte: b'1',
+                position: 8
+            })
+        ));
+
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        assert!(matches!(
+            reader.read_sequence(),
+            Err(FastaError::InvalidCharacter {
+                byte: b'1',
+                position: 8
+            })
+        ));
+
+        let mut reader = FastaReader::new(Cursor::new(b"ACGT\n"));
+        assert!(matches!(
+            reader.next_record(),
+            Err(FastaError::MalformedHeader { line_no: 1 })
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_open_gzip_sequence_file() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("hll_rust_test_{}.fa.gz", std::process::id()));
+        // Two gzip members, like a bgzip file
+        let mut data = Vec::new();
+        for chunk in [&b">seq1\nAC"[..], &b"GT\n"[..]] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(chunk).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+        std::fs::write(&path, data).unwrap();
+
+        let mut reader = FastaReader::new(open_sequence_file(&path).unwrap());
+        reader.next_record().unwrap();
+        assert_eq!(reader.read_sequence().unwrap(), b"ACGT".to_vec());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_open_sequence_reader_short_reads() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        // Returns one byte per read, like a slow pipe
+        struct OneByte(Cursor<Vec<u8>>);
+        impl Read for OneByte {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = buf.len().min(1);
+                self.0.read(&mut buf[..len])
+            }
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b">seq1\nACGT\n").unwrap();
+        let data = encoder.finish().unwrap();
+        let reader = open_sequence_reader(OneByte(Cursor::new(data))).unwrap();
+        let mut reader = FastaReader::new(reader);
+        reader.next_record().unwrap();
+        assert_eq!(reader.read_sequence().unwrap(), b"ACGT".to_vec());
+
+        // Input shorter than the magic is passed through
+        for data in [&b""[..], b">", b">s\nA\n"] {
+            let mut reader = open_sequence_reader(OneByte(Cursor::new(data.to_vec()))).unwrap();
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, data);
+        }
+    }
+
+    #[test]
+    fn test_minimizers() {
+        let data = b">seq1\nACGTTGCAAGGCTTAACGT\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let kmers: Vec<Vec<u8>> = reader.canonical_kmers(5).map(|r| r.unwrap()).collect();
+
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let minimizers: Vec<Vec<u8>> = reader.minimizers(5, 4).map(|r| r.unwrap()).collect();
+
+        // Every window of 4 kmers contains one of the selected minimizers
+        assert!(!minimizers.is_empty() && minimizers.len() < kmers.len());
+        for window in kmers.windows(4) {
+            let min = window.iter().min_by_key(|kmer| xxh64(kmer, 0)).unwrap();
+            assert!(minimizers.contains(min));
+        }
+
+        // With w = 1 every kmer is its own minimizer
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        assert_eq!(reader.minimizers(5, 1).count(), kmers.len());
+    }
+
+    #[test]
+    fn test_next_canonical_kmer_matches_iterator() {
+        let data = b">seq1\nACGTTG\nCAAGG\nC\n>seq2\nAC\n";
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let expected: Vec<Vec<u8>> = reader.canonical_kmers(4).map(|r| r.unwrap()).collect();
+
+        let mut reader = FastaReader::new(Cursor::new(data));
+        reader.next_record().unwrap();
+        let mut kmers = reader.kmers(4);
+        let mut streamed = Vec::new();
+        while let Some(kmer) = kmers.next_canonical_kmer().unwrap() {
+            streamed.push(kmer.to_vec());
+        }
+        drop(kmers);
+
+        assert_eq!(streamed, expected);
+        assert!(reader.next_record().unwrap());
+        assert_eq!(reader.id, Some(b"seq2".to_vec()));
+    }
 }
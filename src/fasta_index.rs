@@ -0,0 +1,320 @@
+//! Random access to FASTA files through samtools-style `.fai` indexes.
+
+use crate::fasta::{CanonicalKmerStream, FastaError, SequenceKmerStream, is_residue};
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter};
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+/// One line of a `.fai` index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiRecord {
+    /// Sequence name, the header up to the first whitespace.
+    pub name: String,
+    /// Number of bases.
+    pub length: u64,
+    /// Byte offset of the first base in the file.
+    pub offset: u64,
+    /// Bases per line.
+    pub line_bases: u64,
+    /// Bytes per line, including the line terminator.
+    pub line_width: u64,
+}
+
+impl FaiRecord {
+    // Byte offset of the base at `pos`
+    fn base_offset(&self, pos: u64) -> u64 {
+        self.offset + pos / self.line_bases * self.line_width + pos % self.line_bases
+    }
+}
+
+/// A `.fai` index listing where every sequence of a FASTA file starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FastaIndex {
+    pub records: Vec<FaiRecord>,
+}
+
+impl FastaIndex {
+    /// Builds the index by scanning an uncompressed FASTA file.
+    ///
+    /// All lines of a sequence except the last must have the same length, as
+    /// required by samtools.
+    pub fn build<R: BufRead>(mut reader: R) -> Result<Self, FastaError> {
+        let mut records = Vec::new();
+        let mut current: Option<FaiRecord> = None;
+        // Set once a line shorter than the first one was seen
+        let mut last_line_seen = false;
+        let mut line = Vec::new();
+        let mut offset = 0u64;
+        let mut line_no = 0;
+
+        loop {
+            line.clear();
+            let bytes_read = reader.read_until(b'\n', &mut line)?;
+            if bytes_read == 0 {
+                break;
+            }
+            line_no += 1;
+            offset += bytes_read as u64;
+
+            if line.starts_with(b">") {
+                records.extend(current.take());
+                let name = line[1..]
+                    .split(|byte| byte.is_ascii_whitespace())
+                    .next()
+                    .unwrap_or_default();
+                current = Some(FaiRecord {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    length: 0,
+                    offset,
+                    line_bases: 0,
+                    line_width: 0,
+                });
+                last_line_seen = false;
+                continue;
+            }
+
+            let Some(record) = current.as_mut() else {
+                return Err(FastaError::MalformedHeader { line_no });
+            };
+            let bases = line.trim_ascii_end();
+            if let Some(i) = bases.iter().position(|&byte| !is_residue(byte)) {
+                return Err(FastaError::InvalidCharacter {
+                    byte: bases[i],
+                    position: record.length as usize + i,
+                });
+            }
+            if bases.is_empty() {
+                last_line_seen = true;
+                continue;
+            }
+            if last_line_seen {
+                return Err(FastaError::MalformedIndex { line_no });
+            }
+
+            if record.line_bases == 0 {
+                record.line_bases = bases.len() as u64;
+                record.line_width = bytes_read as u64;
+            } else if bases.len() as u64 > record.line_bases
+                || (bases.len() as u64 == record.line_bases
+                    && bytes_read as u64 != record.line_width)
+            {
+                return Err(FastaError::MalformedIndex { line_no });
+            } else if (bases.len() as u64) < record.line_bases {
+                last_line_seen = true;
+            }
+            record.length += bases.len() as u64;
+        }
+        records.extend(current);
+
+        Ok(FastaIndex { records })
+    }
+
+    /// Reads an index in the `.fai` format.
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, FastaError> {
+        let mut records = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let malformed = || FastaError::MalformedIndex { line_no: i + 1 };
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 5 {
+                return Err(malformed());
+            }
+            let number = |field: &str| field.parse::<u64>().map_err(|_| malformed());
+            records.push(FaiRecord {
+                name: fields[0].to_string(),
+                length: number(fields[1])?,
+                offset: number(fields[2])?,
+                line_bases: number(fields[3])?,
+                line_width: number(fields[4])?,
+            });
+        }
+        Ok(FastaIndex { records })
+    }
+
+    /// Writes the index in the `.fai` format.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for record in &self.records {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                record.name, record.length, record.offset, record.line_bases, record.line_width
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the entry of the sequence with the given name.
+    pub fn get(&self, name: &str) -> Option<&FaiRecord> {
+        self.records.iter().find(|record| record.name == name)
+    }
+}
+
+/// A FASTA reader that fetches regions of single sequences without reading
+/// the rest of the file.
+pub struct IndexedFastaReader<R> {
+    reader: R,
+    index: FastaIndex,
+}
+
+#[cfg(feature = "fs")]
+impl IndexedFastaReader<BufReader<File>> {
+    /// Opens an uncompressed FASTA file together with its `.fai` index.
+    ///
+    /// If `<path>.fai` does not exist, the index is built and written there.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FastaError> {
+        let path = path.as_ref();
+        let mut fai_path = PathBuf::from(path).into_os_string();
+        fai_path.push(".fai");
+
+        let index = match File::open(&fai_path) {
+            Ok(file) => FastaIndex::read(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let index = FastaIndex::build(BufReader::new(File::open(path)?))?;
+                let mut writer = BufWriter::new(File::create(&fai_path)?);
+                index.write(&mut writer)?;
+                writer.flush()?;
+                index
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(IndexedFastaReader::new(
+            BufReader::new(File::open(path)?),
+            index,
+        ))
+    }
+}
+
+impl<R: Read + Seek> IndexedFastaReader<R> {
+    pub fn new(reader: R, index: FastaIndex) -> Self {
+        IndexedFastaReader { reader, index }
+    }
+
+    pub fn index(&self) -> &FastaIndex {
+        &self.index
+    }
+
+    /// Reads the bases `start..end` (0-based, end exclusive) of a sequence.
+    pub fn fetch_sequence(
+        &mut self,
+        name: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<u8>, FastaError> {
+        let record = self
+            .index
+            .get(name)
+            .ok_or_else(|| FastaError::UnknownSequence(name.to_string()))?;
+        if start >= end || end > record.length {
+            return Err(FastaError::InvalidRegion {
+                start,
+                end,
+                length: record.length,
+            });
+        }
+
+        let first = record.base_offset(start);
+        let last = record.base_offset(end - 1);
+        let mut bytes = vec![0; (last - first + 1) as usize];
+        self.reader.seek(SeekFrom::Start(first))?;
+        self.reader.read_exact(&mut bytes)?;
+
+        bytes.retain(|&byte| byte != b'\n' && byte != b'\r');
+        Ok(bytes)
+    }
+
+    /// Fetches the bases `start..end` (0-based, end exclusive) of a sequence
+    /// for iterating over their kmers.
+    pub fn fetch(&mut self, name: &str, start: u64, end: u64) -> Result<Region, FastaError> {
+        Ok(Region {
+            sequence: self.fetch_sequence(name, start, end)?,
+        })
+    }
+}
+
+/// The bases of a region fetched by [`IndexedFastaReader::fetch`].
+pub struct Region {
+    pub sequence: Vec<u8>,
+}
+
+impl Region {
+    /// Returns an iterator over the kmers of the region.
+    pub fn kmers(&self, k: usize) -> SequenceKmerStream<'_> {
+        SequenceKmerStream::new(&self.sequence, k)
+    }
+
+    /// Returns an iterator over the canonical kmers of the region.
+    pub fn canonical_kmers(&self, k: usize) -> CanonicalKmerStream<SequenceKmerStream<'_>> {
+        CanonicalKmerStream::new(self.kmers(k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FASTA: &[u8] = b">chr1 first\nACGT\nTTGG\nCA\n>chr2\nGGGG\nAA\n";
+
+    #[test]
+    fn test_build_index_and_fetch() {
+        let index = FastaIndex::build(Cursor::new(FASTA)).unwrap();
+        let mut fai = Vec::new();
+        index.write(&mut fai).unwrap();
+        // Same as `samtools faidx`
+        assert_eq!(fai, b"chr1\t10\t12\t4\t5\nchr2\t6\t31\t4\t5\n");
+        assert_eq!(FastaIndex::read(Cursor::new(&fai)).unwrap(), index);
+
+        let mut reader = IndexedFastaReader::new(Cursor::new(FASTA), index);
+        assert_eq!(reader.fetch_sequence("chr1", 2, 9).unwrap(), b"GTTTGGC");
+        assert_eq!(reader.fetch_sequence("chr2", 0, 6).unwrap(), b"GGGGAA");
+
+        let region = reader.fetch("chr1", 3, 7).unwrap();
+        let kmers: Vec<Vec<u8>> = region.canonical_kmers(3).map(|r| r.unwrap()).collect();
+        // TTT -> AAA, TTG -> CAA
+        assert_eq!(kmers, vec![b"AAA".to_vec(), b"CAA".to_vec()]);
+
+        assert!(matches!(
+            reader.fetch_sequence("chr3", 0, 1),
+            Err(FastaError::UnknownSequence(_))
+        ));
+        assert!(matches!(
+            reader.fetch_sequence("chr2", 2, 7),
+            Err(FastaError::InvalidRegion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_uneven_lines_cannot_be_indexed() {
+        let fasta = b">chr1\nACG\nACGT\n";
+        assert!(matches!(
+            FastaIndex::build(Cursor::new(fasta)),
+            Err(FastaError::MalformedIndex { line_no: 3 })
+        ));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_open_creates_index() {
+        let path = std::env::temp_dir().join(format!("hll_rust_test_{}.fa", std::process::id()));
+        let fai_path = path.with_extension("fa.fai");
+        std::fs::write(&path, FASTA).unwrap();
+
+        let mut reader = IndexedFastaReader::open(&path).unwrap();
+        assert_eq!(reader.fetch_sequence("chr2", 4, 6).unwrap(), b"AA");
+        assert!(fai_path.exists());
+        // The second time the index is read from disk
+        let reader = IndexedFastaReader::open(&path).unwrap();
+        assert_eq!(reader.index().records.len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&fai_path).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! C interface for HLL sketches (`ffi` feature).
+//!
+//! The declarations are in `include/hll.h`, generated with
+//! `cbindgen --config cbindgen.toml --output include/hll.h`.
+//!
+//! Sketches use the deterministic Xxh64 hash, so serialized sketches are
+//! compatible with the ones written by the `hll` tool.
+
+use crate::{Counter, HLLCounter, MergeableCounter};
+use std::os::raw::c_int;
+use std::slice;
+use xxhash_rust::xxh64::Xxh64Builder;
+
+// Same range as the `hll` command line tool
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 24;
+
+/// Opaque handle to a HyperLogLog sketch.
+pub struct HllSketch(HLLCounter<Xxh64Builder>);
+
+/// Creates an empty sketch with 2^precision registers.
+///
+/// Returns NULL if the precision is not between 4 and 24. The sketch must be
+/// released with `hll_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn hll_new(precision: u32) -> *mut HllSketch {
+    if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(HllSketch(HLLCounter::new(precision as usize))))
+}
+
+/// Releases a sketch created by `hll_new` or `hll_deserialize`. NULL is ignored.
+///
+/// # Safety
+///
+/// `sketch` must be NULL or a sketch that was not freed before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hll_free(sketch: *mut HllSketch) {
+    if !sketch.is_null() {
+        drop(unsafe { Box::from_raw(sketch) });
+    }
+}
+
+/// Adds the item of `len` bytes at `data` to the sketch.
+///
+/// # Safety
+///
+/// `sketch` must be a valid sketch and `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hll_add_bytes(sketch: *mut HllSketch, data: *const u8, len: usize) {
+    let sketch = unsafe { &mut *sketch };
+    let item = if len == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(data, len) }
+    };
+    sketch.0.add(item);
+}
+
+/// Merges `src` into `dst`.
+///
+/// Returns 0 on success and -1 if the sketches cannot be merged.
+///
+/// # Safety
+///
+/// `dst` and `src` must be valid sketches.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hll_merge(dst: *mut HllSketch, src: *const HllSketch) -> c_int {
+    let (dst, src) = unsafe { (&mut *dst, &*src) };
+    match dst.0.merge(&src.0) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Returns the cardinality estimate of the sketch.
+///
+/// # Safety
+///
+/// `sketch` must be a valid sketch.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hll_estimate(sketch: *const HllSketch) -> f64 {
+    unsafe { &*sketch }.0.estimate()
+}
+
+/// Serializes the sketch into `out`, which has room for `capacity` bytes.
+///
+/// Returns the size of the serialized sketch. Nothing is written if it is
+/// larger than `capacity`, so the size can be queried with a NULL `out` and a
+/// capacity of 0.
+///
+/// # Safety
+///
+/// `sketch` must be a valid sketch and `out` must point to `capacity` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hll_serialize(
+    sketch: *const HllSketch,
+    out: *mut u8,
+    capacity: usize,
+) -> usize {
+    let mut bytes = Vec::new();
+    unsafe { &*sketch }
+        .0
+        .write_to(&mut bytes)
+        .expect("writing to a Vec cannot fail");
+    if bytes.len() <= capacity {
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+    }
+    bytes.len()
+}
+
+/// Reads a sketch written by `hll_serialize` or the `hll sketch` command.
+///
+/// Returns NULL if the data is not a valid sketch. The sketch must be
+/// released with `hll_free`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hll_deserialize(data: *const u8, len: usize) -> *mut HllSketch {
+    if data.is_null() {
+        return std::ptr::null_mut();
+    }
+    let bytes = unsafe { slice::from_raw_parts(data, len) };
+    match HLLCounter::read_from(bytes) {
+        Ok(counter) => Box::into_raw(Box::new(HllSketch(counter))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        assert!(hll_new(30).is_null());
+
+        let a = hll_new(12);
+        let b = hll_new(12);
+        unsafe {
+            for i in 0..10_000u64 {
+                let item = i.to_le_bytes();
+                hll_add_bytes(if i % 2 == 0 { a } else { b }, item.as_ptr(), item.len());
+            }
+            assert_eq!(hll_merge(a, b), 0);
+            let estimate = hll_estimate(a);
+            assert!((estimate - 10_000.0).abs() / 10_000.0 < 0.05);
+
+            let len = hll_serialize(a, std::ptr::null_mut(), 0);
+            let mut bytes = vec![0u8; len];
+            assert_eq!(hll_serialize(a, bytes.as_mut_ptr(), bytes.len()), len);
+            let c = hll_deserialize(bytes.as_ptr(), bytes.len());
+            assert!(!c.is_null());
+            assert_eq!(hll_estimate(c), estimate);
+
+            let d = hll_new(10);
+            assert_eq!(hll_merge(c, d), -1);
+            assert!(hll_deserialize(b"nope".as_ptr(), 4).is_null());
+
+            for sketch in [a, b, c, d] {
+                hll_free(sketch);
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::counters::MergeError;
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// A Bloom filter for approximate set membership.
+///
+/// `contains` never returns false negatives, but may return false positives
+/// with a probability that depends on the number of bits, hash functions and
+/// inserted items.
+pub struct BloomFilter<S = RandomState> {
+    num_bits: usize,
+    num_hashes: usize,
+    bits: Vec<u64>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> BloomFilter<S> {
+    /// Creates a filter with `num_bits` bits and `num_hashes` hash functions.
+    pub fn new(num_bits: usize, num_hashes: usize) -> Self {
+        assert!(num_bits > 0, "Bloom filter needs at least one bit");
+        assert!(
+            num_hashes > 0,
+            "Bloom filter needs at least one hash function"
+        );
+        let hasher = S::default();
+        BloomFilter {
+            num_bits,
+            num_hashes,
+            bits: vec![0; num_bits.div_ceil(64)],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Creates a filter sized for `expected_items` insertions at the given
+    /// false positive rate.
+    pub fn with_rate(expected_items: usize, false_positive_rate: f64) -> Self {
+        let (num_bits, num_hashes) = Self::optimal_size(expected_items, false_positive_rate);
+        Self::new(num_bits, num_hashes)
+    }
+
+    /// Returns the optimal number of bits and hash functions for `expected_items`
+    /// insertions at the given false positive rate.
+    pub fn optimal_size(expected_items: usize, false_positive_rate: f64) -> (usize, usize) {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * false_positive_rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as usize;
+        (num_bits.max(1), num_hashes)
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let hash = self.hasher.hash_one(item);
+        self.insert_hash(hash);
+    }
+
+    pub fn insert_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.insert_hash(hash);
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.contains_hash(hash)
+    }
+
+    pub fn contains_u64(&self, item: u64) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.contains_hash(hash)
+    }
+
+    #[inline(always)]
+    fn indices(&self, hash: u64) -> impl Iterator<Item = usize> + use<S> {
+        double_hash_indices(&self.hasher, hash, self.num_hashes, self.num_bits)
+    }
+
+    fn insert_hash(&mut self, hash: u64) {
+        for index in self.indices(hash) {
+            self.bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    fn contains_hash(&self, hash: u64) -> bool {
+        self.indices(hash)
+            .all(|index| self.bits[index / 64] & (1 << (index % 64)) != 0)
+    }
+
+    /// Merges `other` into `self`, so that `self` contains the items of both filters.
+    ///
+    /// Fails if the filters differ in size or hash function.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((self.num_bits, 0), (other.num_bits, 0))?;
+        check_mergeable(
+            (self.num_hashes, self.fingerprint),
+            (other.num_hashes, other.fingerprint),
+        )?;
+        for (word_self, word_other) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word_self |= *word_other;
+        }
+        Ok(())
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    /// Estimates the number of distinct inserted items from the number of set bits.
+    pub fn estimate_cardinality(&self) -> f64 {
+        let set_bits: usize = self.bits.iter().map(|w| w.count_ones() as usize).sum();
+        let m = self.num_bits as f64;
+        let unset = (m - set_bits as f64).max(1.0);
+        -m / self.num_hashes as f64 * (unset / m).ln()
+    }
+
+    /// Estimates the current false positive rate from the fraction of set bits.
+    pub fn false_positive_rate(&self) -> f64 {
+        let set_bits: usize = self.bits.iter().map(|w| w.count_ones() as usize).sum();
+        (set_bits as f64 / self.num_bits as f64).powi(self.num_hashes as i32)
+    }
+}
+
+// Double hashing (Kirsch & Mitzenmacher): the i-th of `num_hashes` indices
+// below `len` is h1 + i * h2.
+#[inline(always)]
+pub(crate) fn double_hash_indices<S: BuildHasher>(
+    hasher: &S,
+    hash: u64,
+    num_hashes: usize,
+    len: usize,
+) -> impl Iterator<Item = usize> + use<S> {
+    let h2 = hasher.hash_one(hash) | 1;
+    let len = len as u64;
+    (0..num_hashes as u64).map(move |i| (hash.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::<Xxh64Builder>::with_rate(10_000, 0.01);
+        for i in 0..10_000u64 {
+            filter.insert_u64(i);
+        }
+        assert!((0..10_000u64).all(|i| filter.contains_u64(i)));
+
+        let false_positives = (10_000..110_000u64)
+            .filter(|&i| filter.contains_u64(i))
+            .count();
+        assert!(false_positives < 2_000);
+        assert!((filter.estimate_cardinality() - 10_000.0).abs() < 500.0);
+    }
+}
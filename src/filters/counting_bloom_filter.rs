@@ -0,0 +1,201 @@
+use crate::counters::MergeError;
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use crate::filters::BloomFilter;
+use crate::filters::bloom_filter::double_hash_indices;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// A counting Bloom filter for approximate per-item counts.
+///
+/// Every bit of a [`BloomFilter`] is replaced by a small counter. The count of
+/// an item is the minimum of its counters, which never undercounts but may
+/// overcount like the false positives of a Bloom filter. Counters saturate
+/// at 255.
+///
+/// A typical use is dropping k-mers that occur only once, which are mostly
+/// sequencing errors, before counting distinct k-mers:
+///
+/// ```
+/// use hll_rust::filters::CountingBloomFilter;
+/// use hll_rust::{Counter, HLLCounter};
+///
+/// let mut filter = CountingBloomFilter::<std::hash::RandomState>::with_rate(1_000, 0.01);
+/// let mut counter = HLLCounter::<std::hash::RandomState>::new(12);
+/// for kmer in [1u64, 2, 2, 3, 3, 3] {
+///     // Count each k-mer once, when it is seen for the second time
+///     if filter.add_and_count_u64(kmer) == 2 {
+///         counter.add_u64(kmer);
+///     }
+/// }
+/// assert_eq!(counter.estimate().round(), 2.0);
+/// ```
+pub struct CountingBloomFilter<S = RandomState> {
+    num_counters: usize,
+    num_hashes: usize,
+    counters: Vec<u8>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> CountingBloomFilter<S> {
+    /// Creates a filter with `num_counters` counters and `num_hashes` hash functions.
+    pub fn new(num_counters: usize, num_hashes: usize) -> Self {
+        assert!(
+            num_counters > 0,
+            "counting Bloom filter needs at least one counter"
+        );
+        assert!(
+            num_hashes > 0,
+            "counting Bloom filter needs at least one hash function"
+        );
+        let hasher = S::default();
+        CountingBloomFilter {
+            num_counters,
+            num_hashes,
+            counters: vec![0; num_counters],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Creates a filter sized for `expected_items` distinct items at the given
+    /// false positive rate of `contains`.
+    pub fn with_rate(expected_items: usize, false_positive_rate: f64) -> Self {
+        let (num_counters, num_hashes) =
+            BloomFilter::<S>::optimal_size(expected_items, false_positive_rate);
+        Self::new(num_counters, num_hashes)
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        self.add_and_count(item);
+    }
+
+    pub fn insert_u64(&mut self, item: u64) {
+        self.add_and_count_u64(item);
+    }
+
+    /// Adds one occurrence of `item` and returns its count including this one.
+    pub fn add_and_count(&mut self, item: &[u8]) -> u32 {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash)
+    }
+
+    pub fn add_and_count_u64(&mut self, item: u64) -> u32 {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash)
+    }
+
+    /// Estimates how often `item` was added.
+    pub fn count(&self, item: &[u8]) -> u32 {
+        let hash = self.hasher.hash_one(item);
+        self.count_hash(hash)
+    }
+
+    pub fn count_u64(&self, item: u64) -> u32 {
+        let hash = self.hasher.hash_one(item);
+        self.count_hash(hash)
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.count(item) > 0
+    }
+
+    pub fn contains_u64(&self, item: u64) -> bool {
+        self.count_u64(item) > 0
+    }
+
+    /// Removes one occurrence of `item`. Does nothing if `item` is not in the
+    /// filter. Saturated counters are never decremented, since their true
+    /// value is unknown.
+    pub fn remove(&mut self, item: &[u8]) {
+        let hash = self.hasher.hash_one(item);
+        if self.count_hash(hash) == 0 {
+            return;
+        }
+        for index in self.indices(hash) {
+            if self.counters[index] != u8::MAX {
+                self.counters[index] -= 1;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn indices(&self, hash: u64) -> impl Iterator<Item = usize> + use<S> {
+        double_hash_indices(&self.hasher, hash, self.num_hashes, self.num_counters)
+    }
+
+    fn add_hash(&mut self, hash: u64) -> u32 {
+        let mut count = u8::MAX;
+        for index in self.indices(hash) {
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(1);
+            count = count.min(*counter);
+        }
+        count as u32
+    }
+
+    fn count_hash(&self, hash: u64) -> u32 {
+        self.indices(hash)
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap_or(0) as u32
+    }
+
+    /// Merges `other` into `self`, adding up the counts of both filters.
+    ///
+    /// Fails if the filters differ in size or hash function.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((self.num_counters, 0), (other.num_counters, 0))?;
+        check_mergeable(
+            (self.num_hashes, self.fingerprint),
+            (other.num_hashes, other.fingerprint),
+        )?;
+        for (counter, &other_counter) in self.counters.iter_mut().zip(other.counters.iter()) {
+            *counter = counter.saturating_add(other_counter);
+        }
+        Ok(())
+    }
+
+    pub fn num_counters(&self) -> usize {
+        self.num_counters
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_counting_bloom_filter() {
+        let mut filter = CountingBloomFilter::<Xxh64Builder>::with_rate(10_000, 0.01);
+        for i in 0..10_000u64 {
+            // Item i is added (i % 3) + 1 times
+            for n in 1..=(i % 3) as u32 + 1 {
+                assert!(filter.add_and_count_u64(i) >= n);
+            }
+        }
+
+        let exact = (0..10_000u64)
+            .filter(|&i| filter.count_u64(i) == (i % 3) as u32 + 1)
+            .count();
+        assert!(exact > 9_500);
+        assert!((0..10_000u64).all(|i| filter.count_u64(i) > (i % 3) as u32));
+
+        let false_positives = (10_000..110_000u64)
+            .filter(|&i| filter.contains_u64(i))
+            .count();
+        assert!(false_positives < 2_000);
+
+        let item = 42u64.to_le_bytes();
+        filter.insert(&item);
+        filter.insert(&item);
+        filter.remove(&item);
+        assert!(filter.count(&item) >= 1);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod bloom_filter;
+pub mod counting_bloom_filter;
+pub mod quotient_filter;
+
+pub use bloom_filter::BloomFilter;
+pub use counting_bloom_filter::CountingBloomFilter;
+pub use quotient_filter::QuotientFilter;
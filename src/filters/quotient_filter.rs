@@ -0,0 +1,554 @@
+use crate::counters::MergeError;
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+// Metadata bits below the remainder of every slot
+const OCCUPIED: u64 = 1;
+const CONTINUATION: u64 = 2;
+const SHIFTED: u64 = 4;
+const METADATA_BITS: u32 = 3;
+
+// Largest fraction of used slots before `insert` grows the filter
+const MAX_LOAD: f64 = 0.9;
+
+/// A quotient filter (Bender et al., 2012) for approximate set membership
+/// with deletion.
+///
+/// Every item is reduced to a fingerprint of `quotient_bits +
+/// remainder_bits` hash bits. The quotient selects one of 2^quotient_bits
+/// slots, in which the remainder is stored together with three metadata
+/// bits; colliding remainders are kept sorted in runs that are shifted to
+/// the following slots. Unlike a [`BloomFilter`](crate::filters::BloomFilter),
+/// the fingerprints can be removed again and moved to a larger filter, at
+/// about the same memory for the same false positive rate.
+///
+/// Two items with the same fingerprint are indistinguishable: the second
+/// insertion is ignored, and removing either item removes both, so `remove`
+/// can cause false negatives with a probability close to the false positive
+/// rate.
+///
+/// ```
+/// use hll_rust::filters::QuotientFilter;
+///
+/// let mut filter = QuotientFilter::<std::hash::RandomState>::with_rate(1_000, 0.01);
+/// filter.insert(b"ACGT");
+/// assert!(filter.contains(b"ACGT"));
+/// filter.remove(b"ACGT");
+/// assert!(!filter.contains(b"ACGT"));
+/// ```
+pub struct QuotientFilter<S = RandomState> {
+    quotient_bits: u32,
+    remainder_bits: u32,
+    len: usize,
+    // Slots of remainder_bits + 3 bits, packed into words
+    table: Vec<u64>,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> QuotientFilter<S> {
+    /// Creates a filter with 2^`quotient_bits` slots of `remainder_bits`
+    /// bits each. The false positive rate is about load * 2^-remainder_bits.
+    pub fn new(quotient_bits: u32, remainder_bits: u32) -> Self {
+        Self::with_hasher(quotient_bits, remainder_bits, S::default())
+    }
+
+    fn with_hasher(quotient_bits: u32, remainder_bits: u32, hasher: S) -> Self {
+        assert!(
+            (1..=40).contains(&quotient_bits),
+            "quotient filter needs between 1 and 40 quotient bits"
+        );
+        assert!(
+            (1..=61).contains(&remainder_bits),
+            "quotient filter needs between 1 and 61 remainder bits"
+        );
+        assert!(
+            quotient_bits + remainder_bits <= 64,
+            "quotient filter fingerprints are at most 64 bits"
+        );
+        let slot_bits = (remainder_bits + METADATA_BITS) as usize;
+        QuotientFilter {
+            quotient_bits,
+            remainder_bits,
+            len: 0,
+            table: vec![0; (slot_bits << quotient_bits).div_ceil(64)],
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Creates a filter sized for `expected_items` distinct items at the given
+    /// false positive rate, without growing.
+    pub fn with_rate(expected_items: usize, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let slots = (expected_items.max(1) as f64 / MAX_LOAD).ceil() as usize;
+        let quotient_bits = slots.next_power_of_two().trailing_zeros().max(1);
+        let remainder_bits = (-false_positive_rate.log2()).ceil().max(1.0) as u32;
+        Self::new(quotient_bits, remainder_bits.min(64 - quotient_bits))
+    }
+
+    /// Inserts `item`. Returns false if its fingerprint was already present.
+    ///
+    /// Once more than 90% of the slots are used, the filter grows, see
+    /// [`QuotientFilter::grow`]. Panics if it cannot grow any more.
+    pub fn insert(&mut self, item: &[u8]) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.insert_fingerprint(hash & self.fingerprint_mask())
+    }
+
+    pub fn insert_u64(&mut self, item: u64) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.insert_fingerprint(hash & self.fingerprint_mask())
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.contains_fingerprint(hash & self.fingerprint_mask())
+    }
+
+    pub fn contains_u64(&self, item: u64) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.contains_fingerprint(hash & self.fingerprint_mask())
+    }
+
+    /// Removes `item`. Returns false if its fingerprint was not present.
+    pub fn remove(&mut self, item: &[u8]) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.remove_fingerprint(hash & self.fingerprint_mask())
+    }
+
+    pub fn remove_u64(&mut self, item: u64) -> bool {
+        let hash = self.hasher.hash_one(item);
+        self.remove_fingerprint(hash & self.fingerprint_mask())
+    }
+
+    /// Doubles the number of slots, moving one bit of every fingerprint from
+    /// the remainder to the quotient. This roughly keeps the false positive
+    /// rate of the current load, but doubles it compared to a filter of the
+    /// same load before growing.
+    ///
+    /// Panics if only one remainder bit is left.
+    pub fn grow(&mut self) {
+        assert!(
+            self.remainder_bits > 1 && self.quotient_bits < 40,
+            "quotient filter cannot grow beyond {} slots",
+            self.capacity()
+        );
+        let fingerprints: Vec<u64> = self.fingerprints().collect();
+        let hasher = std::mem::take(&mut self.hasher);
+        let mut grown = Self::with_hasher(self.quotient_bits + 1, self.remainder_bits - 1, hasher);
+        for fingerprint in fingerprints {
+            grown.insert_fingerprint(fingerprint);
+        }
+        *self = grown;
+    }
+
+    /// Merges `other` into `self`, so that `self` contains the items of both
+    /// filters, growing `self` if needed.
+    ///
+    /// Fails if the filters differ in fingerprint size or hash function.
+    /// Filters created with the same size can always be merged, even after
+    /// one of them grew.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable(
+            (self.fingerprint_bits() as usize, self.fingerprint),
+            (other.fingerprint_bits() as usize, other.fingerprint),
+        )?;
+        for fingerprint in other.fingerprints() {
+            self.insert_fingerprint(fingerprint);
+        }
+        Ok(())
+    }
+
+    /// Number of distinct fingerprints in the filter.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of slots.
+    pub fn capacity(&self) -> usize {
+        1 << self.quotient_bits
+    }
+
+    pub fn quotient_bits(&self) -> u32 {
+        self.quotient_bits
+    }
+
+    pub fn remainder_bits(&self) -> u32 {
+        self.remainder_bits
+    }
+
+    /// Fraction of the slots in use.
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Estimates the current false positive rate: the chance that a new
+    /// item's quotient is in use and its remainder matches one stored there.
+    pub fn false_positive_rate(&self) -> f64 {
+        1.0 - (-self.load_factor() / (1u64 << self.remainder_bits) as f64).exp()
+    }
+
+    fn fingerprint_bits(&self) -> u32 {
+        self.quotient_bits + self.remainder_bits
+    }
+
+    fn fingerprint_mask(&self) -> u64 {
+        low_mask(self.fingerprint_bits())
+    }
+
+    fn insert_fingerprint(&mut self, fingerprint: u64) -> bool {
+        if self.contains_fingerprint(fingerprint) {
+            return false;
+        }
+        if (self.len + 1) as f64 > MAX_LOAD * self.capacity() as f64 {
+            self.grow();
+        }
+        let quotient = (fingerprint >> self.remainder_bits) as usize;
+        let remainder = fingerprint & low_mask(self.remainder_bits);
+        let home = self.slot(quotient);
+        let mut entry = remainder << METADATA_BITS;
+        self.len += 1;
+
+        // The home slot is free: no run has to be shifted
+        if home & (OCCUPIED | CONTINUATION | SHIFTED) == 0 {
+            self.set_slot(quotient, entry | OCCUPIED);
+            return true;
+        }
+        let had_run = home & OCCUPIED != 0;
+        self.set_slot(quotient, home | OCCUPIED);
+
+        let start = self.run_start(quotient);
+        let mut index = start;
+        if had_run {
+            // Insert into the sorted run, known not to contain the remainder
+            loop {
+                if self.slot(index) >> METADATA_BITS > remainder {
+                    break;
+                }
+                index = self.next(index);
+                if self.slot(index) & CONTINUATION == 0 {
+                    break;
+                }
+            }
+            if index == start {
+                // The old head of the run continues after the new one
+                let head = self.slot(start);
+                self.set_slot(start, head | CONTINUATION);
+            } else {
+                entry |= CONTINUATION;
+            }
+        }
+        if index != quotient {
+            entry |= SHIFTED;
+        }
+        self.shift_in(index, entry);
+        true
+    }
+
+    fn contains_fingerprint(&self, fingerprint: u64) -> bool {
+        let quotient = (fingerprint >> self.remainder_bits) as usize;
+        let remainder = fingerprint & low_mask(self.remainder_bits);
+        self.find(quotient, remainder).is_some()
+    }
+
+    fn remove_fingerprint(&mut self, fingerprint: u64) -> bool {
+        let quotient = (fingerprint >> self.remainder_bits) as usize;
+        let remainder = fingerprint & low_mask(self.remainder_bits);
+        let Some(index) = self.find(quotient, remainder) else {
+            return false;
+        };
+        let removed = self.slot(index);
+        let was_run_start = removed & CONTINUATION == 0;
+        if was_run_start && self.slot(self.next(index)) & CONTINUATION == 0 {
+            // Last remainder of the run
+            let home = self.slot(quotient);
+            self.set_slot(quotient, home & !OCCUPIED);
+        }
+        self.shift_out(index, quotient);
+        if was_run_start {
+            // The next remainder of the run becomes its head, possibly in its
+            // home slot
+            let next = self.slot(index);
+            if next & CONTINUATION != 0 {
+                let mut head = next & !CONTINUATION;
+                if index == quotient {
+                    head &= !SHIFTED;
+                }
+                self.set_slot(index, head);
+            }
+        }
+        self.len -= 1;
+        true
+    }
+
+    // Slot of `remainder` in the run of `quotient`
+    fn find(&self, quotient: usize, remainder: u64) -> Option<usize> {
+        if self.slot(quotient) & OCCUPIED == 0 {
+            return None;
+        }
+        let mut index = self.run_start(quotient);
+        loop {
+            let stored = self.slot(index) >> METADATA_BITS;
+            if stored == remainder {
+                return Some(index);
+            } else if stored > remainder {
+                return None;
+            }
+            index = self.next(index);
+            if self.slot(index) & CONTINUATION == 0 {
+                return None;
+            }
+        }
+    }
+
+    // First slot of the run of the occupied `quotient`: walks back to the
+    // start of the cluster, then forward over one run per occupied slot.
+    fn run_start(&self, quotient: usize) -> usize {
+        let mut canonical = quotient;
+        while self.slot(canonical) & SHIFTED != 0 {
+            canonical = self.prev(canonical);
+        }
+        let mut run = canonical;
+        while canonical != quotient {
+            loop {
+                run = self.next(run);
+                if self.slot(run) & CONTINUATION == 0 {
+                    break;
+                }
+            }
+            loop {
+                canonical = self.next(canonical);
+                if self.slot(canonical) & OCCUPIED != 0 {
+                    break;
+                }
+            }
+        }
+        run
+    }
+
+    // Writes `entry` to `index`, shifting the following remainders of the
+    // cluster one slot to the right. Occupied bits belong to the slots and
+    // stay in place.
+    fn shift_in(&mut self, mut index: usize, mut entry: u64) {
+        loop {
+            let mut previous = self.slot(index);
+            let empty = previous & (OCCUPIED | CONTINUATION | SHIFTED) == 0;
+            if !empty {
+                previous |= SHIFTED;
+                if previous & OCCUPIED != 0 {
+                    entry |= OCCUPIED;
+                    previous &= !OCCUPIED;
+                }
+            }
+            self.set_slot(index, entry);
+            if empty {
+                return;
+            }
+            entry = previous;
+            index = self.next(index);
+        }
+    }
+
+    // Removes the remainder at `index` of the run of `quotient`, shifting the
+    // rest of the cluster one slot to the left. Remainders that reach their
+    // home slot are no longer marked as shifted.
+    fn shift_out(&mut self, mut index: usize, mut quotient: usize) {
+        let first = index;
+        let mut current = self.slot(index);
+        loop {
+            let next_index = self.next(index);
+            let next = self.slot(next_index);
+            let cluster_start = next & (OCCUPIED | CONTINUATION | SHIFTED) == OCCUPIED;
+            if next & (OCCUPIED | CONTINUATION | SHIFTED) == 0
+                || cluster_start
+                || next_index == first
+            {
+                self.set_slot(index, current & OCCUPIED);
+                return;
+            }
+            let mut moved = next;
+            if next & CONTINUATION == 0 {
+                // Head of the next run: find its home slot
+                loop {
+                    quotient = self.next(quotient);
+                    if self.slot(quotient) & OCCUPIED != 0 {
+                        break;
+                    }
+                }
+                if quotient == index {
+                    moved &= !SHIFTED;
+                }
+            }
+            self.set_slot(index, (moved & !OCCUPIED) | (current & OCCUPIED));
+            index = next_index;
+            current = next;
+        }
+    }
+
+    // All fingerprints, by walking every cluster from its start
+    fn fingerprints(&self) -> impl Iterator<Item = u64> + '_ {
+        let slots = self.capacity();
+        let start = (0..slots)
+            .find(|&i| self.slot(i) & (OCCUPIED | CONTINUATION | SHIFTED) == OCCUPIED)
+            .unwrap_or(0);
+        let mut quotient = start;
+        (0..slots).filter_map(move |offset| {
+            let index = (start + offset) & (slots - 1);
+            let slot = self.slot(index);
+            if slot & (OCCUPIED | CONTINUATION | SHIFTED) == 0 {
+                return None;
+            }
+            if slot & SHIFTED == 0 {
+                quotient = index;
+            } else if slot & CONTINUATION == 0 {
+                loop {
+                    quotient = (quotient + 1) & (slots - 1);
+                    if self.slot(quotient) & OCCUPIED != 0 {
+                        break;
+                    }
+                }
+            }
+            Some(((quotient as u64) << self.remainder_bits) | (slot >> METADATA_BITS))
+        })
+    }
+
+    fn next(&self, index: usize) -> usize {
+        (index + 1) & (self.capacity() - 1)
+    }
+
+    fn prev(&self, index: usize) -> usize {
+        index.wrapping_sub(1) & (self.capacity() - 1)
+    }
+
+    #[inline(always)]
+    fn slot(&self, index: usize) -> u64 {
+        let slot_bits = self.remainder_bits + METADATA_BITS;
+        let bit = index * slot_bits as usize;
+        let (word, offset) = (bit / 64, (bit % 64) as u32);
+        let mut value = self.table[word] >> offset;
+        if offset + slot_bits > 64 {
+            value |= self.table[word + 1] << (64 - offset);
+        }
+        value & low_mask(slot_bits)
+    }
+
+    #[inline(always)]
+    fn set_slot(&mut self, index: usize, value: u64) {
+        let slot_bits = self.remainder_bits + METADATA_BITS;
+        let bit = index * slot_bits as usize;
+        let (word, offset) = (bit / 64, (bit % 64) as u32);
+        let mask = low_mask(slot_bits);
+        self.table[word] = (self.table[word] & !(mask << offset)) | (value << offset);
+        if offset + slot_bits > 64 {
+            let low = 64 - offset;
+            self.table[word + 1] = (self.table[word + 1] & !(mask >> low)) | (value >> low);
+        }
+    }
+}
+
+fn low_mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_quotient_filter() {
+        let mut filter = QuotientFilter::<Xxh64Builder>::with_rate(10_000, 0.01);
+        let capacity = filter.capacity();
+        for i in 0..10_000u64 {
+            filter.insert_u64(i);
+        }
+        assert_eq!(filter.capacity(), capacity);
+        // Some items share a fingerprint
+        assert!(filter.len() > 9_900);
+        assert!((0..10_000u64).all(|i| filter.contains_u64(i)));
+        let false_positives = (10_000..110_000u64)
+            .filter(|&i| filter.contains_u64(i))
+            .count();
+        assert!(false_positives < 1_000, "{false_positives}");
+
+        for i in (0..10_000u64).step_by(2) {
+            filter.remove_u64(i);
+        }
+        let kept = (0..10_000u64)
+            .filter(|&i| filter.contains_u64(i) == (i % 2 == 1))
+            .count();
+        assert!(kept > 9_900);
+        let remaining: Vec<u64> = (0..10_000u64).filter(|&i| filter.contains_u64(i)).collect();
+
+        // Growing and merging keep all items
+        let mut other = QuotientFilter::<Xxh64Builder>::with_rate(10_000, 0.01);
+        for i in 20_000..40_000u64 {
+            other.insert_u64(i);
+        }
+        assert!(other.capacity() > capacity);
+        filter.merge(&other).unwrap();
+        assert!(remaining.iter().all(|&i| filter.contains_u64(i)));
+        assert!((20_000..40_000u64).all(|i| filter.contains_u64(i)));
+        assert!(
+            filter
+                .merge(&QuotientFilter::with_rate(10_000, 0.001))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_quotient_filter_matches_set() {
+        // Random operations on small, nearly full tables with long clusters
+        // that wrap around, checked against the exact set of fingerprints.
+        // The last table grows to 7 quotient bits.
+        for (quotient_bits, remainder_bits, max_len) in
+            [(6, 4, 57), (3, 3, 7), (8, 2, 230), (4, 4, 100)]
+        {
+            let mut filter = QuotientFilter::<Xxh64Builder>::new(quotient_bits, remainder_bits);
+            let mut expected = HashSet::new();
+            let mut state = 0x9e37_79b9_7f4a_7c15u64;
+            for step in 0..20_000u32 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let fingerprint = (state >> 8) & low_mask(quotient_bits + remainder_bits);
+                if state.is_multiple_of(3) || expected.len() >= max_len {
+                    assert_eq!(
+                        filter.remove_fingerprint(fingerprint),
+                        expected.remove(&fingerprint),
+                        "step {step}"
+                    );
+                } else {
+                    assert_eq!(
+                        filter.insert_fingerprint(fingerprint),
+                        expected.insert(fingerprint),
+                        "step {step}"
+                    );
+                }
+                assert_eq!(filter.len(), expected.len());
+                if step.is_multiple_of(100) {
+                    let mut stored: Vec<u64> = filter.fingerprints().collect();
+                    stored.sort();
+                    let mut exact: Vec<u64> = expected.iter().copied().collect();
+                    exact.sort();
+                    assert_eq!(stored, exact, "step {step}");
+                }
+            }
+        }
+    }
+}
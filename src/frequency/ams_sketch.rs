@@ -0,0 +1,175 @@
+use crate::counters::MergeError;
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// AMS ("Tug-of-War") sketch for the second frequency moment F2, the sum of
+/// the squared item counts.
+///
+/// Every item adds its count with a random sign to one counter per row
+/// (Fast-AGMS variant), so the squared counters estimate F2 while the
+/// contributions of different items cancel out. Each row's estimate is the
+/// sum of its squared counters, and the median of the rows is returned. With
+/// `width = 8 / epsilon^2` and `depth = ln(1 / delta)`, the estimate is off by
+/// more than `epsilon * F2` with probability at most `delta`.
+///
+/// F2 measures the skew of a distribution: for k-mer abundances, repeats
+/// increase F2 far beyond the number of k-mers.
+pub struct AmsSketch<S = RandomState> {
+    width: usize,
+    depth: usize,
+    // depth rows of width counters each
+    table: Vec<i64>,
+    total_count: u64,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> AmsSketch<S> {
+    /// Creates a sketch with `depth` rows of `width` counters.
+    pub fn new(width: usize, depth: usize) -> Self {
+        assert!(width > 0, "AMS sketch needs at least one column");
+        assert!(depth > 0, "AMS sketch needs at least one row");
+        let hasher = S::default();
+        AmsSketch {
+            width,
+            depth,
+            table: vec![0; width * depth],
+            total_count: 0,
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Creates a sketch whose F2 estimate is off by at most `epsilon * F2`
+    /// with probability `1 - delta`.
+    pub fn with_error(epsilon: f64, delta: f64) -> Self {
+        assert!(epsilon > 0.0, "epsilon must be positive");
+        assert!(delta > 0.0 && delta < 1.0, "delta must be in (0, 1)");
+        let width = (8.0 / (epsilon * epsilon)).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+        Self::new(width, depth)
+    }
+
+    pub fn add(&mut self, item: &[u8]) {
+        self.add_count(item, 1);
+    }
+
+    pub fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash, 1);
+    }
+
+    /// Adds `count` occurrences of `item`.
+    pub fn add_count(&mut self, item: &[u8], count: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash, count);
+    }
+
+    // Double hashing as in the Count-Min sketch: row i uses h1 + i * h2 to
+    // pick the column, and the top bit of its mix as the sign.
+    #[inline(always)]
+    fn cells(&self, hash: u64) -> impl Iterator<Item = (usize, bool)> + use<S> {
+        let h2 = self.hasher.hash_one(hash) | 1;
+        let width = self.width as u64;
+        (0..self.depth as u64).map(move |row| {
+            let h = hash.wrapping_add(row.wrapping_mul(h2));
+            let negative = h.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 63 == 1;
+            ((row * width + h % width) as usize, negative)
+        })
+    }
+
+    fn add_hash(&mut self, hash: u64, count: u64) {
+        let count = count as i64;
+        for (cell, negative) in self.cells(hash) {
+            let delta = if negative { -count } else { count };
+            self.table[cell] = self.table[cell].wrapping_add(delta);
+        }
+        self.total_count = self.total_count.saturating_add(count as u64);
+    }
+
+    /// Estimates F2, the sum of the squared counts of all items.
+    pub fn estimate_f2(&self) -> f64 {
+        let mut rows: Vec<f64> = self
+            .table
+            .chunks(self.width)
+            .map(|row| row.iter().map(|&c| (c as f64) * (c as f64)).sum())
+            .collect();
+        rows.sort_unstable_by(f64::total_cmp);
+        let mid = rows.len() / 2;
+        if rows.len() % 2 == 1 {
+            rows[mid]
+        } else {
+            (rows[mid - 1] + rows[mid]) / 2.0
+        }
+    }
+
+    /// Merges `other` into `self`, so that `self` counts the items of both sketches.
+    ///
+    /// Fails if the sketches differ in size or hash function.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        self.check_mergeable(other)?;
+        for (cell_self, cell_other) in self.table.iter_mut().zip(other.table.iter()) {
+            *cell_self = cell_self.wrapping_add(*cell_other);
+        }
+        self.total_count = self.total_count.saturating_add(other.total_count);
+        Ok(())
+    }
+
+    // Fails if the sketches differ in size or hash function
+    pub(crate) fn check_mergeable(&self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((self.width, 0), (other.width, 0))?;
+        check_mergeable(
+            (self.depth, self.fingerprint),
+            (other.depth, other.fingerprint),
+        )
+    }
+
+    /// Total number of added occurrences, i.e. F1.
+    pub fn total_count(&self) -> u64 {
+        self.total_count
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_ams_sketch() {
+        let mut a = AmsSketch::<Xxh64Builder>::with_error(0.05, 0.01);
+        let mut b = AmsSketch::<Xxh64Builder>::with_error(0.05, 0.01);
+        // Item i occurs 1 + i % 10 times, plus one heavy hitter
+        let mut f2 = 0.0;
+        for i in 0..5_000u64 {
+            let count = 1 + i % 10;
+            for _ in 0..count {
+                a.add_u64(i);
+            }
+            f2 += (count * count) as f64;
+        }
+        a.add_count(b"ACGT", 1_000);
+        b.add_count(b"ACGT", 1_000);
+        f2 += 1_000.0 * 1_000.0;
+
+        let estimate = a.estimate_f2();
+        assert!((estimate - f2).abs() < 0.05 * f2, "{estimate} vs {f2}");
+        assert_eq!(a.total_count(), 28_500);
+
+        // The heavy hitter now occurs 2000 times
+        a.merge(&b).unwrap();
+        let f2 = f2 + 2_000.0 * 2_000.0 - 1_000.0 * 1_000.0;
+        assert!((a.estimate_f2() - f2).abs() < 0.05 * f2);
+        assert!(a.merge(&AmsSketch::new(10, 3)).is_err());
+    }
+}
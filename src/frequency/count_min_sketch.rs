@@ -0,0 +1,153 @@
+use crate::counters::MergeError;
+use crate::counters::counter_base::{check_mergeable, hasher_fingerprint};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// Count-Min sketch for approximate item frequencies.
+///
+/// Estimates never undercount. With `width = e / epsilon` and
+/// `depth = ln(1 / delta)`, an estimate exceeds the true count by more than
+/// `epsilon * total_count` with probability at most `delta`.
+pub struct CountMinSketch<S = RandomState> {
+    width: usize,
+    depth: usize,
+    // depth rows of width counters each
+    table: Vec<u64>,
+    total_count: u64,
+    hasher: S,
+    // Fingerprint of the hasher, compared when merging
+    fingerprint: u64,
+}
+
+impl<S: BuildHasher + Default> CountMinSketch<S> {
+    /// Creates a sketch with `depth` rows of `width` counters.
+    pub fn new(width: usize, depth: usize) -> Self {
+        assert!(width > 0, "Count-Min sketch needs at least one column");
+        assert!(depth > 0, "Count-Min sketch needs at least one row");
+        let hasher = S::default();
+        CountMinSketch {
+            width,
+            depth,
+            table: vec![0; width * depth],
+            total_count: 0,
+            fingerprint: hasher_fingerprint(&hasher),
+            hasher,
+        }
+    }
+
+    /// Creates a sketch whose estimates overcount by at most
+    /// `epsilon * total_count` with probability `1 - delta`.
+    pub fn with_error(epsilon: f64, delta: f64) -> Self {
+        assert!(epsilon > 0.0, "epsilon must be positive");
+        assert!(delta > 0.0 && delta < 1.0, "delta must be in (0, 1)");
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+        Self::new(width, depth)
+    }
+
+    pub fn add(&mut self, item: &[u8]) {
+        self.add_count(item, 1);
+    }
+
+    pub fn add_u64(&mut self, item: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash, 1);
+    }
+
+    /// Adds `count` occurrences of `item`.
+    pub fn add_count(&mut self, item: &[u8], count: u64) {
+        let hash = self.hasher.hash_one(item);
+        self.add_hash(hash, count);
+    }
+
+    /// Estimates how often `item` was added.
+    pub fn estimate_count(&self, item: &[u8]) -> u64 {
+        let hash = self.hasher.hash_one(item);
+        self.estimate_hash(hash)
+    }
+
+    pub fn estimate_count_u64(&self, item: u64) -> u64 {
+        let hash = self.hasher.hash_one(item);
+        self.estimate_hash(hash)
+    }
+
+    // Double hashing: the column of row i is h1 + i * h2.
+    #[inline(always)]
+    fn cells(&self, hash: u64) -> impl Iterator<Item = usize> + use<S> {
+        let h2 = self.hasher.hash_one(hash) | 1;
+        let width = self.width as u64;
+        (0..self.depth as u64).map(move |row| {
+            let column = hash.wrapping_add(row.wrapping_mul(h2)) % width;
+            (row * width + column) as usize
+        })
+    }
+
+    fn add_hash(&mut self, hash: u64, count: u64) {
+        for cell in self.cells(hash) {
+            self.table[cell] = self.table[cell].saturating_add(count);
+        }
+        self.total_count = self.total_count.saturating_add(count);
+    }
+
+    fn estimate_hash(&self, hash: u64) -> u64 {
+        self.cells(hash)
+            .map(|cell| self.table[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Merges `other` into `self`, so that `self` counts the items of both sketches.
+    ///
+    /// Fails if the sketches differ in size or hash function.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        check_mergeable((self.width, 0), (other.width, 0))?;
+        check_mergeable(
+            (self.depth, self.fingerprint),
+            (other.depth, other.fingerprint),
+        )?;
+        for (cell_self, cell_other) in self.table.iter_mut().zip(other.table.iter()) {
+            *cell_self = cell_self.saturating_add(*cell_other);
+        }
+        self.total_count = self.total_count.saturating_add(other.total_count);
+        Ok(())
+    }
+
+    /// Total number of added occurrences.
+    pub fn total_count(&self) -> u64 {
+        self.total_count
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_count_min_sketch() {
+        let mut a = CountMinSketch::<Xxh64Builder>::with_error(0.001, 0.01);
+        let mut b = CountMinSketch::<Xxh64Builder>::with_error(0.001, 0.01);
+        for i in 0..10_000u64 {
+            a.add_u64(i);
+        }
+        for _ in 0..500 {
+            a.add(b"ACGT");
+            b.add(b"ACGT");
+        }
+
+        let estimate = a.estimate_count(b"ACGT");
+        assert!((500..=500 + 11).contains(&estimate));
+
+        a.merge(&b).unwrap();
+        assert!(a.estimate_count(b"ACGT") >= 1_000);
+        assert_eq!(a.total_count(), 11_000);
+    }
+}
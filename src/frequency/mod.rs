@@ -0,0 +1,7 @@
+pub mod ams_sketch;
+pub mod count_min_sketch;
+pub mod stream_stats;
+
+pub use ams_sketch::AmsSketch;
+pub use count_min_sketch::CountMinSketch;
+pub use stream_stats::StreamStats;
@@ -0,0 +1,107 @@
+use crate::counters::{Counter, HLLCounter, MergeError, MergeableCounter};
+use crate::frequency::AmsSketch;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// The frequency moments F0, F1 and F2 of a stream, estimated in one pass.
+///
+/// F0, the number of distinct items, is estimated by a [`HLLCounter`], F1,
+/// the number of items, is counted exactly, and F2, the sum of the squared
+/// item counts, is estimated by an [`AmsSketch`]. Together they describe
+/// the skew of e.g. a k-mer abundance distribution, see
+/// [`StreamStats::mean_count`] and [`StreamStats::repeat_rate`].
+///
+/// ```
+/// use hll_rust::frequency::StreamStats;
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let mut stats = StreamStats::<Xxh64Builder>::new(12, 0.05, 0.01);
+/// for i in 0..10_000u64 {
+///     stats.add_u64(i % 1_000);
+/// }
+/// assert_eq!(stats.f1(), 10_000);
+/// assert!((stats.f0() - 1_000.0).abs() < 50.0);
+/// // Every item occurs 10 times: F2 = 1000 * 10^2
+/// assert!((stats.f2() - 100_000.0).abs() < 5_000.0);
+/// ```
+pub struct StreamStats<S = RandomState> {
+    distinct: HLLCounter<S>,
+    ams: AmsSketch<S>,
+}
+
+impl<S: BuildHasher + Default> StreamStats<S> {
+    /// Estimates F0 with an HLL counter of the given precision and F2 with
+    /// an [`AmsSketch::with_error`] of the given error bounds.
+    pub fn new(precision: usize, epsilon: f64, delta: f64) -> Self {
+        StreamStats {
+            distinct: HLLCounter::new(precision),
+            ams: AmsSketch::with_error(epsilon, delta),
+        }
+    }
+
+    pub fn add(&mut self, item: &[u8]) {
+        self.distinct.add(item);
+        self.ams.add(item);
+    }
+
+    pub fn add_u64(&mut self, item: u64) {
+        self.distinct.add_u64(item);
+        self.ams.add_u64(item);
+    }
+
+    /// Estimated number of distinct items.
+    pub fn f0(&self) -> f64 {
+        self.distinct.estimate()
+    }
+
+    /// Exact number of items.
+    pub fn f1(&self) -> u64 {
+        self.ams.total_count()
+    }
+
+    /// Estimated sum of the squared item counts.
+    pub fn f2(&self) -> f64 {
+        self.ams.estimate_f2()
+    }
+
+    /// Average number of occurrences per distinct item, F1 / F0.
+    pub fn mean_count(&self) -> f64 {
+        let f0 = self.f0();
+        if f0 == 0.0 {
+            0.0
+        } else {
+            self.f1() as f64 / f0
+        }
+    }
+
+    /// Probability that two occurrences drawn at random are the same item,
+    /// F2 / F1^2. It is 1 / F0 for uniform counts and grows with the skew.
+    pub fn repeat_rate(&self) -> f64 {
+        let f1 = self.f1() as f64;
+        if f1 == 0.0 {
+            0.0
+        } else {
+            self.f2() / (f1 * f1)
+        }
+    }
+
+    /// The counter estimating F0.
+    pub fn distinct(&self) -> &HLLCounter<S> {
+        &self.distinct
+    }
+
+    /// The sketch estimating F2.
+    pub fn ams(&self) -> &AmsSketch<S> {
+        &self.ams
+    }
+
+    /// Merges `other` into `self`, so that `self` describes both streams.
+    ///
+    /// Fails if the sketches differ in size or hash function, leaving `self`
+    /// unchanged.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        self.ams.check_mergeable(&other.ams)?;
+        self.distinct.merge(&other.distinct)?;
+        self.ams.merge(&other.ams)
+    }
+}
@@ -0,0 +1,448 @@
+//! Counting the values of Apache Arrow arrays, e.g. the columns of Parquet
+//! files read with the `parquet` crate.
+//!
+//! The values are hashed straight from the Arrow buffers: strings and binary
+//! values as their bytes, like [`Counter::add`], and integers, floats, dates
+//! and timestamps as 64-bit values, like [`Counter::add_u64`]. Nulls are not
+//! counted, as in SQL's `COUNT(DISTINCT ...)`.
+//!
+//! ```
+//! use arrow_array::StringArray;
+//! use hll_rust::interop::arrow::ArrowCounter;
+//! use hll_rust::{Counter, HLLCounter};
+//! use xxhash_rust::xxh64::Xxh64Builder;
+//!
+//! let column = StringArray::from(vec![Some("a"), None, Some("b"), Some("a")]);
+//! let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+//! assert_eq!(counter.add_arrow_array(&column).unwrap(), 3);
+//! assert!((counter.estimate() - 2.0).abs() < 0.5);
+//! ```
+//!
+//! [`HLLAccumulator`] holds the state of an approximate distinct count
+//! aggregate over Arrow batches. With the `datafusion` feature it is the
+//! accumulator of the SQL function registered by
+//! `interop::datafusion::register`.
+
+use crate::counters::{Counter, HLLCounter, MergeableCounter};
+use arrow_array::cast::AsArray;
+use arrow_array::types::*;
+use arrow_array::{Array, ArrayRef, ArrowPrimitiveType, BinaryArray};
+use arrow_schema::{ArrowError, DataType};
+use std::fmt;
+use std::sync::Arc;
+use xxhash_rust::xxh64::Xxh64Builder;
+
+/// Name of the user-defined aggregate function whose state is an
+/// [`HLLAccumulator`].
+pub const HLL_UDAF_NAME: &str = "approx_count_distinct_hll";
+
+/// Adds the values of Arrow arrays to a counter.
+pub trait ArrowCounter {
+    /// Adds every non-null value of `array` and returns the number of values
+    /// added.
+    ///
+    /// Supports boolean, integer, floating point, date, time, timestamp and
+    /// duration arrays, (large) strings and binaries including their view
+    /// and fixed size variants, and dictionary arrays of these, whose values
+    /// are each hashed once. Fails for other types, e.g. nested arrays.
+    fn add_arrow_array(&mut self, array: &dyn Array) -> Result<usize, ArrowError>;
+}
+
+impl<C: Counter> ArrowCounter for C {
+    fn add_arrow_array(&mut self, array: &dyn Array) -> Result<usize, ArrowError> {
+        if let Some(dictionary) = array.as_any_dictionary_opt() {
+            // Only the values that some key refers to are counted
+            let keys = dictionary.normalized_keys();
+            let mut used = vec![false; dictionary.values().len()];
+            let mut added = 0;
+            for (row, &key) in keys.iter().enumerate() {
+                if dictionary.keys().is_valid(row) && dictionary.values().is_valid(key) {
+                    used[key] = true;
+                    added += 1;
+                }
+            }
+            add_values(self, dictionary.values().as_ref(), Some(&used))?;
+            return Ok(added);
+        }
+        add_values(self, array, None)
+    }
+}
+
+// Adds the valid values of `array`, only those of rows marked in `selected`
+// if given. Returns the number of values added.
+fn add_values<C: Counter>(
+    counter: &mut C,
+    array: &dyn Array,
+    selected: Option<&[bool]>,
+) -> Result<usize, ArrowError> {
+    let rows = (0..array.len())
+        .filter(|&row| array.is_valid(row) && selected.is_none_or(|selected| selected[row]));
+    match array.data_type() {
+        DataType::Utf8
+        | DataType::LargeUtf8
+        | DataType::Utf8View
+        | DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => add_bytes(counter, array, rows),
+        _ => add_primitives(counter, array, rows),
+    }
+}
+
+// Adds boolean, numeric and temporal values as 64-bit values.
+fn add_primitives<C: Counter>(
+    counter: &mut C,
+    array: &dyn Array,
+    rows: impl Iterator<Item = usize>,
+) -> Result<usize, ArrowError> {
+    let mut added = 0;
+    let mut add_u64 = |value: u64| {
+        counter.add_u64(value);
+        added += 1;
+    };
+    match array.data_type() {
+        DataType::Boolean => {
+            let array = array.as_boolean();
+            rows.for_each(|row| add_u64(array.value(row) as u64));
+        }
+        DataType::Int8 => add_integers::<Int8Type>(array, rows, add_u64),
+        DataType::Int16 => add_integers::<Int16Type>(array, rows, add_u64),
+        DataType::Int32 => add_integers::<Int32Type>(array, rows, add_u64),
+        DataType::Int64 => add_integers::<Int64Type>(array, rows, add_u64),
+        DataType::UInt8 => add_integers::<UInt8Type>(array, rows, add_u64),
+        DataType::UInt16 => add_integers::<UInt16Type>(array, rows, add_u64),
+        DataType::UInt32 => add_integers::<UInt32Type>(array, rows, add_u64),
+        DataType::UInt64 => add_integers::<UInt64Type>(array, rows, add_u64),
+        DataType::Date32 => add_integers::<Date32Type>(array, rows, add_u64),
+        DataType::Date64 => add_integers::<Date64Type>(array, rows, add_u64),
+        DataType::Time32(_) | DataType::Time64(_) | DataType::Timestamp(..) => {
+            // The unit only scales the values, so the bits are counted as is
+            match array.data_type().primitive_width() {
+                Some(4) => add_integers::<Int32Type>(&reinterpret(array)?, rows, add_u64),
+                _ => add_integers::<Int64Type>(&reinterpret(array)?, rows, add_u64),
+            }
+        }
+        DataType::Duration(_) => add_integers::<Int64Type>(&reinterpret(array)?, rows, add_u64),
+        DataType::Float32 => {
+            let array = array.as_primitive::<Float32Type>();
+            rows.for_each(|row| add_u64(float_bits(array.value(row) as f64)));
+        }
+        DataType::Float64 => {
+            let array = array.as_primitive::<Float64Type>();
+            rows.for_each(|row| add_u64(float_bits(array.value(row))));
+        }
+        data_type => return Err(unsupported(data_type)),
+    }
+    Ok(added)
+}
+
+// Adds integers as their value sign-extended to 64 bits, so the same number
+// is counted once in columns of different widths.
+fn add_integers<T>(
+    array: &dyn Array,
+    rows: impl Iterator<Item = usize>,
+    mut add_u64: impl FnMut(u64),
+) where
+    T: ArrowPrimitiveType,
+    T::Native: Into<i128>,
+{
+    let array = array.as_primitive::<T>();
+    rows.for_each(|row| add_u64(array.value(row).into() as i64 as u64));
+}
+
+// Views a temporal array as signed integers of the same width.
+fn reinterpret(array: &dyn Array) -> Result<arrow_array::ArrayRef, ArrowError> {
+    let data = array.to_data();
+    let data_type = match array.data_type().primitive_width() {
+        Some(4) => DataType::Int32,
+        _ => DataType::Int64,
+    };
+    let data = data.into_builder().data_type(data_type).build()?;
+    Ok(arrow_array::make_array(data))
+}
+
+// Bits of a float, with -0.0 counted as 0.0 and all NaNs as one value.
+fn float_bits(value: f64) -> u64 {
+    if value.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        (value + 0.0).to_bits()
+    }
+}
+
+// Adds string and binary values as their bytes.
+fn add_bytes<C: Counter>(
+    counter: &mut C,
+    array: &dyn Array,
+    rows: impl Iterator<Item = usize>,
+) -> Result<usize, ArrowError> {
+    let mut added = 0;
+    let mut add = |value: &[u8]| {
+        counter.add(value);
+        added += 1;
+    };
+    match array.data_type() {
+        DataType::Utf8 => {
+            let array = array.as_string::<i32>();
+            rows.for_each(|row| add(array.value(row).as_bytes()));
+        }
+        DataType::LargeUtf8 => {
+            let array = array.as_string::<i64>();
+            rows.for_each(|row| add(array.value(row).as_bytes()));
+        }
+        DataType::Utf8View => {
+            let array = array.as_string_view();
+            rows.for_each(|row| add(array.value(row).as_bytes()));
+        }
+        DataType::Binary => {
+            let array = array.as_binary::<i32>();
+            rows.for_each(|row| add(array.value(row)));
+        }
+        DataType::LargeBinary => {
+            let array = array.as_binary::<i64>();
+            rows.for_each(|row| add(array.value(row)));
+        }
+        DataType::BinaryView => {
+            let array = array.as_binary_view();
+            rows.for_each(|row| add(array.value(row)));
+        }
+        DataType::FixedSizeBinary(_) => {
+            let array = array.as_fixed_size_binary();
+            rows.for_each(|row| add(array.value(row)));
+        }
+        data_type => return Err(unsupported(data_type)),
+    }
+    Ok(added)
+}
+
+fn unsupported(data_type: &DataType) -> ArrowError {
+    ArrowError::NotYetImplemented(format!("Counting arrays of type {data_type}"))
+}
+
+/// The state of an approximate `COUNT(DISTINCT ...)` over Arrow batches,
+/// with the methods of a DataFusion `Accumulator`.
+///
+/// The aggregate function [`HLL_UDAF_NAME`] uses these methods: partitions count their batches with [`update_batch`](Self::update_batch),
+/// send their [`state`](Self::state), a binary array of one sketch, and the
+/// final aggregation combines them with [`merge_batch`](Self::merge_batch).
+/// Sketches are hashed with xxHash64, so the states of different processes
+/// can be merged.
+///
+/// ```
+/// use arrow_array::{ArrayRef, Int64Array};
+/// use hll_rust::interop::arrow::HLLAccumulator;
+/// use std::sync::Arc;
+///
+/// let mut partitions = [HLLAccumulator::new(12), HLLAccumulator::new(12)];
+/// let batch: ArrayRef = Arc::new(Int64Array::from_iter_values(0..1000));
+/// partitions[0].update_batch(&[batch.clone()]).unwrap();
+/// partitions[1].update_batch(&[batch]).unwrap();
+///
+/// let mut total = HLLAccumulator::new(12);
+/// for partition in &partitions {
+///     total.merge_batch(&[partition.state()]).unwrap();
+/// }
+/// assert!(total.evaluate().abs_diff(1000) < 50);
+/// ```
+pub struct HLLAccumulator {
+    counter: HLLCounter<Xxh64Builder>,
+}
+
+impl fmt::Debug for HLLAccumulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HLLAccumulator")
+            .field("precision", &self.counter.precision())
+            .field("estimate", &self.counter.estimate())
+            .finish()
+    }
+}
+
+impl HLLAccumulator {
+    /// Creates an empty state with sketches of the given precision.
+    pub fn new(precision: usize) -> Self {
+        HLLAccumulator {
+            counter: HLLCounter::new(precision),
+        }
+    }
+
+    /// Counts the non-null values of the single argument array, see
+    /// [`ArrowCounter::add_arrow_array`].
+    pub fn update_batch(&mut self, values: &[ArrayRef]) -> Result<(), ArrowError> {
+        let [array] = values else {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "{HLL_UDAF_NAME} takes one argument, got {}",
+                values.len()
+            )));
+        };
+        self.counter.add_arrow_array(array.as_ref())?;
+        Ok(())
+    }
+
+    /// The partial state: a binary array holding the sketch, serialized by
+    /// [`HLLCounter::write_to`].
+    pub fn state(&self) -> ArrayRef {
+        let mut sketch = Vec::new();
+        self.counter
+            .write_to(&mut sketch)
+            .expect("writing to memory");
+        Arc::new(BinaryArray::from_vec(vec![&sketch]))
+    }
+
+    /// Merges the sketches of partial states, each row one sketch. Null rows
+    /// are skipped.
+    ///
+    /// Fails for invalid sketches or sketches of another precision.
+    pub fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<(), ArrowError> {
+        let [sketches] = states else {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "{HLL_UDAF_NAME} has one state field, got {}",
+                states.len()
+            )));
+        };
+        let Some(sketches) = sketches.as_binary_opt::<i32>() else {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "{HLL_UDAF_NAME} states are binary, got {}",
+                sketches.data_type()
+            )));
+        };
+        for sketch in sketches.iter().flatten() {
+            let sketch = HLLCounter::read_from(sketch)
+                .map_err(|e| ArrowError::IoError(format!("Invalid HLL sketch: {e}"), e))?;
+            self.counter
+                .merge(&sketch)
+                .map_err(|e| ArrowError::InvalidArgumentError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// The estimated number of distinct values, rounded.
+    pub fn evaluate(&self) -> u64 {
+        self.counter.estimate().round() as u64
+    }
+
+    /// Memory of the state in bytes.
+    pub fn size(&self) -> usize {
+        self.counter.memory_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashCounter;
+    use arrow_array::{
+        BinaryArray, DictionaryArray, Float64Array, Int32Array, Int64Array, LargeStringArray,
+        ListArray, StringArray, StringViewArray, TimestampMillisecondArray,
+    };
+
+    fn exact() -> HashCounter<Xxh64Builder> {
+        HashCounter::new(0)
+    }
+
+    #[test]
+    fn test_primitive_arrays() {
+        let mut counter = exact();
+        let ints = Int32Array::from(vec![Some(1), None, Some(-1), Some(1), Some(3)]);
+        assert_eq!(counter.add_arrow_array(&ints).unwrap(), 4);
+        assert_eq!(counter.estimate(), 3.0);
+        // The same numbers in a wider column are not counted again
+        let longs = Int64Array::from(vec![1, -1, 3, 4]);
+        counter.add_arrow_array(&longs).unwrap();
+        assert_eq!(counter.estimate(), 4.0);
+
+        let mut counter = exact();
+        let floats = Float64Array::from(vec![0.0, -0.0, f64::NAN, -f64::NAN, 1.5]);
+        counter.add_arrow_array(&floats).unwrap();
+        assert_eq!(counter.estimate(), 3.0);
+
+        let mut counter = exact();
+        let timestamps = TimestampMillisecondArray::from(vec![Some(5), Some(5), None, Some(6)]);
+        assert_eq!(counter.add_arrow_array(&timestamps).unwrap(), 3);
+        assert_eq!(counter.estimate(), 2.0);
+    }
+
+    #[test]
+    fn test_string_arrays_match_bytes() {
+        let values = ["chr1", "chr2", "chr1", "chrX"];
+        let mut expected = exact();
+        for value in values {
+            expected.add(value.as_bytes());
+        }
+
+        let arrays: [Arc<dyn Array>; 4] = [
+            Arc::new(StringArray::from(values.to_vec())),
+            Arc::new(LargeStringArray::from(values.to_vec())),
+            Arc::new(StringViewArray::from(values.to_vec())),
+            Arc::new(BinaryArray::from_vec(
+                values.iter().map(|value| value.as_bytes()).collect(),
+            )),
+        ];
+        for array in arrays {
+            let mut counter = exact();
+            assert_eq!(counter.add_arrow_array(array.as_ref()).unwrap(), 4);
+            assert_eq!(counter.estimate(), expected.estimate());
+        }
+    }
+
+    #[test]
+    fn test_dictionary_arrays() {
+        let dictionary: DictionaryArray<Int32Type> =
+            vec![Some("a"), Some("b"), None, Some("a"), Some("c")]
+                .into_iter()
+                .collect();
+        // Unused dictionary values are not counted
+        let sliced = dictionary.slice(0, 4);
+        let mut counter = exact();
+        assert_eq!(counter.add_arrow_array(&sliced).unwrap(), 3);
+        assert_eq!(counter.estimate(), 2.0);
+    }
+
+    #[test]
+    fn test_accumulator_partial_aggregation() {
+        let values: ArrayRef = Arc::new(StringArray::from_iter_values(
+            (0..5000).map(|i| format!("read{i}")),
+        ));
+        let mut single = HLLAccumulator::new(10);
+        single.update_batch(std::slice::from_ref(&values)).unwrap();
+
+        let mut partitions = [HLLAccumulator::new(10), HLLAccumulator::new(10)];
+        partitions[0]
+            .update_batch(&[values.slice(0, 3000)])
+            .unwrap();
+        partitions[1]
+            .update_batch(&[values.slice(2000, 3000)])
+            .unwrap();
+        let mut merged = HLLAccumulator::new(10);
+        for partition in &partitions {
+            merged.merge_batch(&[partition.state()]).unwrap();
+        }
+        assert_eq!(
+            merged.counter.register_values(),
+            single.counter.register_values()
+        );
+        assert_eq!(merged.evaluate(), single.evaluate());
+
+        // States of another precision or type are rejected
+        assert!(
+            merged
+                .merge_batch(&[HLLAccumulator::new(12).state()])
+                .is_err()
+        );
+        assert!(merged.merge_batch(std::slice::from_ref(&values)).is_err());
+        let garbage: ArrayRef = Arc::new(BinaryArray::from_vec(vec![b"nope"]));
+        assert!(matches!(
+            merged.merge_batch(&[garbage]),
+            Err(ArrowError::IoError(..))
+        ));
+        assert!(merged.update_batch(&[values.clone(), values]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let list = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![Some(vec![Some(1)])]);
+        assert!(matches!(
+            exact().add_arrow_array(&list),
+            Err(ArrowError::NotYetImplemented(_))
+        ));
+    }
+}
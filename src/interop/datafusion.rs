@@ -0,0 +1,213 @@
+//! An approximate `COUNT(DISTINCT ...)` aggregate function for DataFusion.
+//!
+//! [`register`] adds the function [`HLL_UDAF_NAME`] to a session. Every
+//! partition counts its rows into an [`HLLAccumulator`], whose sketch is
+//! passed on as a binary state and merged by the final aggregation, so the
+//! result does not depend on how the rows are partitioned.
+//!
+//! ```
+//! use datafusion::prelude::SessionContext;
+//! use hll_rust::interop::datafusion::register;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> datafusion::error::Result<()> {
+//! let ctx = SessionContext::new();
+//! register(&ctx);
+//! let df = ctx
+//!     .sql("SELECT approx_count_distinct_hll(column1) FROM (VALUES ('a'), ('b'), ('a'))")
+//!     .await?;
+//! df.show().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::interop::arrow::{HLL_UDAF_NAME, HLLAccumulator};
+use arrow_array::ArrayRef;
+use arrow_schema::{DataType, Field, FieldRef};
+use datafusion::common::{Result, ScalarValue};
+use datafusion::logical_expr::function::{AccumulatorArgs, StateFieldsArgs};
+use datafusion::logical_expr::{
+    Accumulator, AggregateUDF, AggregateUDFImpl, Signature, Volatility,
+};
+use datafusion::prelude::SessionContext;
+use std::sync::Arc;
+
+/// Precision of the sketches of the function added by [`register`].
+pub const DEFAULT_PRECISION: usize = 14;
+
+/// The aggregate function [`HLL_UDAF_NAME`], counting the non-null values of
+/// its single argument into sketches of a fixed precision.
+///
+/// Arguments of any type supported by
+/// [`ArrowCounter`](crate::interop::arrow::ArrowCounter) are accepted, and the
+/// estimate is returned as `UInt64`.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct HLLUdaf {
+    signature: Signature,
+    precision: usize,
+}
+
+impl HLLUdaf {
+    /// Creates the function with sketches of the given precision.
+    ///
+    /// Panics if `precision` is not in `4..=24`.
+    pub fn new(precision: usize) -> Self {
+        // Fails here instead of in the first query
+        HLLAccumulator::new(precision);
+        HLLUdaf {
+            signature: Signature::any(1, Volatility::Immutable),
+            precision,
+        }
+    }
+}
+
+impl AggregateUDFImpl for HLLUdaf {
+    fn name(&self) -> &str {
+        HLL_UDAF_NAME
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::UInt64)
+    }
+
+    fn is_nullable(&self) -> bool {
+        false
+    }
+
+    fn accumulator(&self, _args: AccumulatorArgs) -> Result<Box<dyn Accumulator>> {
+        Ok(Box::new(HLLAccumulator::new(self.precision)))
+    }
+
+    fn state_fields(&self, args: StateFieldsArgs) -> Result<Vec<FieldRef>> {
+        Ok(vec![Arc::new(Field::new(
+            format!("{}[sketch]", args.name),
+            DataType::Binary,
+            true,
+        ))])
+    }
+}
+
+impl Accumulator for HLLAccumulator {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        Ok(HLLAccumulator::update_batch(self, values)?)
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        Ok(ScalarValue::UInt64(Some(HLLAccumulator::evaluate(self))))
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self) + HLLAccumulator::size(self)
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        let sketches = HLLAccumulator::state(self);
+        Ok(vec![ScalarValue::try_from_array(&sketches, 0)?])
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        Ok(HLLAccumulator::merge_batch(self, states)?)
+    }
+}
+
+/// Creates the aggregate function [`HLL_UDAF_NAME`] with sketches of the
+/// given precision, e.g. to register it with another precision than
+/// [`register`] does.
+///
+/// Panics if `precision` is not in `4..=24`.
+pub fn hll_udaf(precision: usize) -> AggregateUDF {
+    AggregateUDF::from(HLLUdaf::new(precision))
+}
+
+/// Registers the aggregate function [`HLL_UDAF_NAME`] with sketches of
+/// [`DEFAULT_PRECISION`] in `ctx`.
+pub fn register(ctx: &SessionContext) {
+    ctx.register_udaf(hll_udaf(DEFAULT_PRECISION));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Int64Array, RecordBatch, StringArray, UInt64Array};
+    use arrow_schema::Schema;
+    use datafusion::datasource::MemTable;
+    use datafusion::prelude::SessionConfig;
+
+    #[tokio::test]
+    async fn test_sql_aggregate() {
+        // Four partitions of overlapping values, so partial states are merged
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("sample", DataType::Utf8, false),
+            Field::new("kmer", DataType::Int64, true),
+        ]));
+        let partitions = (0..4)
+            .map(|i| {
+                let kmers = Int64Array::from_iter(
+                    (i * 1000..i * 1000 + 2000).map(|kmer| (kmer % 3 != 0).then_some(kmer)),
+                );
+                let samples = StringArray::from_iter_values(
+                    (0..2000).map(|row| if row % 2 == 0 { "a" } else { "b" }),
+                );
+                let batch =
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(samples), Arc::new(kmers)])
+                        .unwrap();
+                vec![batch]
+            })
+            .collect();
+        let table = MemTable::try_new(schema, partitions).unwrap();
+
+        let ctx = SessionContext::new_with_config(SessionConfig::new().with_target_partitions(4));
+        register(&ctx);
+        ctx.register_table("kmers", Arc::new(table)).unwrap();
+
+        let batches = ctx
+            .sql(
+                "SELECT sample, approx_count_distinct_hll(kmer), COUNT(DISTINCT kmer) \
+                 FROM kmers GROUP BY sample ORDER BY sample",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let estimates = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        let exact = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        // Nulls, every third k-mer, are not counted
+        assert_eq!(exact.values(), &[1666, 1667]);
+        for (estimate, exact) in estimates.values().iter().zip(exact.values()) {
+            assert!(
+                estimate.abs_diff(*exact as u64) < 50,
+                "{estimate} vs {exact}"
+            );
+        }
+
+        let batches = ctx
+            .sql("SELECT approx_count_distinct_hll(kmer) FROM kmers")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let estimate = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert!(estimate.value(0).abs_diff(3333) < 100);
+    }
+}
@@ -0,0 +1,468 @@
+//! Apache DataSketches HLL serialization format.
+//!
+//! [`serialize`] writes a counter as a compact DataSketches HLL sketch in the
+//! HLL_4, HLL_6 or HLL_8 register encoding, and [`deserialize`] reads sketches
+//! written by the Java, C++ and Python libraries in any mode (LIST, SET or HLL).
+//!
+//! The registers are transferred as they are. To merge sketches with ones
+//! built by DataSketches, the items must also be hashed like DataSketches
+//! does, which [`update`] implements.
+
+use crate::counters::{Counter, HLLCounter};
+use std::hash::BuildHasher;
+use std::io;
+
+const HLL_FAMILY_ID: u8 = 7;
+const SER_VER: u8 = 1;
+const LIST_PREINTS: u8 = 2;
+const HASH_SET_PREINTS: u8 = 3;
+const HLL_PREINTS: u8 = 10;
+
+const EMPTY_FLAG: u8 = 4;
+const COMPACT_FLAG: u8 = 8;
+const OUT_OF_ORDER_FLAG: u8 = 16;
+
+const MODE_LIST: u8 = 0;
+const MODE_SET: u8 = 1;
+const MODE_HLL: u8 = 2;
+
+// Offsets within the preamble
+const LIST_INT_ARR_START: usize = 8;
+const HASH_SET_COUNT_INT: usize = 8;
+const HASH_SET_INT_ARR_START: usize = 12;
+const HLL_BYTE_ARR_START: usize = 40;
+
+// Coupons and aux entries pack a 26-bit register address with a 6-bit value
+const KEY_BITS_26: u32 = 26;
+const KEY_MASK_26: u32 = (1 << KEY_BITS_26) - 1;
+// HLL_4 nibble marking a value that is stored in the aux table
+const AUX_TOKEN: u8 = 15;
+// Minimal log2 size of the HLL_4 aux table, by lg_k
+const LG_AUX_ARR_INTS: [u8; 22] = [
+    0, 2, 2, 2, 2, 2, 2, 3, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 11, 12, 13,
+];
+
+/// Smallest precision (`lg_k`) supported by DataSketches.
+pub const MIN_LG_K: usize = 4;
+/// Largest precision (`lg_k`) supported by DataSketches.
+pub const MAX_LG_K: usize = 21;
+/// Seed DataSketches uses to hash items.
+pub const DEFAULT_UPDATE_SEED: u64 = 9001;
+
+/// Register encoding of a serialized sketch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HllType {
+    /// 4 bits per register relative to the minimum, plus a table of outliers.
+    Hll4,
+    /// 6 bits per register.
+    Hll6,
+    /// One byte per register.
+    Hll8,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> io::Result<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("Truncated DataSketches HLL sketch."))
+}
+
+/// Adds an item hashed the way DataSketches' `HllSketch::update` hashes
+/// byte arrays and strings, so the registers match those of a DataSketches
+/// sketch fed the same items. Empty items are ignored, like in DataSketches.
+///
+/// Integers are hashed as their 8 little endian bytes in DataSketches.
+pub fn update<S: BuildHasher + Default, R: crate::counters::Registers>(
+    counter: &mut HLLCounter<S, R>,
+    item: &[u8],
+) {
+    if item.is_empty() {
+        return;
+    }
+    let (h1, h2) = murmur_hash3_x64_128(item, DEFAULT_UPDATE_SEED);
+    let index = (h1 as usize) & ((1 << counter.precision()) - 1);
+    let rho = std::cmp::min(h2.leading_zeros(), 62) + 1;
+    counter.update_register(index, rho as u8);
+}
+
+/// Serializes the counter as a compact DataSketches HLL sketch.
+///
+/// Fails if the precision is outside of `MIN_LG_K..=MAX_LG_K`. The sketch is
+/// flagged as out of order, so readers use the register based estimator
+/// instead of the HIP accumulator, which this crate does not track.
+pub fn serialize<S: BuildHasher + Default, R: crate::counters::Registers>(
+    counter: &HLLCounter<S, R>,
+    hll_type: HllType,
+) -> io::Result<Vec<u8>> {
+    let lg_k = counter.precision();
+    if !(MIN_LG_K..=MAX_LG_K).contains(&lg_k) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "DataSketches supports precisions between {} and {}.",
+                MIN_LG_K, MAX_LG_K
+            ),
+        ));
+    }
+    // DataSketches stores at most 6 bits per register
+    let registers: Vec<u8> = counter
+        .register_values()
+        .into_iter()
+        .map(|reg| reg.min(63))
+        .collect();
+    let k = registers.len();
+
+    let cur_min = match hll_type {
+        HllType::Hll4 => *registers.iter().min().unwrap(),
+        _ => 0,
+    };
+    let num_at_cur_min = registers.iter().filter(|&&reg| reg == cur_min).count() as u32;
+    let (kxq0, kxq1) = registers.iter().fold((0.0, 0.0), |(kxq0, kxq1), &reg| {
+        let inv_pow = 2f64.powi(-(reg as i32));
+        if reg < 32 {
+            (kxq0 + inv_pow, kxq1)
+        } else {
+            (kxq0, kxq1 + inv_pow)
+        }
+    });
+
+    let mut aux = Vec::new();
+    let array = match hll_type {
+        HllType::Hll8 => registers.clone(),
+        HllType::Hll6 => {
+            let mut array = vec![0u8; k * 3 / 4 + 1];
+            for (slot, &reg) in registers.iter().enumerate() {
+                let bit = slot * 6;
+                let (byte, shift) = (bit / 8, bit % 8);
+                let value = (reg as u16) << shift;
+                array[byte] |= value as u8;
+                array[byte + 1] |= (value >> 8) as u8;
+            }
+            array
+        }
+        HllType::Hll4 => {
+            let mut array = vec![0u8; k / 2];
+            for (slot, &reg) in registers.iter().enumerate() {
+                let mut nibble = reg - cur_min;
+                if nibble >= AUX_TOKEN {
+                    nibble = AUX_TOKEN;
+                    aux.push(((reg as u32) << KEY_BITS_26) | slot as u32);
+                }
+                array[slot / 2] |= nibble << (4 * (slot % 2));
+            }
+            array
+        }
+    };
+
+    let lg_arr = if aux.is_empty() {
+        0
+    } else {
+        // Size of the aux hash table a reader would allocate
+        let mut size = aux.len().next_power_of_two();
+        if 4 * aux.len() > 3 * size {
+            size <<= 1;
+        }
+        std::cmp::max(LG_AUX_ARR_INTS[lg_k], size.trailing_zeros() as u8)
+    };
+    let empty = registers.iter().all(|&reg| reg == 0);
+    let mut flags = COMPACT_FLAG | OUT_OF_ORDER_FLAG;
+    if empty {
+        flags |= EMPTY_FLAG;
+    }
+    let type_bits = match hll_type {
+        HllType::Hll4 => 0,
+        HllType::Hll6 => 1,
+        HllType::Hll8 => 2,
+    };
+
+    let mut bytes = Vec::with_capacity(HLL_BYTE_ARR_START + array.len() + 4 * aux.len());
+    bytes.extend_from_slice(&[
+        HLL_PREINTS,
+        SER_VER,
+        HLL_FAMILY_ID,
+        lg_k as u8,
+        lg_arr,
+        flags,
+        cur_min,
+        MODE_HLL | (type_bits << 2),
+    ]);
+    bytes.extend_from_slice(&counter.estimate().to_le_bytes());
+    bytes.extend_from_slice(&kxq0.to_le_bytes());
+    bytes.extend_from_slice(&kxq1.to_le_bytes());
+    bytes.extend_from_slice(&num_at_cur_min.to_le_bytes());
+    bytes.extend_from_slice(&(aux.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&array);
+    for pair in aux {
+        bytes.extend_from_slice(&pair.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Reads a DataSketches HLL sketch in any mode and register encoding.
+pub fn deserialize<S: BuildHasher + Default, R: crate::counters::Registers>(
+    bytes: &[u8],
+) -> io::Result<HLLCounter<S, R>> {
+    if bytes.len() < 8 {
+        return Err(invalid("Truncated DataSketches HLL sketch."));
+    }
+    let (pre_ints, ser_ver, family, lg_k) = (bytes[0], bytes[1], bytes[2], bytes[3] as usize);
+    let (lg_arr, flags, cur_min, mode) = (bytes[4], bytes[5], bytes[6], bytes[7]);
+    if family != HLL_FAMILY_ID || ser_ver != SER_VER {
+        return Err(invalid("Not a DataSketches HLL sketch."));
+    }
+    if !(MIN_LG_K..=MAX_LG_K).contains(&lg_k) {
+        return Err(invalid("Invalid DataSketches HLL precision."));
+    }
+    // A hash table of coupons or aux entries never has more than twice as
+    // many slots as there are registers
+    if lg_arr as usize > lg_k + 1 {
+        return Err(invalid("Invalid DataSketches HLL table size."));
+    }
+    let k = 1 << lg_k;
+    let compact = flags & COMPACT_FLAG != 0;
+    let mut registers = vec![0u8; k];
+
+    // Coupons of the LIST and SET modes and the aux entries of HLL_4
+    let apply_pairs = |registers: &mut [u8], start: usize, count: usize| -> io::Result<()> {
+        for i in 0..count {
+            let pair = read_u32(bytes, start + 4 * i)?;
+            if pair == 0 {
+                // Empty slot of a hash table
+                continue;
+            }
+            let slot = (pair & KEY_MASK_26) as usize & (k - 1);
+            let value = (pair >> KEY_BITS_26) as u8;
+            registers[slot] = registers[slot].max(value);
+        }
+        Ok(())
+    };
+
+    match (mode & 3, pre_ints) {
+        _ if flags & EMPTY_FLAG != 0 => {}
+        (MODE_LIST, LIST_PREINTS) => {
+            let count = if compact {
+                bytes[6] as usize
+            } else {
+                1 << lg_arr
+            };
+            apply_pairs(&mut registers, LIST_INT_ARR_START, count)?;
+        }
+        (MODE_SET, HASH_SET_PREINTS) => {
+            let count = if compact {
+                read_u32(bytes, HASH_SET_COUNT_INT)? as usize
+            } else {
+                1 << lg_arr
+            };
+            apply_pairs(&mut registers, HASH_SET_INT_ARR_START, count)?;
+        }
+        (MODE_HLL, HLL_PREINTS) => {
+            let array_len = match (mode >> 2) & 3 {
+                0 => k / 2,
+                1 => k * 3 / 4 + 1,
+                2 => k,
+                _ => return Err(invalid("Invalid DataSketches HLL type.")),
+            };
+            let array = bytes
+                .get(HLL_BYTE_ARR_START..HLL_BYTE_ARR_START + array_len)
+                .ok_or_else(|| invalid("Truncated DataSketches HLL sketch."))?;
+
+            match (mode >> 2) & 3 {
+                0 => {
+                    for (slot, reg) in registers.iter_mut().enumerate() {
+                        let nibble = (array[slot / 2] >> (4 * (slot % 2))) & 0xf;
+                        // Outliers are restored from the aux table below
+                        if nibble != AUX_TOKEN {
+                            *reg = cur_min + nibble;
+                        }
+                    }
+                    let aux_count = read_u32(bytes, 36)? as usize;
+                    if aux_count > 0 {
+                        let count = if compact { aux_count } else { 1 << lg_arr };
+                        apply_pairs(&mut registers, HLL_BYTE_ARR_START + array_len, count)?;
+                    }
+                }
+                1 => {
+                    for (slot, reg) in registers.iter_mut().enumerate() {
+                        let bit = slot * 6;
+                        let (byte, shift) = (bit / 8, bit % 8);
+                        let two_bytes = array[byte] as u16 | (array[byte + 1] as u16) << 8;
+                        *reg = ((two_bytes >> shift) & 0x3f) as u8;
+                    }
+                }
+                _ => registers.copy_from_slice(array),
+            }
+        }
+        _ => return Err(invalid("Invalid DataSketches HLL mode.")),
+    }
+
+    Ok(HLLCounter::from_dense_registers(
+        lg_k,
+        S::default(),
+        registers,
+    ))
+}
+
+/// MurmurHash3 x64 128-bit variant, returning both 64-bit halves.
+pub fn murmur_hash3_x64_128(data: &[u8], seed: u64) -> (u64, u64) {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+
+    let fmix = |mut k: u64| {
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xff51afd7ed558ccd);
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+        k ^ (k >> 33)
+    };
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    let read_le = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &byte| (acc << 8) | byte as u64)
+    };
+
+    let (mut h1, mut h2) = (seed, seed);
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        h1 ^= mix_k1(read_le(&block[..8]));
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dce729);
+
+        h2 ^= mix_k2(read_le(&block[8..]));
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x38495ab5);
+    }
+
+    let tail = blocks.remainder();
+    if tail.len() > 8 {
+        h2 ^= mix_k2(read_le(&tail[8..]));
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(read_le(&tail[..tail.len().min(8)]));
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix(h1);
+    h2 = fmix(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_murmur_hash3() {
+        assert_eq!(
+            murmur_hash3_x64_128(b"", DEFAULT_UPDATE_SEED),
+            (0x1e70a32266491bb9, 0x609736b252406b94)
+        );
+        assert_eq!(
+            murmur_hash3_x64_128(b"hello", DEFAULT_UPDATE_SEED),
+            (0x21b77bd4a835c1aa, 0xc3001500fe032ef2)
+        );
+        assert_eq!(
+            murmur_hash3_x64_128(b"0123456789abcdefXYZ", DEFAULT_UPDATE_SEED),
+            (0x30cf7cd176466bae, 0x16b7b6db4f2aba73)
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_all_types() {
+        let mut counter = HLLCounter::<Xxh64Builder>::new(12);
+        for i in 0..20_000u64 {
+            update(&mut counter, &i.to_le_bytes());
+        }
+        // Force an HLL_4 outlier
+        counter.update_register(7, 40);
+        let registers = counter.register_values();
+
+        for hll_type in [HllType::Hll4, HllType::Hll6, HllType::Hll8] {
+            let bytes = serialize(&counter, hll_type).unwrap();
+            let restored = deserialize::<Xxh64Builder, Vec<u8>>(&bytes).unwrap();
+            assert_eq!(restored.register_values(), registers, "{:?}", hll_type);
+        }
+        assert!((counter.estimate() - 20_000.0).abs() / 20_000.0 < 0.05);
+    }
+
+    #[test]
+    fn test_deserialize_list_mode() {
+        // Compact LIST sketch with lg_k = 12 and two coupons
+        let mut bytes = vec![
+            LIST_PREINTS,
+            SER_VER,
+            HLL_FAMILY_ID,
+            12,
+            3,
+            COMPACT_FLAG,
+            2,
+            8,
+        ];
+        bytes.extend_from_slice(&((5u32 << KEY_BITS_26) | 4097).to_le_bytes());
+        bytes.extend_from_slice(&((3u32 << KEY_BITS_26) | 10).to_le_bytes());
+
+        let counter = deserialize::<Xxh64Builder, Vec<u8>>(&bytes).unwrap();
+        let registers = counter.register_values();
+        assert_eq!(registers[1], 5);
+        assert_eq!(registers[10], 3);
+        assert_eq!(registers.iter().filter(|&&reg| reg != 0).count(), 2);
+        assert!(deserialize::<Xxh64Builder, Vec<u8>>(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_malformed() {
+        let mut counter = HLLCounter::<Xxh64Builder>::new(10);
+        for i in 0..5_000u64 {
+            update(&mut counter, &i.to_le_bytes());
+        }
+        counter.update_register(3, 40);
+        let hll4 = serialize(&counter, HllType::Hll4).unwrap();
+
+        // Truncated sketches fail instead of panicking
+        for len in 0..hll4.len() {
+            assert!(deserialize::<Xxh64Builder, Vec<u8>>(&hll4[..len]).is_err());
+        }
+
+        // Table sizes too large to shift by, with the sizes of updatable
+        // (non-compact) LIST, SET and HLL_4 sketches taken from them
+        let updatable = |mut bytes: Vec<u8>, lg_arr: u8| {
+            bytes[4] = lg_arr;
+            bytes[5] &= !COMPACT_FLAG;
+            bytes
+        };
+        let list = vec![LIST_PREINTS, SER_VER, HLL_FAMILY_ID, 10, 3, 0, 0, 0];
+        let mut set = vec![HASH_SET_PREINTS, SER_VER, HLL_FAMILY_ID, 10, 5, 0, 0, 1];
+        set.extend_from_slice(&[0; 4]);
+        for bytes in [list, set, hll4] {
+            for lg_arr in [12, 32, 64, 255] {
+                assert_eq!(
+                    deserialize::<Xxh64Builder, Vec<u8>>(&updatable(bytes.clone(), lg_arr))
+                        .err()
+                        .unwrap()
+                        .kind(),
+                    io::ErrorKind::InvalidData
+                );
+            }
+            // A valid size with missing table data
+            assert!(deserialize::<Xxh64Builder, Vec<u8>>(&updatable(bytes, 11)).is_err());
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! Conversion of sketches to and from the formats of other libraries.
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "datafusion")]
+pub mod datafusion;
+pub mod datasketches;
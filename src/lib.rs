@@ -1,9 +1,64 @@
+#[cfg(feature = "bam")]
+pub mod alignment;
+#[cfg(feature = "tokio")]
+pub mod async_fasta;
 pub mod counters;
+pub mod distance;
 pub mod fasta;
+pub mod fasta_index;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filters;
+pub mod frequency;
+pub mod interop;
+#[cfg(feature = "parallel")]
 pub mod parallel_counting;
+pub mod sketch_db;
+pub mod synthetic;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+pub use counters::AdaptiveCounter;
+pub use counters::AtomicHLLCounter;
+pub use counters::AuditCounter;
+pub use counters::BuildError;
 pub use counters::Counter;
+pub use counters::CounterBuilder;
+pub use counters::CounterMap;
+pub use counters::DistinctSampler;
+pub use counters::Estimate;
+pub use counters::EstimateWithError;
+pub use counters::Estimator;
+pub use counters::EstimatorKind;
+#[cfg(feature = "fs")]
+pub use counters::ExternalHashCounter;
 pub use counters::FMCounter;
 pub use counters::HLLCounter;
+pub use counters::HLLCounterConst;
+pub use counters::HLLSnapshot;
+pub use counters::HLLState;
 pub use counters::HashCounter;
+pub use counters::HyperBitBit;
+pub use counters::HyperMinHashCounter;
+pub use counters::KMVCounter;
 pub use counters::LinearCounter;
+pub use counters::MergeError;
+pub use counters::MergeTree;
+pub use counters::MergeableCounter;
+pub use counters::PCSACounter;
+pub use counters::PackedHLLCounter;
+pub use counters::RedisHashBuilder;
+pub use counters::SaturatedEstimate;
+pub use counters::ShardedCounter;
+pub use counters::SlidingHLLCounter;
+pub use counters::StackHLLCounter;
+pub use counters::ThetaSketch;
+pub use counters::ULLCounter;
+pub use filters::BloomFilter;
+pub use filters::CountingBloomFilter;
+pub use filters::QuotientFilter;
+pub use frequency::AmsSketch;
+pub use frequency::CountMinSketch;
+pub use frequency::StreamStats;
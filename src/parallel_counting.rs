@@ -1,106 +1,3288 @@
-use crate::Counter;
-use crate::HLLCounter;
-use crate::fasta::FastaReader;
+#[cfg(feature = "bam")]
+use crate::alignment::{AlignmentFilter, AlignmentReader};
+use crate::counters::{AtomicHLLCounter, DistinctSampler, MergeTree};
+use crate::fasta::{
+    ENCODING, EncodedKmer, FastaError, FastaReader, FastqReader, GZIP_MAGIC,
+    MAX_PROTEIN_K_MER_LENGTH, PHRED_OFFSET, ProteinAlphabet, RecordStats, SyncmerKind,
+    for_each_nthash, for_each_protein_kmer, get_canonical_u128, is_syncmer, open_sequence_file,
+    reverse_complement, validate_sequence,
+};
+use crate::{Counter, HLLCounter, MergeableCounter};
 use rayon::prelude::*;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs::File;
-use std::io::{self, BufReader};
-
-// A=00, C=01, G=10, T=11
-const ENCODING: [u8; 256] = {
-    let mut table = [0xFF; 256];
-    table[b'A' as usize] = 0;
-    table[b'C' as usize] = 1;
-    table[b'G' as usize] = 2;
-    table[b'T' as usize] = 3;
-    // Handle lowercase as well, though we uppercase the sequence
-    table[b'a' as usize] = 0;
-    table[b'c' as usize] = 1;
-    table[b'g' as usize] = 2;
-    table[b't' as usize] = 3;
-    table
-};
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use xxhash_rust::xxh64::Xxh64;
+
+/// Default k-mer length of the parallel pipeline.
+pub const K_MER_LENGTH: usize = 31;
+
+/// Largest k-mer length that fits the 2-bit encoding into a u64.
+pub const MAX_K_MER_LENGTH: usize = 32;
+
+/// Number of k-mers per chunk when splitting long sequences for parallel processing.
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+// Number of k-mers passed to `Counter::add_u64_batch` at once
+const KMER_BATCH_SIZE: usize = 8;
+
+// Number of FASTQ reads counted per rayon job
+const READ_BATCH_SIZE: usize = 4096;
 
-const K_MER_LENGTH: usize = 31;
-const K_MER_MASK: u64 = (1u64 << 2 * K_MER_LENGTH) - 1; // Mask for 31-mer (62 bits)
+// Smallest byte range of a FASTA file read by a single rayon job
+const MIN_SPLIT_BYTES: u64 = 1 << 20;
 
+// Splits a sequence of length `len` into ranges that each start `chunk_size`
+// k-mers apart and overlap by k - 1 bases, so every k-mer lies in exactly one
+// chunk.
+#[cfg(feature = "tokio")]
+fn chunk_ranges(
+    len: usize,
+    k: usize,
+    chunk_size: usize,
+) -> impl IndexedParallelIterator<Item = Range<usize>> {
+    let num_chunks = len.saturating_sub(k - 1).div_ceil(chunk_size).max(1);
+    (0..num_chunks).into_par_iter().map(move |i| {
+        let start = i * chunk_size;
+        let end = std::cmp::min(start + chunk_size + k - 1, len);
+        start..std::cmp::max(start, end)
+    })
+}
+
+// Calls `f` with the 2-bit encoded canonical k-mers of a sequence, skipping
+// k-mers with unknown characters. Returns the number of k-mers seen.
 #[inline(always)]
-fn get_canonical_u64(kmer: u64) -> u64 {
-    // Reverse complement for 2-bit encoding (A=00, C=01, G=10, T=11)
-    // 1. Reverse bits
-    // 2. Shift right by 2 (since we use 62 bits for 31-mer)
-    // 3. Swap adjacent bits (to fix 2-bit chunk order)
-    // 4. XOR with mask (to complement)
+fn for_each_canonical_kmer<F: FnMut(u64)>(seq: &[u8], k: usize, mut f: F) -> u64 {
+    let mut kmers_seen: u64 = 0;
 
-    let mut r = kmer.reverse_bits();
-    r >>= 64 - 2 * K_MER_LENGTH; // Align to LSB (64 - 2*K_MER_LENGTH)
+    // Fast path using u64 for k <= 32
+    // We use a rolling window with 2-bit encoding
+    let mut kmer_u64: u64 = 0;
+    let mut valid_len = 0;
 
-    // Swap adjacent bits: (r >> 1) & 0x55... | (r & 0x55...) << 1
-    // 0x5555... is the mask 0101..., allowing us to select every 2nd bit
-    r = ((r >> 1) & 0x5555555555555555) | ((r & 0x5555555555555555) << 1);
+    for &byte in seq.iter() {
+        let code = ENCODING[byte as usize];
+        if code == 0xFF {
+            // Skip unknown characters
+            valid_len = 0;
+            kmer_u64 = 0;
+        } else {
+            kmer_u64 = kmer_u64.push(code, k);
+            valid_len += 1;
 
-    // Complement: XOR with 11...11 (62 bits)
-    // 11 binary is 3 decimal. We want to XOR each 2-bit pair with 11.
-    // So we XOR with all ones (masked to 62 bits).
-    r ^= (1u64 << 2 * K_MER_LENGTH) - 1;
+            if valid_len >= k {
+                f(kmer_u64.canonical(k));
+                kmers_seen += 1;
+            }
+        }
+    }
 
-    if kmer < r { kmer } else { r }
+    kmers_seen
 }
 
-pub fn run_parallel_fasta_analysis<S: std::hash::BuildHasher + Default + Send + Sync>(
-    path: &str,
-) -> io::Result<(u64, HLLCounter<S>)> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+// Adds the canonical k-mers of a sequence to `counter` in batches, so the
+// counter can hash several k-mers before touching its registers. Returns the
+// number of k-mers seen.
+fn add_canonical_kmers<C: Counter>(seq: &[u8], k: usize, counter: &mut C) -> u64 {
+    let mut batch = [0u64; KMER_BATCH_SIZE];
+    let mut len = 0;
+    let kmers_seen = for_each_canonical_kmer(seq, k, |kmer| {
+        batch[len] = kmer;
+        len += 1;
+        if len == KMER_BATCH_SIZE {
+            counter.add_u64_batch(&batch);
+            len = 0;
+        }
+    });
+    counter.add_u64_batch(&batch[..len]);
+    kmers_seen
+}
+
+// Iterates over the sequences of a FASTA file.
+fn fasta_sequences<P: AsRef<Path>>(
+    path: P,
+) -> Result<impl Iterator<Item = Result<Vec<u8>, FastaError>>, FastaError> {
+    Ok(read_sequences(open_sequence_file(path)?))
+}
+
+// Iterates over the sequences of FASTA data read from `reader`.
+fn read_sequences<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Vec<u8>, FastaError>> {
+    read_records(reader).map(|res| res.map(|(_, seq, _)| seq))
+}
+
+// Iterates over the (id, sequence, statistics) of the records of FASTA data
+// read from `reader`.
+fn read_records<R: BufRead>(
+    reader: R,
+) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>, RecordStats), FastaError>> {
     let mut fasta_reader = FastaReader::new(reader);
 
-    let sequences = std::iter::from_fn(move || match fasta_reader.next_record() {
+    std::iter::from_fn(move || match fasta_reader.next_record() {
         Ok(true) => match fasta_reader.read_sequence() {
-            Ok(seq) => Some(Ok(seq)),
+            Ok(seq) => Some(Ok((
+                fasta_reader.id.take().unwrap_or_default(),
+                seq,
+                *fasta_reader.stats(),
+            ))),
             Err(e) => Some(Err(e)),
         },
         Ok(false) => None,
         Err(e) => Some(Err(e)),
+    })
+}
+
+// Merges counters created by the same `make_counter`, which must hash with
+// the same function.
+fn merge_counter<C: MergeableCounter>(counter: &mut C, other: &C) {
+    counter.merge(other).unwrap_or_else(|e| {
+        panic!("counters created by make_counter must be mergeable, e.g. not use RandomState: {e}")
     });
+}
+
+// Merges many counters created by `make_counter` in a balanced parallel tree,
+// see `merge_counter`.
+fn merge_counter_tree<C, F>(counters: &[&C], make_counter: &F) -> C
+where
+    C: MergeableCounter + Send + Sync,
+    F: Fn() -> C + Sync,
+{
+    MergeTree::merge_refs(counters, make_counter).unwrap_or_else(|e| {
+        panic!("counters created by make_counter must be mergeable, e.g. not use RandomState: {e}")
+    })
+}
 
-    let final_counter = sequences
-        .par_bridge()
-        .map(|res| {
-            let seq = res.expect("Error reading sequence");
-            let mut counter = HLLCounter::<S>::new(16);
-            let mut kmers_seen: u64 = 0;
+// One counter per thread of the rayon pool, created on first use, so a
+// pipeline allocates and merges one counter per thread instead of one per
+// record, batch or chunk. Must be created inside the pool it is used in.
+//
+// The counters may be any state that `merge` can combine, e.g. a counter
+// for every k-mer length.
+struct CounterPool<'a, T, F> {
+    // Indexed by `rayon::current_thread_index`, the last slot is shared by
+    // callers outside of the pool
+    slots: Vec<Mutex<Option<(u64, T)>>>,
+    make_counter: &'a F,
+    merge: fn(&mut T, &T),
+}
 
-            // Fast path using u64 for 31-mers
-            // We use a rolling window with 2-bit encoding
-            let mut kmer_u64: u64 = 0;
-            let mut valid_len = 0;
+impl<'a, C, F> CounterPool<'a, C, F>
+where
+    C: MergeableCounter,
+    F: Fn() -> C,
+{
+    fn new(make_counter: &'a F) -> Self {
+        CounterPool::with_merge(make_counter, merge_counter)
+    }
+
+    // Adds the canonical k-mers of `seq` to the counter of the current
+    // thread and returns their number.
+    fn add_kmers(&self, seq: &[u8], k: usize) -> u64 {
+        self.count(|counter| add_canonical_kmers(seq, k, counter))
+    }
+}
+
+impl<'a, T, F> CounterPool<'a, T, F>
+where
+    F: Fn() -> T,
+{
+    fn with_merge(make_counter: &'a F, merge: fn(&mut T, &T)) -> Self {
+        CounterPool {
+            slots: (0..=rayon::current_num_threads())
+                .map(|_| Mutex::new(None))
+                .collect(),
+            make_counter,
+            merge,
+        }
+    }
+
+    // Calls `count` with the counter of the current thread, which returns
+    // the number of k-mers it added. The lock is held while counting, which
+    // must therefore not run rayon jobs that could use the same slot.
+    fn count<G: FnOnce(&mut T) -> u64>(&self, count: G) -> u64 {
+        let index = rayon::current_thread_index()
+            .filter(|&index| index < self.slots.len() - 1)
+            .unwrap_or(self.slots.len() - 1);
+        let mut slot = self.slots[index].lock().unwrap_or_else(|e| e.into_inner());
+        let (kmers_seen, counter) = slot.get_or_insert_with(|| (0, (self.make_counter)()));
+        let added = count(counter);
+        *kmers_seen += added;
+        added
+    }
+
+    // Merges the counters of all threads into a new counter, leaving them
+    // in place, e.g. to estimate the cardinality of a running pipeline.
+    // Waits for each thread to finish the k-mers it is counting.
+    fn merged(&self) -> T {
+        let mut merged = (self.make_counter)();
+        for slot in &self.slots {
+            if let Some((_, counter)) = &*slot.lock().unwrap_or_else(|e| e.into_inner()) {
+                (self.merge)(&mut merged, counter);
+            }
+        }
+        merged
+    }
+
+    // Merges the counters of all threads that counted anything.
+    fn into_merged(self) -> (u64, T) {
+        let mut counted = self
+            .slots
+            .into_iter()
+            .filter_map(|slot| slot.into_inner().unwrap_or_else(|e| e.into_inner()));
+        let mut merged = counted.next().unwrap_or_else(|| (0, (self.make_counter)()));
+        for (kmers_seen, counter) in counted {
+            merged.0 += kmers_seen;
+            (self.merge)(&mut merged.1, &counter);
+        }
+        merged
+    }
+}
+
+// Counts the canonical k-mers of one sequence into its own counter,
+// splitting long sequences (e.g. whole chromosomes) into chunks so a single
+// record can use all cores.
+#[cfg(feature = "tokio")]
+fn count_sequence<C, F>(seq: &[u8], k: usize, chunk_size: usize, make_counter: &F) -> (u64, C)
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    chunk_ranges(seq.len(), k, chunk_size)
+        .map(|range| {
+            let mut counter = make_counter();
+            let kmers_seen = add_canonical_kmers(&seq[range], k, &mut counter);
+            (kmers_seen, counter)
+        })
+        .reduce(
+            || (0, make_counter()),
+            |(count_a, mut a), (count_b, b)| {
+                merge_counter(&mut a, &b);
+                (count_a + count_b, a)
+            },
+        )
+}
+
+// Counts the bytes consumed from the wrapped reader.
+struct CountingReader<'a, R> {
+    inner: R,
+    bytes_read: &'a AtomicU64,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.bytes_read.fetch_add(amt as u64, Ordering::Relaxed);
+        self.inner.consume(amt);
+    }
+}
+
+// Hashes and counts the bytes consumed from the wrapped reader, to tell
+// whether a checkpoint was made over the same data.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Xxh64,
+    len: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for HashingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The consumed bytes are still buffered, so this does no I/O
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.hasher.update(&buf[..amt.min(buf.len())]);
+        }
+        self.len += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// Counts the canonical 31-mers of a FASTA file in parallel.
+///
+/// Gzip/bgzip compressed files are decompressed transparently.
+///
+/// Every record is split into chunks of [`CHUNK_SIZE`] k-mers, so a single
+/// record can use all cores. Every thread of the rayon pool counts its
+/// chunks into one counter created by `make_counter` on first use, and the
+/// counters of the threads are merged at the end. Returns the total number
+/// of k-mers seen along with the merged counter.
+///
+/// Stops at the first error reading or parsing the file and returns it.
+/// Panics if the counters cannot be merged, so `make_counter` must create
+/// counters with the same hash function: `RandomState` draws new keys for
+/// every counter.
+///
+/// To measure the actual error of a sketch on the file, count into an
+/// [`AuditCounter`](crate::AuditCounter), which keeps an exact count next to
+/// the sketch in the same pass.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::run_parallel_fasta_analysis;
+/// use hll_rust::{Counter, HLLCounter, HashCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, hll) = run_parallel_fasta_analysis("genome.fa", || HLLCounter::<Xxh64Builder>::new(16))?;
+/// let (_, exact) = run_parallel_fasta_analysis("genome.fa", || HashCounter::<Xxh64Builder>::new(0))?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_fasta_analysis<C, F>(
+    path: &str,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_fasta_analysis_with_k(path, K_MER_LENGTH, make_counter)
+}
+
+/// Like [`run_parallel_fasta_analysis`], but counts canonical k-mers of length `k`.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_fasta_analysis_with_k<C, F>(
+    path: &str,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    count_file(path, k, CHUNK_SIZE, 0, &make_counter)
+}
+
+// Counts the canonical k-mers of all sequences of a FASTA file.
+//
+// Uncompressed files with many records of similar size are split into byte
+// ranges at record boundaries, each read by its own rayon job, see
+// `count_ranges`. Compressed files and standard input can only be read front
+// to back, and files of few or uneven records would leave threads idle
+// while the largest record is counted, so these are streamed through
+// `count_reader`, which spreads the chunks of every record over all
+// workers.
+fn count_file<P, C, F>(
+    path: P,
+    k: usize,
+    chunk_size: usize,
+    queue_chunks: usize,
+    make_counter: &F,
+) -> Result<(u64, C), FastaError>
+where
+    P: AsRef<Path>,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    let path = path.as_ref();
+    let threads = rayon::current_num_threads();
+    match record_ranges(path, 4 * threads, MIN_SPLIT_BYTES)? {
+        Some(ranges) if ranges_balanced(&ranges, threads) => {
+            count_ranges(path, &ranges, k, chunk_size, make_counter)
+        }
+        _ => count_reader(
+            open_sequence_file(path)?,
+            k,
+            chunk_size,
+            queue_chunks,
+            make_counter,
+        ),
+    }
+}
+
+// Splits an uncompressed FASTA file into at most `max_parts` byte ranges of
+// at least `min_split_bytes` that each start at a record header, or returns
+// `None` if the file cannot be read at arbitrary offsets.
+//
+// Every range boundary is found by its own rayon job scanning forward from
+// an even split of the file to the next line starting with `>`.
+fn record_ranges(
+    path: &Path,
+    max_parts: usize,
+    min_split_bytes: u64,
+) -> io::Result<Option<Vec<Range<u64>>>> {
+    if path == Path::new("-") {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+    let len = metadata.len();
+    let mut magic = [0; 2];
+    if len >= 2 {
+        file.read_exact(&mut magic)?;
+        if magic == GZIP_MAGIC {
+            return Ok(None);
+        }
+    }
+
+    let split_bytes = len.div_ceil(max_parts as u64).max(min_split_bytes);
+    let mut starts = (1..len.div_ceil(split_bytes))
+        .into_par_iter()
+        .map(|i| next_record_start(path, i * split_bytes))
+        .collect::<io::Result<Vec<u64>>>()?;
+    starts.insert(0, 0);
+    starts.push(len);
+    starts.dedup();
+    Ok(Some(starts.windows(2).map(|w| w[0]..w[1]).collect()))
+}
+
+// Returns whether `threads` threads counting the byte `ranges` each stay
+// busy: there is a range for every thread and none is larger than an even
+// share of the file per thread.
+fn ranges_balanced(ranges: &[Range<u64>], threads: usize) -> bool {
+    let len = ranges.last().map_or(0, |range| range.end);
+    let share = len.div_ceil(threads as u64);
+    ranges.len() >= threads && ranges.iter().all(|range| range.end - range.start <= share)
+}
+
+// Returns the offset of the first line starting with `>` at or after
+// `offset` (> 0), or the file length if there is none.
+fn next_record_start(path: &Path, offset: u64) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset - 1))?;
+    let mut reader = BufReader::new(file);
+    let mut pos = offset - 1;
+    loop {
+        // Moves to the start of the next line, so a header starting exactly
+        // at `offset` is found as well
+        let skipped = reader.skip_until(b'\n')?;
+        if skipped == 0 {
+            return Ok(pos);
+        }
+        pos += skipped as u64;
+        if reader.fill_buf()?.first() == Some(&b'>') {
+            return Ok(pos);
+        }
+    }
+}
+
+// Counts the canonical k-mers of the records in the byte `ranges` of a FASTA
+// file, each range opened and read by its own rayon job.
+fn count_ranges<C, F>(
+    path: &Path,
+    ranges: &[Range<u64>],
+    k: usize,
+    chunk_size: usize,
+    make_counter: &F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    let pool = CounterPool::new(make_counter);
+    ranges.par_iter().try_for_each(|range| {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let reader = BufReader::new(file.take(range.end - range.start));
+        let mut chunker = SequenceChunker::new(reader, chunk_size + k - 1, k - 1);
+        while let Some(chunk) = chunker.next_chunk()? {
+            pool.add_kmers(&chunk.bases, k);
+        }
+        Ok::<(), FastaError>(())
+    })?;
+    Ok(pool.into_merged())
+}
+
+// Counts the canonical k-mers of all sequences of FASTA data read from
+// `reader` with `count_chunks`, in chunks of `chunk_size` k-mers.
+fn count_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    chunk_size: usize,
+    queue_chunks: usize,
+    make_counter: &F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    let mut chunker = SequenceChunker::new(reader, chunk_size + k - 1, k - 1);
+    count_chunks(
+        || chunker.next_chunk(),
+        queue_chunks,
+        make_counter,
+        merge_counter,
+        |chunk, counter| add_canonical_kmers(&chunk.bases, k, counter),
+    )
+}
+
+// Counts the chunks returned by `next_chunk` with `for_each_chunk` into a
+// `CounterPool`. `count_chunk` adds the k-mers of a chunk to the counter of
+// the current thread and returns their number.
+fn count_chunks<N, T, F, V>(
+    next_chunk: N,
+    queue_chunks: usize,
+    make_counter: &F,
+    merge: fn(&mut T, &T),
+    count_chunk: V,
+) -> Result<(u64, T), FastaError>
+where
+    N: FnMut() -> Result<Option<Chunk>, FastaError> + Send,
+    T: Send,
+    F: Fn() -> T + Sync,
+    V: Fn(&Chunk, &mut T) -> u64 + Sync,
+{
+    let pool = CounterPool::with_merge(make_counter, merge);
+    for_each_chunk(next_chunk, queue_chunks, |chunk| {
+        pool.count(|counter| count_chunk(&chunk, counter));
+    })?;
+    Ok(pool.into_merged())
+}
+
+// Calls `visit` in parallel with the chunks returned by `next_chunk` until
+// it returns `None` or an error.
+//
+// A reader thread calls `next_chunk` and sends the chunks through a channel
+// of `queue_chunks` chunks (two per worker if 0) to one worker per thread of
+// the rayon pool. The reader blocks while the channel is full, so at most
+// the queued chunks and one chunk per worker are in memory, however long
+// the sequences are.
+fn for_each_chunk<N, V>(mut next_chunk: N, queue_chunks: usize, visit: V) -> Result<(), FastaError>
+where
+    N: FnMut() -> Result<Option<Chunk>, FastaError> + Send,
+    V: Fn(Chunk) + Sync,
+{
+    let workers = rayon::current_num_threads();
+    let queue_chunks = if queue_chunks == 0 {
+        2 * workers
+    } else {
+        queue_chunks
+    };
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = crossbeam_channel::bounded(queue_chunks);
+        // Not a rayon task, so the reader never waits for a busy pool
+        let producer = scope.spawn(move || {
+            while let Some(chunk) = next_chunk()? {
+                sender
+                    .send(chunk)
+                    .expect("workers run until the channel is closed");
+            }
+            Ok::<(), FastaError>(())
+        });
+
+        (0..workers).into_par_iter().for_each(|_| {
+            for chunk in receiver.iter() {
+                visit(chunk);
+            }
+        });
+        producer
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkerState {
+    // Before the first record, at the start of a line
+    Start,
+    LineStart,
+    Header,
+    Sequence,
+}
+
+// A chunk of sequence counted by `for_each_chunk`, e.g. cut from a record by
+// `SequenceChunker` or a batch of FASTQ reads.
+struct Chunk {
+    bases: Vec<u8>,
+    // Number of leading bases that were part of the previous chunk of the
+    // record, so k-mers ending in them were already counted
+    overlap: usize,
+    // Index of the record in the input
+    record: usize,
+}
+
+// Cuts the sequences of FASTA data into chunks of at most `chunk_len` bases,
+// consecutive chunks of a record overlapping by `overlap` bases, so every
+// k-mer of length `overlap + 1` lies in exactly one chunk. The data is read
+// in blocks instead of lines, so memory stays bounded even for chromosomes
+// written on a single line.
+struct SequenceChunker<R> {
+    reader: R,
+    chunk_len: usize,
+    overlap: usize,
+    chunk: Vec<u8>,
+    // Number of bases of `chunk` that were not part of the previous chunk
+    fresh: usize,
+    // Whether `chunk` starts with the end of the previous chunk
+    continued: bool,
+    // Number of records started so far
+    records: usize,
+    // Number of bases of the current record, for error messages
+    record_bases: usize,
+    // Number of lines read so far, for error messages
+    line_no: usize,
+    state: ChunkerState,
+}
+
+impl<R: BufRead> SequenceChunker<R> {
+    fn new(reader: R, chunk_len: usize, overlap: usize) -> Self {
+        SequenceChunker {
+            reader,
+            chunk_len,
+            overlap,
+            chunk: Vec::with_capacity(chunk_len),
+            fresh: 0,
+            continued: false,
+            records: 0,
+            record_bases: 0,
+            line_no: 0,
+            state: ChunkerState::Start,
+        }
+    }
+
+    // Returns the next chunk, `None` at the end of the data.
+    fn next_chunk(&mut self) -> Result<Option<Chunk>, FastaError> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(self.end_record());
+            }
+            match self.state {
+                ChunkerState::Start | ChunkerState::LineStart if buf[0] == b'>' => {
+                    self.reader.consume(1);
+                    self.state = ChunkerState::Header;
+                    let last = self.end_record();
+                    self.record_bases = 0;
+                    self.records += 1;
+                    if last.is_some() {
+                        return Ok(last);
+                    }
+                }
+                ChunkerState::Start => {
+                    return Err(FastaError::MalformedHeader {
+                        line_no: self.line_no + 1,
+                    });
+                }
+                ChunkerState::Header => {
+                    let (len, eol) = line_end(buf);
+                    self.reader.consume(len);
+                    if eol {
+                        self.line_no += 1;
+                        self.state = ChunkerState::LineStart;
+                    }
+                }
+                ChunkerState::LineStart | ChunkerState::Sequence => {
+                    let (len, eol) = line_end(buf);
+                    let line_len = if eol { len - 1 } else { len };
+                    let take = line_len.min(self.chunk_len - self.chunk.len());
+                    let start = self.chunk.len();
+                    self.chunk.extend(
+                        buf[..take]
+                            .iter()
+                            .filter(|&&byte| !byte.is_ascii_whitespace()),
+                    );
+                    validate_sequence(&self.chunk[start..], self.record_bases)?;
+                    let added = self.chunk.len() - start;
+                    self.record_bases += added;
+                    self.fresh += added;
+
+                    if take == line_len && eol {
+                        self.reader.consume(len);
+                        self.line_no += 1;
+                        self.state = ChunkerState::LineStart;
+                    } else {
+                        self.reader.consume(take);
+                        self.state = ChunkerState::Sequence;
+                    }
+                    if self.chunk.len() == self.chunk_len {
+                        return Ok(Some(self.split_chunk()));
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns the full chunk and keeps its last `overlap` bases as the start
+    // of the next one.
+    fn split_chunk(&mut self) -> Chunk {
+        let mut next = Vec::with_capacity(self.chunk_len);
+        next.extend_from_slice(&self.chunk[self.chunk.len() - self.overlap..]);
+        self.fresh = 0;
+        let bases = std::mem::replace(&mut self.chunk, next);
+        self.take_chunk(bases, true)
+    }
+
+    // Returns the last chunk of the current record if it has new bases.
+    fn end_record(&mut self) -> Option<Chunk> {
+        let fresh = std::mem::take(&mut self.fresh);
+        if fresh == 0 {
+            self.chunk.clear();
+            self.continued = false;
+            return None;
+        }
+        let bases = std::mem::replace(&mut self.chunk, Vec::with_capacity(self.chunk_len));
+        Some(self.take_chunk(bases, false))
+    }
+
+    // Wraps the bases of a chunk, of which the next chunk is a continuation
+    // if `continued`.
+    fn take_chunk(&mut self, bases: Vec<u8>, continued: bool) -> Chunk {
+        let overlap = if self.continued { self.overlap } else { 0 };
+        self.continued = continued;
+        Chunk {
+            bases,
+            overlap,
+            record: self.records - 1,
+        }
+    }
+}
+
+// Length of the line at the start of `buf` including its newline, and
+// whether the newline is in `buf`.
+fn line_end(buf: &[u8]) -> (usize, bool) {
+    match buf.iter().position(|&byte| byte == b'\n') {
+        Some(i) => (i + 1, true),
+        None => (buf.len(), false),
+    }
+}
+
+// Counts the canonical k-mers of the sequences of an iterator with
+// `count_chunks`, in chunks of `chunk_size` k-mers. Chunks that start after
+// `cancel` was cancelled are skipped.
+fn count_sequences<I, C, F>(
+    sequences: I,
+    k: usize,
+    chunk_size: usize,
+    make_counter: &F,
+    cancel: Option<&CancelToken>,
+) -> Result<(u64, C), FastaError>
+where
+    I: Iterator<Item = Result<Vec<u8>, FastaError>> + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    count_chunks(
+        sequence_chunks(sequences, chunk_size + k - 1, k - 1),
+        0,
+        make_counter,
+        merge_counter,
+        |chunk, counter| {
+            if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+                return 0;
+            }
+            add_canonical_kmers(&chunk.bases, k, counter)
+        },
+    )
+}
+
+// Cuts sequences that were read whole, e.g. with their records, into chunks
+// like `SequenceChunker`. Returns the `next_chunk` of `for_each_chunk`.
+fn sequence_chunks<I>(
+    mut sequences: I,
+    chunk_len: usize,
+    overlap: usize,
+) -> impl FnMut() -> Result<Option<Chunk>, FastaError>
+where
+    I: Iterator<Item = Result<Vec<u8>, FastaError>>,
+{
+    // The current sequence and the start of its next chunk
+    let mut current: Option<(Vec<u8>, usize)> = None;
+    let mut records = 0;
+    move || loop {
+        if let Some((seq, start)) = &mut current {
+            let end = std::cmp::min(*start + chunk_len, seq.len());
+            let chunk = Chunk {
+                bases: seq[*start..end].to_vec(),
+                overlap: if *start == 0 { 0 } else { overlap },
+                record: records - 1,
+            };
+            if end == seq.len() {
+                current = None;
+            } else {
+                *start = end - overlap;
+            }
+            return Ok(Some(chunk));
+        }
+        match sequences.next().transpose()? {
+            Some(seq) => {
+                records += 1;
+                if !seq.is_empty() {
+                    current = Some((seq, 0));
+                }
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Like [`run_parallel_fasta_analysis_with_k`], but reads the FASTA data
+/// from `reader` instead of a file, e.g. standard input or a network stream.
+///
+/// Compressed input is not detected; wrap the reader with
+/// [`open_sequence_reader`](crate::fasta::open_sequence_reader) for that.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::fasta::open_sequence_reader;
+/// use hll_rust::parallel_counting::run_parallel_fasta_analysis_from_reader;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let reader = open_sequence_reader(std::io::stdin())?;
+/// let (_, hll) = run_parallel_fasta_analysis_from_reader(reader, 31, || {
+///     HLLCounter::<Xxh64Builder>::new(16)
+/// })?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_fasta_analysis_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    count_reader(reader, k, CHUNK_SIZE, 0, &make_counter)
+}
+
+/// Like [`run_parallel_fasta_analysis_from_reader`], but reads the FASTA data
+/// asynchronously, e.g. from an upload to a tokio web service.
+///
+/// The records are read on the runtime, and the k-mers of every record are
+/// counted with `spawn_blocking` on rayon while the next record is read, so
+/// the runtime is never blocked by hashing.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`, or if called outside of a
+/// tokio runtime.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::run_parallel_fasta_analysis_async;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// # async fn sketch(upload: &[u8]) -> Result<(), hll_rust::fasta::FastaError> {
+/// let (_, hll) =
+///     run_parallel_fasta_analysis_async(upload, 31, || HLLCounter::<Xxh64Builder>::new(16))
+///         .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub async fn run_parallel_fasta_analysis_async<R, C, F>(
+    reader: R,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+    C: MergeableCounter + Send + 'static,
+    F: Fn() -> C + Send + Sync + 'static,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+
+    let make_counter = std::sync::Arc::new(make_counter);
+    let mut fasta_reader = crate::async_fasta::AsyncFastaReader::new(reader);
+    let mut total = (0, make_counter());
+    // Counting of the previous record, running while the next one is read
+    let mut pending: Option<tokio::task::JoinHandle<(u64, C)>> = None;
+
+    while fasta_reader.next_record().await? {
+        let seq = fasta_reader.read_sequence().await?;
+        if let Some(task) = pending.take() {
+            let (kmers_seen, counter) = task.await.map_err(io::Error::other)?;
+            total.0 += kmers_seen;
+            merge_counter(&mut total.1, &counter);
+        }
+        let make_counter = make_counter.clone();
+        pending = Some(tokio::task::spawn_blocking(move || {
+            count_sequence(&seq, k, CHUNK_SIZE, &*make_counter)
+        }));
+    }
+    if let Some(task) = pending {
+        let (kmers_seen, counter) = task.await.map_err(io::Error::other)?;
+        total.0 += kmers_seen;
+        merge_counter(&mut total.1, &counter);
+    }
+    Ok(total)
+}
+
+/// Counts the canonical k-mers of the reads of a FASTQ file in parallel,
+/// ignoring bases below the Phred quality `min_quality`.
+///
+/// Gzip/bgzip compressed files are decompressed transparently. Low-quality
+/// bases are trimmed from the 3' end of every read, and those left inside
+/// the read are masked, so no counted k-mer contains a base below
+/// `min_quality`: the k-mers match those of
+/// [`FastqReader::with_min_quality`]. Reads are counted in batches of several
+/// thousand into one counter per thread, which are merged as by
+/// [`run_parallel_fasta_analysis`].
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::run_parallel_fastq_analysis;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, hll) =
+///     run_parallel_fastq_analysis("reads.fq.gz", 31, 20, || HLLCounter::<Xxh64Builder>::new(16))?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_fastq_analysis<C, F>(
+    path: &str,
+    k: usize,
+    min_quality: u8,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_fastq_analysis_from_reader(open_sequence_file(path)?, k, min_quality, make_counter)
+}
+
+/// Like [`run_parallel_fastq_analysis`], but reads the FASTQ data from
+/// `reader` instead of a file.
+///
+/// Compressed input is not detected; wrap the reader with
+/// [`open_sequence_reader`](crate::fasta::open_sequence_reader) for that.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_fastq_analysis_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    min_quality: u8,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    let mut fastq_reader = FastqReader::new(reader);
+    let batches = fastq_batches(move |batch| {
+        if !fastq_reader.next_record()? {
+            return Ok(false);
+        }
+        mask_low_quality(
+            &fastq_reader.sequence,
+            &fastq_reader.quality,
+            min_quality,
+            batch,
+        );
+        Ok(true)
+    });
+    count_fastq_batches(batches, k, &make_counter)
+}
+
+/// Counts the canonical k-mers of the read pairs of paired-end FASTQ files
+/// `r1_path` and `r2_path` into a single sketch of the library.
+///
+/// The files are read in lockstep, and the mates of every pair must have
+/// the same name, apart from a `/1` or `/2` suffix. Bases are filtered by
+/// quality as by [`run_parallel_fastq_analysis`]. With `merge_overlaps`,
+/// mates whose ends overlap exactly by at least [`MIN_MATE_OVERLAP`] bases
+/// are merged into their fragment first, so the k-mers of the overlap are
+/// seen once per pair instead of twice; the distinct k-mers are the same
+/// either way.
+///
+/// Fails with [`FastaError::MismatchedMates`] if the mates of a pair have
+/// different names or one file has more reads than the other.
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::run_parallel_paired_fastq_analysis;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, library) = run_parallel_paired_fastq_analysis(
+///     "lib_R1.fq.gz",
+///     "lib_R2.fq.gz",
+///     31,
+///     20,
+///     true,
+///     || HLLCounter::<Xxh64Builder>::new(16),
+/// )?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_paired_fastq_analysis<C, F>(
+    r1_path: &str,
+    r2_path: &str,
+    k: usize,
+    min_quality: u8,
+    merge_overlaps: bool,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_paired_fastq_analysis_from_readers(
+        open_sequence_file(r1_path)?,
+        open_sequence_file(r2_path)?,
+        k,
+        min_quality,
+        merge_overlaps,
+        make_counter,
+    )
+}
+
+/// Like [`run_parallel_paired_fastq_analysis`], but reads the mates from
+/// `r1` and `r2` instead of files.
+///
+/// Compressed input is not detected; wrap the readers with
+/// [`open_sequence_reader`](crate::fasta::open_sequence_reader) for that.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_paired_fastq_analysis_from_readers<R1, R2, C, F>(
+    r1: R1,
+    r2: R2,
+    k: usize,
+    min_quality: u8,
+    merge_overlaps: bool,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R1: BufRead + Send,
+    R2: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    let mut mates = (FastqReader::new(r1), FastqReader::new(r2));
+    let mut pair = 0;
+    let batches = fastq_batches(move |batch| {
+        let found = (mates.0.next_record()?, mates.1.next_record()?);
+        pair += 1;
+        let (r1, r2) = (&mates.0, &mates.1);
+        match found {
+            (false, false) => return Ok(false),
+            (true, true) if mate_name(&r1.id) == mate_name(&r2.id) => {}
+            _ => return Err(FastaError::MismatchedMates { pair }),
+        }
+
+        if merge_overlaps {
+            let r2_rc = reverse_complement(&r2.sequence);
+            if let Some(overlap) = mate_overlap(&r1.sequence, &r2_rc) {
+                let mut sequence = r1.sequence.clone();
+                sequence.extend_from_slice(&r2_rc[overlap..]);
+                let mut quality = r1.quality.clone();
+                quality.extend(r2.quality.iter().rev().skip(overlap));
+                mask_low_quality(&sequence, &quality, min_quality, batch);
+                return Ok(true);
+            }
+        }
+        mask_low_quality(&r1.sequence, &r1.quality, min_quality, batch);
+        mask_low_quality(&r2.sequence, &r2.quality, min_quality, batch);
+        Ok(true)
+    });
+    count_fastq_batches(batches, k, &make_counter)
+}
+
+/// Counts the canonical k-mers of the reads of a SAM or BAM file in
+/// parallel, like the reads of a FASTQ file.
+///
+/// Records skipped by `filter`, by default secondary and supplementary
+/// alignments, are not counted, so every read is seen once. Reads are
+/// counted in batches as by [`run_parallel_fastq_analysis`], without a
+/// quality filter.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::alignment::AlignmentFilter;
+/// use hll_rust::parallel_counting::run_parallel_alignment_analysis;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, hll) = run_parallel_alignment_analysis(
+///     "sample.bam",
+///     31,
+///     AlignmentFilter::default(),
+///     || HLLCounter::<Xxh64Builder>::new(16),
+/// )?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+#[cfg(feature = "bam")]
+pub fn run_parallel_alignment_analysis<C, F>(
+    path: &str,
+    k: usize,
+    filter: AlignmentFilter,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_alignment_analysis_from_reader(open_sequence_file(path)?, k, filter, make_counter)
+}
+
+/// Like [`run_parallel_alignment_analysis`], but reads the SAM or BAM data
+/// from `reader` instead of a file.
+///
+/// Compressed input is not detected; wrap the reader with
+/// [`open_sequence_reader`](crate::fasta::open_sequence_reader) for BAM data.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+#[cfg(feature = "bam")]
+pub fn run_parallel_alignment_analysis_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    filter: AlignmentFilter,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    let mut alignment_reader = AlignmentReader::new(reader)?.with_filter(filter);
+    let batches = fastq_batches(move |batch| {
+        if !alignment_reader.next_record()? {
+            return Ok(false);
+        }
+        batch.extend_from_slice(&alignment_reader.sequence);
+        batch.push(b'N');
+        Ok(true)
+    });
+    count_fastq_batches(batches, k, &make_counter)
+}
+
+/// Smallest overlap, in bases, of mates merged by
+/// [`run_parallel_paired_fastq_analysis`].
+pub const MIN_MATE_OVERLAP: usize = 20;
+
+// The name of a mate: its ID up to the first whitespace, without a `/1` or
+// `/2` suffix.
+fn mate_name(id: &Option<Vec<u8>>) -> &[u8] {
+    let id = id.as_deref().unwrap_or_default();
+    let name = id.split(u8::is_ascii_whitespace).next().unwrap_or(id);
+    name.strip_suffix(b"/1")
+        .or_else(|| name.strip_suffix(b"/2"))
+        .unwrap_or(name)
+}
+
+// Length of the longest overlap of at least `MIN_MATE_OVERLAP` bases of the
+// end of `r1` with the start of the reverse complement of its mate.
+fn mate_overlap(r1: &[u8], r2_rc: &[u8]) -> Option<usize> {
+    (MIN_MATE_OVERLAP..=r1.len().min(r2_rc.len()))
+        .rev()
+        .find(|&len| r1[r1.len() - len..].eq_ignore_ascii_case(&r2_rc[..len]))
+}
+
+// Counts batches of reads as returned by `fastq_batches` with
+// `count_chunks`.
+fn count_fastq_batches<I, C, F>(
+    batches: I,
+    k: usize,
+    make_counter: &F,
+) -> Result<(u64, C), FastaError>
+where
+    I: Iterator<Item = Result<Vec<u8>, FastaError>> + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    let mut batches = batches.map(|res| {
+        res.map(|bases| Chunk {
+            bases,
+            overlap: 0,
+            record: 0,
+        })
+    });
+    count_chunks(
+        // Stops at the first read error instead of counting the rest
+        || batches.next().transpose(),
+        0,
+        make_counter,
+        merge_counter,
+        |batch, counter| add_canonical_kmers(&batch.bases, k, counter),
+    )
+}
+
+// Iterates over batches of reads, each batch filled by up to
+// `READ_BATCH_SIZE` calls of `add_read`, which returns `false` once all
+// reads were added. Stops after the first error.
+fn fastq_batches<F>(mut add_read: F) -> impl Iterator<Item = Result<Vec<u8>, FastaError>>
+where
+    F: FnMut(&mut Vec<u8>) -> Result<bool, FastaError>,
+{
+    let mut finished = false;
+
+    std::iter::from_fn(move || {
+        if finished {
+            return None;
+        }
+        let mut batch = Vec::new();
+        for _ in 0..READ_BATCH_SIZE {
+            match add_read(&mut batch) {
+                Ok(true) => {}
+                Ok(false) => {
+                    finished = true;
+                    break;
+                }
+                Err(e) => {
+                    finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        (!batch.is_empty()).then_some(Ok(batch))
+    })
+}
+
+// Appends a read to `batch` with its low-quality 3' end trimmed and the
+// other bases below `min_quality` replaced by `N`, followed by an `N`, so no
+// k-mer spans two reads.
+fn mask_low_quality(sequence: &[u8], quality: &[u8], min_quality: u8, batch: &mut Vec<u8>) {
+    let is_low = |q: u8| q.saturating_sub(PHRED_OFFSET) < min_quality;
+    let end = quality
+        .iter()
+        .rposition(|&q| !is_low(q))
+        .map_or(0, |i| i + 1);
+    batch.extend(
+        sequence[..end]
+            .iter()
+            .zip(quality)
+            .map(|(&base, &q)| if is_low(q) { b'N' } else { base }),
+    );
+    batch.push(b'N');
+}
+
+/// The k-mers of several FASTA files, see [`run_parallel_dataset_analysis`].
+#[derive(Debug, Clone)]
+pub struct DatasetResult<C> {
+    /// Number of k-mers seen and counter of every file, in the order of the
+    /// paths.
+    pub files: Vec<(u64, C)>,
+    /// Number of k-mers seen and merged counter of all files.
+    pub combined: (u64, C),
+}
+
+/// Settings of [`run_parallel_fasta_analysis_with_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelConfig {
+    /// Number of worker threads, or 0 for one per core.
+    pub threads: usize,
+    /// Number of k-mers, i.e. roughly bytes of sequence, per chunk when
+    /// splitting long sequences.
+    pub chunk_bytes: usize,
+    /// Number of chunks read ahead of the workers, or 0 for two per thread.
+    /// Together with `chunk_bytes` and `threads`, this bounds the memory
+    /// used for sequence data while a file is streamed: compressed files,
+    /// standard input and files of few or uneven records. Files of many
+    /// similar records are instead split into byte ranges read by the
+    /// workers themselves, each holding one chunk at a time.
+    pub queue_chunks: usize,
+    /// Size passed to the `make_counter` of
+    /// [`run_parallel_fasta_analysis_with_config`], e.g. the precision of
+    /// HyperLogLog counters.
+    pub counter_precision: usize,
+    /// k-mer length.
+    pub k: usize,
+}
+
+impl Default for ParallelConfig {
+    fn default() -> Self {
+        ParallelConfig {
+            threads: 0,
+            chunk_bytes: CHUNK_SIZE,
+            queue_chunks: 0,
+            counter_precision: 16,
+            k: K_MER_LENGTH,
+        }
+    }
+}
+
+/// Like [`run_parallel_fasta_analysis`], but runs on a dedicated thread pool
+/// of `config.threads` threads instead of the global one, so applications
+/// such as servers can bound the CPU used by an analysis.
+///
+/// The counters are created by calling `make_counter` with
+/// `config.counter_precision`.
+///
+/// Panics if `config.k` is not in `1..=MAX_K_MER_LENGTH` or
+/// `config.chunk_bytes` is 0, and, like [`run_parallel_fasta_analysis`], if
+/// the counters cannot be merged, e.g. because they hash with `RandomState`.
+///
+/// ```no_run
+/// use hll_rust::{Counter, HLLCounter};
+/// use hll_rust::parallel_counting::{ParallelConfig, run_parallel_fasta_analysis_with_config};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let config = ParallelConfig { threads: 4, counter_precision: 14, ..Default::default() };
+/// let (_, hll) =
+///     run_parallel_fasta_analysis_with_config("genome.fa", &config, HLLCounter::<Xxh64Builder>::new)?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_fasta_analysis_with_config<C, F>(
+    path: &str,
+    config: &ParallelConfig,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn(usize) -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&config.k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    assert!(config.chunk_bytes > 0, "chunk size must be positive");
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.threads)
+        .build()
+        .map_err(io::Error::other)?;
+    pool.install(|| {
+        count_file(
+            path,
+            config.k,
+            config.chunk_bytes,
+            config.queue_chunks,
+            &|| make_counter(config.counter_precision),
+        )
+    })
+}
+
+/// Counts the canonical k-mers of several FASTA files concurrently.
+///
+/// All files share the rayon pool, so many small genomes keep every core
+/// busy instead of being processed one after another.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_dataset_analysis<C, F>(
+    paths: &[&Path],
+    k: usize,
+    make_counter: F,
+) -> Result<DatasetResult<C>, FastaError>
+where
+    C: MergeableCounter + Send + Sync,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+
+    let files: Vec<(u64, C)> = paths
+        .par_iter()
+        .map(|path| count_file(path, k, CHUNK_SIZE, 0, &make_counter))
+        .collect::<Result<_, FastaError>>()?;
+
+    let counters: Vec<&C> = files.iter().map(|(_, counter)| counter).collect();
+    let combined = merge_counter_tree(&counters, &make_counter);
+    let total = files.iter().map(|(kmers, _)| kmers).sum();
+    Ok(DatasetResult {
+        files,
+        combined: (total, combined),
+    })
+}
+
+/// The k-mers of one record of a FASTA file, see
+/// [`run_parallel_fasta_analysis_per_record`].
+#[derive(Debug, Clone)]
+pub struct RecordResult<C> {
+    /// Record ID, the header line without the leading `>`.
+    pub id: String,
+    /// Number of bases.
+    pub length: usize,
+    /// Number of k-mers seen.
+    pub kmers: u64,
+    /// Base composition of the record, e.g. to normalize the k-mer
+    /// complexity by [`RecordStats::effective_length`].
+    pub stats: RecordStats,
+    /// Counter of the record's k-mers.
+    pub hll: C,
+}
 
-            for &byte in seq.iter() {
+/// Like [`run_parallel_fasta_analysis_with_k`], but also returns a counter
+/// for every record, e.g. to compare the k-mer complexity of chromosomes or
+/// to spot outlier contigs.
+///
+/// Returns the total number of k-mers, the merged counter of all records and
+/// the per-record results in file order.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_fasta_analysis_per_record<C, F>(
+    path: &str,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C, Vec<RecordResult<C>>), FastaError>
+where
+    C: MergeableCounter + Send + Sync,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_fasta_analysis_per_record_from_reader(open_sequence_file(path)?, k, make_counter)
+}
+
+/// Like [`run_parallel_fasta_analysis_per_record`], but reads the FASTA data
+/// from `reader`, see [`run_parallel_fasta_analysis_from_reader`].
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_fasta_analysis_per_record_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C, Vec<RecordResult<C>>), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send + Sync,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+
+    // The chunks of every record are counted into their own counters, which
+    // are merged into the counter of the record
+    let mut records = Vec::new();
+    let sequences = read_records(reader).map(|res| {
+        res.map(|(id, seq, stats)| {
+            records.push((id, seq.len(), stats));
+            seq
+        })
+    });
+    let counters = Mutex::new(HashMap::new());
+    for_each_chunk(
+        sequence_chunks(sequences, CHUNK_SIZE + k - 1, k - 1),
+        0,
+        |chunk| {
+            let mut counter = make_counter();
+            let kmers = add_canonical_kmers(&chunk.bases, k, &mut counter);
+            let mut counters = counters.lock().unwrap_or_else(|e| e.into_inner());
+            match counters.entry(chunk.record) {
+                Entry::Vacant(entry) => {
+                    entry.insert((kmers, counter));
+                }
+                Entry::Occupied(mut entry) => {
+                    let (record_kmers, record_counter) = entry.get_mut();
+                    *record_kmers += kmers;
+                    merge_counter(record_counter, &counter);
+                }
+            }
+        },
+    )?;
+
+    let mut counters = counters.into_inner().unwrap_or_else(|e| e.into_inner());
+    let records: Vec<RecordResult<C>> = records
+        .into_iter()
+        .enumerate()
+        .map(|(index, (id, length, stats))| {
+            let (kmers, hll) = counters
+                .remove(&index)
+                .unwrap_or_else(|| (0, make_counter()));
+            RecordResult {
+                id: String::from_utf8_lossy(&id).into_owned(),
+                length,
+                kmers,
+                stats,
+                hll,
+            }
+        })
+        .collect();
+
+    let counters: Vec<&C> = records.iter().map(|record| &record.hll).collect();
+    let merged = merge_counter_tree(&counters, &make_counter);
+    let total = records.iter().map(|record| record.kmers).sum();
+    Ok((total, merged, records))
+}
+
+/// A snapshot of a running analysis, passed to the progress callback of
+/// [`run_parallel_fasta_analysis_with_progress`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of k-mers counted so far.
+    pub kmers_seen: u64,
+    /// Cardinality estimate of the k-mers counted so far.
+    pub estimate: f64,
+    /// Number of (decompressed) bytes read from the file so far.
+    pub bytes_read: u64,
+}
+
+/// Like [`run_parallel_fasta_analysis_with_k`], but calls `progress` whenever
+/// another `interval` k-mers have been counted.
+///
+/// Every thread counts into its own counter, and the number of k-mers seen
+/// is kept in an atomic. Only when a report is due are the counters merged
+/// into a copy for the estimate, so the workers never wait on each other
+/// while counting. Reports are checked once per chunk of [`CHUNK_SIZE`]
+/// k-mers and are never concurrent; a report that falls due while another
+/// is being made is folded into it or the next one.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::run_parallel_fasta_analysis_with_progress;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (total, hll) = run_parallel_fasta_analysis_with_progress(
+///     "genome.fa",
+///     31,
+///     || HLLCounter::<Xxh64Builder>::new(14),
+///     10_000_000,
+///     |p| eprintln!("{} k-mers, ~{:.0} distinct, {} bytes", p.kmers_seen, p.estimate, p.bytes_read),
+/// )?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH` or `interval` is 0.
+pub fn run_parallel_fasta_analysis_with_progress<C, F, P>(
+    path: &str,
+    k: usize,
+    make_counter: F,
+    interval: u64,
+    progress: P,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+    P: Fn(Progress) + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    assert!(interval > 0, "progress interval must be positive");
+
+    let bytes_read = AtomicU64::new(0);
+    let reader = CountingReader {
+        inner: open_sequence_file(path)?,
+        bytes_read: &bytes_read,
+    };
+    let pool = CounterPool::new(&make_counter);
+    let total = AtomicU64::new(0);
+    // K-mers at the next report, held while reporting
+    let next_report = Mutex::new(interval);
+
+    let mut chunker = SequenceChunker::new(reader, CHUNK_SIZE + k - 1, k - 1);
+    for_each_chunk(
+        || chunker.next_chunk(),
+        0,
+        |chunk| {
+            let kmers_seen = pool.add_kmers(&chunk.bases, k);
+            let seen = total.fetch_add(kmers_seen, Ordering::AcqRel) + kmers_seen;
+            // Another thread reporting covers this chunk or leaves it to
+            // the next report
+            let Ok(mut next_report) = next_report.try_lock() else {
+                return;
+            };
+            let seen = seen.max(total.load(Ordering::Acquire));
+            if seen >= *next_report {
+                *next_report = (seen / interval + 1) * interval;
+                progress(Progress {
+                    kmers_seen: seen,
+                    estimate: pool.merged().estimate(),
+                    bytes_read: bytes_read.load(Ordering::Relaxed),
+                });
+            }
+        },
+    )?;
+
+    // A report skipped while another one was made
+    let (seen, counter) = pool.into_merged();
+    if seen >= next_report.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        progress(Progress {
+            kmers_seen: seen,
+            estimate: counter.estimate(),
+            bytes_read: bytes_read.load(Ordering::Relaxed),
+        });
+    }
+    Ok((seen, counter))
+}
+
+/// A flag to stop a running analysis from another thread, e.g. from the
+/// cancel button of a GUI or when a client of a service disconnects.
+///
+/// Clones share the same flag, and an existing `Arc<AtomicBool>` can be
+/// turned into a token.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the analyses using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        CancelToken(flag)
+    }
+}
+
+/// The k-mers counted by [`run_parallel_fasta_analysis_cancellable`].
+#[derive(Debug, Clone)]
+pub struct CancellableResult<C> {
+    /// Number of k-mers seen.
+    pub kmers_seen: u64,
+    /// Counter of the k-mers seen.
+    pub counter: C,
+    /// Whether the analysis was cancelled, so only part of the file was
+    /// counted.
+    pub cancelled: bool,
+}
+
+/// Like [`run_parallel_fasta_analysis_with_k`], but stops early once
+/// `cancel` is cancelled and returns the k-mers counted until then.
+///
+/// Workers check the token before every chunk of [`CHUNK_SIZE`] k-mers and
+/// no further records are read once it is cancelled, so chunks that are
+/// already being counted finish first. The chunks are counted into one
+/// counter per thread as by [`run_parallel_fasta_analysis`], so a cancelled
+/// run still merges only a counter per thread.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::{CancelToken, run_parallel_fasta_analysis_cancellable};
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let cancel = CancelToken::new();
+/// let timeout = cancel.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+///     timeout.cancel();
+/// });
+/// let result = run_parallel_fasta_analysis_cancellable(
+///     "genome.fa",
+///     31,
+///     || HLLCounter::<Xxh64Builder>::new(14),
+///     &cancel,
+/// )?;
+/// if result.cancelled {
+///     eprintln!("Stopped after {} k-mers", result.kmers_seen);
+/// }
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_fasta_analysis_cancellable<C, F>(
+    path: &str,
+    k: usize,
+    make_counter: F,
+    cancel: &CancelToken,
+) -> Result<CancellableResult<C>, FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    let sequences = fasta_sequences(path)?.take_while(|_| !cancel.is_cancelled());
+    let (kmers_seen, counter) =
+        count_sequences(sequences, k, CHUNK_SIZE, &make_counter, Some(cancel))?;
+    Ok(CancellableResult {
+        kmers_seen,
+        counter,
+        cancelled: cancel.is_cancelled(),
+    })
+}
+
+/// Where and how often [`run_parallel_fasta_analysis_with_checkpoints`]
+/// saves the state of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointConfig {
+    /// The resume file, replaced atomically at every checkpoint.
+    pub path: PathBuf,
+    /// Number of FASTA records counted between two checkpoints.
+    pub interval: usize,
+}
+
+// Identifies a checkpoint file, followed by its version
+const CHECKPOINT_MAGIC: &[u8; 4] = b"HLLK";
+const CHECKPOINT_VERSION: u8 = 2;
+
+// The state of a run after its first `records` records
+struct Checkpoint<S> {
+    records: u64,
+    // Offset of the next record in the decompressed data
+    offset: u64,
+    kmers_seen: u64,
+    // Length of the input file, 0 for standard input
+    input_len: u64,
+    // Hash of the first `hashed_len` bytes of the decompressed data: the
+    // records counted and the header of the next one, read ahead
+    hashed_len: u64,
+    prefix_hash: u64,
+    counter: HLLCounter<S>,
+}
+
+impl<S: BuildHasher + Default> Checkpoint<S> {
+    // Writes the checkpoint to a temporary file renamed to `path`, so an
+    // interruption never leaves a partial checkpoint behind.
+    fn save(&self, path: &Path, k: usize) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut writer = io::BufWriter::new(File::create(&tmp)?);
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        writer.write_all(&[CHECKPOINT_VERSION, k as u8])?;
+        for value in [
+            self.records,
+            self.offset,
+            self.kmers_seen,
+            self.input_len,
+            self.hashed_len,
+            self.prefix_hash,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        self.counter.write_to(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+
+    // Reads the checkpoint at `path` with the hasher of the run, `None` if
+    // there is none.
+    fn load(path: &Path, k: usize, hasher: S) -> io::Result<Option<Self>> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut reader = match File::open(path) {
+            Ok(file) => io::BufReader::new(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != CHECKPOINT_MAGIC || header[4] != CHECKPOINT_VERSION {
+            return Err(invalid("Not a checkpoint file."));
+        }
+        if header[5] as usize != k {
+            return Err(invalid("Checkpoint of a run with another k-mer length."));
+        }
+        let mut values = [0u64; 6];
+        for value in &mut values {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            *value = u64::from_le_bytes(bytes);
+        }
+        let [
+            records,
+            offset,
+            kmers_seen,
+            input_len,
+            hashed_len,
+            prefix_hash,
+        ] = values;
+        if hashed_len < offset {
+            return Err(invalid("Not a checkpoint file."));
+        }
+        Ok(Some(Checkpoint {
+            records,
+            offset,
+            kmers_seen,
+            input_len,
+            hashed_len,
+            prefix_hash,
+            counter: HLLCounter::read_from_with_hasher(reader, hasher)?,
+        }))
+    }
+}
+
+/// Like [`run_parallel_fasta_analysis_with_k`], but saves the merged sketch
+/// to a resume file every `checkpoint.interval` records, so an interrupted
+/// run continues where its last checkpoint left off instead of restarting.
+///
+/// A run resumes from `checkpoint.path` if that file exists, skipping the
+/// records counted before; compressed files are decompressed up to that
+/// point. The file is removed once the run completes. Records are counted in
+/// parallel within every interval, so intervals of a few records suit
+/// genomes and larger ones suit many short sequences.
+///
+/// The checkpoint must come from a run over the same file with the same k
+/// and a counter that `make_counter` can merge with, which rules out
+/// `RandomState`. It records the length of the file and a hash of the data
+/// counted before, so a file that was replaced or changed in the part
+/// already counted is not resumed. Fails with an
+/// [`io::ErrorKind::InvalidData`] error for other checkpoints.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH` or the interval is 0.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::{CheckpointConfig, run_parallel_fasta_analysis_with_checkpoints};
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let checkpoint = CheckpointConfig {
+///     path: "reads.fa.resume".into(),
+///     interval: 100_000,
+/// };
+/// let (_, hll) = run_parallel_fasta_analysis_with_checkpoints(
+///     "reads.fa.gz",
+///     31,
+///     || HLLCounter::<Xxh64Builder>::new(16),
+///     &checkpoint,
+/// )?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_fasta_analysis_with_checkpoints<S, F>(
+    path: &str,
+    k: usize,
+    make_counter: F,
+    checkpoint: &CheckpointConfig,
+) -> Result<(u64, HLLCounter<S>), FastaError>
+where
+    S: BuildHasher + Default + Send,
+    F: Fn() -> HLLCounter<S> + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    assert!(
+        checkpoint.interval > 0,
+        "checkpoint interval must be positive"
+    );
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let input_len = if path == "-" {
+        0
+    } else {
+        std::fs::metadata(path)?.len()
+    };
+
+    let mut state = Checkpoint {
+        records: 0,
+        offset: 0,
+        kmers_seen: 0,
+        input_len,
+        hashed_len: 0,
+        prefix_hash: Xxh64::new(0).digest(),
+        counter: make_counter(),
+    };
+    if let Some(saved) = Checkpoint::load(&checkpoint.path, k, make_counter().into_hasher())? {
+        if saved.input_len != input_len {
+            return Err(invalid("Checkpoint of a run over another file.").into());
+        }
+        state
+            .counter
+            .merge(&saved.counter)
+            .map_err(|_| invalid("Checkpoint sketch does not match the counters."))?;
+        state = Checkpoint {
+            counter: state.counter,
+            ..saved
+        };
+    }
+
+    let mut reader = HashingReader {
+        inner: open_sequence_file(path)?,
+        hasher: Xxh64::new(0),
+        len: 0,
+    };
+    // Skip the records counted before, and read the header of the next one
+    // again to hash it
+    io::copy(&mut (&mut reader).take(state.offset), &mut io::sink())?;
+    let mut lookahead = Vec::new();
+    (&mut reader)
+        .take(state.hashed_len - state.offset)
+        .read_to_end(&mut lookahead)?;
+    if reader.len != state.hashed_len {
+        return Err(invalid("Checkpoint is past the end of the file.").into());
+    }
+    if reader.hasher.digest() != state.prefix_hash {
+        return Err(invalid("Checkpoint of a run over another file.").into());
+    }
+    let mut fasta_reader = FastaReader::new(io::Cursor::new(lookahead).chain(reader));
+
+    loop {
+        let mut records = 0;
+        let sequences = std::iter::from_fn(|| {
+            if records == checkpoint.interval {
+                return None;
+            }
+            match fasta_reader.next_record() {
+                Ok(true) => {
+                    records += 1;
+                    Some(fasta_reader.read_sequence())
+                }
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            }
+        });
+        let (kmers_seen, counter) = count_sequences(sequences, k, CHUNK_SIZE, &make_counter, None)?;
+        state.kmers_seen += kmers_seen;
+        merge_counter(&mut state.counter, &counter);
+        if records < checkpoint.interval {
+            break;
+        }
+
+        // The header read again on resume was consumed with the first record
+        let (_, reader) = fasta_reader.get_ref().get_ref();
+        state.records += records as u64;
+        state.hashed_len = reader.len;
+        state.offset = state.hashed_len - fasta_reader.lookahead_len() as u64;
+        state.prefix_hash = reader.hasher.digest();
+        state.save(&checkpoint.path, k)?;
+    }
+
+    match std::fs::remove_file(&checkpoint.path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    Ok((state.kmers_seen, state.counter))
+}
+
+/// Counts the canonical k-mers of a FASTA file in parallel into a single
+/// shared [`AtomicHLLCounter`], without per-record counters or merging.
+///
+/// Returns the total number of k-mers seen.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn run_parallel_fasta_analysis_shared<S: BuildHasher + Default + Sync>(
+    path: &str,
+    k: usize,
+    counter: &AtomicHLLCounter<S>,
+) -> Result<u64, FastaError> {
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+
+    let total = AtomicU64::new(0);
+    let mut chunker = SequenceChunker::new(open_sequence_file(path)?, CHUNK_SIZE + k - 1, k - 1);
+    for_each_chunk(
+        || chunker.next_chunk(),
+        0,
+        |chunk| {
+            let kmers_seen = for_each_canonical_kmer(&chunk.bases, k, |kmer| counter.add_u64(kmer));
+            total.fetch_add(kmers_seen, Ordering::Relaxed);
+        },
+    )?;
+    Ok(total.into_inner())
+}
+
+/// Like [`run_parallel_fasta_analysis_with_k`], but only counts the canonical
+/// k-mers that are `(k, s)` syncmers of the given kind, see
+/// [`SyncmerStream`](crate::fasta::SyncmerStream).
+///
+/// Sketching only syncmers is cheaper, and the number of distinct k-mers can
+/// be estimated by dividing the estimate by [`SyncmerKind::density`].
+/// Returns the total number of k-mers seen (not just syncmers) along with
+/// the merged counter of the syncmers.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH` or `s` is not in `1..=k`.
+pub fn run_parallel_syncmer_analysis<C, F>(
+    path: &str,
+    k: usize,
+    s: usize,
+    kind: SyncmerKind,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_syncmer_analysis_from_reader(open_sequence_file(path)?, k, s, kind, make_counter)
+}
+
+/// Like [`run_parallel_syncmer_analysis`], but reads the FASTA data from
+/// `reader`, see [`run_parallel_fasta_analysis_from_reader`].
+pub fn run_parallel_syncmer_analysis_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    s: usize,
+    kind: SyncmerKind,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k-mer length must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    assert!(
+        (1..=k).contains(&s),
+        "s-mer length must be between 1 and the k-mer length"
+    );
+    let mut chunker = SequenceChunker::new(reader, CHUNK_SIZE + k - 1, k - 1);
+    count_chunks(
+        || chunker.next_chunk(),
+        0,
+        &make_counter,
+        merge_counter,
+        |chunk, counter| {
+            for_each_canonical_kmer(&chunk.bases, k, |kmer| {
+                if is_syncmer(kmer, k, s, kind) {
+                    counter.add_u64(kmer);
+                }
+            })
+        },
+    )
+}
+
+/// Counts the k-mers of a protein FASTA file (e.g. a proteome) in parallel,
+/// encoding residues with `alphabet`, see
+/// [`ProteinKmerStream`](crate::fasta::ProteinKmerStream).
+///
+/// Returns the total number of k-mers seen along with the merged counter.
+///
+/// Panics if `k` is not in `1..=MAX_PROTEIN_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::fasta::ProteinAlphabet;
+/// use hll_rust::parallel_counting::run_parallel_protein_analysis;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, hll) = run_parallel_protein_analysis("proteome.faa", 10, ProteinAlphabet::Dayhoff, || {
+///     HLLCounter::<Xxh64Builder>::new(14)
+/// })?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_protein_analysis<C, F>(
+    path: &str,
+    k: usize,
+    alphabet: ProteinAlphabet,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_protein_analysis_from_reader(open_sequence_file(path)?, k, alphabet, make_counter)
+}
+
+/// Like [`run_parallel_protein_analysis`], but reads the FASTA data from
+/// `reader`, see [`run_parallel_fasta_analysis_from_reader`].
+pub fn run_parallel_protein_analysis_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    alphabet: ProteinAlphabet,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(
+        (1..=MAX_PROTEIN_K_MER_LENGTH).contains(&k),
+        "protein k-mer length must be between 1 and {MAX_PROTEIN_K_MER_LENGTH}"
+    );
+    let mut chunker = SequenceChunker::new(reader, CHUNK_SIZE + k - 1, k - 1);
+    count_chunks(
+        || chunker.next_chunk(),
+        0,
+        &make_counter,
+        merge_counter,
+        |chunk, counter| {
+            for_each_protein_kmer(&chunk.bases, k, alphabet, |kmer| counter.add_u64(kmer))
+        },
+    )
+}
+
+/// Like [`run_parallel_fasta_analysis_with_k`], but counts the canonical
+/// ntHash values of the k-mers, see [`NtHash`](crate::fasta::NtHash).
+///
+/// The rolling hash needs no reverse complements and supports any `k`, e.g.
+/// k-mers longer than [`MAX_K_MER_LENGTH`]. The counters see hashes instead
+/// of encoded k-mers, so they must not be merged with counters of the other
+/// analyses.
+///
+/// Panics if `k` is 0.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::run_parallel_nthash_analysis;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, hll) = run_parallel_nthash_analysis("genome.fa", 51, || HLLCounter::<Xxh64Builder>::new(16))?;
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn run_parallel_nthash_analysis<C, F>(
+    path: &str,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_nthash_analysis_from_reader(open_sequence_file(path)?, k, make_counter)
+}
+
+/// Like [`run_parallel_nthash_analysis`], but reads the FASTA data from
+/// `reader`, see [`run_parallel_fasta_analysis_from_reader`].
+///
+/// Panics if `k` is 0.
+pub fn run_parallel_nthash_analysis_from_reader<R, C, F>(
+    reader: R,
+    k: usize,
+    make_counter: F,
+) -> Result<(u64, C), FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(k > 0, "k-mer length must be positive");
+    let mut chunker = SequenceChunker::new(reader, CHUNK_SIZE + k - 1, k - 1);
+    count_chunks(
+        || chunker.next_chunk(),
+        0,
+        &make_counter,
+        merge_counter,
+        |chunk, counter| for_each_nthash(&chunk.bases, k, |hash| counter.add_u64(hash)),
+    )
+}
+
+/// Largest k-mer length supported by [`run_parallel_fasta_analysis_multi_k`].
+pub const MAX_MULTI_K_MER_LENGTH: usize = 64;
+
+/// Counts the canonical k-mers of a FASTA file for several k-mer lengths in a
+/// single pass over the file.
+///
+/// A single rolling 2-bit encoder is shared by all k-mer lengths, and every
+/// thread of the rayon pool counts into one counter per k-mer length. Returns
+/// the total number of k-mers seen and the merged counter for every entry of
+/// `ks`, in the same order.
+///
+/// Panics if `ks` is empty or any k is not in `1..=MAX_MULTI_K_MER_LENGTH`.
+pub fn run_parallel_fasta_analysis_multi_k<C, F>(
+    path: &str,
+    ks: &[usize],
+    make_counter: F,
+) -> Result<Vec<(u64, C)>, FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    run_parallel_fasta_analysis_multi_k_from_reader(open_sequence_file(path)?, ks, make_counter)
+}
+
+/// Like [`run_parallel_fasta_analysis_multi_k`], but reads the FASTA data
+/// from `reader`, see [`run_parallel_fasta_analysis_from_reader`].
+///
+/// Panics if `ks` is empty or any k is not in `1..=MAX_MULTI_K_MER_LENGTH`.
+pub fn run_parallel_fasta_analysis_multi_k_from_reader<R, C, F>(
+    reader: R,
+    ks: &[usize],
+    make_counter: F,
+) -> Result<Vec<(u64, C)>, FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+{
+    assert!(!ks.is_empty(), "at least one k-mer length is required");
+    assert!(
+        ks.iter().all(|k| (1..=MAX_MULTI_K_MER_LENGTH).contains(k)),
+        "k-mer lengths must be between 1 and {MAX_MULTI_K_MER_LENGTH}"
+    );
+    let max_k = *ks.iter().max().unwrap();
+
+    let new_counters = || {
+        ks.iter()
+            .map(|_| (0u64, make_counter()))
+            .collect::<Vec<_>>()
+    };
+    let mut chunker = SequenceChunker::new(reader, CHUNK_SIZE + max_k - 1, max_k - 1);
+    let (_, counters) = count_chunks(
+        || chunker.next_chunk(),
+        0,
+        &new_counters,
+        |counters, other| merge_counters(counters, other),
+        |chunk, counters| {
+            let mut kmer_u128: u128 = 0;
+            let mut valid_len = 0;
+            for (pos, &byte) in chunk.bases.iter().enumerate() {
                 let code = ENCODING[byte as usize];
                 if code == 0xFF {
-                    // Skip unknown characters
                     valid_len = 0;
-                    kmer_u64 = 0;
-                } else {
-                    kmer_u64 = ((kmer_u64 << 2) & K_MER_MASK) | (code as u64);
-                    valid_len += 1;
-
-                    if valid_len >= K_MER_LENGTH {
-                        let canonical = get_canonical_u64(kmer_u64);
-                        counter.add_u64(canonical);
-                        kmers_seen += 1;
+                    kmer_u128 = 0;
+                    continue;
+                }
+                kmer_u128 = (kmer_u128 << 2) | (code as u128);
+                valid_len += 1;
+                // Chunks overlap by max_k - 1 bases, so shorter k-mers
+                // ending in the overlap belong to the previous chunk.
+                if pos < chunk.overlap {
+                    continue;
+                }
+
+                for (&k, (count, counter)) in ks.iter().zip(counters.iter_mut()) {
+                    if valid_len < k {
+                        continue;
+                    }
+                    let kmer = kmer_u128 & (u128::MAX >> (128 - 2 * k));
+                    let canonical = get_canonical_u128(kmer, k);
+                    if k <= MAX_K_MER_LENGTH {
+                        counter.add_u64(canonical as u64);
+                    } else {
+                        counter.add(&canonical.to_le_bytes());
                     }
+                    *count += 1;
                 }
             }
+            // The k-mers of every length are counted by the counters
+            0
+        },
+    )?;
+    Ok(counters)
+}
 
-            (kmers_seen, counter)
+// Merges the counters of `run_parallel_fasta_analysis_multi_k`, one for
+// every k-mer length.
+fn merge_counters<C: MergeableCounter>(counters: &mut [(u64, C)], other: &[(u64, C)]) {
+    for ((count, counter), (other_count, other)) in counters.iter_mut().zip(other) {
+        *count += other_count;
+        merge_counter(counter, other);
+    }
+}
+
+/// Estimates the number of distinct canonical k-mers of a FASTA file for
+/// every k in `ks`, in one pass with
+/// [`run_parallel_fasta_analysis_multi_k`].
+///
+/// Returns `(k, distinct estimate, total k-mers)` for every k, in the order
+/// of `ks`. The complexity, distinct / total, rises with k until almost all
+/// k-mers are unique; the smallest k close to that plateau is usually an
+/// informative k for the organism.
+///
+/// Panics if `ks` is empty or any k is not in `1..=MAX_MULTI_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::parallel_counting::complexity_profile;
+/// use hll_rust::{Counter, HLLCounter};
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// for (k, distinct, total) in
+///     complexity_profile("genome.fa", (11..=31).step_by(2), || HLLCounter::<Xxh64Builder>::new(14))?
+/// {
+///     println!("{k}\t{distinct:.0}\t{:.4}", distinct / total as f64);
+/// }
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn complexity_profile<C, F, K>(
+    path: &str,
+    ks: K,
+    make_counter: F,
+) -> Result<Vec<(usize, f64, u64)>, FastaError>
+where
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+    K: IntoIterator<Item = usize>,
+{
+    complexity_profile_from_reader(open_sequence_file(path)?, ks, make_counter)
+}
+
+/// Like [`complexity_profile`], but reads the FASTA data from `reader`, see
+/// [`run_parallel_fasta_analysis_from_reader`].
+///
+/// Panics if `ks` is empty or any k is not in `1..=MAX_MULTI_K_MER_LENGTH`.
+pub fn complexity_profile_from_reader<R, C, F, K>(
+    reader: R,
+    ks: K,
+    make_counter: F,
+) -> Result<Vec<(usize, f64, u64)>, FastaError>
+where
+    R: BufRead + Send,
+    C: MergeableCounter + Send,
+    F: Fn() -> C + Send + Sync,
+    K: IntoIterator<Item = usize>,
+{
+    let ks: Vec<usize> = ks.into_iter().collect();
+    let counted = run_parallel_fasta_analysis_multi_k_from_reader(reader, &ks, make_counter)?;
+    Ok(ks
+        .into_iter()
+        .zip(counted)
+        .map(|(k, (total, counter))| (k, counter.estimate(), total))
+        .collect())
+}
+
+/// Samples distinct canonical k-mers of a FASTA file uniformly, see
+/// [`DistinctSampler`].
+///
+/// The k-mers are 2-bit encoded, [`decode_kmer`](crate::fasta::decode_kmer)
+/// turns them back into bases. Returns the total number of k-mers seen and
+/// the merged sampler.
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+///
+/// ```no_run
+/// use hll_rust::counters::DistinctSampler;
+/// use hll_rust::fasta::decode_kmer;
+/// use hll_rust::parallel_counting::sample_distinct_kmers;
+/// use xxhash_rust::xxh64::Xxh64Builder;
+///
+/// let (_, sampler) = sample_distinct_kmers("genome.fa", 21, || {
+///     DistinctSampler::<u64, Xxh64Builder>::new(10)
+/// })?;
+/// for &kmer in sampler.sample() {
+///     println!("{}", String::from_utf8_lossy(&decode_kmer(kmer, 21)));
+/// }
+/// # Ok::<(), hll_rust::fasta::FastaError>(())
+/// ```
+pub fn sample_distinct_kmers<S, F>(
+    path: &str,
+    k: usize,
+    make_sampler: F,
+) -> Result<(u64, DistinctSampler<u64, S>), FastaError>
+where
+    S: BuildHasher + Default + Send,
+    F: Fn() -> DistinctSampler<u64, S> + Send + Sync,
+{
+    sample_distinct_kmers_from_reader(open_sequence_file(path)?, k, make_sampler)
+}
+
+/// Like [`sample_distinct_kmers`], but reads the FASTA data from `reader`,
+/// see [`run_parallel_fasta_analysis_from_reader`].
+///
+/// Panics if `k` is not in `1..=MAX_K_MER_LENGTH`.
+pub fn sample_distinct_kmers_from_reader<R, S, F>(
+    reader: R,
+    k: usize,
+    make_sampler: F,
+) -> Result<(u64, DistinctSampler<u64, S>), FastaError>
+where
+    R: BufRead + Send,
+    S: BuildHasher + Default + Send,
+    F: Fn() -> DistinctSampler<u64, S> + Send + Sync,
+{
+    assert!(
+        (1..=MAX_K_MER_LENGTH).contains(&k),
+        "k must be between 1 and {MAX_K_MER_LENGTH}"
+    );
+    let mut chunker = SequenceChunker::new(reader, CHUNK_SIZE + k - 1, k - 1);
+    count_chunks(
+        || chunker.next_chunk(),
+        0,
+        &make_sampler,
+        merge_sampler,
+        |chunk, sampler| {
+            for_each_canonical_kmer(&chunk.bases, k, |kmer| {
+                sampler.add(kmer);
+            })
+        },
+    )
+}
+
+// Merges samplers created by the same `make_sampler`, see `merge_counter`.
+fn merge_sampler<S: BuildHasher + Default>(
+    sampler: &mut DistinctSampler<u64, S>,
+    other: &DistinctSampler<u64, S>,
+) {
+    sampler.merge(other).unwrap_or_else(|e| {
+        panic!("samplers created by make_sampler must be mergeable, e.g. not use RandomState: {e}")
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_cover_every_kmer_once() {
+        let k = 31;
+        for len in [
+            0,
+            10,
+            CHUNK_SIZE,
+            CHUNK_SIZE + k - 1,
+            CHUNK_SIZE + k,
+            3 * CHUNK_SIZE + 7,
+        ] {
+            let sequences = [Ok(b"ACGT".to_vec()), Ok(vec![b'A'; len])];
+            let mut next_chunk = sequence_chunks(sequences.into_iter(), CHUNK_SIZE + k - 1, k - 1);
+            let mut chunks = Vec::new();
+            while let Some(chunk) = next_chunk().unwrap() {
+                chunks.push(chunk);
+            }
+            let kmers: usize = chunks
+                .iter()
+                .map(|chunk| chunk.bases.len().saturating_sub(k - 1))
+                .sum();
+            assert_eq!(kmers, len.saturating_sub(k - 1), "len {len}");
+            assert_eq!(chunks[0].record, 0);
+            assert_eq!(chunks[0].overlap, 0);
+            for (i, chunk) in chunks[1..].iter().enumerate() {
+                assert_eq!(chunk.record, 1);
+                assert_eq!(chunk.overlap, if i == 0 { 0 } else { k - 1 });
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounded_queue_matches_records() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let k = 5;
+        let fasta: &[u8] = b">a\r\nACGTTGCAAGGCTTAACG\r\nacgtNNTTGCAGATTACA\r\n\n>empty\n>b desc\nGATTACAGATTACAGGGCCCAAATTTACGATCGATCGGCTAGCTAGGACT";
+        let make_counter = || HashCounter::<Xxh64Builder>::new(0);
+        let (expected_seen, expected): (u64, HashCounter<Xxh64Builder>) =
+            count_sequences(read_sequences(fasta), k, 4, &make_counter, None).unwrap();
+        for chunk_size in [1, 3, 8, 1000] {
+            for queue_chunks in [0, 1] {
+                let (seen, counter): (u64, HashCounter<Xxh64Builder>) =
+                    count_reader(fasta, k, chunk_size, queue_chunks, &make_counter).unwrap();
+                assert_eq!(seen, expected_seen, "chunk size {chunk_size}");
+                assert_eq!(
+                    counter.estimate(),
+                    expected.estimate(),
+                    "chunk size {chunk_size}"
+                );
+            }
+        }
+
+        let count = |data: &[u8]| {
+            count_reader::<_, HashCounter<Xxh64Builder>, _>(data, k, 8, 1, &make_counter)
+        };
+        assert!(matches!(
+            count(b"\n>a\nACGT"),
+            Err(FastaError::MalformedHeader { line_no: 1 })
+        ));
+        assert!(matches!(
+            count(b">a\nACGT\n>b\nACGTACGTACG!T"),
+            Err(FastaError::InvalidCharacter {
+                byte: b'!',
+                position: 11
+            })
+        ));
+        assert_eq!(count(b"").unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_record_ranges_split_at_headers() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let k = 7;
+        let mut simulator = crate::synthetic::SequenceSimulator::new(7);
+        let mut data = Vec::new();
+        for i in 0..20 {
+            let seq = simulator.generate(50 + 37 * i);
+            data.extend_from_slice(format!(">seq{i}\n").as_bytes());
+            for line in seq.chunks(60) {
+                data.extend_from_slice(line);
+                data.push(b'\n');
+            }
+        }
+        let path = std::env::temp_dir().join(format!("hll_rust_split_{}.fa", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        let make_counter = || HashCounter::<Xxh64Builder>::new(0);
+        let (expected_seen, expected): (u64, HashCounter<Xxh64Builder>) =
+            count_reader(&data[..], k, 100, 0, &make_counter).unwrap();
+        for parts in [1, 2, 7, 1000] {
+            let ranges = record_ranges(&path, parts, 1).unwrap().unwrap();
+            assert!(ranges.len() <= parts);
+            assert_eq!(ranges.first().unwrap().start, 0);
+            assert_eq!(ranges.last().unwrap().end, data.len() as u64);
+            for range in &ranges {
+                assert_eq!(data[range.start as usize], b'>');
+            }
+            let (seen, counter): (u64, HashCounter<Xxh64Builder>) =
+                count_ranges(&path, &ranges, k, 100, &make_counter).unwrap();
+            assert_eq!(seen, expected_seen, "{parts} parts");
+            assert_eq!(counter.estimate(), expected.estimate(), "{parts} parts");
+        }
+
+        // Many records of similar size keep every thread busy, a single
+        // record is left to the chunk stream
+        let ranges = record_ranges(&path, 16, 1).unwrap().unwrap();
+        assert!(ranges_balanced(&ranges, 4));
+        assert!(!ranges_balanced(&ranges, 64));
+        assert!(!ranges_balanced(&[0..10, 10..100, 100..110], 2));
+        let single = path.with_extension("single.fa");
+        let mut record = b">chr1\n".to_vec();
+        record.extend(data.iter().filter(|&&byte| b"ACGT".contains(&byte)));
+        std::fs::write(&single, &record).unwrap();
+        let ranges = record_ranges(&single, 16, 1).unwrap().unwrap();
+        assert_eq!(ranges, vec![0..record.len() as u64]);
+        assert!(!ranges_balanced(&ranges, 2));
+        std::fs::remove_file(single).unwrap();
+
+        let gz_path = path.with_extension("fa.gz");
+        std::fs::write(&gz_path, [0x1f, 0x8b, 0]).unwrap();
+        assert!(record_ranges(&gz_path, 2, 1).unwrap().is_none());
+        std::fs::remove_file(gz_path).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_one_counter_per_thread() {
+        use crate::HashCounter;
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let created = AtomicU64::new(0);
+        let make_counter = || {
+            created.fetch_add(1, Ordering::Relaxed);
+            HashCounter::<Xxh64Builder>::new(0)
+        };
+        let mut fasta = Vec::new();
+        for record in 0..500 {
+            fasta.extend_from_slice(format!(">seq{record}\nACGTTGCAAGGCTTAACG\n").as_bytes());
+        }
+        let (kmers_seen, _) =
+            count_sequences(read_sequences(&fasta[..]), 5, 4, &make_counter, None).unwrap();
+        assert_eq!(kmers_seen, 500 * 14);
+        let threads = rayon::current_num_threads() as u64;
+        assert!(created.load(Ordering::Relaxed) <= threads + 1);
+
+        // Nothing counted
+        created.store(0, Ordering::Relaxed);
+        let (kmers_seen, _) =
+            count_sequences(read_sequences(&b""[..]), 5, 4, &make_counter, None).unwrap();
+        assert_eq!(kmers_seen, 0);
+        assert_eq!(created.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_config_matches_global_pool() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let path = std::env::temp_dir().join(format!("hll_rust_config_{}.fa", std::process::id()));
+        let seq: Vec<u8> = (0..5_000u64)
+            .map(|i| b"ACGT"[(i * i % 7 % 4) as usize])
+            .collect();
+        let mut data = b">seq\n".to_vec();
+        data.extend_from_slice(&seq);
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = ParallelConfig {
+            threads: 2,
+            chunk_bytes: 100,
+            queue_chunks: 1,
+            counter_precision: 0,
+            k: 11,
+        };
+        let (total, counter) = run_parallel_fasta_analysis_with_config(
+            path,
+            &config,
+            HashCounter::<Xxh64Builder>::new,
+        )
+        .unwrap();
+        let (expected_total, expected) =
+            run_parallel_fasta_analysis_with_k(path, 11, || HashCounter::<Xxh64Builder>::new(0))
+                .unwrap();
+        assert_eq!(total, expected_total);
+        assert_eq!(counter.estimate(), expected.estimate());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_async_analysis_matches_reader() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let mut data = Vec::new();
+        for record in 0..5u64 {
+            data.extend_from_slice(format!(">seq{record}\n").as_bytes());
+            let seq: Vec<u8> = (0..3_000u64)
+                .map(|i| b"ACGTN"[(i * i * (record + 1) % 11 % 5) as usize])
+                .collect();
+            data.extend_from_slice(&seq);
+            data.push(b'\n');
+        }
+
+        let (total, counter) = run_parallel_fasta_analysis_async(&data[..], 15, || {
+            HashCounter::<Xxh64Builder>::new(0)
         })
-        .reduce(
-            || (0, HLLCounter::<S>::new(16)),
-            |(count_a, mut a), (count_b, b)| {
-                a.merge(&b);
-                (count_a + count_b, a)
+        .await
+        .unwrap();
+        let (expected_total, expected) =
+            run_parallel_fasta_analysis_from_reader(&data[..], 15, || {
+                HashCounter::<Xxh64Builder>::new(0)
+            })
+            .unwrap();
+        assert_eq!(total, expected_total);
+        assert_eq!(counter.estimate(), expected.estimate());
+    }
+
+    #[test]
+    fn test_fastq_analysis_masks_low_quality() {
+        use crate::{Counter, HashCounter};
+        use std::collections::HashSet;
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let mut data = Vec::new();
+        for read in 0..(READ_BATCH_SIZE as u64 + 100) {
+            let seq: Vec<u8> = (0..60u64)
+                .map(|i| b"ACGT"[((i + read) * (i + 3 * read) % 13 % 4) as usize])
+                .collect();
+            let quality: Vec<u8> = (0..60u64)
+                .map(|i| PHRED_OFFSET + ((i * 7 + read) % 41) as u8)
+                .collect();
+            data.extend_from_slice(format!("@read{read}\n").as_bytes());
+            data.extend_from_slice(&seq);
+            data.extend_from_slice(b"\n+\n");
+            data.extend_from_slice(&quality);
+            data.push(b'\n');
+        }
+
+        let k = 7;
+        let mut reader = FastqReader::new(&data[..]).with_min_quality(10);
+        let mut expected = HashSet::new();
+        let mut expected_total = 0;
+        while reader.next_record().unwrap() {
+            for kmer in reader.canonical_kmers(k) {
+                expected.insert(kmer.unwrap());
+                expected_total += 1;
+            }
+        }
+
+        let (total, counter) = run_parallel_fastq_analysis_from_reader(&data[..], k, 10, || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        assert_eq!(total, expected_total);
+        assert_eq!(counter.estimate(), expected.len() as f64);
+
+        // Reads entirely below the threshold contribute nothing
+        let (total, _) = run_parallel_fastq_analysis_from_reader(&data[..], k, 50, || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        assert_eq!(total, 0);
+
+        let mut batch = Vec::new();
+        mask_low_quality(b"ACGTAC", b"I#II##", 10, &mut batch);
+        assert_eq!(batch, b"ANGTN");
+    }
+
+    #[test]
+    fn test_paired_fastq_analysis() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        fn fastq(reads: &[(String, Vec<u8>)]) -> Vec<u8> {
+            let mut data = Vec::new();
+            for (id, seq) in reads {
+                data.extend_from_slice(format!("@{id}\n").as_bytes());
+                data.extend_from_slice(seq);
+                data.extend_from_slice(b"\n+\n");
+                data.extend(std::iter::repeat_n(b'I', seq.len()));
+                data.push(b'\n');
+            }
+            data
+        }
+
+        // Fragments of 200 bases read from both ends with 120 base mates,
+        // which overlap by 40 bases
+        let (mut r1, mut r2) = (Vec::new(), Vec::new());
+        for pair in 0..50u64 {
+            let fragment = crate::synthetic::SequenceSimulator::new(pair).generate(200);
+            r1.push((format!("frag{pair}/1"), fragment[..120].to_vec()));
+            r2.push((format!("frag{pair}/2"), reverse_complement(&fragment[80..])));
+        }
+        let (r1_data, r2_data) = (fastq(&r1), fastq(&r2));
+        let count = |merge_overlaps| {
+            run_parallel_paired_fastq_analysis_from_readers(
+                &r1_data[..],
+                &r2_data[..],
+                11,
+                20,
+                merge_overlaps,
+                || HashCounter::<Xxh64Builder>::new(0),
+            )
+            .unwrap()
+        };
+        let (separate_total, separate) = count(false);
+        let (merged_total, merged) = count(true);
+        assert_eq!(separate_total, 50 * 2 * (120 - 10));
+        assert_eq!(merged_total, 50 * (200 - 10));
+        assert_eq!(merged.estimate(), separate.estimate());
+
+        // Mates must pair up by name and number
+        for r2 in [&r2[1..], &r2[..49]] {
+            let result = run_parallel_paired_fastq_analysis_from_readers(
+                &r1_data[..],
+                &fastq(r2)[..],
+                11,
+                20,
+                true,
+                || HashCounter::<Xxh64Builder>::new(0),
+            );
+            assert!(matches!(result, Err(FastaError::MismatchedMates { .. })));
+        }
+        assert_eq!(mate_name(&Some(b"read7/2 1:N:0".to_vec())), b"read7");
+    }
+
+    #[cfg(feature = "bam")]
+    #[test]
+    fn test_alignment_analysis() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let mut sam = b"@HD\tVN:1.6\n".to_vec();
+        let mut fastq = Vec::new();
+        for read in 0..100u64 {
+            let seq = crate::synthetic::SequenceSimulator::new(read).generate(80);
+            let seq = String::from_utf8(seq).unwrap();
+            sam.extend_from_slice(
+                format!("r{read}\t0\tchr1\t1\t60\t80M\t*\t0\t0\t{seq}\t*\n").as_bytes(),
+            );
+            // Secondary alignments of the same read are skipped by default
+            sam.extend_from_slice(
+                format!("r{read}\t256\tchr2\t1\t0\t80M\t*\t0\t0\t{seq}\t*\n").as_bytes(),
+            );
+            fastq.extend_from_slice(format!("@r{read}\n{seq}\n+\n{}\n", "I".repeat(80)).as_bytes());
+        }
+
+        let (total, counter) = run_parallel_alignment_analysis_from_reader(
+            &sam[..],
+            21,
+            AlignmentFilter::default(),
+            || HashCounter::<Xxh64Builder>::new(0),
+        )
+        .unwrap();
+        let (expected_total, expected) =
+            run_parallel_fastq_analysis_from_reader(&fastq[..], 21, 0, || {
+                HashCounter::<Xxh64Builder>::new(0)
+            })
+            .unwrap();
+        assert_eq!(total, expected_total);
+        assert_eq!(counter.estimate(), expected.estimate());
+
+        let (all_total, _) =
+            run_parallel_alignment_analysis_from_reader(&sam[..], 21, AlignmentFilter::ALL, || {
+                HashCounter::<Xxh64Builder>::new(0)
+            })
+            .unwrap();
+        assert_eq!(all_total, 2 * total);
+    }
+
+    #[test]
+    fn test_checkpoints_resume_interrupted_run() {
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let path = dir.join(format!("hll_rust_checkpoint_{id}.fa"));
+        let checkpoint = CheckpointConfig {
+            path: dir.join(format!("hll_rust_checkpoint_{id}.resume")),
+            interval: 3,
+        };
+        let fasta = |bad_record: Option<u64>, records: u64| {
+            let mut data = Vec::new();
+            for record in 0..records {
+                let mut seq = crate::synthetic::SequenceSimulator::new(record).generate(500);
+                if bad_record == Some(record) {
+                    seq[250] = b'1';
+                }
+                data.extend_from_slice(format!(">seq{record}\n").as_bytes());
+                data.extend_from_slice(&seq);
+                data.push(b'\n');
+            }
+            std::fs::write(&path, data).unwrap();
+        };
+        let path_str = path.to_str().unwrap();
+        let make_counter = || HLLCounter::<Xxh64Builder>::new(12);
+
+        // The run fails at the eighth record, after checkpoints at 3 and 6
+        fasta(Some(7), 10);
+        assert!(
+            run_parallel_fasta_analysis_with_checkpoints(path_str, 21, make_counter, &checkpoint)
+                .is_err()
+        );
+        let saved = Checkpoint::load(&checkpoint.path, 21, Xxh64Builder::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.records, 6);
+        assert_eq!(saved.kmers_seen, 6 * (500 - 20));
+        assert!(Checkpoint::load(&checkpoint.path, 31, Xxh64Builder::default()).is_err());
+        // The sketch was counted with another seed
+        assert!(Checkpoint::load(&checkpoint.path, 21, Xxh64Builder::new(42)).is_err());
+
+        // A file changed in the part already counted or in length is not resumed
+        for (bad_record, records) in [(Some(2), 10), (Some(5), 10), (None, 11)] {
+            fasta(bad_record, records);
+            let err = run_parallel_fasta_analysis_with_checkpoints(
+                path_str,
+                21,
+                make_counter,
+                &checkpoint,
+            )
+            .err()
+            .unwrap();
+            assert!(matches!(err, FastaError::Io(e) if e.kind() == io::ErrorKind::InvalidData));
+        }
+
+        // After fixing the file, the run resumes at the seventh record
+        fasta(None, 10);
+        let (total, counter) =
+            run_parallel_fasta_analysis_with_checkpoints(path_str, 21, make_counter, &checkpoint)
+                .unwrap();
+        let (expected_total, expected) =
+            run_parallel_fasta_analysis_with_k(path_str, 21, make_counter).unwrap();
+        assert_eq!(total, expected_total);
+        assert_eq!(counter.register_values(), expected.register_values());
+        assert!(!checkpoint.path.exists());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_cancellation() {
+        use crate::HashCounter;
+        use std::sync::atomic::AtomicUsize;
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let path = std::env::temp_dir().join(format!("hll_rust_cancel_{}.fa", std::process::id()));
+        let mut data = Vec::new();
+        for record in 0..50u64 {
+            data.extend_from_slice(format!(">seq{record}\n").as_bytes());
+            data.extend(crate::synthetic::SequenceSimulator::new(record).generate(1_000));
+            data.push(b'\n');
+        }
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+        let make_counter = || HashCounter::<Xxh64Builder>::new(0);
+
+        let cancel = CancelToken::new();
+        let result =
+            run_parallel_fasta_analysis_cancellable(path, 21, make_counter, &cancel).unwrap();
+        let (expected_total, expected) =
+            run_parallel_fasta_analysis_with_k(path, 21, make_counter).unwrap();
+        assert!(!result.cancelled);
+        assert_eq!(result.kmers_seen, expected_total);
+        assert_eq!(result.counter.estimate(), expected.estimate());
+
+        // Cancelled once the first thread starts counting, i.e. creates its
+        // counter
+        let cancel = CancelToken::from(Arc::new(AtomicBool::new(false)));
+        let created = AtomicUsize::new(0);
+        let result = run_parallel_fasta_analysis_cancellable(
+            path,
+            21,
+            || {
+                if created.fetch_add(1, Ordering::Relaxed) == 0 {
+                    cancel.cancel();
+                }
+                make_counter()
             },
+            &cancel,
+        )
+        .unwrap();
+        assert!(result.cancelled);
+        assert!(result.kmers_seen < expected_total);
+        assert_eq!(result.kmers_seen % (1_000 - 20), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_audit_counter() {
+        use crate::{AuditCounter, Counter, HLLCounter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let seq: Vec<u8> = (0..20_000u64)
+            .map(|i| b"ACGT"[(i * i % 13 % 4) as usize])
+            .collect();
+        let mut data = b">seq\n".to_vec();
+        data.extend_from_slice(&seq);
+
+        let (total, audit) = run_parallel_fasta_analysis_from_reader(&data[..], 15, || {
+            AuditCounter::<HLLCounter<Xxh64Builder>>::new(12)
+        })
+        .unwrap();
+        let (_, hll) = run_parallel_fasta_analysis_from_reader(&data[..], 15, || {
+            HLLCounter::<Xxh64Builder>::new(12)
+        })
+        .unwrap();
+        let (_, exact) = run_parallel_fasta_analysis_from_reader(&data[..], 15, || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+
+        assert_eq!(total, 20_000 - 14);
+        assert_eq!(audit.estimate(), hll.estimate());
+        assert_eq!(audit.exact_count(), exact.estimate());
+        assert_eq!(
+            audit.relative_error(),
+            (hll.estimate() - exact.estimate()) / exact.estimate()
+        );
+        assert!(audit.relative_error().abs() < 0.05);
+    }
+
+    #[test]
+    fn test_reader_matches_file() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let data: &[u8] = b">a\nACGTACGTTTGACCA\n>b\nGGGACCATTTACG\n";
+        let (total, counter) = run_parallel_fasta_analysis_from_reader(data, 5, || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        assert_eq!(total, 20);
+        assert_eq!(counter.estimate(), 17.0);
+
+        let (total, merged, records) =
+            run_parallel_fasta_analysis_per_record_from_reader(data, 5, || {
+                HashCounter::<Xxh64Builder>::new(0)
+            })
+            .unwrap();
+        assert_eq!(total, 20);
+        assert_eq!(merged.estimate(), 17.0);
+        assert_eq!(records[1].id, "b");
+        assert_eq!(records[1].stats.gc_content(), 7.0 / 13.0);
+
+        let multi = run_parallel_fasta_analysis_multi_k_from_reader(data, &[5, 13], || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        assert_eq!(multi[0].0, 20);
+        assert_eq!(multi[0].1.estimate(), 17.0);
+        assert_eq!(multi[1].0, 4);
+    }
+
+    #[test]
+    fn test_syncmer_analysis() {
+        use crate::fasta::SyncmerStream;
+        use crate::{Counter, HashCounter};
+        use std::collections::HashSet;
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let data: &[u8] = b">a\nACGTTGCATGCATCGATCGGATCGATCGTAGCTAGCTGAC\n>b\nTTGACCGTAGGCTAGCATNGACTGATCGGCTAGCAT\n";
+        let (k, s) = (9, 4);
+        let (total, counter) =
+            run_parallel_syncmer_analysis_from_reader(data, k, s, SyncmerKind::Open, || {
+                HashCounter::<Xxh64Builder>::new(0)
+            })
+            .unwrap();
+        let (expected_total, _) = run_parallel_fasta_analysis_from_reader(data, k, || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        assert_eq!(total, expected_total);
+
+        let mut reader = FastaReader::new(data);
+        let mut syncmers = HashSet::new();
+        while reader.next_record().unwrap() {
+            let stream = SyncmerStream::new(reader.encoded_kmers(k), k, s, SyncmerKind::Open);
+            syncmers.extend(stream.map(|kmer| kmer.unwrap()));
+        }
+        assert!(!syncmers.is_empty());
+        assert_eq!(counter.estimate(), syncmers.len() as f64);
+    }
+
+    #[test]
+    fn test_protein_analysis() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        // KVL occurs twice; DIV and EIV are the same in the Dayhoff alphabet
+        let data: &[u8] = b">p1\nMKVLDIV\n>p2\nKVLEIV*\n";
+        let make_counter = || HashCounter::<Xxh64Builder>::new(0);
+        let (total, standard) = run_parallel_protein_analysis_from_reader(
+            data,
+            3,
+            ProteinAlphabet::Standard,
+            make_counter,
+        )
+        .unwrap();
+        assert_eq!(total, 9);
+        assert_eq!(standard.estimate(), 8.0);
+        let (_, dayhoff) = run_parallel_protein_analysis_from_reader(
+            data,
+            3,
+            ProteinAlphabet::Dayhoff,
+            make_counter,
+        )
+        .unwrap();
+        assert!(dayhoff.estimate() < standard.estimate());
+    }
+
+    #[test]
+    fn test_nthash_analysis() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let mut state = 7u64;
+        let mut data = b">a\n".to_vec();
+        for i in 0..5_000 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            data.push(b"ACGT"[(state >> 62) as usize]);
+            if i % 70 == 69 {
+                data.push(b'\n');
+            }
+        }
+        // Repeat a part, including its reverse complement
+        data.extend_from_slice(b"\n>b\nNN");
+        let repeat = data[3..500].to_vec();
+        data.extend_from_slice(&repeat);
+        data.extend_from_slice(b"\n>c\n");
+        data.extend_from_slice(&crate::fasta::reverse_complement(&data[600..900]));
+        data.push(b'\n');
+
+        let make_counter = || HashCounter::<Xxh64Builder>::new(0);
+        let encoded =
+            run_parallel_fasta_analysis_multi_k_from_reader(&data[..], &[21, 45], make_counter)
+                .unwrap();
+        for (i, k) in [21, 45].into_iter().enumerate() {
+            let (total, hashes) =
+                run_parallel_nthash_analysis_from_reader(&data[..], k, make_counter).unwrap();
+            assert_eq!(total, encoded[i].0);
+            assert_eq!(hashes.estimate(), encoded[i].1.estimate());
+        }
+    }
+
+    #[test]
+    fn test_complexity_profile() {
+        use crate::HashCounter;
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        // The repeat unit ACGTT has 5 distinct 5-mers, however long the repeat
+        let data: &[u8] = b">a\nACGTTACGTTACGTTACGTT\n>b\nGATTACA\n";
+        let profile = complexity_profile_from_reader(data, [1, 3, 5, 7], || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        assert_eq!(
+            profile,
+            [(1, 2.0, 27), (3, 7.0, 23), (5, 8.0, 19), (7, 6.0, 15)]
+        );
+    }
+
+    #[test]
+    fn test_sample_distinct_kmers() {
+        use crate::fasta::decode_kmer;
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        // GATTACA has the canonical 3-mers GAT (= ATC), ATT (= AAT), TTA (= TAA),
+        // TAC (= GTA), ACA
+        let data: &[u8] = b">a\nGATTACA\n>b\nGATNNTAC\n";
+        let make_sampler = || DistinctSampler::<u64, Xxh64Builder>::new(4);
+        let (total, sampler) = sample_distinct_kmers_from_reader(data, 3, make_sampler).unwrap();
+        assert_eq!(total, 7);
+        assert_eq!(sampler.len(), 4);
+        let mut sample: Vec<Vec<u8>> = sampler.sample().map(|&kmer| decode_kmer(kmer, 3)).collect();
+        sample.sort();
+        sample.dedup();
+        assert_eq!(sample.len(), 4);
+        let distinct: [&[u8]; 5] = [b"AAT", b"ACA", b"ATC", b"GTA", b"TAA"];
+        assert!(sample.iter().all(|kmer| distinct.contains(&&kmer[..])));
+
+        let (_, all) = sample_distinct_kmers_from_reader(data, 3, || {
+            DistinctSampler::<u64, Xxh64Builder>::new(10)
+        })
+        .unwrap();
+        assert_eq!(all.estimate(), 5.0);
+    }
+
+    #[test]
+    fn test_dataset_analysis() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let dir = std::env::temp_dir();
+        let a = dir.join(format!("hll_rust_dataset_a_{}.fa", std::process::id()));
+        let b = dir.join(format!("hll_rust_dataset_b_{}.fa", std::process::id()));
+        std::fs::write(&a, b">a\nACGTAC\n").unwrap();
+        std::fs::write(&b, b">b\nGTACGG\n>c\nAAAA\n").unwrap();
+
+        let result = run_parallel_dataset_analysis(&[a.as_path(), b.as_path()], 3, || {
+            HashCounter::<Xxh64Builder>::new(0)
+        })
+        .unwrap();
+        let (total, combined) = &result.combined;
+        let counts: Vec<(u64, f64)> = result
+            .files
+            .iter()
+            .map(|(n, c)| (*n, c.estimate()))
+            .collect();
+        // ACG, CGT, GTA, TAC -> ACG, GTA; GTA, TAC, ACG, CGG -> GTA, ACG, CCG; AAA
+        assert_eq!(counts, vec![(4, 2.0), (6, 4.0)]);
+        assert_eq!(*total, 10);
+        assert_eq!(combined.estimate(), 4.0);
+
+        std::fs::remove_file(a).unwrap();
+        std::fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn test_per_record_results() {
+        use crate::{Counter, HashCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let path = std::env::temp_dir().join(format!("hll_rust_records_{}.fa", std::process::id()));
+        let data = b">chr1 first\nACGTACGTAC\n>chr2\nGGGG\n>chr3\nAC\n";
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+
+        let make_counter = || HashCounter::<Xxh64Builder>::new(0);
+        let (total, merged, records) =
+            run_parallel_fasta_analysis_per_record(path, 3, make_counter).unwrap();
+        let (expected_total, expected) =
+            run_parallel_fasta_analysis_with_k(path, 3, make_counter).unwrap();
+
+        assert_eq!(total, expected_total);
+        assert_eq!(merged.estimate(), expected.estimate());
+        let summary: Vec<(&str, usize, u64)> = records
+            .iter()
+            .map(|r| (r.id.as_str(), r.length, r.kmers))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("chr1 first", 10, 8), ("chr2", 4, 2), ("chr3", 2, 0)]
+        );
+        // ACG, CGT, GTA, TAC canonicalize to ACG and GTA; GGG to CCC
+        assert_eq!(records[0].hll.estimate(), 2.0);
+        assert_eq!(records[1].hll.estimate(), 1.0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_multi_k_matches_single_k() {
+        use crate::{Counter, HLLCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        // A pseudo-random sequence spanning several chunks
+        let mut state = 12345u64;
+        let seq: Vec<u8> = (0..2 * CHUNK_SIZE + 1000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("hll_rust_multi_k_{}.fa", std::process::id()));
+        let mut data = b">seq1\n".to_vec();
+        data.extend_from_slice(&seq);
+        data.push(b'\n');
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+
+        let make_counter = HLLCounter::<Xxh64Builder>::new;
+        let multi =
+            run_parallel_fasta_analysis_multi_k(path, &[5, 21, 40], || make_counter(12)).unwrap();
+        for (&k, (total, counter)) in [5, 21].iter().zip(&multi) {
+            let (single_total, single) =
+                run_parallel_fasta_analysis_with_k(path, k, || make_counter(12)).unwrap();
+            assert_eq!(*total, single_total);
+            assert_eq!(counter.estimate(), single.estimate());
+        }
+        assert_eq!(multi[2].0, (seq.len() - 39) as u64);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_errors_are_returned() {
+        use crate::{AtomicHLLCounter, Counter, HLLCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let path = std::env::temp_dir().join(format!("hll_rust_bad_{}.fa", std::process::id()));
+        std::fs::write(&path, b">seq1\nACGTACGT\n>seq2\nACG\x01T\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let result =
+            run_parallel_fasta_analysis_with_k(path, 3, || HLLCounter::<Xxh64Builder>::new(8));
+        assert!(matches!(
+            result,
+            Err(FastaError::InvalidCharacter { byte: 1, .. })
+        ));
+        let counter = AtomicHLLCounter::<Xxh64Builder>::new(8);
+        assert!(run_parallel_fasta_analysis_shared(path, 3, &counter).is_err());
+        let result = run_parallel_fasta_analysis_multi_k(path, &[3, 5], || {
+            HLLCounter::<Xxh64Builder>::new(8)
+        });
+        assert!(result.is_err());
+
+        std::fs::remove_file(path).unwrap();
+        assert!(matches!(
+            run_parallel_fasta_analysis(path, || HLLCounter::<Xxh64Builder>::new(8)),
+            Err(FastaError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_progress_reports() {
+        use crate::{Counter, HLLCounter};
+        use xxhash_rust::xxh64::Xxh64Builder;
+
+        let path =
+            std::env::temp_dir().join(format!("hll_rust_progress_{}.fa", std::process::id()));
+        let data = b">seq1\nACGTACGTAA\n>seq2\nTTGCA\nGGAT\n>seq3\nCCCAT\n";
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+
+        let reports = Mutex::new(Vec::new());
+        let (total, counter) = run_parallel_fasta_analysis_with_progress(
+            path,
+            3,
+            || HLLCounter::<Xxh64Builder>::new(8),
+            1,
+            |p| reports.lock().unwrap().push(p),
+        )
+        .unwrap();
+        let reports = reports.into_inner().unwrap();
+
+        // Every record is a single chunk and triggers a report, unless
+        // another thread is reporting at the time
+        assert_eq!(total, 8 + 7 + 3);
+        assert!((1..=3).contains(&reports.len()));
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].kmers_seen < w[1].kmers_seen)
         );
+        let last = reports.last().unwrap();
+        assert_eq!(last.kmers_seen, total);
+        assert_eq!(last.estimate, counter.estimate());
+        assert!(last.bytes_read > 0 && last.bytes_read <= data.len() as u64);
 
-    Ok(final_counter)
+        std::fs::remove_file(path).unwrap();
+    }
 }
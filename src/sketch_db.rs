@@ -0,0 +1,452 @@
+//! Many named HLL sketches in one append-only file, like the sketch files
+//! written by `mash sketch -o`.
+//!
+//! The file starts with a short header, followed by one record per sketch:
+//! the name, k-mer size and precision, then the sketch in the encoding of
+//! [`HLLCounter::to_compressed_bytes`]. Records are only ever appended, so
+//! adding a genome to a collection never rewrites the sketches already in
+//! it. Opening a database reads the record headers into an in-memory index,
+//! seeking past the sketches themselves.
+
+use crate::counters::{HLLCounter, Registers};
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs::{File, OpenOptions};
+use std::hash::BuildHasher;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// First bytes of sketch database files.
+pub const SKETCH_DB_MAGIC: &[u8; 4] = b"HLLD";
+const SKETCH_DB_VERSION: u8 = 1;
+const HEADER_LEN: u64 = 5;
+
+/// Index entry of one sketch in a [`SketchDb`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SketchEntry {
+    /// Name of the sketch, unique within the database.
+    pub name: String,
+    /// K-mer size the sketch was counted with.
+    pub k: usize,
+    /// HLL precision of the sketch.
+    pub precision: usize,
+    /// Byte offset of the compressed sketch in the file.
+    pub offset: u64,
+    /// Length of the compressed sketch in bytes.
+    pub length: u64,
+}
+
+/// An append-only file of named sketches with an index of their names,
+/// k-mer sizes, precisions and offsets.
+///
+/// A record is `name length (u16)`, `name`, `k (u32)`, `precision (u8)`,
+/// `sketch length (u32)` and the sketch, integers little-endian.
+pub struct SketchDb<F> {
+    inner: F,
+    entries: Vec<SketchEntry>,
+    by_name: HashMap<String, usize>,
+    // End of the last complete record, where the next one is appended
+    end: u64,
+}
+
+#[cfg(feature = "fs")]
+impl SketchDb<File> {
+    /// Opens the database at `path`, creating an empty one if the file does
+    /// not exist.
+    ///
+    /// A record cut short by an interrupted append is truncated from the
+    /// file, see [`SketchDb::new`].
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let db = Self::new(file)?;
+        if db.inner.metadata()?.len() > db.end {
+            db.inner.set_len(db.end)?;
+        }
+        Ok(db)
+    }
+}
+
+impl<F: Read + Write + Seek> SketchDb<F> {
+    /// Reads the index of the database in `inner`, writing the header first
+    /// if it is empty.
+    ///
+    /// A record cut short at the end, by an append that was interrupted, is
+    /// left out of the index and overwritten by the next append.
+    /// [`SketchDb::open`] also truncates it from the file; other storage
+    /// should be truncated to the end of the last entry if the next sketch
+    /// may be smaller than the partial record.
+    pub fn new(mut inner: F) -> io::Result<Self> {
+        let len = inner.seek(SeekFrom::End(0))?;
+        if len == 0 {
+            inner.write_all(SKETCH_DB_MAGIC)?;
+            inner.write_all(&[SKETCH_DB_VERSION])?;
+        }
+
+        inner.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&mut inner);
+        let mut header = [0u8; HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != SKETCH_DB_MAGIC {
+            return Err(invalid_data("Not a sketch database."));
+        }
+        if header[4] != SKETCH_DB_VERSION {
+            return Err(invalid_data("Unsupported sketch database version."));
+        }
+
+        let mut entries = Vec::new();
+        let mut by_name = HashMap::new();
+        let mut pos = HEADER_LEN;
+        while pos < len {
+            let entry = match read_record_header(&mut reader, pos) {
+                Ok(entry) if entry.offset + entry.length <= len => entry,
+                // Only the last record can be incomplete
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            reader.seek_relative(entry.length as i64)?;
+            pos = entry.offset + entry.length;
+            if by_name.insert(entry.name.clone(), entries.len()).is_some() {
+                return Err(invalid_data("Duplicate sketch name in database."));
+            }
+            entries.push(entry);
+        }
+
+        Ok(SketchDb {
+            inner,
+            entries,
+            by_name,
+            end: pos.max(HEADER_LEN),
+        })
+    }
+
+    /// Returns the index entries in the order the sketches were added.
+    pub fn entries(&self) -> &[SketchEntry] {
+        &self.entries
+    }
+
+    /// Returns the index entry of the sketch named `name`.
+    pub fn get(&self, name: &str) -> Option<&SketchEntry> {
+        self.by_name.get(name).map(|&i| &self.entries[i])
+    }
+
+    /// Returns the number of sketches.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the database holds no sketches.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends a sketch counted with k-mer size `k` under `name`.
+    ///
+    /// Fails with `AlreadyExists` if the database has a sketch of that name.
+    pub fn append<S: BuildHasher + Default, R: Registers>(
+        &mut self,
+        name: &str,
+        k: usize,
+        sketch: &HLLCounter<S, R>,
+    ) -> io::Result<&SketchEntry> {
+        if self.by_name.contains_key(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Sketch '{name}' is already in the database."),
+            ));
+        }
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Sketch name too long."))?;
+        let k32 = u32::try_from(k)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "K-mer size too large."))?;
+        let bytes = sketch.to_compressed_bytes();
+
+        let mut record = Vec::with_capacity(name.len() + bytes.len() + 11);
+        record.extend_from_slice(&name_len.to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(&k32.to_le_bytes());
+        record.push(sketch.precision() as u8);
+        record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        let offset = self.end + record.len() as u64;
+        record.extend_from_slice(&bytes);
+
+        // Loads move the position away from the end
+        self.inner.seek(SeekFrom::Start(self.end))?;
+        self.inner.write_all(&record)?;
+        self.inner.flush()?;
+        self.end += record.len() as u64;
+
+        self.by_name.insert(name.to_string(), self.entries.len());
+        self.entries.push(SketchEntry {
+            name: name.to_string(),
+            k,
+            precision: sketch.precision(),
+            offset,
+            length: bytes.len() as u64,
+        });
+        Ok(self.entries.last().unwrap())
+    }
+
+    /// Reads the sketch named `name` with the default hasher of `S`, or
+    /// `None` if there is none.
+    pub fn load<S: BuildHasher + Default>(
+        &mut self,
+        name: &str,
+    ) -> io::Result<Option<HLLCounter<S>>> {
+        self.load_with_hasher(name, S::default())
+    }
+
+    /// Reads the sketch named `name` with `hasher`, or `None` if there is
+    /// none.
+    ///
+    /// Fails with an `InvalidData` error if the sketch was counted with
+    /// another hash function or seed.
+    pub fn load_with_hasher<S: BuildHasher + Default>(
+        &mut self,
+        name: &str,
+        hasher: S,
+    ) -> io::Result<Option<HLLCounter<S>>> {
+        let Some(&i) = self.by_name.get(name) else {
+            return Ok(None);
+        };
+        let entry = &self.entries[i];
+        let mut bytes = vec![0u8; entry.length as usize];
+        self.inner.seek(SeekFrom::Start(entry.offset))?;
+        self.inner.read_exact(&mut bytes)?;
+        read_sketch(entry, &bytes, hasher).map(Some)
+    }
+
+    /// Returns an iterator reading the sketches one at a time in the order
+    /// they were added, without holding the others in memory.
+    pub fn iter<S: BuildHasher + Default + Clone>(&mut self) -> SketchIter<'_, F, S> {
+        self.iter_with_hasher(S::default())
+    }
+
+    /// Like [`SketchDb::iter`], but reads the sketches with `hasher`, see
+    /// [`SketchDb::load_with_hasher`].
+    pub fn iter_with_hasher<S: BuildHasher + Default + Clone>(
+        &mut self,
+        hasher: S,
+    ) -> SketchIter<'_, F, S> {
+        SketchIter {
+            reader: BufReader::new(&mut self.inner),
+            entries: self.entries.iter(),
+            pos: None,
+            hasher,
+        }
+    }
+
+    /// Returns the underlying file.
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+/// Iterator over the sketches of a [`SketchDb`], see [`SketchDb::iter`].
+pub struct SketchIter<'a, F, S> {
+    reader: BufReader<&'a mut F>,
+    entries: std::slice::Iter<'a, SketchEntry>,
+    // Position of `reader`, `None` before the first read
+    pos: Option<u64>,
+    hasher: S,
+}
+
+impl<'a, F: Read + Seek, S: BuildHasher + Default + Clone> Iterator for SketchIter<'a, F, S> {
+    type Item = io::Result<(&'a SketchEntry, HLLCounter<S>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        let result = (|| {
+            // Records are contiguous, so skipping the record header keeps
+            // the buffered data
+            match self.pos {
+                Some(pos) => self.reader.seek_relative((entry.offset - pos) as i64)?,
+                None => {
+                    self.reader.seek(SeekFrom::Start(entry.offset))?;
+                }
+            }
+            let mut bytes = vec![0u8; entry.length as usize];
+            self.reader.read_exact(&mut bytes)?;
+            self.pos = Some(entry.offset + entry.length);
+            read_sketch(entry, &bytes, self.hasher.clone())
+        })();
+        if result.is_err() {
+            // The position is unknown after a failed read
+            self.pos = None;
+        }
+        Some(result.map(|sketch| (entry, sketch)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Decodes the compressed sketch of `entry`, which must match its index entry.
+fn read_sketch<S: BuildHasher + Default>(
+    entry: &SketchEntry,
+    bytes: &[u8],
+    hasher: S,
+) -> io::Result<HLLCounter<S>> {
+    let sketch = HLLCounter::from_compressed_bytes_with_hasher(bytes, hasher)?;
+    if sketch.precision() != entry.precision {
+        return Err(invalid_data("Sketch precision does not match the index."));
+    }
+    Ok(sketch)
+}
+
+// Reads the header of the record at `pos`, up to the sketch.
+fn read_record_header<R: Read>(reader: &mut R, pos: u64) -> io::Result<SketchEntry> {
+    let mut name_len = [0u8; 2];
+    reader.read_exact(&mut name_len)?;
+    let name_len = u16::from_le_bytes(name_len) as usize;
+    let mut fields = vec![0u8; name_len + 9];
+    reader.read_exact(&mut fields)?;
+
+    let (name, rest) = fields.split_at(name_len);
+    let name =
+        String::from_utf8(name.to_vec()).map_err(|_| invalid_data("Sketch name is not UTF-8."))?;
+    let k = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+    let precision = rest[4] as usize;
+    let length = u32::from_le_bytes(rest[5..9].try_into().unwrap()) as u64;
+    Ok(SketchEntry {
+        name,
+        k,
+        precision,
+        offset: pos + 2 + fields.len() as u64,
+        length,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::Counter;
+    use std::io::Cursor;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    fn sketch(precision: usize, items: std::ops::Range<u64>) -> HLLCounter<Xxh64Builder> {
+        let mut counter = HLLCounter::new(precision);
+        for i in items {
+            counter.add_u64(i);
+        }
+        counter
+    }
+
+    #[test]
+    fn test_append_and_iterate() {
+        let mut db = SketchDb::new(Cursor::new(Vec::new())).unwrap();
+        assert!(db.is_empty());
+        let sketches = [
+            ("genome_a", 21, sketch(14, 0..100)),
+            ("genome_b", 31, sketch(12, 50..100_000)),
+        ];
+        for (name, k, counter) in &sketches {
+            db.append(name, *k, counter).unwrap();
+        }
+        assert_eq!(
+            db.append("genome_a", 21, &sketches[0].2)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        // Reopening reads the index back, and appends go after the records
+        let mut db = SketchDb::new(Cursor::new(db.into_inner().into_inner())).unwrap();
+        db.append("genome_c", 21, &sketch(10, 0..10)).unwrap();
+        let entry = db.get("genome_b").unwrap();
+        assert_eq!((entry.k, entry.precision), (31, 12));
+
+        let loaded = db.load::<Xxh64Builder>("genome_a").unwrap().unwrap();
+        assert_eq!(loaded.estimate(), sketches[0].2.estimate());
+        assert!(db.load::<Xxh64Builder>("genome_d").unwrap().is_none());
+        let err = db
+            .load_with_hasher("genome_a", Xxh64Builder::new(42))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let read: Vec<_> = db
+            .iter::<Xxh64Builder>()
+            .map(|result| result.unwrap())
+            .map(|(entry, counter)| (entry.name.clone(), counter.estimate()))
+            .collect();
+        assert_eq!(read.len(), 3);
+        for ((name, estimate), (expected_name, _, counter)) in read.iter().zip(&sketches) {
+            assert_eq!(name, expected_name);
+            assert_eq!(*estimate, counter.estimate());
+        }
+        assert_eq!(read[2].0, "genome_c");
+    }
+
+    #[test]
+    fn test_interrupted_append_is_overwritten() {
+        let mut db = SketchDb::new(Cursor::new(Vec::new())).unwrap();
+        db.append("genome_a", 21, &sketch(14, 0..1000)).unwrap();
+        let complete = db.into_inner().into_inner();
+        let mut db = SketchDb::new(Cursor::new(complete.clone())).unwrap();
+        db.append("genome_b", 21, &sketch(14, 0..2000)).unwrap();
+        let torn = db.into_inner().into_inner();
+
+        // Appends cut short in the record header and in the sketch
+        for len in [complete.len() + 5, torn.len() - 1] {
+            let mut db = SketchDb::new(Cursor::new(torn[..len].to_vec())).unwrap();
+            assert_eq!(db.len(), 1);
+            db.append("genome_c", 31, &sketch(14, 0..5000)).unwrap();
+
+            let mut db = SketchDb::new(Cursor::new(db.into_inner().into_inner())).unwrap();
+            let names: Vec<_> = db.entries().iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, ["genome_a", "genome_c"]);
+            let loaded = db.load::<Xxh64Builder>("genome_c").unwrap().unwrap();
+            assert_eq!(loaded.estimate(), sketch(14, 0..5000).estimate());
+        }
+        assert!(SketchDb::new(Cursor::new(b"HLLR\x01".to_vec())).is_err());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_open_truncates_interrupted_append() {
+        let path = std::env::temp_dir().join(format!("hll_rust_db_{}.hlld", std::process::id()));
+        let mut db = SketchDb::open(&path).unwrap();
+        db.append("genome_a", 21, &sketch(14, 0..1000)).unwrap();
+        db.append("genome_b", 21, &sketch(14, 0..2000)).unwrap();
+        let end = db.entries()[0].offset + db.entries()[0].length;
+        let file = db.into_inner();
+        file.set_len(file.metadata().unwrap().len() - 10).unwrap();
+
+        let mut db = SketchDb::open(&path).unwrap();
+        assert_eq!(db.len(), 1);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), end);
+        db.append("genome_b", 21, &sketch(10, 0..10)).unwrap();
+        drop(db);
+        assert_eq!(SketchDb::open(&path).unwrap().len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_precision_must_match_index() {
+        let mut db = SketchDb::new(Cursor::new(Vec::new())).unwrap();
+        let entry = db
+            .append("genome", 21, &sketch(14, 0..1000))
+            .unwrap()
+            .clone();
+        let mut bytes = db.into_inner().into_inner();
+        // The precision byte of the record header
+        bytes[entry.offset as usize - 5] = 12;
+
+        let mut db = SketchDb::new(Cursor::new(bytes)).unwrap();
+        let err = db.load::<Xxh64Builder>("genome").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(db.iter::<Xxh64Builder>().next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Random DNA sequences with a known number of distinct k-mers, e.g. to test
+//! the accuracy of counters without shipping genome files.
+
+use crate::fasta::get_canonical;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+// Bases of FASTA files written by `write_fasta` per line
+const FASTA_LINE_WIDTH: usize = 80;
+
+/// Generates random DNA with a configurable GC content, repeats and mutation
+/// rate.
+///
+/// The generator is seeded, so the same configuration always yields the same
+/// sequences. The exact number of distinct k-mers of a sequence is returned by
+/// [`distinct_kmers`] and [`distinct_canonical_kmers`].
+#[derive(Debug, Clone)]
+pub struct SequenceSimulator {
+    gc_content: f64,
+    repeat_fraction: f64,
+    repeat_length: usize,
+    mutation_rate: f64,
+    // SplitMix64 state
+    state: u64,
+}
+
+impl SequenceSimulator {
+    /// Uniformly random bases without repeats or mutations.
+    pub fn new(seed: u64) -> Self {
+        SequenceSimulator {
+            gc_content: 0.5,
+            repeat_fraction: 0.0,
+            repeat_length: 0,
+            mutation_rate: 0.0,
+            state: seed,
+        }
+    }
+
+    /// Fraction of `G` and `C` bases, e.g. 0.41 for the human genome.
+    ///
+    /// Panics if `gc_content` is not between 0 and 1.
+    pub fn with_gc_content(mut self, gc_content: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&gc_content),
+            "GC content must be between 0 and 1"
+        );
+        self.gc_content = gc_content;
+        self
+    }
+
+    /// Makes about `fraction` of the sequence out of copies of earlier
+    /// segments of `length` bases, which are mutated with the mutation rate
+    /// like diverged transposable elements.
+    ///
+    /// Panics if `fraction` is not between 0 and 1 or `length` is 0.
+    pub fn with_repeats(mut self, fraction: f64, length: usize) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "repeat fraction must be between 0 and 1"
+        );
+        assert!(length > 0, "repeat length must be positive");
+        self.repeat_fraction = fraction;
+        self.repeat_length = length;
+        self
+    }
+
+    /// Probability that a base of a repeat copy or of a sequence passed to
+    /// [`SequenceSimulator::mutate`] is substituted by another base.
+    ///
+    /// Panics if `mutation_rate` is not between 0 and 1.
+    pub fn with_mutation_rate(mut self, mutation_rate: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&mutation_rate),
+            "mutation rate must be between 0 and 1"
+        );
+        self.mutation_rate = mutation_rate;
+        self
+    }
+
+    /// Generates a sequence of `len` uppercase `ACGT` bases.
+    pub fn generate(&mut self, len: usize) -> Vec<u8> {
+        let mut seq = Vec::with_capacity(len);
+        if self.repeat_length == 0 {
+            seq.extend((0..len).map(|_| self.random_base()));
+            return seq;
+        }
+
+        // Blocks of the repeat length are either random or a mutated copy
+        // of an earlier block
+        while seq.len() < len {
+            let block_len = self.repeat_length.min(len - seq.len());
+            if seq.len() >= block_len && self.next_f64() < self.repeat_fraction {
+                let start = (self.next_u64() % (seq.len() - block_len + 1) as u64) as usize;
+                for i in start..start + block_len {
+                    let base = self.mutate_base(seq[i]);
+                    seq.push(base);
+                }
+            } else {
+                seq.extend((0..block_len).map(|_| self.random_base()));
+            }
+        }
+        seq
+    }
+
+    /// Returns a copy of `seq` whose bases are substituted with the mutation
+    /// rate, e.g. a related genome with an average nucleotide identity of
+    /// about 1 - mutation rate.
+    pub fn mutate(&mut self, seq: &[u8]) -> Vec<u8> {
+        seq.iter().map(|&base| self.mutate_base(base)).collect()
+    }
+
+    fn mutate_base(&mut self, base: u8) -> u8 {
+        if self.next_f64() >= self.mutation_rate {
+            return base;
+        }
+        loop {
+            let substitute = self.random_base();
+            if substitute != base {
+                return substitute;
+            }
+        }
+    }
+
+    fn random_base(&mut self) -> u8 {
+        let r = self.next_f64();
+        if r < self.gc_content {
+            if r < self.gc_content / 2.0 {
+                b'G'
+            } else {
+                b'C'
+            }
+        } else if r < (1.0 + self.gc_content) / 2.0 {
+            b'A'
+        } else {
+            b'T'
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Exact number of distinct k-mers of `seq`, skipping k-mers with bases
+/// other than `ACGT`.
+///
+/// Panics if `k` is 0.
+pub fn distinct_kmers(seq: &[u8], k: usize) -> usize {
+    assert!(k > 0, "k-mer length must be positive");
+    acgt_kmers(seq, k).collect::<HashSet<_>>().len()
+}
+
+/// Exact number of distinct canonical k-mers of `seq`, counting a k-mer and
+/// its reverse complement once, as the canonical k-mer streams of
+/// [`FastaReader`](crate::fasta::FastaReader) do.
+///
+/// Panics if `k` is 0.
+pub fn distinct_canonical_kmers(seq: &[u8], k: usize) -> usize {
+    assert!(k > 0, "k-mer length must be positive");
+    acgt_kmers(seq, k)
+        .map(get_canonical)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+fn acgt_kmers(seq: &[u8], k: usize) -> impl Iterator<Item = &[u8]> {
+    seq.windows(k).filter(|kmer| {
+        kmer.iter()
+            .all(|base| matches!(base, b'A' | b'C' | b'G' | b'T'))
+    })
+}
+
+/// Writes `seq` as a FASTA record with the header `id`, 80 bases per line.
+pub fn write_fasta<W: Write>(mut writer: W, id: &str, seq: &[u8]) -> io::Result<()> {
+    writeln!(writer, ">{}", id)?;
+    for line in seq.chunks(FASTA_LINE_WIDTH) {
+        writer.write_all(line)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::FastaReader;
+    use crate::{Counter, HLLCounter};
+    use std::io::Cursor;
+    use xxhash_rust::xxh64::Xxh64Builder;
+
+    #[test]
+    fn test_gc_content_and_determinism() {
+        let seq = SequenceSimulator::new(1)
+            .with_gc_content(0.3)
+            .generate(100_000);
+        assert_eq!(seq.len(), 100_000);
+        let gc = seq.iter().filter(|&&b| b == b'G' || b == b'C').count();
+        assert!((gc as f64 / 100_000.0 - 0.3).abs() < 0.01);
+        assert_eq!(
+            seq,
+            SequenceSimulator::new(1)
+                .with_gc_content(0.3)
+                .generate(100_000)
+        );
+        assert_ne!(seq, SequenceSimulator::new(2).generate(100_000));
+    }
+
+    #[test]
+    fn test_repeats_reduce_distinct_kmers() {
+        let random = SequenceSimulator::new(3).generate(50_000);
+        // Random 21-mers of 50 kb are almost surely all distinct
+        assert_eq!(distinct_kmers(&random, 21), 50_000 - 20);
+
+        let repetitive = SequenceSimulator::new(3)
+            .with_repeats(0.5, 1_000)
+            .generate(50_000);
+        assert_eq!(repetitive.len(), 50_000);
+        let distinct = distinct_kmers(&repetitive, 21);
+        assert!((20_000..30_000).contains(&distinct), "{distinct} k-mers");
+
+        // Mutated repeats share fewer k-mers with their source
+        let diverged = SequenceSimulator::new(3)
+            .with_repeats(0.5, 1_000)
+            .with_mutation_rate(0.05)
+            .generate(50_000);
+        assert!(distinct_kmers(&diverged, 21) > distinct);
+    }
+
+    #[test]
+    fn test_mutate() {
+        let mut simulator = SequenceSimulator::new(4).with_mutation_rate(0.01);
+        let seq = simulator.generate(100_000);
+        let mutated = simulator.mutate(&seq);
+        let differences = seq.iter().zip(&mutated).filter(|(a, b)| a != b).count();
+        assert!((800..1_200).contains(&differences), "{differences}");
+    }
+
+    #[test]
+    fn test_canonical_ground_truth_matches_counter() {
+        let seq = SequenceSimulator::new(5)
+            .with_repeats(0.2, 500)
+            .generate(200_000);
+        let seq_rc = crate::fasta::reverse_complement(&seq);
+        let truth = distinct_canonical_kmers(&seq, 21);
+        assert_eq!(truth, distinct_canonical_kmers(&seq_rc, 21));
+        assert!(truth <= distinct_kmers(&seq, 21));
+
+        let mut fasta = Vec::new();
+        write_fasta(&mut fasta, "chr1", &seq).unwrap();
+        let mut reader = FastaReader::new(Cursor::new(fasta));
+        let mut counter = HLLCounter::<Xxh64Builder>::new(14);
+        while reader.next_record().unwrap() {
+            let mut kmers = reader.kmers(21);
+            while let Some(kmer) = kmers.next_canonical_kmer().unwrap() {
+                counter.add(kmer);
+            }
+        }
+        let error = (counter.estimate() - truth as f64).abs() / truth as f64;
+        assert!(error < 0.05, "relative error {error}");
+    }
+}
@@ -0,0 +1,354 @@
+//! Reading large sequence files through io_uring, Linux only.
+//!
+//! [`UringReader`] keeps several large reads in flight into registered,
+//! page-aligned buffers and, by default, opens the file with `O_DIRECT` to
+//! bypass the page cache. On fast NVMe drives this reaches device throughput
+//! where a `BufReader` issuing one small read at a time does not. The reader
+//! implements `BufRead`, so it can be passed to
+//! `run_parallel_fasta_analysis_from_reader` or wrapped by
+//! [`open_sequence_reader`](crate::fasta::open_sequence_reader) for
+//! compressed files.
+
+use io_uring::{IoUring, opcode, types};
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Read};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr::NonNull;
+
+// Alignment of the buffers, read offsets and read lengths, as required by
+// `O_DIRECT` on common block devices
+const ALIGNMENT: usize = 4096;
+
+/// Settings of a [`UringReader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UringOptions {
+    /// Bytes per read, rounded up to a multiple of 4 KiB.
+    pub block_size: usize,
+    /// Number of reads in flight, and thus of buffers.
+    pub depth: usize,
+    /// Open the file with `O_DIRECT`, falling back to buffered reads if the
+    /// file system does not support it.
+    pub direct: bool,
+}
+
+impl Default for UringOptions {
+    fn default() -> Self {
+        UringOptions {
+            block_size: 1 << 20,
+            depth: 8,
+            direct: true,
+        }
+    }
+}
+
+// A heap buffer aligned to `ALIGNMENT`
+struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// The buffer is only accessed through the reader owning it
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    fn new(len: usize) -> Self {
+        let layout = Layout::from_size_align(len, ALIGNMENT).expect("valid buffer size");
+        // SAFETY: `len` is non-zero
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        AlignedBuffer { ptr, layout }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: the allocation is `layout.size()` initialized bytes
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and `&mut self` rules out other references
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `new` with the same layout
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+// A read of `len` bytes at `offset` submitted into one of the buffers
+#[derive(Clone, Copy)]
+struct PendingRead {
+    offset: u64,
+    len: usize,
+    // The completion result, `None` while the read is in flight
+    result: Option<i32>,
+}
+
+/// A sequential reader of a file that reads ahead through io_uring.
+///
+/// The buffers are used round-robin: while the data of one buffer is
+/// consumed, reads of the following blocks of the file fill the others.
+pub struct UringReader {
+    ring: IoUring,
+    file: File,
+    // File length at opening, reads stop there
+    len: u64,
+    direct: bool,
+    // Whether the buffers are registered with the ring
+    fixed: bool,
+    buffers: Vec<AlignedBuffer>,
+    // The read submitted into every buffer, `None` past the end of the file
+    reads: Vec<Option<PendingRead>>,
+    next_offset: u64,
+    // The buffer being consumed, and its consumed and valid bytes
+    current: usize,
+    pos: usize,
+    filled: usize,
+    // Whether `current` holds data that was handed out, so it must be
+    // resubmitted before moving to the next buffer
+    holding: bool,
+}
+
+impl UringReader {
+    /// Opens a file with the default [`UringOptions`].
+    ///
+    /// Fails if the kernel does not support io_uring or forbids it, e.g. in
+    /// some containers.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::with_options(path, UringOptions::default())
+    }
+
+    /// Opens a file for reading with the given options.
+    pub fn with_options<P: AsRef<Path>>(path: P, options: UringOptions) -> io::Result<Self> {
+        let path = path.as_ref();
+        let (file, direct) = if options.direct {
+            match OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECT)
+                .open(path)
+            {
+                Ok(file) => (file, true),
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => (File::open(path)?, false),
+                Err(e) => return Err(e),
+            }
+        } else {
+            (File::open(path)?, false)
+        };
+        let len = file.metadata()?.len();
+
+        let depth = options.depth.clamp(1, u16::MAX as usize);
+        let block_size = options.block_size.max(1).next_multiple_of(ALIGNMENT);
+        let ring = IoUring::new(depth.next_power_of_two() as u32)?;
+        let buffers: Vec<_> = (0..depth).map(|_| AlignedBuffer::new(block_size)).collect();
+        let iovecs: Vec<libc::iovec> = buffers
+            .iter()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.ptr.as_ptr().cast(),
+                iov_len: block_size,
+            })
+            .collect();
+        // SAFETY: the buffers outlive the ring, see `Drop`. Registration
+        // fails e.g. if it exceeds the locked memory limit, in which case
+        // plain reads are used.
+        let fixed = unsafe { ring.submitter().register_buffers(&iovecs) }.is_ok();
+
+        let mut reader = UringReader {
+            ring,
+            file,
+            len,
+            direct,
+            fixed,
+            buffers,
+            reads: vec![None; depth],
+            next_offset: 0,
+            current: 0,
+            pos: 0,
+            filled: 0,
+            holding: false,
+        };
+        for i in 0..depth {
+            reader.submit(i)?;
+        }
+        Ok(reader)
+    }
+
+    /// Returns whether the file was opened with `O_DIRECT`.
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    // Submits the read of the next block of the file into buffer `i`.
+    fn submit(&mut self, i: usize) -> io::Result<()> {
+        if self.next_offset >= self.len {
+            self.reads[i] = None;
+            return Ok(());
+        }
+        let offset = self.next_offset;
+        let block_size = self.buffers[i].layout.size();
+        let len = block_size.min((self.len - offset) as usize);
+        self.next_offset += block_size as u64;
+
+        let fd = types::Fd(self.file.as_raw_fd());
+        let ptr = self.buffers[i].ptr.as_ptr();
+        // Full blocks keep `O_DIRECT` reads aligned, the last one is short
+        let entry = if self.fixed {
+            opcode::ReadFixed::new(fd, ptr, block_size as u32, i as u16)
+                .offset(offset)
+                .build()
+        } else {
+            opcode::Read::new(fd, ptr, block_size as u32)
+                .offset(offset)
+                .build()
+        };
+        // SAFETY: the buffer is not accessed until the read completes, and
+        // stays alive until then, see `Drop`
+        unsafe {
+            self.ring
+                .submission()
+                .push(&entry.user_data(i as u64))
+                .expect("one submission queue entry per buffer");
+        }
+        self.ring.submit()?;
+        self.reads[i] = Some(PendingRead {
+            offset,
+            len,
+            result: None,
+        });
+        Ok(())
+    }
+
+    // Waits for the read into buffer `i` and returns its number of bytes.
+    fn wait(&mut self, i: usize) -> io::Result<usize> {
+        loop {
+            for entry in self.ring.completion() {
+                if let Some(read) = self.reads[entry.user_data() as usize].as_mut() {
+                    read.result = Some(entry.result());
+                }
+            }
+            let read = self.reads[i].expect("buffer has a read");
+            let Some(result) = read.result else {
+                self.ring.submit_and_wait(1)?;
+                continue;
+            };
+            if result < 0 {
+                return Err(io::Error::from_raw_os_error(-result));
+            }
+
+            // Short reads other than at the end of the file are completed
+            // synchronously
+            let mut done = result as usize;
+            while done < read.len {
+                let buffer = &mut self.buffers[i].as_mut_slice()[done..read.len];
+                match self.file.read_at(buffer, read.offset + done as u64)? {
+                    0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    n => done += n,
+                }
+            }
+            return Ok(read.len);
+        }
+    }
+}
+
+impl BufRead for UringReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.filled {
+            if self.holding {
+                self.holding = false;
+                self.submit(self.current)?;
+                self.current = (self.current + 1) % self.buffers.len();
+            }
+            if self.reads[self.current].is_none() {
+                return Ok(&[]);
+            }
+            self.filled = self.wait(self.current)?;
+            self.pos = 0;
+            self.holding = true;
+        }
+        Ok(&self.buffers[self.current].as_slice()[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+impl Read for UringReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl Drop for UringReader {
+    fn drop(&mut self) {
+        // The kernel may still write into the buffers of reads in flight
+        while self
+            .reads
+            .iter()
+            .any(|read| read.is_some_and(|read| read.result.is_none()))
+        {
+            if self.ring.submit_and_wait(1).is_err() {
+                // The buffers cannot be freed safely
+                std::mem::forget(std::mem::take(&mut self.buffers));
+                return;
+            }
+            for entry in self.ring.completion() {
+                if let Some(read) = self.reads[entry.user_data() as usize].as_mut() {
+                    read.result = Some(entry.result());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_whole_file() {
+        let path = std::env::temp_dir().join(format!("hll_rust_uring_{}.fa", std::process::id()));
+        let data: Vec<u8> = (0..5 * ALIGNMENT + 123)
+            .map(|i| b"ACGT\n"[i * 7 % 5])
+            .collect();
+        std::fs::write(&path, &data).unwrap();
+
+        for direct in [false, true] {
+            let options = UringOptions {
+                block_size: 2 * ALIGNMENT,
+                depth: 2,
+                direct,
+            };
+            let mut reader = match UringReader::with_options(&path, options) {
+                Ok(reader) => reader,
+                // io_uring may be disabled, e.g. by a seccomp filter
+                Err(e) => {
+                    eprintln!("skipping io_uring test: {e}");
+                    break;
+                }
+            };
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, data, "direct {direct}");
+        }
+
+        // Dropped with reads in flight
+        let options = UringOptions {
+            block_size: ALIGNMENT,
+            depth: 4,
+            direct: false,
+        };
+        if let Ok(mut reader) = UringReader::with_options(&path, options) {
+            assert_eq!(reader.fill_buf().unwrap(), &data[..ALIGNMENT]);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+//! JavaScript bindings for the WebAssembly build (`wasm` feature).
+//!
+//! Exposes an `HLLCounter` class and a `countFastaKmers` function so browser
+//! tools can estimate cardinalities of uploaded files client-side.
+
+use crate::fasta::{EncodedKmer, FastaReader};
+use crate::{Counter, EstimateWithError, HLLCounter, MergeableCounter};
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+use xxhash_rust::xxh64::Xxh64Builder;
+
+/// HyperLogLog counter using the deterministic Xxh64 hash, so sketches are
+/// compatible with the ones written by the `hll` tool. FASTA k-mers are
+/// added 2-bit encoded like `hll sketch` does, not as text.
+#[wasm_bindgen(js_name = HLLCounter)]
+pub struct WasmHLLCounter {
+    inner: HLLCounter<Xxh64Builder>,
+}
+
+#[wasm_bindgen(js_class = HLLCounter)]
+impl WasmHLLCounter {
+    #[wasm_bindgen(constructor)]
+    pub fn new(precision: usize) -> Result<WasmHLLCounter, JsError> {
+        Ok(WasmHLLCounter {
+            inner: HLLCounter::builder().precision(precision).build()?,
+        })
+    }
+
+    /// Adds an item given as raw bytes.
+    pub fn add(&mut self, item: &[u8]) {
+        self.inner.add(item);
+    }
+
+    /// Adds a string item, hashed as its UTF-8 bytes.
+    #[wasm_bindgen(js_name = addString)]
+    pub fn add_string(&mut self, item: &str) {
+        self.inner.add(item.as_bytes());
+    }
+
+    /// Adds the 2-bit encoded canonical k-mers of all records of FASTA data,
+    /// as `hll sketch` does, and returns the number of k-mers seen.
+    #[wasm_bindgen(js_name = addFasta)]
+    pub fn add_fasta(&mut self, data: &[u8], k: usize) -> Result<f64, JsError> {
+        if !(1..=u64::MAX_K).contains(&k) {
+            return Err(JsError::new(&format!(
+                "k must be between 1 and {}",
+                u64::MAX_K
+            )));
+        }
+        let mut reader = FastaReader::new(Cursor::new(data));
+        let mut kmers_seen: u64 = 0;
+        while reader.next_record()? {
+            for kmer in reader.encoded_kmers::<u64>(k) {
+                self.inner.add_u64(kmer?);
+                kmers_seen += 1;
+            }
+        }
+        Ok(kmers_seen as f64)
+    }
+
+    pub fn estimate(&self) -> f64 {
+        self.inner.estimate()
+    }
+
+    /// Relative standard error of the estimate.
+    #[wasm_bindgen(js_name = relativeStdError)]
+    pub fn relative_std_error(&self) -> f64 {
+        self.inner.relative_std_error()
+    }
+
+    pub fn precision(&self) -> usize {
+        self.inner.precision()
+    }
+
+    /// Merges another counter of the same precision into this one.
+    pub fn merge(&mut self, other: &WasmHLLCounter) -> Result<(), JsError> {
+        self.inner
+            .merge(&other.inner)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Serializes the sketch in the same format as `hll sketch`.
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.inner
+            .write_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Deserializes a sketch written by `toBytes` or `hll sketch`.
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmHLLCounter, JsError> {
+        Ok(WasmHLLCounter {
+            inner: HLLCounter::read_from(bytes)?,
+        })
+    }
+}
+
+/// Estimates the number of distinct canonical k-mers in FASTA data.
+#[wasm_bindgen(js_name = countFastaKmers)]
+pub fn count_fasta_kmers(data: &[u8], k: usize, precision: usize) -> Result<f64, JsError> {
+    let mut counter = WasmHLLCounter::new(precision)?;
+    counter.add_fasta(data, k)?;
+    Ok(counter.estimate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_fasta_sketch_matches_cli() {
+        use crate::parallel_counting::run_parallel_fasta_analysis_with_k;
+
+        let mut data = Vec::new();
+        for record in 0..3u64 {
+            let seq = crate::synthetic::SequenceSimulator::new(record).generate(2000);
+            data.extend_from_slice(format!(">seq{record}\n").as_bytes());
+            for line in seq.chunks(70) {
+                data.extend_from_slice(line);
+                data.push(b'\n');
+            }
+        }
+        let path = std::env::temp_dir().join(format!("hll_rust_wasm_{}.fa", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        for precision in [10, 14] {
+            let mut counter = WasmHLLCounter::new(precision).unwrap();
+            let seen = counter.add_fasta(&data, 21).unwrap();
+            // The sketch `hll sketch -k 21` writes
+            let (cli_seen, sketch) =
+                run_parallel_fasta_analysis_with_k(path.to_str().unwrap(), 21, || {
+                    HLLCounter::<Xxh64Builder>::new(precision)
+                })
+                .unwrap();
+            let mut cli_bytes = Vec::new();
+            sketch.write_to(&mut cli_bytes).unwrap();
+            assert_eq!(seen, cli_seen as f64);
+            assert_eq!(counter.to_bytes(), cli_bytes, "precision {precision}");
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! Property tests of the accuracy guarantees: every counter estimates random
+//! streams of byte items within a few standard errors of the exact count,
+//! for a randomly seeded, a seeded and a deliberately weak hash function.
+
+use hll_rust::{
+    AdaptiveCounter, Counter, EstimateWithError, FMCounter, HLLCounter, HashCounter,
+    HyperMinHashCounter, KMVCounter, LinearCounter, PCSACounter, PackedHLLCounter, ThetaSketch,
+    ULLCounter,
+};
+use proptest::prelude::*;
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use xxhash_rust::xxh64::Xxh64Builder;
+
+// Allowed deviation in standard errors. Every counter of every stream is
+// checked, so the bound is wide enough to make false alarms negligible.
+const MAX_STD_ERRORS: f64 = 6.0;
+
+/// 64-bit FNV-1a, a fast hash whose low bits mix poorly, followed by a
+/// single multiply-xorshift round so that it stays usable for sketches.
+#[derive(Default, Clone)]
+struct WeakHasher(u64);
+
+impl Hasher for WeakHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        if self.0 == 0 {
+            self.0 = 0xcbf2_9ce4_8422_2325;
+        }
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let hash = self.0.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        hash ^ (hash >> 32)
+    }
+}
+
+#[derive(Default, Clone)]
+struct WeakHashBuilder;
+
+impl BuildHasher for WeakHashBuilder {
+    type Hasher = WeakHasher;
+
+    fn build_hasher(&self) -> WeakHasher {
+        WeakHasher::default()
+    }
+}
+
+// Adds the items and checks the estimate against the exact count, with an
+// allowance of one item for rounding at tiny cardinalities.
+fn check_counter<C: EstimateWithError>(name: &str, mut counter: C, items: &[Vec<u8>], exact: f64) {
+    for item in items {
+        counter.add(item);
+    }
+    let estimate = counter.estimate();
+    let std_error = counter.relative_std_error() * exact.max(estimate);
+    assert!(
+        (estimate - exact).abs() <= MAX_STD_ERRORS * std_error + 1.0,
+        "{name}: estimated {estimate:.1} distinct items, {exact} exact \
+         (standard error {std_error:.1})"
+    );
+
+    // A cleared counter estimates the same items the same way again
+    counter.clear();
+    for item in items {
+        counter.add(item);
+    }
+    assert_eq!(counter.estimate(), estimate, "{name}: estimate after clear");
+
+    // Packed items count the same through `add_u64`, which most counters
+    // override, and `add_item`
+    let packed: Vec<u64> = items
+        .iter()
+        .map(|item| {
+            item.iter()
+                .fold(item.len() as u64, |acc, &b| acc << 8 | b as u64)
+        })
+        .collect();
+    counter.clear();
+    for &item in &packed {
+        counter.add_u64(item);
+    }
+    let estimate = counter.estimate();
+    counter.clear();
+    for item in &packed {
+        counter.add_item(item);
+    }
+    assert_eq!(counter.estimate(), estimate, "{name}: add_u64 and add_item");
+}
+
+fn check_counters<S: BuildHasher + Default + Clone>(hasher: S, items: &[Vec<u8>]) {
+    let exact = items.iter().collect::<HashSet<_>>().len() as f64;
+    let h = || hasher.clone();
+
+    check_counter("HLL", HLLCounter::<S>::with_hasher(12, h()), items, exact);
+    check_counter(
+        "packed HLL",
+        PackedHLLCounter::with_hasher(12, h()),
+        items,
+        exact,
+    );
+    check_counter("ULL", ULLCounter::with_hasher(12, h()), items, exact);
+    check_counter(
+        "HyperMinHash",
+        HyperMinHashCounter::with_hasher(12, h()),
+        items,
+        exact,
+    );
+    check_counter(
+        "adaptive",
+        AdaptiveCounter::with_hasher(12, h()),
+        items,
+        exact,
+    );
+    check_counter("PCSA", PCSACounter::with_hasher(8, h()), items, exact);
+    check_counter("FM", FMCounter::with_hasher(32, h()), items, exact);
+    check_counter(
+        "linear",
+        LinearCounter::with_hasher(1 << 16, h()),
+        items,
+        exact,
+    );
+    check_counter("KMV", KMVCounter::with_hasher(256, h()), items, exact);
+    check_counter("theta", ThetaSketch::with_hasher(256, h()), items, exact);
+    check_counter("exact", HashCounter::with_hasher(0, h()), items, exact);
+}
+
+// Up to 5000 items of up to 3 bytes, so short items repeat often
+fn byte_items() -> impl Strategy<Value = Vec<Vec<u8>>> {
+    prop::collection::vec(prop::collection::vec(any::<u8>(), 0..=3), 0..5_000)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn estimates_within_bound_random_state(items in byte_items()) {
+        check_counters(RandomState::new(), &items);
+    }
+
+    #[test]
+    fn estimates_within_bound_xxh64(items in byte_items(), seed in any::<u64>()) {
+        check_counters(Xxh64Builder::new(seed), &items);
+    }
+
+    #[test]
+    fn estimates_within_bound_weak_hasher(items in byte_items()) {
+        check_counters(WeakHashBuilder, &items);
+    }
+}